@@ -0,0 +1,277 @@
+// analytics.rs - Per-game usage samples and the aggregates behind developer dashboards
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Samples older than this are dropped by `purge_expired`
+pub const ANALYTICS_RETENTION_SECONDS: i64 = 90 * SECONDS_PER_DAY;
+/// Longest range one dashboard query may cover
+pub const MAX_DASHBOARD_DAYS: i64 = 366;
+
+#[derive(Debug, Clone)]
+struct SessionRecord {
+    player_id: String,
+    started_at: i64,
+    ended_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DailyActiveUsers {
+    /// Midnight UTC starting the day
+    pub day: i64,
+    pub users: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ConcurrencySample {
+    pub at: i64,
+    pub players: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct WaitPercentiles {
+    pub samples: usize,
+    pub p50_seconds: Option<f64>,
+    pub p90_seconds: Option<f64>,
+    pub p99_seconds: Option<f64>,
+}
+
+/// Everything a developer dashboard shows for one game over a time range
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameDashboard {
+    pub game_id: String,
+    pub from: i64,
+    pub to: i64,
+    pub daily_active_users: Vec<DailyActiveUsers>,
+    pub concurrent_players: Vec<ConcurrencySample>,
+    /// Over sessions that ended in the range
+    pub average_session_seconds: Option<f64>,
+    /// Mean of sampled players / capacity across the game's rooms, 0.0 - 1.0
+    pub average_room_fill: Option<f64>,
+    pub matchmaking_wait: WaitPercentiles,
+}
+
+/// Collects sessions, concurrency, room fill and matchmaking waits per game. The
+/// game server and matchmaker feed it; `dashboard` aggregates on request.
+pub struct AnalyticsService {
+    open_sessions: Mutex<HashMap<String, (String, i64)>>, // player_id -> (game_id, started_at)
+    sessions: Mutex<HashMap<String, Vec<SessionRecord>>>, // game_id -> finished sessions
+    concurrency: Mutex<HashMap<String, Vec<ConcurrencySample>>>, // game_id -> samples, oldest first
+    room_fill: Mutex<HashMap<String, Vec<(i64, f64)>>>, // game_id -> (at, fill ratio)
+    matchmaking_waits: Mutex<HashMap<String, Vec<(i64, f64)>>>, // game_id -> (matched at, seconds waited)
+}
+
+impl AnalyticsService {
+    pub fn new() -> Self {
+        Self {
+            open_sessions: Mutex::new(HashMap::new()),
+            sessions: Mutex::new(HashMap::new()),
+            concurrency: Mutex::new(HashMap::new()),
+            room_fill: Mutex::new(HashMap::new()),
+            matchmaking_waits: Mutex::new(HashMap::new()),
+        }
+    }
+    
+    /// A player connected to a game. An earlier open session of theirs is closed.
+    pub fn session_started(&self, player_id: &str, game_id: &str, now: i64) {
+        self.session_ended(player_id, now);
+        self.open_sessions.lock().unwrap().insert(player_id.to_string(), (game_id.to_string(), now));
+    }
+    
+    pub fn session_ended(&self, player_id: &str, now: i64) {
+        let Some((game_id, started_at)) = self.open_sessions.lock().unwrap().remove(player_id) else { return };
+        
+        self.sessions.lock().unwrap().entry(game_id).or_default().push(SessionRecord {
+            player_id: player_id.to_string(),
+            started_at,
+            ended_at: Some(now),
+        });
+    }
+    
+    pub fn record_concurrency(&self, game_id: &str, players: usize, now: i64) {
+        self.concurrency.lock().unwrap()
+            .entry(game_id.to_string())
+            .or_default()
+            .push(ConcurrencySample { at: now, players });
+    }
+    
+    pub fn record_room_fill(&self, game_id: &str, players: usize, max_players: usize, now: i64) {
+        if max_players == 0 {
+            return;
+        }
+        self.room_fill.lock().unwrap()
+            .entry(game_id.to_string())
+            .or_default()
+            .push((now, players as f64 / max_players as f64));
+    }
+    
+    pub fn record_matchmaking_wait(&self, game_id: &str, waited: Duration, now: i64) {
+        self.matchmaking_waits.lock().unwrap()
+            .entry(game_id.to_string())
+            .or_default()
+            .push((now, waited.as_secs_f64()));
+    }
+    
+    /// Aggregate a game's samples between `from` and `to` (inclusive, Unix seconds).
+    /// Ranges may span at most `MAX_DASHBOARD_DAYS`.
+    pub fn dashboard(&self, game_id: &str, from: i64, to: i64) -> Result<GameDashboard, String> {
+        let span = to.checked_sub(from).filter(|span| *span >= 0)
+            .ok_or_else(|| "Dashboard range must not end before it starts".to_string())?;
+        if span >= MAX_DASHBOARD_DAYS * SECONDS_PER_DAY {
+            return Err(format!("Dashboard range is limited to {} days", MAX_DASHBOARD_DAYS));
+        }
+        
+        let mut sessions: Vec<SessionRecord> = self.sessions.lock().unwrap().get(game_id).cloned().unwrap_or_default();
+        sessions.extend(self.open_sessions.lock().unwrap()
+            .iter()
+            .filter(|(_, (game, _))| game == game_id)
+            .map(|(player_id, (_, started_at))| SessionRecord {
+                player_id: player_id.clone(),
+                started_at: *started_at,
+                ended_at: None,
+            }));
+        
+        let mut daily_active_users = Vec::new();
+        let first_day = from.div_euclid(SECONDS_PER_DAY) * SECONDS_PER_DAY;
+        for index in 0..=(to - first_day) / SECONDS_PER_DAY {
+            let day = first_day + index * SECONDS_PER_DAY;
+            let day_end = day.saturating_add(SECONDS_PER_DAY);
+            let users: HashSet<&str> = sessions.iter()
+                .filter(|s| s.started_at < day_end && s.ended_at.is_none_or(|end| end >= day))
+                .map(|s| s.player_id.as_str())
+                .collect();
+            daily_active_users.push(DailyActiveUsers { day, users: users.len() });
+        }
+        
+        let lengths: Vec<f64> = sessions.iter()
+            .filter_map(|s| s.ended_at.filter(|end| (from..=to).contains(end)).map(|end| (end - s.started_at) as f64))
+            .collect();
+        
+        let concurrent_players = self.concurrency.lock().unwrap()
+            .get(game_id)
+            .map(|samples| samples.iter().filter(|s| (from..=to).contains(&s.at)).copied().collect())
+            .unwrap_or_default();
+        
+        let fills: Vec<f64> = self.room_fill.lock().unwrap()
+            .get(game_id)
+            .map(|samples| samples.iter().filter(|(at, _)| (from..=to).contains(at)).map(|(_, fill)| *fill).collect())
+            .unwrap_or_default();
+        
+        let mut waits: Vec<f64> = self.matchmaking_waits.lock().unwrap()
+            .get(game_id)
+            .map(|samples| samples.iter().filter(|(at, _)| (from..=to).contains(at)).map(|(_, wait)| *wait).collect())
+            .unwrap_or_default();
+        waits.sort_by(|a, b| a.total_cmp(b));
+        
+        Ok(GameDashboard {
+            game_id: game_id.to_string(),
+            from,
+            to,
+            daily_active_users,
+            concurrent_players,
+            average_session_seconds: mean(&lengths),
+            average_room_fill: mean(&fills),
+            matchmaking_wait: WaitPercentiles {
+                samples: waits.len(),
+                p50_seconds: percentile(&waits, 50.0),
+                p90_seconds: percentile(&waits, 90.0),
+                p99_seconds: percentile(&waits, 99.0),
+            },
+        })
+    }
+    
+    /// Drop samples older than `ANALYTICS_RETENTION_SECONDS`
+    pub fn purge_expired(&self, now: i64) {
+        let cutoff = now - ANALYTICS_RETENTION_SECONDS;
+        
+        for records in self.sessions.lock().unwrap().values_mut() {
+            records.retain(|s| s.ended_at.is_none_or(|end| end >= cutoff));
+        }
+        for samples in self.concurrency.lock().unwrap().values_mut() {
+            samples.retain(|s| s.at >= cutoff);
+        }
+        for samples in self.room_fill.lock().unwrap().values_mut() {
+            samples.retain(|(at, _)| *at >= cutoff);
+        }
+        for samples in self.matchmaking_waits.lock().unwrap().values_mut() {
+            samples.retain(|(at, _)| *at >= cutoff);
+        }
+    }
+}
+
+impl Default for AnalyticsService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+// ============================================
+// Tests
+// ============================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_dashboard_aggregates() {
+        let analytics = AnalyticsService::new();
+        let day = SECONDS_PER_DAY;
+        
+        analytics.session_started("a", "arena", 100);
+        analytics.session_ended("a", 700);
+        analytics.session_started("b", "arena", 200);
+        analytics.session_ended("b", 500);
+        analytics.session_started("a", "arena", day + 10);
+        analytics.session_started("c", "other", 100);
+        
+        analytics.record_concurrency("arena", 2, 300);
+        analytics.record_room_fill("arena", 2, 4, 300);
+        analytics.record_room_fill("arena", 4, 4, 300);
+        for wait in 1..=10 {
+            analytics.record_matchmaking_wait("arena", Duration::from_secs(wait), 400);
+        }
+        
+        assert!(analytics.dashboard("arena", 10, 0).is_err());
+        assert!(analytics.dashboard("arena", 0, i64::MAX).is_err());
+        assert!(analytics.dashboard("arena", i64::MAX - day, i64::MAX).is_ok());
+        
+        let dashboard = analytics.dashboard("arena", 0, 2 * day - 1).unwrap();
+        assert_eq!(dashboard.daily_active_users, vec![
+            DailyActiveUsers { day: 0, users: 2 },
+            DailyActiveUsers { day, users: 1 },
+        ]);
+        assert_eq!(dashboard.concurrent_players, vec![ConcurrencySample { at: 300, players: 2 }]);
+        assert_eq!(dashboard.average_session_seconds, Some(450.0));
+        assert_eq!(dashboard.average_room_fill, Some(0.75));
+        assert_eq!(dashboard.matchmaking_wait.samples, 10);
+        assert_eq!(dashboard.matchmaking_wait.p50_seconds, Some(5.0));
+        assert_eq!(dashboard.matchmaking_wait.p90_seconds, Some(9.0));
+        assert_eq!(dashboard.matchmaking_wait.p99_seconds, Some(10.0));
+        
+        analytics.purge_expired(ANALYTICS_RETENTION_SECONDS + 600);
+        let purged = analytics.dashboard("arena", 0, 2 * day - 1).unwrap();
+        assert_eq!(purged.average_session_seconds, Some(600.0));
+        assert!(purged.concurrent_players.is_empty());
+        assert_eq!(purged.matchmaking_wait, WaitPercentiles::default());
+    }
+}
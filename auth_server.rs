@@ -1,10 +1,17 @@
 // auth_server.rs - User Authentication and Account Management System
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 use bcrypt::{hash, verify, DEFAULT_COST};
 use jsonwebtoken::{encode, decode, Header, Validation, EncodingKey, DecodingKey};
 use chrono::{Utc, Duration};
+use sha2::{Digest, Sha256};
+
+use crate::cache::{CacheStats, TtlCache};
+use crate::email::Mailer;
+use crate::idempotency::IdempotencyCache;
+use crate::persistence::{PendingWrite, WriteBehindQueue, WriteKind};
+use crate::transport::{decode_json, DecodeError};
 
 // ============================================
 // Data Structures
@@ -21,8 +28,173 @@ pub struct User {
     pub avatar_url: Option<String>,
     pub games_created: Vec<String>,
     pub games_played: Vec<String>,
+    /// Legacy in-memory friend list; moved into the social graph by `migrate_social_graph`
+    #[serde(default)]
     pub friends: Vec<String>,
     pub achievements: Vec<Achievement>,
+    #[serde(default)]
+    pub state: AccountState,
+    #[serde(default)]
+    pub xp: u64,
+    #[serde(default)]
+    pub ratings: HashMap<String, i32>, // game_id -> rating
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub bio: Option<String>,
+    #[serde(default)]
+    pub status_message: Option<String>,
+    #[serde(default)]
+    pub pronouns: Option<String>,
+    #[serde(default)]
+    pub badges: Vec<Badge>,
+    #[serde(default)]
+    pub discoverable: Discoverability,
+    /// Legacy, most recent first; moved into the social graph by `migrate_social_graph`
+    #[serde(default)]
+    pub recent_players: Vec<String>,
+    #[serde(default)]
+    pub privacy: PrivacySettings,
+    #[serde(default)]
+    pub age_bracket: Option<AgeBracket>,
+    #[serde(default)]
+    pub parental_controls: ParentalControls,
+    /// Game IDs this user moderates; "*" grants authority over every game
+    #[serde(default)]
+    pub moderator_scopes: Vec<String>,
+    /// Steam/Meta accounts linked through OAuth
+    #[serde(default)]
+    pub linked_identities: Vec<LinkedIdentity>,
+    #[serde(default)]
+    pub friend_import: FriendImportConsent,
+    #[serde(default)]
+    pub inventory: HashMap<String, u32>, // item_id -> count
+    #[serde(default)]
+    pub onboarding: HashMap<String, Vec<String>>, // game_id -> completed tutorial steps
+}
+
+/// Account lifecycle. Anything other than `Active` (or a lapsed suspension) is
+/// refused at login and when a token is verified.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum AccountState {
+    #[default]
+    Active,
+    /// None = until an admin lifts it
+    Suspended { until: Option<i64>, reason: String },
+    /// Soft-deleted; purged for good at `purge_at` unless the owner logs back in first
+    PendingDeletion { purge_at: i64 },
+    Banned { reason: String },
+}
+
+impl AccountState {
+    /// Ok if the account may log in and use tokens at `now`
+    pub fn check_access(&self, now: i64) -> Result<(), String> {
+        match self {
+            AccountState::Active => Ok(()),
+            AccountState::Suspended { until: Some(until), .. } if *until <= now => Ok(()),
+            AccountState::Suspended { until: Some(until), reason } => {
+                Err(format!("Account is suspended until {}: {}", until, reason))
+            }
+            AccountState::Suspended { until: None, reason } => Err(format!("Account is suspended: {}", reason)),
+            AccountState::PendingDeletion { .. } => Err("Account is scheduled for deletion".to_string()),
+            AccountState::Banned { .. } => Err("Account is banned".to_string()),
+        }
+    }
+    
+    pub fn is_active(&self, now: i64) -> bool {
+        self.check_access(now).is_ok()
+    }
+}
+
+/// Content rating of a game or room, ordered from least to most restricted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+pub enum ContentRating {
+    #[default]
+    Everyone,
+    Teen,
+    Mature,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AgeBracket {
+    Under13,
+    Teen,
+    Adult,
+}
+
+impl AgeBracket {
+    pub fn max_rating(&self) -> ContentRating {
+        match self {
+            AgeBracket::Under13 => ContentRating::Everyone,
+            AgeBracket::Teen => ContentRating::Teen,
+            AgeBracket::Adult => ContentRating::Mature,
+        }
+    }
+}
+
+/// Limits a parent or guardian can place on top of the age bracket
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ParentalControls {
+    #[serde(default)]
+    pub max_rating: Option<ContentRating>,
+    /// Room tags (e.g. "gambling") this account may not join
+    #[serde(default)]
+    pub blocked_tags: Vec<String>,
+}
+
+/// Who a privacy setting lets through; "friends" means people the user has friended
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum PrivacyAudience {
+    #[default]
+    Everyone,
+    Friends,
+    Nobody,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct PrivacySettings {
+    #[serde(default)]
+    pub invites: PrivacyAudience,
+    #[serde(default)]
+    pub direct_messages: PrivacyAudience,
+    #[serde(default)]
+    pub presence: PrivacyAudience,
+    /// Keep this user's voice out of moderation recordings
+    #[serde(default)]
+    pub voice_recording_opt_out: bool,
+}
+
+/// Something one user does to another that privacy settings can block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrivacyAction {
+    Invite,
+    DirectMessage,
+    ViewPresence,
+}
+
+/// Who can find a user through search and friend suggestions
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Discoverability {
+    #[default]
+    Everyone,
+    FriendsOfFriends,
+    Nobody,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Badge {
+    pub id: String,
+    pub name: String,
+    pub awarded_at: i64,
+}
+
+/// Editable profile fields; `None` leaves a field unchanged, `Some("")` clears it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileUpdate {
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub status_message: Option<String>,
+    pub pronouns: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +203,59 @@ pub struct Achievement {
     pub name: String,
     pub description: String,
     pub unlocked_at: i64,
+    /// Environment-scoped game that awarded it; None for production and platform achievements
+    #[serde(default)]
+    pub sandbox_game_id: Option<String>,
+}
+
+/// Public view of another user, safe to show in search results
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserSummary {
+    pub id: String,
+    pub username: String,
+    pub display_name: String,
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserSearchPage {
+    pub results: Vec<UserSummary>,
+    pub total: usize,
+    pub next_offset: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FriendSuggestion {
+    pub user: UserSummary,
+    pub mutual_friends: usize,
+    pub played_recently: bool,
+    /// Friends on a linked platform account
+    #[serde(default)]
+    pub platform_friend: bool,
+}
+
+/// One player's part in a finished match
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchParticipant {
+    pub user_id: String,
+    pub score: i64,
+    pub won: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    pub user_id: String,
+    pub username: String,
+    pub rating: i32,
+}
+
+/// Account flagged for moderator review because it shares devices with banned accounts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModerationFlag {
+    pub user_id: String,
+    pub banned_accounts: Vec<String>,
+    pub shared_devices: Vec<String>,
+    pub flagged_at: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -38,15 +263,40 @@ pub struct SignupRequest {
     pub username: String,
     pub email: String,
     pub password: String,
+    /// Optional client-supplied device/hardware identifiers
+    #[serde(default)]
+    pub device_ids: Vec<String>,
+    /// CAPTCHA token or proof-of-work solution, when the deployment requires one
+    #[serde(default)]
+    pub challenge_response: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
+    #[serde(default)]
+    pub device_ids: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Largest signup or login body parsed; real requests are a few hundred bytes
+pub const MAX_AUTH_REQUEST_BYTES: usize = 16 * 1024;
+
+impl SignupRequest {
+    /// Parse an untrusted request body
+    pub fn decode(body: &[u8]) -> Result<Self, DecodeError> {
+        decode_json(body, MAX_AUTH_REQUEST_BYTES)
+    }
+}
+
+impl LoginRequest {
+    /// Parse an untrusted request body
+    pub fn decode(body: &[u8]) -> Result<Self, DecodeError> {
+        decode_json(body, MAX_AUTH_REQUEST_BYTES)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthResponse {
     pub success: bool,
     pub message: String,
@@ -63,6 +313,28 @@ pub struct UserProfile {
     pub games_created: Vec<String>,
     pub games_played: Vec<String>,
     pub friend_count: usize,
+    pub xp: u64,
+    /// Name shown in rooms; falls back to the login username
+    pub display_name: String,
+    pub bio: Option<String>,
+    pub status_message: Option<String>,
+    pub pronouns: Option<String>,
+    pub badges: Vec<Badge>,
+}
+
+/// A user's tutorial progress in one game, checked by the client on connect
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OnboardingState {
+    pub game_id: String,
+    pub completed_steps: Vec<String>,
+    /// Tutorial steps still to do, in order
+    pub remaining_steps: Vec<String>,
+}
+
+impl OnboardingState {
+    pub fn needs_tutorial(&self) -> bool {
+        !self.remaining_steps.is_empty()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -70,15 +342,103 @@ struct Claims {
     sub: String,  // user id
     exp: i64,     // expiration time
     iat: i64,     // issued at
+    aud: String,  // service the token is valid for
+    #[serde(default)]
+    games: Vec<String>, // game_ids a game-session token is scoped to
+}
+
+/// Stage a game build runs against. Sandboxed environments get their own namespace:
+/// `Environment::scope_game` turns a game ID into the key used for its rooms, queues,
+/// tokens, ratings and achievements, so test builds never touch production data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Environment {
+    Dev,
+    Staging,
+    #[default]
+    Prod,
+}
+
+impl Environment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Environment::Dev => "dev",
+            Environment::Staging => "staging",
+            Environment::Prod => "prod",
+        }
+    }
+    
+    /// Namespaced game key; production keeps the bare game ID so existing data stays put
+    pub fn scope_game(&self, game_id: &str) -> String {
+        match self {
+            Environment::Prod => game_id.to_string(),
+            env => format!("{}#{}", game_id, env.as_str()),
+        }
+    }
+    
+    /// Split a possibly scoped game key back into the game ID and its environment
+    pub fn of_game(scoped_game_id: &str) -> (&str, Environment) {
+        match scoped_game_id.rsplit_once('#') {
+            Some((game_id, "dev")) => (game_id, Environment::Dev),
+            Some((game_id, "staging")) => (game_id, Environment::Staging),
+            _ => (scoped_game_id, Environment::Prod),
+        }
+    }
+}
+
+/// Service a token is issued for; tokens are rejected by every other service
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenAudience {
+    /// Account APIs; issued at signup and login
+    Platform,
+    /// Game transport, valid only for the given game_id
+    GameSession(String),
+}
+
+impl TokenAudience {
+    fn claim(&self) -> &'static str {
+        match self {
+            TokenAudience::Platform => "vr-platform",
+            TokenAudience::GameSession(_) => "vr-game-session",
+        }
+    }
 }
 
 // ============================================
 // Authentication Service
 // ============================================
 
+const DEFAULT_RATING: i32 = 1000;
+const RATING_K_FACTOR: f64 = 32.0;
+pub const MATCH_WIN_XP: u64 = 100;
+pub const MATCH_PLAYED_XP: u64 = 25;
+const MAX_DISPLAY_NAME_LEN: usize = 32;
+const MAX_BIO_LEN: usize = 280;
+const MAX_STATUS_LEN: usize = 80;
+const MAX_PRONOUNS_LEN: usize = 24;
+const MAX_RECENT_PLAYERS: usize = 50;
+const MAX_SEARCH_PAGE: usize = 50;
+const ACCOUNT_DELETION_GRACE_SECONDS: i64 = 30 * 24 * 60 * 60;
+const PROFILE_CACHE_TTL_SECONDS: u64 = 30;
+
 pub struct AuthService {
     users: Arc<Mutex<HashMap<String, User>>>,
     email_to_id: Arc<Mutex<HashMap<String, String>>>,
+    device_users: Arc<Mutex<HashMap<String, HashSet<String>>>>, // device_id -> user_ids
+    review_queue: Arc<Mutex<Vec<ModerationFlag>>>,
+    challenge_verifier: Option<Arc<dyn ChallengeVerifier>>,
+    mailer: Option<Arc<Mailer>>,
+    blocked_words: Vec<String>,
+    social: Arc<dyn SocialGraphStore>,
+    signups: IdempotencyCache<AuthResponse>,
+    achievement_unlocks: IdempotencyCache<()>,
+    profiles: TtlCache<String, Option<UserProfile>>, // user_id -> profile
+    friend_sources: HashMap<Platform, Arc<dyn PlatformFriendSource>>,
+    identities: Arc<Mutex<HashMap<(Platform, String), String>>>, // (platform, external_id) -> user_id
+    platform_friends: Arc<Mutex<HashMap<String, HashSet<String>>>>, // user_id -> imported user_ids
+    friend_syncs: Arc<Mutex<HashMap<String, i64>>>, // user_id -> last sync
+    tutorials: Arc<Mutex<HashMap<String, Vec<String>>>>, // game_id -> tutorial steps in order
+    write_behind: Option<Arc<WriteBehindQueue>>,
     jwt_secret: String,
 }
 
@@ -89,10 +449,66 @@ impl AuthService {
         Self {
             users: Arc::new(Mutex::new(HashMap::new())),
             email_to_id: Arc::new(Mutex::new(HashMap::new())),
+            device_users: Arc::new(Mutex::new(HashMap::new())),
+            review_queue: Arc::new(Mutex::new(Vec::new())),
+            challenge_verifier: None,
+            mailer: None,
+            blocked_words: Vec::new(),
+            social: Arc::new(InMemorySocialGraph::new()),
+            signups: IdempotencyCache::default(),
+            achievement_unlocks: IdempotencyCache::default(),
+            profiles: TtlCache::new("profiles", std::time::Duration::from_secs(PROFILE_CACHE_TTL_SECONDS)),
+            friend_sources: HashMap::new(),
+            identities: Arc::new(Mutex::new(HashMap::new())),
+            platform_friends: Arc::new(Mutex::new(HashMap::new())),
+            friend_syncs: Arc::new(Mutex::new(HashMap::new())),
+            tutorials: Arc::new(Mutex::new(HashMap::new())),
+            write_behind: None,
             jwt_secret,
         }
     }
     
+    /// Require signups to pass a CAPTCHA or proof-of-work challenge
+    pub fn with_challenge_verifier(mut self, verifier: Arc<dyn ChallengeVerifier>) -> Self {
+        self.challenge_verifier = Some(verifier);
+        self
+    }
+    
+    /// Send account emails (ban notices, ...) through `mailer`
+    pub fn with_mailer(mut self, mailer: Arc<Mailer>) -> Self {
+        self.mailer = Some(mailer);
+        self
+    }
+    
+    /// Import friend lists from a platform's API for users who linked that platform
+    pub fn with_friend_source(mut self, source: Arc<dyn PlatformFriendSource>) -> Self {
+        self.friend_sources.insert(source.platform(), source);
+        self
+    }
+    
+    /// Keep friends, blocks and recent players in `store` instead of memory
+    pub fn with_social_store(mut self, store: Arc<dyn SocialGraphStore>) -> Self {
+        self.social = store;
+        self
+    }
+    
+    /// Persist achievements and match stats through `queue` instead of on the caller's thread
+    pub fn with_write_behind(mut self, queue: Arc<WriteBehindQueue>) -> Self {
+        self.write_behind = Some(queue);
+        self
+    }
+    
+    /// Reject profile text containing any of these words (case-insensitive)
+    pub fn with_blocked_words(mut self, words: Vec<String>) -> Self {
+        self.blocked_words = words.into_iter().map(|w| w.to_lowercase()).collect();
+        self
+    }
+    
+    /// Challenge a client must solve before signing up, if the verifier issues one
+    pub fn signup_challenge(&self) -> Option<String> {
+        self.challenge_verifier.as_ref().and_then(|v| v.issue_challenge())
+    }
+    
     /// Sign up a new user
     pub fn signup(&self, request: SignupRequest) -> AuthResponse {
         println!("[AuthService] Signup request for: {}", request.username);
@@ -125,6 +541,24 @@ impl AuthService {
             };
         }
         
+        // Anti-automation challenge
+        if let Some(verifier) = &self.challenge_verifier {
+            let result = match &request.challenge_response {
+                Some(response) => verifier.verify(response),
+                None => Err("Challenge response required".to_string()),
+            };
+            
+            if let Err(e) = result {
+                println!("[AuthService] Signup challenge failed for {}: {}", request.username, e);
+                return AuthResponse {
+                    success: false,
+                    message: e,
+                    token: None,
+                    user: None,
+                };
+            }
+        }
+        
         // Check if email already exists
         {
             let email_map = self.email_to_id.lock().unwrap();
@@ -165,6 +599,24 @@ impl AuthService {
             games_played: Vec::new(),
             friends: Vec::new(),
             achievements: Vec::new(),
+            state: AccountState::Active,
+            xp: 0,
+            ratings: HashMap::new(),
+            display_name: None,
+            bio: None,
+            status_message: None,
+            pronouns: None,
+            badges: Vec::new(),
+            discoverable: Discoverability::default(),
+            recent_players: Vec::new(),
+            privacy: PrivacySettings::default(),
+            age_bracket: None,
+            parental_controls: ParentalControls::default(),
+            moderator_scopes: Vec::new(),
+            linked_identities: Vec::new(),
+            friend_import: FriendImportConsent::default(),
+            inventory: HashMap::new(),
+            onboarding: HashMap::new(),
         };
         
         // Store user
@@ -172,14 +624,17 @@ impl AuthService {
             let mut users = self.users.lock().unwrap();
             users.insert(user_id.clone(), user.clone());
         }
+        self.profiles.invalidate(&user_id);
         
         {
             let mut email_map = self.email_to_id.lock().unwrap();
             email_map.insert(request.email.clone(), user_id.clone());
         }
         
+        self.link_devices(&user_id, &request.device_ids);
+        
         // Generate JWT token
-        let token = self.generate_token(&user_id);
+        let token = self.generate_token(&user_id, &TokenAudience::Platform, Duration::days(30));
         
         println!("[AuthService] User created successfully: {}", user.username);
         
@@ -230,7 +685,19 @@ impl AuthService {
         // Verify password
         match verify(&request.password, &user.password_hash) {
             Ok(valid) if valid => {
-                let token = self.generate_token(&user.id);
+                if let Err(message) = self.admit_login(&user) {
+                    println!("[AuthService] Login refused for {}: {}", user.username, message);
+                    
+                    return AuthResponse {
+                        success: false,
+                        message,
+                        token: None,
+                        user: None,
+                    };
+                }
+                
+                let token = self.generate_token(&user.id, &TokenAudience::Platform, Duration::days(30));
+                self.link_devices(&user.id, &request.device_ids);
                 
                 println!("[AuthService] Login successful: {}", user.username);
                 
@@ -254,27 +721,231 @@ impl AuthService {
         }
     }
     
-    /// Verify a JWT token and return user ID
+    /// Verify a platform JWT token and return user ID
     pub fn verify_token(&self, token: &str) -> Option<String> {
-        let validation = Validation::default();
+        self.verify_token_for(token, &TokenAudience::Platform)
+    }
+    
+    /// Verify a JWT token for a specific audience and return user ID
+    pub fn verify_token_for(&self, token: &str, audience: &TokenAudience) -> Option<String> {
+        let mut validation = Validation::default();
+        validation.set_audience(&[audience.claim()]);
         
-        match decode::<Claims>(
+        let claims = match decode::<Claims>(
             token,
             &DecodingKey::from_secret(self.jwt_secret.as_bytes()),
             &validation,
         ) {
-            Ok(data) => Some(data.claims.sub),
+            Ok(data) => data.claims,
             Err(e) => {
                 eprintln!("[AuthService] Token verification failed: {}", e);
-                None
+                return None;
+            }
+        };
+        
+        if let TokenAudience::GameSession(game_id) = audience {
+            if !claims.games.contains(game_id) {
+                eprintln!("[AuthService] Token verification failed: not scoped to game {}", game_id);
+                return None;
+            }
+        }
+        
+        // Tokens outlive suspensions, deletion requests and purges, so re-check the account
+        let now = Utc::now().timestamp();
+        let access = match self.users.lock().unwrap().get(&claims.sub) {
+            Some(user) => user.state.check_access(now),
+            None => Err("Account no longer exists".to_string()),
+        };
+        if let Err(e) = access {
+            eprintln!("[AuthService] Token verification failed for {}: {}", claims.sub, e);
+            return None;
+        }
+        
+        Some(claims.sub)
+    }
+    
+    /// Mint a short-lived token that only the game transport accepts, for one game.
+    /// `game_id` may be environment-scoped (see `Environment::scope_game`); sandbox
+    /// tokens are only issued to the game's developers and moderators.
+    pub fn mint_game_token(&self, user_id: &str, game_id: &str) -> Option<String> {
+        {
+            let users = self.users.lock().unwrap();
+            match users.get(user_id) {
+                Some(user) if user.state.is_active(Utc::now().timestamp()) => {}
+                _ => return None,
             }
         }
+        
+        let (base_game_id, environment) = Environment::of_game(game_id);
+        if environment != Environment::Prod && !self.can_manage_sandbox(user_id, base_game_id) {
+            println!("[AuthService] Refused {} token for {} to {}", environment.as_str(), base_game_id, user_id);
+            return None;
+        }
+        
+        println!("[AuthService] Game token minted: {} for {}", game_id, user_id);
+        
+        Some(self.generate_token(
+            user_id,
+            &TokenAudience::GameSession(game_id.to_string()),
+            Duration::hours(2),
+        ))
     }
     
-    /// Get user profile by ID
-    pub fn get_user(&self, user_id: &str) -> Option<UserProfile> {
+    /// Verify a token for `audience` and resolve the account behind it, rejecting inactive accounts
+    pub fn authenticate(&self, token: &str, audience: &TokenAudience) -> Result<UserProfile, String> {
+        let user_id = self.verify_token_for(token, audience)
+            .ok_or_else(|| "Invalid or expired token".to_string())?;
+        
+        self.get_active_user(&user_id)
+    }
+    
+    /// Resolve a user who is allowed to play, rejecting suspended, deleted and banned accounts
+    pub fn get_active_user(&self, user_id: &str) -> Result<UserProfile, String> {
         let users = self.users.lock().unwrap();
-        users.get(user_id).map(|u| self.user_to_profile(u))
+        let user = users.get(user_id).ok_or_else(|| "User not found".to_string())?;
+        
+        if let Err(e) = user.state.check_access(Utc::now().timestamp()) {
+            println!("[AuthService] Rejected inactive user {}: {}", user_id, e);
+            return Err(e);
+        }
+        
+        Ok(self.user_to_profile(user))
+    }
+    
+    /// Ban or unban a user
+    pub fn set_banned(&self, user_id: &str, banned: bool) -> bool {
+        let state = if banned {
+            AccountState::Banned { reason: "Community guidelines violation".to_string() }
+        } else {
+            AccountState::Active
+        };
+        
+        self.set_account_state(user_id, state).is_ok()
+    }
+    
+    pub fn account_state(&self, user_id: &str) -> Option<AccountState> {
+        self.users.lock().unwrap().get(user_id).map(|u| u.state.clone())
+    }
+    
+    /// Admin transition to any account state; a ban also emails the user
+    pub fn set_account_state(&self, user_id: &str, state: AccountState) -> Result<(), String> {
+        let (email, username) = {
+            let mut users = self.users.lock().unwrap();
+            let user = users.get_mut(user_id).ok_or_else(|| "User not found".to_string())?;
+            
+            println!("[AuthService] User {} state: {:?} -> {:?}", user_id, user.state, state);
+            user.state = state.clone();
+            (user.email.clone(), user.username.clone())
+        };
+        
+        if let (AccountState::Banned { .. }, Some(mailer)) = (&state, &self.mailer) {
+            let vars = HashMap::from([("username", username)]);
+            if let Err(e) = mailer.send_template(&email, "account_banned", "en", &vars) {
+                eprintln!("[AuthService] Failed to queue ban notice for {}: {}", user_id, e);
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Suspend an account, optionally until a time after which it is usable again
+    pub fn suspend_account(&self, user_id: &str, until: Option<i64>, reason: &str) -> Result<(), String> {
+        self.set_account_state(user_id, AccountState::Suspended { until, reason: reason.to_string() })
+    }
+    
+    /// Soft-delete an account; it is purged after the grace period unless restored
+    pub fn request_deletion(&self, user_id: &str) -> Result<(), String> {
+        let purge_at = Utc::now().timestamp() + ACCOUNT_DELETION_GRACE_SECONDS;
+        self.set_account_state(user_id, AccountState::PendingDeletion { purge_at })
+    }
+    
+    /// Lift a suspension, ban or pending deletion
+    pub fn restore_account(&self, user_id: &str) -> Result<(), String> {
+        self.set_account_state(user_id, AccountState::Active)
+    }
+    
+    /// Permanently remove accounts whose deletion grace period is over, along with
+    /// their email, device links and social graph. Returns the purged user IDs.
+    pub fn purge_pending_deletions(&self, now: i64) -> Vec<String> {
+        let purged: Vec<User> = {
+            let mut users = self.users.lock().unwrap();
+            let due: Vec<String> = users.values()
+                .filter(|u| matches!(u.state, AccountState::PendingDeletion { purge_at } if purge_at <= now))
+                .map(|u| u.id.clone())
+                .collect();
+            
+            due.iter().filter_map(|id| users.remove(id)).collect()
+        };
+        
+        if purged.is_empty() {
+            return Vec::new();
+        }
+        
+        {
+            let mut email_map = self.email_to_id.lock().unwrap();
+            let mut device_users = self.device_users.lock().unwrap();
+            
+            for user in &purged {
+                email_map.remove(&user.email);
+                for linked in device_users.values_mut() {
+                    linked.remove(&user.id);
+                }
+            }
+            device_users.retain(|_, linked| !linked.is_empty());
+        }
+        
+        for user in &purged {
+            self.forget_platform_friends(&user.id);
+            let mut identities = self.identities.lock().unwrap();
+            for identity in &user.linked_identities {
+                identities.remove(&(identity.platform, identity.external_id.clone()));
+            }
+        }
+        
+        for user in &purged {
+            // Former friends' cached profiles still count this user
+            let friends = self.get_friends(&user.id);
+            if let Err(e) = self.social.remove_user(&user.id) {
+                eprintln!("[AuthService] Failed to remove social graph for {}: {}", user.id, e);
+            }
+            for friend_id in &friends {
+                self.invalidate_friend_counts(&user.id, friend_id);
+            }
+        }
+        
+        for user in &purged {
+            self.profiles.invalidate(&user.id);
+        }
+        
+        println!("[AuthService] Purged {} deleted accounts", purged.len());
+        
+        purged.into_iter().map(|u| u.id).collect()
+    }
+    
+    /// Purge deleted accounts every `interval` on the current tokio runtime
+    pub fn start_deletion_purger(self: &Arc<Self>, interval: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        let auth = self.clone();
+        
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                auth.purge_pending_deletions(Utc::now().timestamp());
+            }
+        })
+    }
+    
+    /// Get user profile by ID. Profiles are cached; every write to a profile field
+    /// invalidates the cached copy.
+    pub fn get_user(&self, user_id: &str) -> Option<UserProfile> {
+        self.profiles.get_or_load(user_id.to_string(), || {
+            let users = self.users.lock().unwrap();
+            users.get(user_id).map(|u| self.user_to_profile(u))
+        })
+    }
+    
+    pub fn profile_cache_stats(&self) -> CacheStats {
+        self.profiles.stats()
     }
     
     /// Update user profile
@@ -283,6 +954,7 @@ impl AuthService {
         
         if let Some(user) = users.get_mut(user_id) {
             user.avatar_url = avatar_url;
+            self.profiles.invalidate(user_id);
             println!("[AuthService] User profile updated: {}", user_id);
             true
         } else {
@@ -290,95 +962,1644 @@ impl AuthService {
         }
     }
     
-    /// Add friend
-    pub fn add_friend(&self, user_id: &str, friend_id: &str) -> bool {
-        let mut users = self.users.lock().unwrap();
+    /// Edit display name, bio, status message and pronouns
+    pub fn update_profile(&self, user_id: &str, update: ProfileUpdate) -> Result<UserProfile, String> {
+        let display_name = self.validate_profile_text("Display name", update.display_name, MAX_DISPLAY_NAME_LEN)?;
+        let bio = self.validate_profile_text("Bio", update.bio, MAX_BIO_LEN)?;
+        let status_message = self.validate_profile_text("Status", update.status_message, MAX_STATUS_LEN)?;
+        let pronouns = self.validate_profile_text("Pronouns", update.pronouns, MAX_PRONOUNS_LEN)?;
         
-        if let Some(user) = users.get_mut(user_id) {
-            if !user.friends.contains(&friend_id.to_string()) {
-                user.friends.push(friend_id.to_string());
-                println!("[AuthService] Friend added: {} -> {}", user_id, friend_id);
-                return true;
+        if let Some(Some(name)) = &display_name {
+            if name.chars().count() < 3 {
+                return Err("Display name must be at least 3 characters".to_string());
             }
         }
         
-        false
+        let mut users = self.users.lock().unwrap();
+        let user = users.get_mut(user_id).ok_or_else(|| "User not found".to_string())?;
+        self.profiles.invalidate(user_id);
+        
+        if let Some(value) = display_name {
+            user.display_name = value;
+        }
+        if let Some(value) = bio {
+            user.bio = value;
+        }
+        if let Some(value) = status_message {
+            user.status_message = value;
+        }
+        if let Some(value) = pronouns {
+            user.pronouns = value;
+        }
+        
+        println!("[AuthService] Profile customized: {}", user_id);
+        
+        Ok(self.user_to_profile(user))
     }
     
-    /// Record game creation
-    pub fn add_created_game(&self, user_id: &str, game_id: &str) {
+    /// Award a badge; a badge is only ever awarded once
+    pub fn award_badge(&self, user_id: &str, badge_id: &str, name: &str) -> bool {
         let mut users = self.users.lock().unwrap();
         
-        if let Some(user) = users.get_mut(user_id) {
-            user.games_created.push(game_id.to_string());
-            println!("[AuthService] Game created recorded: {} by {}", game_id, user_id);
+        match users.get_mut(user_id) {
+            Some(user) if !user.badges.iter().any(|b| b.id == badge_id) => {
+                user.badges.push(Badge {
+                    id: badge_id.to_string(),
+                    name: name.to_string(),
+                    awarded_at: Utc::now().timestamp(),
+                });
+                self.profiles.invalidate(user_id);
+                println!("[AuthService] Badge {} awarded to {}", badge_id, user_id);
+                true
+            }
+            _ => false,
         }
     }
     
-    /// Record game play
-    pub fn add_played_game(&self, user_id: &str, game_id: &str) {
+    /// Grant moderator authority over a game, or over all games with "*"
+    pub fn grant_moderator(&self, user_id: &str, scope: &str) -> bool {
         let mut users = self.users.lock().unwrap();
         
-        if let Some(user) = users.get_mut(user_id) {
-            if !user.games_played.contains(&game_id.to_string()) {
-                user.games_played.push(game_id.to_string());
-                println!("[AuthService] Game played recorded: {} by {}", game_id, user_id);
+        match users.get_mut(user_id) {
+            Some(user) => {
+                if !user.moderator_scopes.iter().any(|s| s == scope) {
+                    user.moderator_scopes.push(scope.to_string());
+                    println!("[AuthService] Moderator scope {} granted to {}", scope, user_id);
+                }
+                true
             }
+            None => false,
         }
     }
     
-    /// Unlock achievement
-    pub fn unlock_achievement(&self, user_id: &str, achievement_id: &str, name: &str, description: &str) {
+    pub fn revoke_moderator(&self, user_id: &str, scope: &str) -> bool {
         let mut users = self.users.lock().unwrap();
         
-        if let Some(user) = users.get_mut(user_id) {
-            let achievement = Achievement {
-                id: achievement_id.to_string(),
-                name: name.to_string(),
-                description: description.to_string(),
-                unlocked_at: Utc::now().timestamp(),
-            };
-            
-            user.achievements.push(achievement);
-            println!("[AuthService] Achievement unlocked: {} for {}", name, user_id);
+        match users.get_mut(user_id) {
+            Some(user) => {
+                user.moderator_scopes.retain(|s| s != scope);
+                true
+            }
+            None => false,
         }
     }
     
-    // Private helper methods
+    pub fn is_moderator_for(&self, user_id: &str, game_id: &str) -> bool {
+        let users = self.users.lock().unwrap();
+        
+        users.get(user_id)
+            .filter(|u| u.state.is_active(Utc::now().timestamp()))
+            .is_some_and(|u| u.moderator_scopes.iter().any(|s| s == "*" || s == game_id))
+    }
     
-    fn generate_token(&self, user_id: &str) -> String {
-        let expiration = Utc::now()
-            .checked_add_signed(Duration::days(30))
-            .expect("Valid timestamp")
-            .timestamp();
+    /// Get a user's friend IDs
+    pub fn get_friends(&self, user_id: &str) -> Vec<String> {
+        self.related(user_id, SocialEdgeKind::Friend)
+    }
+    
+    /// Ask `friend_id` to be friends, or accept the request they already sent.
+    /// The friendship only exists once both sides have asked. Returns false if the
+    /// request could not be sent, e.g. when one was already pending.
+    pub fn add_friend(&self, user_id: &str, friend_id: &str) -> bool {
+        {
+            let users = self.users.lock().unwrap();
+            if user_id == friend_id || !users.contains_key(user_id) || !users.contains_key(friend_id) {
+                return false;
+            }
+        }
+        
+        if self.is_blocked_between(user_id, friend_id) || self.get_friends(user_id).iter().any(|f| f == friend_id) {
+            return false;
+        }
+        
+        if self.friend_requests(user_id).iter().any(|id| id == friend_id) {
+            return self.befriend(user_id, friend_id);
+        }
+        if self.friend_requests(friend_id).iter().any(|id| id == user_id) {
+            return false;
+        }
+        
+        let request = SocialEdge {
+            from: friend_id.to_string(),
+            to: user_id.to_string(),
+            kind: SocialEdgeKind::FriendRequest,
+            created_at: Utc::now().timestamp(),
+        };
+        match self.social.put_edge(&request) {
+            Ok(()) => {
+                println!("[AuthService] Friend request: {} -> {}", user_id, friend_id);
+                true
+            }
+            Err(e) => {
+                eprintln!("[AuthService] Failed to send friend request: {}", e);
+                false
+            }
+        }
+    }
+    
+    /// Users waiting for `user_id` to accept their friend request, newest first
+    pub fn friend_requests(&self, user_id: &str) -> Vec<String> {
+        self.related(user_id, SocialEdgeKind::FriendRequest)
+    }
+    
+    /// Turn down a pending friend request. Returns whether there was one.
+    pub fn decline_friend_request(&self, user_id: &str, requester_id: &str) -> bool {
+        self.social.remove_edge(user_id, requester_id, SocialEdgeKind::FriendRequest).unwrap_or_else(|e| {
+            eprintln!("[AuthService] Failed to decline friend request: {}", e);
+            false
+        })
+    }
+    
+    /// End a friendship for both users
+    pub fn remove_friend(&self, user_id: &str, friend_id: &str) -> bool {
+        self.invalidate_friend_counts(user_id, friend_id);
+        
+        self.social.remove_friendship(user_id, friend_id).unwrap_or_else(|e| {
+            eprintln!("[AuthService] Failed to remove friend: {}", e);
+            false
+        })
+    }
+    
+    /// Block a user, ending any friendship between them
+    pub fn block_user(&self, user_id: &str, blocked_id: &str) -> bool {
+        if user_id == blocked_id || !self.users.lock().unwrap().contains_key(blocked_id) {
+            return false;
+        }
+        
+        let edge = SocialEdge {
+            from: user_id.to_string(),
+            to: blocked_id.to_string(),
+            kind: SocialEdgeKind::Block,
+            created_at: Utc::now().timestamp(),
+        };
+        
+        let result = self.social.put_edge(&edge)
+            .and_then(|_| self.social.remove_friendship(user_id, blocked_id))
+            .and_then(|_| self.social.remove_edge(user_id, blocked_id, SocialEdgeKind::FriendRequest))
+            .and_then(|_| self.social.remove_edge(blocked_id, user_id, SocialEdgeKind::FriendRequest));
+        
+        match result {
+            Ok(_) => {
+                self.invalidate_friend_counts(user_id, blocked_id);
+                println!("[AuthService] {} blocked {}", user_id, blocked_id);
+                true
+            }
+            Err(e) => {
+                eprintln!("[AuthService] Failed to block user: {}", e);
+                false
+            }
+        }
+    }
+    
+    pub fn unblock_user(&self, user_id: &str, blocked_id: &str) -> bool {
+        self.social.remove_edge(user_id, blocked_id, SocialEdgeKind::Block).unwrap_or(false)
+    }
+    
+    pub fn blocked_users(&self, user_id: &str) -> Vec<String> {
+        self.related(user_id, SocialEdgeKind::Block)
+    }
+    
+    /// People the user recently finished a match with, most recent first
+    pub fn recent_players(&self, user_id: &str) -> Vec<String> {
+        self.related(user_id, SocialEdgeKind::RecentlyPlayed)
+    }
+    
+    /// Move friends and recent players from the legacy per-user lists into the social
+    /// graph store. One-way legacy friend entries become mutual friendships.
+    /// Returns the number of relationships migrated.
+    pub fn migrate_social_graph(&self) -> Result<usize, String> {
+        let mut users = self.users.lock().unwrap();
+        let mut migrated = 0;
+        
+        for user in users.values_mut() {
+            for friend_id in std::mem::take(&mut user.friends) {
+                self.social.add_friendship(&user.id, &friend_id, user.created_at)?;
+                migrated += 1;
+            }
+            
+            // Legacy list is newest first; keep that order with descending timestamps
+            let recent = std::mem::take(&mut user.recent_players);
+            let newest = Utc::now().timestamp();
+            for (age, other_id) in recent.iter().enumerate() {
+                self.social.put_edge(&SocialEdge {
+                    from: user.id.clone(),
+                    to: other_id.clone(),
+                    kind: SocialEdgeKind::RecentlyPlayed,
+                    created_at: newest - age as i64,
+                })?;
+                migrated += 1;
+            }
+        }
+        
+        self.profiles.invalidate_all();
+        println!("[AuthService] Migrated {} social relationships", migrated);
+        
+        Ok(migrated)
+    }
+    
+    pub fn set_discoverability(&self, user_id: &str, discoverable: Discoverability) -> bool {
+        let mut users = self.users.lock().unwrap();
+        
+        match users.get_mut(user_id) {
+            Some(user) => {
+                user.discoverable = discoverable;
+                true
+            }
+            None => false,
+        }
+    }
+    
+    pub fn privacy_settings(&self, user_id: &str) -> Option<PrivacySettings> {
+        let users = self.users.lock().unwrap();
+        users.get(user_id).map(|u| u.privacy.clone())
+    }
+    
+    pub fn update_privacy_settings(&self, user_id: &str, settings: PrivacySettings) -> bool {
+        let mut users = self.users.lock().unwrap();
+        
+        match users.get_mut(user_id) {
+            Some(user) => {
+                user.privacy = settings;
+                println!("[AuthService] Privacy settings updated: {}", user_id);
+                true
+            }
+            None => false,
+        }
+    }
+    
+    /// Whether `target`'s privacy settings let `actor` do `action` to them
+    pub fn privacy_allows(&self, actor_id: &str, target_id: &str, action: PrivacyAction) -> bool {
+        if actor_id == target_id {
+            return true;
+        }
+        
+        if self.is_blocked_between(actor_id, target_id) {
+            return false;
+        }
+        
+        let users = self.users.lock().unwrap();
+        let target = match users.get(target_id) {
+            Some(u) => u,
+            None => return false,
+        };
+        
+        let audience = match action {
+            PrivacyAction::Invite => target.privacy.invites,
+            PrivacyAction::DirectMessage => target.privacy.direct_messages,
+            PrivacyAction::ViewPresence => target.privacy.presence,
+        };
+        
+        match audience {
+            PrivacyAudience::Everyone => true,
+            PrivacyAudience::Friends => self.get_friends(target_id).iter().any(|f| f == actor_id),
+            PrivacyAudience::Nobody => false,
+        }
+    }
+    
+    pub fn set_age_bracket(&self, user_id: &str, bracket: AgeBracket) -> bool {
+        let mut users = self.users.lock().unwrap();
+        
+        match users.get_mut(user_id) {
+            Some(user) => {
+                user.age_bracket = Some(bracket);
+                true
+            }
+            None => false,
+        }
+    }
+    
+    pub fn set_parental_controls(&self, user_id: &str, controls: ParentalControls) -> bool {
+        let mut users = self.users.lock().unwrap();
+        
+        match users.get_mut(user_id) {
+            Some(user) => {
+                user.parental_controls = controls;
+                println!("[AuthService] Parental controls updated: {}", user_id);
+                true
+            }
+            None => false,
+        }
+    }
+    
+    /// Check a user may enter content with this rating and tags. Users with no
+    /// recorded age are treated as teens.
+    pub fn check_content_access(&self, user_id: &str, rating: ContentRating, tags: &[String]) -> Result<(), String> {
+        let users = self.users.lock().unwrap();
+        let user = users.get(user_id).ok_or_else(|| "User not found".to_string())?;
+        
+        let mut allowed = user.age_bracket.map(|b| b.max_rating()).unwrap_or(ContentRating::Teen);
+        if let Some(parental_max) = user.parental_controls.max_rating {
+            allowed = allowed.min(parental_max);
+        }
+        
+        if rating > allowed {
+            return Err(format!("Content rated {:?} is not available to this account", rating));
+        }
+        
+        if let Some(tag) = tags.iter().find(|t| user.parental_controls.blocked_tags.contains(t)) {
+            return Err(format!("Content tagged '{}' is blocked by parental controls", tag));
+        }
+        
+        Ok(())
+    }
+    
+    /// Case-insensitive prefix search on username and display name, hiding
+    /// users who are not discoverable by the searcher
+    pub fn search_users(&self, searcher_id: &str, query: &str, offset: usize, limit: usize) -> UserSearchPage {
+        let users = self.users.lock().unwrap();
+        let query = query.trim().to_lowercase();
+        let now = Utc::now().timestamp();
+        
+        let empty_page = UserSearchPage { results: Vec::new(), total: 0, next_offset: None };
+        let searcher = match users.get(searcher_id) {
+            Some(user) if !query.is_empty() => user,
+            _ => return empty_page,
+        };
+        
+        let mut matches: Vec<&User> = users.values()
+            .filter(|u| u.id != searcher_id && u.state.is_active(now))
+            .filter(|u| {
+                u.username.to_lowercase().starts_with(&query)
+                    || u.display_name.as_ref().is_some_and(|n| n.to_lowercase().starts_with(&query))
+            })
+            .filter(|u| self.is_discoverable_by(u, searcher))
+            .collect();
+        
+        // Stable order so pages don't shift between requests
+        matches.sort_by(|a, b| a.username.to_lowercase().cmp(&b.username.to_lowercase()).then(a.id.cmp(&b.id)));
+        
+        let total = matches.len();
+        let limit = limit.clamp(1, MAX_SEARCH_PAGE);
+        let results: Vec<UserSummary> = matches.iter()
+            .skip(offset)
+            .take(limit)
+            .map(|u| Self::user_to_summary(u))
+            .collect();
+        
+        let next_offset = if offset + results.len() < total { Some(offset + results.len()) } else { None };
+        
+        UserSearchPage { results, total, next_offset }
+    }
+    
+    /// Suggest people the user recently played with or shares friends with
+    pub fn suggested_friends(&self, user_id: &str, limit: usize) -> Vec<FriendSuggestion> {
+        let users = self.users.lock().unwrap();
+        
+        let user = match users.get(user_id) {
+            Some(u) => u,
+            None => return Vec::new(),
+        };
+        
+        let friends = self.get_friends(user_id);
+        let recent = self.recent_players(user_id);
+        let imported = self.platform_friends.lock().unwrap().get(user_id).cloned().unwrap_or_default();
+        let now = Utc::now().timestamp();
+        
+        let mut suggestions: Vec<FriendSuggestion> = users.values()
+            .filter(|c| c.id != user_id && c.state.is_active(now) && !friends.contains(&c.id))
+            .filter(|c| self.is_discoverable_by(c, user))
+            .filter_map(|candidate| {
+                let mutual_friends = self.get_friends(&candidate.id).iter().filter(|f| friends.contains(f)).count();
+                let played_recently = recent.contains(&candidate.id);
+                let platform_friend = imported.contains(&candidate.id);
+                
+                if mutual_friends == 0 && !played_recently && !platform_friend {
+                    return None;
+                }
+                
+                Some(FriendSuggestion {
+                    user: Self::user_to_summary(candidate),
+                    mutual_friends,
+                    played_recently,
+                    platform_friend,
+                })
+            })
+            .collect();
+        
+        let score = |s: &FriendSuggestion| {
+            s.mutual_friends * 2 + if s.played_recently { 3 } else { 0 } + if s.platform_friend { 4 } else { 0 }
+        };
+        suggestions.sort_by(|a, b| score(b).cmp(&score(a)).then(a.user.username.cmp(&b.user.username)));
+        suggestions.truncate(limit);
+        
+        suggestions
+    }
+    
+    /// Link a Steam/Meta account after the OAuth flow completed. Each platform
+    /// account can belong to one user only.
+    pub fn link_identity(&self, user_id: &str, platform: Platform, external_id: &str, access_token: &str) -> Result<(), String> {
+        if access_token.is_empty() {
+            return Err("An access token is required".to_string());
+        }
+        
+        let mut users = self.users.lock().unwrap();
+        let user = users.get_mut(user_id).ok_or_else(|| "User not found".to_string())?;
+        
+        let mut identities = self.identities.lock().unwrap();
+        let key = (platform, external_id.to_string());
+        if identities.get(&key).is_some_and(|owner| owner != user_id) {
+            return Err("That account is linked to another user".to_string());
+        }
+        
+        if let Some(previous) = user.linked_identities.iter().find(|i| i.platform == platform) {
+            identities.remove(&(platform, previous.external_id.clone()));
+        }
+        user.linked_identities.retain(|i| i.platform != platform);
+        user.linked_identities.push(LinkedIdentity {
+            platform,
+            external_id: external_id.to_string(),
+            access_token: access_token.to_string(),
+            linked_at: Utc::now().timestamp(),
+        });
+        identities.insert(key, user_id.to_string());
+        
+        println!("[AuthService] Linked {:?} account for {}", platform, user_id);
+        Ok(())
+    }
+    
+    pub fn unlink_identity(&self, user_id: &str, platform: Platform) -> bool {
+        let removed = {
+            let mut users = self.users.lock().unwrap();
+            let Some(user) = users.get_mut(user_id) else { return false };
+            let removed: Vec<LinkedIdentity> = user.linked_identities.iter().filter(|i| i.platform == platform).cloned().collect();
+            user.linked_identities.retain(|i| i.platform != platform);
+            removed
+        };
+        
+        let mut identities = self.identities.lock().unwrap();
+        for identity in &removed {
+            identities.remove(&(identity.platform, identity.external_id.clone()));
+        }
+        drop(identities);
+        
+        // Imported suggestions may have come from this platform; the next sync rebuilds them
+        self.forget_platform_friends(user_id);
+        !removed.is_empty()
+    }
+    
+    /// Opt in or out of friend import. Turning it off forgets what was imported.
+    pub fn set_friend_import_consent(&self, user_id: &str, consent: FriendImportConsent) -> Result<(), String> {
+        self.users.lock().unwrap()
+            .get_mut(user_id)
+            .ok_or_else(|| "User not found".to_string())?
+            .friend_import = consent;
+        
+        if consent == FriendImportConsent::Off {
+            self.forget_platform_friends(user_id);
+        }
+        Ok(())
+    }
+    
+    /// Fetch the user's friend lists from every linked platform and match them to
+    /// accounts here. Matches become friend suggestions if the user may discover them,
+    /// or friends outright when both sides chose `AutoAdd`. At most one sync per
+    /// `PLATFORM_FRIEND_SYNC_INTERVAL_SECONDS`. Platforms whose token was lost (tokens
+    /// are never persisted) are skipped and listed in `needs_relink`.
+    pub fn sync_platform_friends(&self, user_id: &str, now: i64) -> Result<FriendImportResult, String> {
+        let (identities, consent) = {
+            let users = self.users.lock().unwrap();
+            let user = users.get(user_id).ok_or_else(|| "User not found".to_string())?;
+            (user.linked_identities.clone(), user.friend_import)
+        };
+        
+        if consent == FriendImportConsent::Off {
+            return Err("Friend import is turned off".to_string());
+        }
+        if identities.is_empty() {
+            return Err("No platform account linked".to_string());
+        }
+        {
+            let mut syncs = self.friend_syncs.lock().unwrap();
+            if syncs.get(user_id).is_some_and(|last| now - last < PLATFORM_FRIEND_SYNC_INTERVAL_SECONDS) {
+                return Err("Friend list was synced recently, try again later".to_string());
+            }
+            syncs.insert(user_id.to_string(), now);
+        }
+        
+        // Platform APIs are called without holding any lock
+        let mut external_friends = Vec::new();
+        let mut needs_relink = Vec::new();
+        for identity in &identities {
+            let Some(source) = self.friend_sources.get(&identity.platform) else { continue };
+            if identity.access_token.is_empty() {
+                println!("[AuthService] {:?} token missing for {}, relink required", identity.platform, user_id);
+                needs_relink.push(identity.platform);
+                continue;
+            }
+            match source.fetch_friends(&identity.external_id, &identity.access_token) {
+                Ok(friends) => external_friends.extend(friends.into_iter().map(|id| (identity.platform, id))),
+                Err(e) => eprintln!("[AuthService] {:?} friend fetch failed for {}: {}", identity.platform, user_id, e),
+            }
+        }
+        
+        let mut matched: Vec<String> = {
+            let index = self.identities.lock().unwrap();
+            external_friends.into_iter()
+                .filter_map(|key| index.get(&key).cloned())
+                .filter(|id| id != user_id)
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect()
+        };
+        matched.retain(|id| !self.is_blocked_between(user_id, id));
+        matched.sort();
+        
+        let mut result = FriendImportResult { matched: matched.len(), needs_relink, ..FriendImportResult::default() };
+        let friends = self.get_friends(user_id);
+        for other_id in matched.iter().filter(|id| !friends.contains(id)) {
+            let (mutual_auto_add, discoverable) = {
+                let users = self.users.lock().unwrap();
+                let (Some(user), Some(other)) = (users.get(user_id), users.get(other_id)) else { continue };
+                (
+                    consent == FriendImportConsent::AutoAdd && other.friend_import == FriendImportConsent::AutoAdd,
+                    self.is_discoverable_by(other, user),
+                )
+            };
+            
+            if mutual_auto_add && self.befriend(user_id, other_id) {
+                result.added.push(other_id.clone());
+            } else if discoverable {
+                result.suggested.push(other_id.clone());
+            } else {
+                // Hidden accounts aren't revealed, not even as a count
+                result.matched -= 1;
+            }
+        }
+        
+        self.platform_friends.lock().unwrap().insert(user_id.to_string(), result.suggested.iter().cloned().collect());
+        
+        println!("[AuthService] Platform friend sync for {}: {} matched, {} added", user_id, result.matched, result.added.len());
+        
+        Ok(result)
+    }
+    
+    /// Background sync: refresh the longest-unsynced opted-in users whose interval has
+    /// passed, at most `max_users` per call to stay inside platform API quotas.
+    /// Returns how many users were synced.
+    pub fn sync_due_platform_friends(&self, now: i64, max_users: usize) -> usize {
+        let mut due: Vec<(i64, String)> = {
+            let users = self.users.lock().unwrap();
+            let syncs = self.friend_syncs.lock().unwrap();
+            users.values()
+                .filter(|u| u.friend_import != FriendImportConsent::Off && !u.linked_identities.is_empty())
+                .map(|u| (syncs.get(&u.id).copied().unwrap_or(i64::MIN), u.id.clone()))
+                .filter(|(last, _)| now.saturating_sub(*last) >= PLATFORM_FRIEND_SYNC_INTERVAL_SECONDS)
+                .collect()
+        };
+        due.sort();
+        due.truncate(max_users);
+        
+        due.iter().filter(|(_, user_id)| self.sync_platform_friends(user_id, now).is_ok()).count()
+    }
+    
+    /// Run `sync_due_platform_friends` every `interval` on the current tokio runtime
+    pub fn start_friend_sync_job(self: &Arc<Self>, interval: std::time::Duration, max_users_per_run: usize) -> tokio::task::JoinHandle<()> {
+        let auth = self.clone();
+        
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                auth.sync_due_platform_friends(Utc::now().timestamp(), max_users_per_run);
+            }
+        })
+    }
+    
+    /// Record game creation
+    pub fn add_created_game(&self, user_id: &str, game_id: &str) {
+        let mut users = self.users.lock().unwrap();
+        
+        if let Some(user) = users.get_mut(user_id) {
+            user.games_created.push(game_id.to_string());
+            self.profiles.invalidate(user_id);
+            println!("[AuthService] Game created recorded: {} by {}", game_id, user_id);
+        }
+    }
+    
+    /// Record game play
+    pub fn add_played_game(&self, user_id: &str, game_id: &str) {
+        let mut users = self.users.lock().unwrap();
+        
+        if let Some(user) = users.get_mut(user_id) {
+            if !user.games_played.contains(&game_id.to_string()) {
+                user.games_played.push(game_id.to_string());
+                self.profiles.invalidate(user_id);
+                println!("[AuthService] Game played recorded: {} by {}", game_id, user_id);
+            }
+        }
+    }
+    
+    /// Define a game's tutorial. Games without steps never send players to a tutorial.
+    pub fn set_tutorial_steps(&self, game_id: &str, steps: Vec<String>) {
+        self.tutorials.lock().unwrap().insert(game_id.to_string(), steps);
+    }
+    
+    pub fn onboarding_state(&self, user_id: &str, game_id: &str) -> Result<OnboardingState, String> {
+        let completed_steps = self.users.lock().unwrap()
+            .get(user_id)
+            .ok_or_else(|| "User not found".to_string())?
+            .onboarding.get(game_id).cloned().unwrap_or_default();
+        
+        let remaining_steps = self.tutorials.lock().unwrap()
+            .get(game_id)
+            .map(|steps| steps.iter().filter(|s| !completed_steps.contains(s)).cloned().collect())
+            .unwrap_or_default();
+        
+        Ok(OnboardingState {
+            game_id: game_id.to_string(),
+            completed_steps,
+            remaining_steps,
+        })
+    }
+    
+    /// Mark a tutorial step done. Completing a step twice is harmless.
+    pub fn complete_tutorial_step(&self, user_id: &str, game_id: &str, step: &str) -> Result<OnboardingState, String> {
+        let known = self.tutorials.lock().unwrap()
+            .get(game_id)
+            .is_some_and(|steps| steps.iter().any(|s| s == step));
+        if !known {
+            return Err("Unknown tutorial step".to_string());
+        }
+        
+        {
+            let mut users = self.users.lock().unwrap();
+            let user = users.get_mut(user_id).ok_or_else(|| "User not found".to_string())?;
+            let completed = user.onboarding.entry(game_id.to_string()).or_default();
+            if !completed.iter().any(|s| s == step) {
+                completed.push(step.to_string());
+                println!("[AuthService] {} completed tutorial step {} in {}", user_id, step, game_id);
+            }
+        }
+        
+        self.onboarding_state(user_id, game_id)
+    }
+    
+    /// Forget a user's tutorial progress so the game treats them as new again
+    pub fn reset_onboarding(&self, user_id: &str, game_id: &str) -> bool {
+        self.users.lock().unwrap()
+            .get_mut(user_id)
+            .is_some_and(|user| user.onboarding.remove(game_id).is_some())
+    }
+    
+    /// Sign up at most once per idempotency key; a retried request gets the original
+    /// response. Failed signups can be retried with the same key.
+    pub fn signup_idempotent(&self, idempotency_key: &str, request: SignupRequest) -> AuthResponse {
+        let key = format!("signup:{}:{}", request.email, idempotency_key);
+        
+        let outcome = self.signups.execute(&key, Utc::now().timestamp(), || {
+            let response = self.signup(request);
+            if response.success { Ok(response) } else { Err(response.message) }
+        });
+        
+        outcome.unwrap_or_else(|message| AuthResponse {
+            success: false,
+            message,
+            token: None,
+            user: None,
+        })
+    }
+    
+    /// Unlock an achievement at most once per idempotency key
+    pub fn unlock_achievement_idempotent(&self, idempotency_key: &str, user_id: &str, achievement_id: &str, name: &str, description: &str) -> Result<(), String> {
+        let key = format!("unlock_achievement:{}:{}", user_id, idempotency_key);
+        
+        self.achievement_unlocks.execute(&key, Utc::now().timestamp(), || {
+            if !self.users.lock().unwrap().contains_key(user_id) {
+                return Err("User not found".to_string());
+            }
+            
+            self.unlock_achievement(user_id, achievement_id, name, description);
+            Ok(())
+        })
+    }
+    
+    /// Unlock achievement
+    pub fn unlock_achievement(&self, user_id: &str, achievement_id: &str, name: &str, description: &str) {
+        self.unlock_game_achievement(user_id, "", achievement_id, name, description);
+    }
+    
+    /// Unlock an achievement awarded by a game. Achievements from sandboxed builds are
+    /// kept apart from production ones and removed by `wipe_environment`.
+    pub fn unlock_game_achievement(&self, user_id: &str, game_id: &str, achievement_id: &str, name: &str, description: &str) {
+        let mut users = self.users.lock().unwrap();
+        
+        if let Some(user) = users.get_mut(user_id) {
+            let achievement = Achievement {
+                id: achievement_id.to_string(),
+                name: name.to_string(),
+                description: description.to_string(),
+                unlocked_at: Utc::now().timestamp(),
+                sandbox_game_id: Self::sandbox_scope(game_id),
+            };
+            
+            self.persist_achievement(user_id, &achievement);
+            user.achievements.push(achievement);
+            println!("[AuthService] Achievement unlocked: {} for {}", name, user_id);
+        }
+    }
+    
+    /// Apply a finished match to every participant's rating, XP and achievements.
+    /// Either every participant is updated or, if any is unknown, none are.
+    pub fn record_match(&self, game_id: &str, participants: &[MatchParticipant]) -> Result<(), String> {
+        let mut users = self.users.lock().unwrap();
+        
+        if let Some(missing) = participants.iter().find(|p| !users.contains_key(&p.user_id)) {
+            return Err(format!("Unknown participant: {}", missing.user_id));
+        }
+        
+        // Pairwise Elo: every winner beat every non-winner
+        let rating_of = |user_id: &str| -> f64 {
+            users[user_id].ratings.get(game_id).copied().unwrap_or(DEFAULT_RATING) as f64
+        };
+        
+        let mut deltas: HashMap<&str, f64> = HashMap::new();
+        
+        for winner in participants.iter().filter(|p| p.won) {
+            for loser in participants.iter().filter(|p| !p.won) {
+                let expected = 1.0 / (1.0 + 10f64.powf((rating_of(&loser.user_id) - rating_of(&winner.user_id)) / 400.0));
+                let change = RATING_K_FACTOR * (1.0 - expected);
+                
+                *deltas.entry(&winner.user_id).or_insert(0.0) += change;
+                *deltas.entry(&loser.user_id).or_insert(0.0) -= change;
+            }
+        }
+        
+        let now = Utc::now().timestamp();
+        
+        for participant in participants {
+            let user = users.get_mut(&participant.user_id).unwrap();
+            self.profiles.invalidate(&participant.user_id);
+            
+            let rating = user.ratings.entry(game_id.to_string()).or_insert(DEFAULT_RATING);
+            *rating += deltas.get(participant.user_id.as_str()).copied().unwrap_or(0.0).round() as i32;
+            
+            user.xp += if participant.won { MATCH_WIN_XP } else { MATCH_PLAYED_XP };
+            
+            if !user.games_played.contains(&game_id.to_string()) {
+                user.games_played.push(game_id.to_string());
+            }
+            
+            let scope = Self::sandbox_scope(game_id);
+            if participant.won && !user.achievements.iter().any(|a| a.id == "first_win" && a.sandbox_game_id == scope) {
+                let achievement = Achievement {
+                    id: "first_win".to_string(),
+                    name: "First Victory".to_string(),
+                    description: "Win your first match".to_string(),
+                    unlocked_at: now,
+                    sandbox_game_id: scope,
+                };
+                self.persist_achievement(&participant.user_id, &achievement);
+                user.achievements.push(achievement);
+            }
+            
+            let stats = serde_json::json!({ "rating": user.ratings[game_id], "xp": user.xp });
+            self.persist(WriteKind::Stats, format!("{}:{}", participant.user_id, game_id), &stats);
+        }
+        
+        drop(users);
+        self.record_recent_players(participants, now);
+        
+        println!("[AuthService] Match recorded for game {}: {} participants", game_id, participants.len());
+        
+        Ok(())
+    }
+    
+    fn persist_achievement(&self, user_id: &str, achievement: &Achievement) {
+        let key = format!("{}:{}:{}", user_id, achievement.sandbox_game_id.as_deref().unwrap_or(""), achievement.id);
+        self.persist(WriteKind::Achievement, key, achievement);
+    }
+    
+    fn persist<T: Serialize>(&self, kind: WriteKind, key: String, value: &T) {
+        let Some(queue) = &self.write_behind else { return };
+        
+        match PendingWrite::new(kind, key, value) {
+            Ok(write) => {
+                queue.enqueue(write);
+            }
+            Err(e) => eprintln!("[AuthService] Failed to encode {:?} write: {}", kind, e),
+        }
+    }
+    
+    /// Add XP and items, e.g. a completed challenge's reward
+    pub fn grant_reward(&self, user_id: &str, xp: u64, items: &[String]) -> Result<(), String> {
+        let mut users = self.users.lock().unwrap();
+        let user = users.get_mut(user_id).ok_or_else(|| "User not found".to_string())?;
+        
+        user.xp += xp;
+        for item in items {
+            *user.inventory.entry(item.clone()).or_insert(0) += 1;
+        }
+        self.profiles.invalidate(user_id);
+        
+        println!("[AuthService] Granted {} XP and {} items to {}", xp, items.len(), user_id);
+        Ok(())
+    }
+    
+    pub fn inventory(&self, user_id: &str) -> HashMap<String, u32> {
+        self.users.lock().unwrap().get(user_id).map(|u| u.inventory.clone()).unwrap_or_default()
+    }
+    
+    /// A user's achievements in one environment: production achievements for
+    /// `Environment::Prod`, otherwise only those earned in that game's sandbox
+    pub fn achievements(&self, user_id: &str, game_id: &str, environment: Environment) -> Vec<Achievement> {
+        let scope = Self::sandbox_scope(&environment.scope_game(game_id));
+        
+        self.users.lock().unwrap()
+            .get(user_id)
+            .map(|u| u.achievements.iter().filter(|a| a.sandbox_game_id == scope).cloned().collect())
+            .unwrap_or_default()
+    }
+    
+    /// The game's creators and moderators
+    pub fn is_game_developer(&self, user_id: &str, game_id: &str) -> bool {
+        let created = self.users.lock().unwrap()
+            .get(user_id)
+            .is_some_and(|u| u.games_created.iter().any(|g| g == game_id));
+        
+        created || self.is_moderator_for(user_id, game_id)
+    }
+    
+    /// Developers of a game may use its sandbox environments
+    pub fn can_manage_sandbox(&self, user_id: &str, game_id: &str) -> bool {
+        self.is_game_developer(user_id, game_id)
+    }
+    
+    /// Delete every rating, achievement and play record a game's sandbox environment
+    /// produced. Production can't be wiped. Returns the number of users touched.
+    pub fn wipe_environment(&self, requester_id: &str, game_id: &str, environment: Environment) -> Result<usize, String> {
+        if environment == Environment::Prod {
+            return Err("The production environment can't be wiped".to_string());
+        }
+        if !self.can_manage_sandbox(requester_id, game_id) {
+            return Err("Only the game's developers can wipe its environments".to_string());
+        }
+        
+        let scoped = environment.scope_game(game_id);
+        let mut users = self.users.lock().unwrap();
+        let mut touched = 0;
+        
+        for user in users.values_mut() {
+            let before = (user.ratings.len(), user.achievements.len(), user.games_played.len());
+            user.ratings.remove(&scoped);
+            user.achievements.retain(|a| a.sandbox_game_id.as_deref() != Some(scoped.as_str()));
+            user.games_played.retain(|g| *g != scoped);
+            
+            if before != (user.ratings.len(), user.achievements.len(), user.games_played.len()) {
+                self.profiles.invalidate(&user.id);
+                touched += 1;
+            }
+        }
+        
+        println!("[AuthService] {} wiped {} data for {} users", requester_id, scoped, touched);
+        
+        Ok(touched)
+    }
+    
+    /// Highest rated players for a game
+    pub fn leaderboard(&self, game_id: &str, limit: usize) -> Vec<LeaderboardEntry> {
+        let users = self.users.lock().unwrap();
+        
+        let mut entries: Vec<LeaderboardEntry> = users.values()
+            .filter_map(|u| u.ratings.get(game_id).map(|rating| LeaderboardEntry {
+                user_id: u.id.clone(),
+                username: u.username.clone(),
+                rating: *rating,
+            }))
+            .collect();
+        
+        entries.sort_by(|a, b| b.rating.cmp(&a.rating).then_with(|| a.username.cmp(&b.username)));
+        entries.truncate(limit);
+        entries
+    }
+    
+    /// Accounts that have been seen on any of this user's devices
+    pub fn linked_accounts(&self, user_id: &str) -> Vec<String> {
+        let device_users = self.device_users.lock().unwrap();
+        
+        let mut linked: Vec<String> = device_users.values()
+            .filter(|users| users.contains(user_id))
+            .flat_map(|users| users.iter().cloned())
+            .filter(|id| id != user_id)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        linked.sort();
+        linked
+    }
+    
+    /// Accounts waiting for a moderator to review a possible ban evasion
+    pub fn pending_reviews(&self) -> Vec<ModerationFlag> {
+        self.review_queue.lock().unwrap().clone()
+    }
+    
+    /// Close a review, banning the account if the moderator confirmed evasion
+    pub fn resolve_review(&self, user_id: &str, ban: bool) -> bool {
+        let mut queue = self.review_queue.lock().unwrap();
+        let before = queue.len();
+        queue.retain(|f| f.user_id != user_id);
+        
+        if queue.len() == before {
+            return false;
+        }
+        drop(queue);
+        
+        if ban {
+            self.set_banned(user_id, true);
+        }
+        
+        println!("[AuthService] Review resolved for {}: banned={}", user_id, ban);
+        true
+    }
+    
+    // Private helper methods
+    
+    fn forget_platform_friends(&self, user_id: &str) {
+        self.platform_friends.lock().unwrap().remove(user_id);
+    }
+    
+    /// Achievement scope for a possibly environment-scoped game key
+    fn sandbox_scope(game_id: &str) -> Option<String> {
+        match Environment::of_game(game_id) {
+            (_, Environment::Prod) => None,
+            _ => Some(game_id.to_string()),
+        }
+    }
+    
+    /// Trim and check one profile field. Outer `None` = unchanged, inner `None` = cleared.
+    fn validate_profile_text(&self, field: &str, value: Option<String>, max_len: usize) -> Result<Option<Option<String>>, String> {
+        let value = match value {
+            Some(v) => v.trim().to_string(),
+            None => return Ok(None),
+        };
+        
+        if value.is_empty() {
+            return Ok(Some(None));
+        }
+        
+        if value.chars().count() > max_len {
+            return Err(format!("{} must be at most {} characters", field, max_len));
+        }
+        
+        if value.chars().any(|c| c.is_control() && c != '\n') {
+            return Err(format!("{} contains invalid characters", field));
+        }
+        
+        let lowered = value.to_lowercase();
+        if self.blocked_words.iter().any(|w| lowered.contains(w.as_str())) {
+            return Err(format!("{} contains blocked words", field));
+        }
+        
+        Ok(Some(Some(value)))
+    }
+    
+    /// Record the devices a user signed in from and flag the account for review
+    /// if any of those devices belongs to a banned account
+    fn link_devices(&self, user_id: &str, device_ids: &[String]) {
+        if device_ids.is_empty() {
+            return;
+        }
+        
+        let mut linked_users: HashMap<String, Vec<String>> = HashMap::new(); // user_id -> shared devices
+        {
+            let mut device_users = self.device_users.lock().unwrap();
+            
+            for device_id in device_ids.iter().filter(|d| !d.is_empty()) {
+                let users = device_users.entry(device_id.clone()).or_default();
+                
+                for other in users.iter().filter(|u| *u != user_id) {
+                    linked_users.entry(other.clone()).or_default().push(device_id.clone());
+                }
+                users.insert(user_id.to_string());
+            }
+        }
+        
+        let (banned_accounts, shared_devices) = {
+            let users = self.users.lock().unwrap();
+            let mut banned_accounts = Vec::new();
+            let mut shared_devices = Vec::new();
+            
+            for (other, devices) in &linked_users {
+                if users.get(other).is_some_and(|u| matches!(u.state, AccountState::Banned { .. })) {
+                    banned_accounts.push(other.clone());
+                    shared_devices.extend(devices.iter().cloned());
+                }
+            }
+            
+            (banned_accounts, shared_devices)
+        };
+        
+        if banned_accounts.is_empty() {
+            return;
+        }
+        
+        let mut queue = self.review_queue.lock().unwrap();
+        if queue.iter().any(|f| f.user_id == user_id) {
+            return;
+        }
+        
+        println!("[AuthService] Account {} flagged: shares devices with banned accounts", user_id);
+        
+        queue.push(ModerationFlag {
+            user_id: user_id.to_string(),
+            banned_accounts,
+            shared_devices,
+            flagged_at: Utc::now().timestamp(),
+        });
+    }
+    
+    fn generate_token(&self, user_id: &str, audience: &TokenAudience, lifetime: Duration) -> String {
+        let expiration = Utc::now()
+            .checked_add_signed(lifetime)
+            .expect("Valid timestamp")
+            .timestamp();
+        
+        let games = match audience {
+            TokenAudience::Platform => Vec::new(),
+            TokenAudience::GameSession(game_id) => vec![game_id.clone()],
+        };
         
         let claims = Claims {
             sub: user_id.to_string(),
             exp: expiration,
             iat: Utc::now().timestamp(),
+            aud: audience.claim().to_string(),
+            games,
         };
         
-        encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(self.jwt_secret.as_bytes()),
-        )
-        .unwrap()
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(self.jwt_secret.as_bytes()),
+        )
+        .unwrap()
+    }
+    
+    /// Login-time state check; logging back in during the deletion grace period
+    /// cancels the deletion
+    fn admit_login(&self, user: &User) -> Result<(), String> {
+        if let AccountState::PendingDeletion { .. } = user.state {
+            println!("[AuthService] Deletion cancelled by login: {}", user.id);
+            return self.restore_account(&user.id);
+        }
+        
+        user.state.check_access(Utc::now().timestamp())
+    }
+    
+    fn is_discoverable_by(&self, target: &User, searcher: &User) -> bool {
+        if self.is_blocked_between(&target.id, &searcher.id) {
+            return false;
+        }
+        
+        match target.discoverable {
+            Discoverability::Everyone => true,
+            Discoverability::Nobody => false,
+            Discoverability::FriendsOfFriends => {
+                let target_friends = self.get_friends(&target.id);
+                let searcher_friends = self.get_friends(&searcher.id);
+                
+                target_friends.contains(&searcher.id)
+                    || target_friends.iter().any(|f| searcher_friends.contains(f))
+            }
+        }
+    }
+    
+    /// Cached profiles carry a friend count
+    /// Make two users friends, clearing any requests between them. Callers have
+    /// consent from both sides.
+    fn befriend(&self, a: &str, b: &str) -> bool {
+        let result = self.social.add_friendship(a, b, Utc::now().timestamp())
+            .and_then(|_| self.social.remove_edge(a, b, SocialEdgeKind::FriendRequest))
+            .and_then(|_| self.social.remove_edge(b, a, SocialEdgeKind::FriendRequest));
+        
+        match result {
+            Ok(_) => {
+                self.invalidate_friend_counts(a, b);
+                println!("[AuthService] Friend added: {} <-> {}", a, b);
+                true
+            }
+            Err(e) => {
+                eprintln!("[AuthService] Failed to add friend: {}", e);
+                false
+            }
+        }
+    }
+    
+    fn invalidate_friend_counts(&self, a: &str, b: &str) {
+        self.profiles.invalidate(a);
+        self.profiles.invalidate(b);
+    }
+    
+    /// IDs on the far end of a user's outgoing edges of one kind, newest first
+    fn related(&self, user_id: &str, kind: SocialEdgeKind) -> Vec<String> {
+        match self.social.edges_from(user_id, kind) {
+            Ok(edges) => edges.into_iter().map(|e| e.to).collect(),
+            Err(e) => {
+                eprintln!("[AuthService] Failed to load {:?} edges for {}: {}", kind, user_id, e);
+                Vec::new()
+            }
+        }
+    }
+    
+    fn is_blocked_between(&self, a: &str, b: &str) -> bool {
+        self.blocked_users(a).iter().any(|id| id == b) || self.blocked_users(b).iter().any(|id| id == a)
+    }
+    
+    /// Link every pair of match participants as recent players, keeping the newest few
+    fn record_recent_players(&self, participants: &[MatchParticipant], now: i64) {
+        for participant in participants {
+            for other in participants.iter().filter(|p| p.user_id != participant.user_id) {
+                let edge = SocialEdge {
+                    from: participant.user_id.clone(),
+                    to: other.user_id.clone(),
+                    kind: SocialEdgeKind::RecentlyPlayed,
+                    created_at: now,
+                };
+                
+                if let Err(e) = self.social.put_edge(&edge) {
+                    eprintln!("[AuthService] Failed to record recent player: {}", e);
+                }
+            }
+            
+            for stale in self.recent_players(&participant.user_id).iter().skip(MAX_RECENT_PLAYERS) {
+                let _ = self.social.remove_edge(&participant.user_id, stale, SocialEdgeKind::RecentlyPlayed);
+            }
+        }
+    }
+    
+    fn user_to_summary(user: &User) -> UserSummary {
+        UserSummary {
+            id: user.id.clone(),
+            username: user.username.clone(),
+            display_name: user.display_name.clone().unwrap_or_else(|| user.username.clone()),
+            avatar_url: user.avatar_url.clone(),
+        }
+    }
+    
+    fn user_to_profile(&self, user: &User) -> UserProfile {
+        UserProfile {
+            id: user.id.clone(),
+            username: user.username.clone(),
+            email: user.email.clone(),
+            avatar_url: user.avatar_url.clone(),
+            games_created: user.games_created.clone(),
+            games_played: user.games_played.clone(),
+            friend_count: self.get_friends(&user.id).len(),
+            xp: user.xp,
+            display_name: user.display_name.clone().unwrap_or_else(|| user.username.clone()),
+            bio: user.bio.clone(),
+            status_message: user.status_message.clone(),
+            pronouns: user.pronouns.clone(),
+            badges: user.badges.clone(),
+        }
+    }
+}
+
+// ============================================
+// Signup Challenges
+// ============================================
+
+/// Verifies a signup challenge response (hCaptcha, Turnstile, proof-of-work, ...)
+pub trait ChallengeVerifier: Send + Sync {
+    /// Server-issued challenge for the client to solve; None for providers
+    /// whose challenge is issued client-side (e.g. CAPTCHA widgets)
+    fn issue_challenge(&self) -> Option<String> {
+        None
+    }
+    
+    fn verify(&self, response: &str) -> Result<(), String>;
+}
+
+/// Hashcash-style challenge for headless VR clients that can't show a CAPTCHA.
+/// The client finds a nonce so that SHA-256("<challenge>:<nonce>") starts with
+/// `difficulty_bits` zero bits and submits "<challenge>:<nonce>".
+/// Challenges are handed out before authentication, so at most
+/// `MAX_OUTSTANDING_CHALLENGES` are kept; past that the oldest is evicted.
+pub struct ProofOfWorkVerifier {
+    difficulty_bits: u32,
+    challenge_ttl_seconds: i64,
+    outstanding: Mutex<HashMap<String, i64>>, // challenge -> expires_at
+}
+
+const MAX_OUTSTANDING_CHALLENGES: usize = 10_000;
+
+impl ProofOfWorkVerifier {
+    pub fn new(difficulty_bits: u32) -> Self {
+        Self {
+            difficulty_bits,
+            challenge_ttl_seconds: 300,
+            outstanding: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl ChallengeVerifier for ProofOfWorkVerifier {
+    fn issue_challenge(&self) -> Option<String> {
+        let challenge = uuid::Uuid::new_v4().simple().to_string();
+        let now = Utc::now().timestamp();
+        
+        let mut outstanding = self.outstanding.lock().unwrap();
+        outstanding.retain(|_, expires_at| *expires_at > now);
+        
+        if outstanding.len() >= MAX_OUTSTANDING_CHALLENGES {
+            let oldest = outstanding.iter()
+                .min_by_key(|(_, expires_at)| **expires_at)
+                .map(|(challenge, _)| challenge.clone());
+            
+            if let Some(oldest) = oldest {
+                outstanding.remove(&oldest);
+            }
+        }
+        
+        outstanding.insert(challenge.clone(), now + self.challenge_ttl_seconds);
+        
+        Some(challenge)
+    }
+    
+    fn verify(&self, response: &str) -> Result<(), String> {
+        let (challenge, _nonce) = response.split_once(':')
+            .ok_or_else(|| "Malformed challenge response".to_string())?;
+        
+        // Challenges are single-use, whether or not the solution is valid
+        let expires_at = self.outstanding.lock().unwrap()
+            .remove(challenge)
+            .ok_or_else(|| "Unknown or already used challenge".to_string())?;
+        
+        if expires_at <= Utc::now().timestamp() {
+            return Err("Challenge expired".to_string());
+        }
+        
+        if leading_zero_bits(&Sha256::digest(response.as_bytes())) < self.difficulty_bits {
+            return Err("Invalid proof of work".to_string());
+        }
+        
+        Ok(())
+    }
+}
+
+/// Brute-force a proof-of-work solution; what a headless client runs
+pub fn solve_proof_of_work(challenge: &str, difficulty_bits: u32) -> String {
+    (0u64..)
+        .map(|nonce| format!("{}:{}", challenge, nonce))
+        .find(|candidate| leading_zero_bits(&Sha256::digest(candidate.as_bytes())) >= difficulty_bits)
+        .expect("nonce space exhausted")
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    
+    for byte in hash {
+        if *byte == 0 {
+            bits += 8;
+        } else {
+            bits += byte.leading_zeros();
+            break;
+        }
+    }
+    
+    bits
+}
+
+// ============================================
+// Social Graph
+// ============================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SocialEdgeKind {
+    Friend,
+    Block,
+    RecentlyPlayed,
+    /// Pending friend request, stored on the recipient: `from` is who was asked
+    FriendRequest,
+}
+
+#[cfg(feature = "database")]
+impl SocialEdgeKind {
+    fn key(&self) -> &'static str {
+        match self {
+            SocialEdgeKind::Friend => "friend",
+            SocialEdgeKind::Block => "block",
+            SocialEdgeKind::RecentlyPlayed => "recent",
+            SocialEdgeKind::FriendRequest => "friend_request",
+        }
+    }
+}
+
+/// A directed relationship from one user to another
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SocialEdge {
+    pub from: String,
+    pub to: String,
+    pub kind: SocialEdgeKind,
+    pub created_at: i64,
+}
+
+/// Storage for relationships between users. A friendship is a pair of `Friend` edges
+/// that is always added and removed together; blocks and recent players are one-way.
+pub trait SocialGraphStore: Send + Sync {
+    /// Insert or refresh a one-way edge; friendships must use `add_friendship`
+    fn put_edge(&self, edge: &SocialEdge) -> Result<(), String>;
+    /// Returns whether the edge existed
+    fn remove_edge(&self, from: &str, to: &str, kind: SocialEdgeKind) -> Result<bool, String>;
+    /// Outgoing edges of one kind, newest first
+    fn edges_from(&self, user_id: &str, kind: SocialEdgeKind) -> Result<Vec<SocialEdge>, String>;
+    /// Add both directions of a friendship atomically
+    fn add_friendship(&self, a: &str, b: &str, created_at: i64) -> Result<(), String>;
+    /// Remove both directions atomically; returns whether they were friends
+    fn remove_friendship(&self, a: &str, b: &str) -> Result<bool, String>;
+    /// Drop every edge to or from a user, e.g. when the account is deleted
+    fn remove_user(&self, user_id: &str) -> Result<(), String>;
+}
+
+pub struct InMemorySocialGraph {
+    edges: Mutex<HashMap<(String, SocialEdgeKind), HashMap<String, i64>>>, // (from, kind) -> to -> created_at
+}
+
+impl InMemorySocialGraph {
+    pub fn new() -> Self {
+        Self {
+            edges: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Default for InMemorySocialGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SocialGraphStore for InMemorySocialGraph {
+    fn put_edge(&self, edge: &SocialEdge) -> Result<(), String> {
+        if edge.kind == SocialEdgeKind::Friend {
+            return Err("Friendships must be added with add_friendship".to_string());
+        }
+        
+        self.edges.lock().unwrap()
+            .entry((edge.from.clone(), edge.kind))
+            .or_default()
+            .insert(edge.to.clone(), edge.created_at);
+        Ok(())
     }
     
-    fn user_to_profile(&self, user: &User) -> UserProfile {
-        UserProfile {
-            id: user.id.clone(),
-            username: user.username.clone(),
-            email: user.email.clone(),
-            avatar_url: user.avatar_url.clone(),
-            games_created: user.games_created.clone(),
-            games_played: user.games_played.clone(),
-            friend_count: user.friends.len(),
+    fn remove_edge(&self, from: &str, to: &str, kind: SocialEdgeKind) -> Result<bool, String> {
+        if kind == SocialEdgeKind::Friend {
+            return Err("Friendships must be removed with remove_friendship".to_string());
+        }
+        
+        let mut edges = self.edges.lock().unwrap();
+        Ok(edges.get_mut(&(from.to_string(), kind)).is_some_and(|targets| targets.remove(to).is_some()))
+    }
+    
+    fn edges_from(&self, user_id: &str, kind: SocialEdgeKind) -> Result<Vec<SocialEdge>, String> {
+        let edges = self.edges.lock().unwrap();
+        
+        let mut result: Vec<SocialEdge> = edges.get(&(user_id.to_string(), kind))
+            .map(|targets| {
+                targets.iter()
+                    .map(|(to, created_at)| SocialEdge {
+                        from: user_id.to_string(),
+                        to: to.clone(),
+                        kind,
+                        created_at: *created_at,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        
+        result.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(a.to.cmp(&b.to)));
+        Ok(result)
+    }
+    
+    fn add_friendship(&self, a: &str, b: &str, created_at: i64) -> Result<(), String> {
+        let mut edges = self.edges.lock().unwrap();
+        
+        for (from, to) in [(a, b), (b, a)] {
+            edges.entry((from.to_string(), SocialEdgeKind::Friend))
+                .or_default()
+                .entry(to.to_string())
+                .or_insert(created_at);
+        }
+        Ok(())
+    }
+    
+    fn remove_friendship(&self, a: &str, b: &str) -> Result<bool, String> {
+        let mut edges = self.edges.lock().unwrap();
+        let mut existed = false;
+        
+        for (from, to) in [(a, b), (b, a)] {
+            if let Some(targets) = edges.get_mut(&(from.to_string(), SocialEdgeKind::Friend)) {
+                existed |= targets.remove(to).is_some();
+            }
+        }
+        Ok(existed)
+    }
+    
+    fn remove_user(&self, user_id: &str) -> Result<(), String> {
+        let mut edges = self.edges.lock().unwrap();
+        
+        edges.retain(|(from, _), _| from != user_id);
+        for targets in edges.values_mut() {
+            targets.remove(user_id);
+        }
+        Ok(())
+    }
+}
+
+/// Social graph in Redis: one sorted set per (user, kind) scored by creation time,
+/// plus a reverse index so a user's incoming edges can be removed
+#[cfg(feature = "database")]
+pub struct RedisSocialGraph {
+    client: redis::Client,
+}
+
+#[cfg(feature = "database")]
+impl RedisSocialGraph {
+    pub fn new(redis_url: &str) -> Result<Self, String> {
+        let client = redis::Client::open(redis_url).map_err(|e| e.to_string())?;
+        Ok(Self { client })
+    }
+    
+    fn out_key(user_id: &str, kind: SocialEdgeKind) -> String {
+        format!("social:{}:out:{}", kind.key(), user_id)
+    }
+    
+    fn in_key(user_id: &str, kind: SocialEdgeKind) -> String {
+        format!("social:{}:in:{}", kind.key(), user_id)
+    }
+}
+
+#[cfg(feature = "database")]
+impl SocialGraphStore for RedisSocialGraph {
+    fn put_edge(&self, edge: &SocialEdge) -> Result<(), String> {
+        if edge.kind == SocialEdgeKind::Friend {
+            return Err("Friendships must be added with add_friendship".to_string());
+        }
+        
+        let mut conn = self.client.get_connection().map_err(|e| e.to_string())?;
+        
+        redis::pipe()
+            .atomic()
+            .cmd("ZADD").arg(Self::out_key(&edge.from, edge.kind)).arg(edge.created_at).arg(&edge.to)
+            .cmd("SADD").arg(Self::in_key(&edge.to, edge.kind)).arg(&edge.from)
+            .query::<()>(&mut conn)
+            .map_err(|e| e.to_string())
+    }
+    
+    fn remove_edge(&self, from: &str, to: &str, kind: SocialEdgeKind) -> Result<bool, String> {
+        if kind == SocialEdgeKind::Friend {
+            return Err("Friendships must be removed with remove_friendship".to_string());
         }
+        
+        let mut conn = self.client.get_connection().map_err(|e| e.to_string())?;
+        
+        let (removed, _): (i64, i64) = redis::pipe()
+            .atomic()
+            .cmd("ZREM").arg(Self::out_key(from, kind)).arg(to)
+            .cmd("SREM").arg(Self::in_key(to, kind)).arg(from)
+            .query(&mut conn)
+            .map_err(|e| e.to_string())?;
+        
+        Ok(removed > 0)
+    }
+    
+    fn edges_from(&self, user_id: &str, kind: SocialEdgeKind) -> Result<Vec<SocialEdge>, String> {
+        let mut conn = self.client.get_connection().map_err(|e| e.to_string())?;
+        
+        let entries: Vec<(String, i64)> = redis::cmd("ZREVRANGE")
+            .arg(Self::out_key(user_id, kind))
+            .arg(0)
+            .arg(-1)
+            .arg("WITHSCORES")
+            .query(&mut conn)
+            .map_err(|e| e.to_string())?;
+        
+        Ok(entries.into_iter()
+            .map(|(to, created_at)| SocialEdge {
+                from: user_id.to_string(),
+                to,
+                kind,
+                created_at,
+            })
+            .collect())
+    }
+    
+    fn add_friendship(&self, a: &str, b: &str, created_at: i64) -> Result<(), String> {
+        let mut conn = self.client.get_connection().map_err(|e| e.to_string())?;
+        let kind = SocialEdgeKind::Friend;
+        
+        redis::pipe()
+            .atomic()
+            .cmd("ZADD").arg(Self::out_key(a, kind)).arg("NX").arg(created_at).arg(b)
+            .cmd("ZADD").arg(Self::out_key(b, kind)).arg("NX").arg(created_at).arg(a)
+            .query::<()>(&mut conn)
+            .map_err(|e| e.to_string())
+    }
+    
+    fn remove_friendship(&self, a: &str, b: &str) -> Result<bool, String> {
+        let mut conn = self.client.get_connection().map_err(|e| e.to_string())?;
+        let kind = SocialEdgeKind::Friend;
+        
+        let (first, second): (i64, i64) = redis::pipe()
+            .atomic()
+            .cmd("ZREM").arg(Self::out_key(a, kind)).arg(b)
+            .cmd("ZREM").arg(Self::out_key(b, kind)).arg(a)
+            .query(&mut conn)
+            .map_err(|e| e.to_string())?;
+        
+        Ok(first + second > 0)
+    }
+    
+    fn remove_user(&self, user_id: &str) -> Result<(), String> {
+        let friends = self.edges_from(user_id, SocialEdgeKind::Friend)?;
+        for friend in friends {
+            self.remove_friendship(user_id, &friend.to)?;
+        }
+        
+        let mut conn = self.client.get_connection().map_err(|e| e.to_string())?;
+        
+        for kind in [SocialEdgeKind::Block, SocialEdgeKind::RecentlyPlayed, SocialEdgeKind::FriendRequest] {
+            let outgoing: Vec<String> = redis::cmd("ZRANGE")
+                .arg(Self::out_key(user_id, kind)).arg(0).arg(-1)
+                .query(&mut conn)
+                .map_err(|e| e.to_string())?;
+            let incoming: Vec<String> = redis::cmd("SMEMBERS")
+                .arg(Self::in_key(user_id, kind))
+                .query(&mut conn)
+                .map_err(|e| e.to_string())?;
+            
+            let mut pipe = redis::pipe();
+            pipe.atomic();
+            for to in &outgoing {
+                pipe.cmd("SREM").arg(Self::in_key(to, kind)).arg(user_id);
+            }
+            for from in &incoming {
+                pipe.cmd("ZREM").arg(Self::out_key(from, kind)).arg(user_id);
+            }
+            pipe.cmd("DEL").arg(Self::out_key(user_id, kind)).arg(Self::in_key(user_id, kind));
+            
+            pipe.query::<()>(&mut conn).map_err(|e| e.to_string())?;
+        }
+        
+        Ok(())
     }
 }
 
+// ============================================
+// Platform Friend Import
+// ============================================
+
+/// Minimum time between friend list syncs for one user
+pub const PLATFORM_FRIEND_SYNC_INTERVAL_SECONDS: i64 = 6 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Steam,
+    Meta,
+}
+
+/// A platform account linked through OAuth
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinkedIdentity {
+    pub platform: Platform,
+    pub external_id: String,
+    /// OAuth token with friend-list scope. Never written to storage, so it is empty
+    /// after a reload until the user links the account again.
+    #[serde(skip_serializing, default)]
+    pub access_token: String,
+    pub linked_at: i64,
+}
+
+/// What a user agreed to do with their platform friend lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FriendImportConsent {
+    /// Never read the user's platform friends
+    #[default]
+    Off,
+    /// Show matching accounts as friend suggestions
+    Suggest,
+    /// Befriend matching accounts directly when they chose `AutoAdd` too
+    AutoAdd,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FriendImportResult {
+    /// Platform friends who have an account here
+    pub matched: usize,
+    pub added: Vec<String>,
+    pub suggested: Vec<String>,
+    /// Linked platforms that must go through OAuth again before they can be read
+    #[serde(default)]
+    pub needs_relink: Vec<Platform>,
+}
+
+/// Reads friend lists from a platform's API (Steam Web API, Meta Graph API)
+pub trait PlatformFriendSource: Send + Sync {
+    fn platform(&self) -> Platform;
+    /// External IDs of the account's friends
+    fn fetch_friends(&self, external_id: &str, access_token: &str) -> Result<Vec<String>, String>;
+}
+
 // ============================================
 // Session Manager
 // ============================================
@@ -417,6 +2638,21 @@ impl SessionManager {
     }
 }
 
+/// Test fixture shared by every module's tests
+#[cfg(test)]
+impl AuthService {
+    /// Sign up `name` (as `name@example.com`) and return the new user's ID
+    pub fn signup_test_user(&self, name: &str) -> String {
+        self.signup(SignupRequest {
+            username: name.to_string(),
+            email: format!("{}@example.com", name),
+            password: "password123".to_string(),
+            device_ids: Vec::new(),
+            challenge_response: None,
+        }).user.unwrap().id
+    }
+}
+
 // ============================================
 // Example Usage / Tests
 // ============================================
@@ -434,6 +2670,8 @@ mod tests {
             username: "testuser".to_string(),
             email: "test@example.com".to_string(),
             password: "password123".to_string(),
+            device_ids: Vec::new(),
+            challenge_response: None,
         };
         
         let signup_resp = auth.signup(signup_req);
@@ -444,6 +2682,7 @@ mod tests {
         let login_req = LoginRequest {
             email: "test@example.com".to_string(),
             password: "password123".to_string(),
+            device_ids: Vec::new(),
         };
         
         let login_resp = auth.login(login_req);
@@ -459,6 +2698,8 @@ mod tests {
             username: "testuser2".to_string(),
             email: "test2@example.com".to_string(),
             password: "password123".to_string(),
+            device_ids: Vec::new(),
+            challenge_response: None,
         };
         
         let resp = auth.signup(signup_req);
@@ -467,4 +2708,505 @@ mod tests {
         let user_id = auth.verify_token(&token);
         assert!(user_id.is_some());
     }
+    
+    #[test]
+    fn test_token_audience_and_game_scope() {
+        let auth = AuthService::new("test_secret_key".to_string());
+        
+        let resp = auth.signup(SignupRequest {
+            username: "scoped".to_string(),
+            email: "scoped@example.com".to_string(),
+            password: "password123".to_string(),
+            device_ids: Vec::new(),
+            challenge_response: None,
+        });
+        let platform_token = resp.token.unwrap();
+        let user_id = resp.user.unwrap().id;
+        
+        let game_a = TokenAudience::GameSession("game_a".to_string());
+        let game_b = TokenAudience::GameSession("game_b".to_string());
+        
+        // Platform tokens don't open game sessions
+        assert!(auth.verify_token_for(&platform_token, &game_a).is_none());
+        
+        let game_token = auth.mint_game_token(&user_id, "game_a").unwrap();
+        assert_eq!(auth.verify_token_for(&game_token, &game_a), Some(user_id));
+        assert!(auth.verify_token_for(&game_token, &game_b).is_none());
+        assert!(auth.verify_token(&game_token).is_none());
+    }
+    
+    #[test]
+    fn test_record_match_updates_ratings_and_xp() {
+        let auth = AuthService::new("test_secret_key".to_string());
+        
+        let mut ids = Vec::new();
+        for name in ["winner", "loser"] {
+            let resp = auth.signup(SignupRequest {
+                username: name.to_string(),
+                email: format!("{}@example.com", name),
+                password: "password123".to_string(),
+                device_ids: Vec::new(),
+                challenge_response: None,
+            });
+            ids.push(resp.user.unwrap().id);
+        }
+        
+        let participants = vec![
+            MatchParticipant { user_id: ids[0].clone(), score: 10, won: true },
+            MatchParticipant { user_id: ids[1].clone(), score: 3, won: false },
+        ];
+        auth.record_match("arena", &participants).unwrap();
+        
+        let board = auth.leaderboard("arena", 10);
+        assert_eq!(board[0].user_id, ids[0]);
+        assert_eq!(board[0].rating, 1016);
+        assert_eq!(board[1].rating, 984);
+        assert_eq!(auth.get_user(&ids[0]).unwrap().xp, 100);
+        
+        // Unknown participants abort the whole update
+        let bad = vec![
+            MatchParticipant { user_id: ids[0].clone(), score: 1, won: true },
+            MatchParticipant { user_id: "ghost".to_string(), score: 0, won: false },
+        ];
+        assert!(auth.record_match("arena", &bad).is_err());
+        assert_eq!(auth.get_user(&ids[0]).unwrap().xp, 100);
+    }
+    
+    #[test]
+    fn test_new_account_on_banned_device_is_flagged() {
+        let auth = AuthService::new("test_secret_key".to_string());
+        
+        let banned = auth.signup(SignupRequest {
+            username: "cheater".to_string(),
+            email: "cheater@example.com".to_string(),
+            password: "password123".to_string(),
+            device_ids: vec!["headset-123".to_string()],
+            challenge_response: None,
+        }).user.unwrap().id;
+        auth.set_banned(&banned, true);
+        
+        let fresh = auth.signup(SignupRequest {
+            username: "innocent".to_string(),
+            email: "innocent@example.com".to_string(),
+            password: "password123".to_string(),
+            device_ids: vec!["headset-123".to_string(), "pc-9".to_string()],
+            challenge_response: None,
+        }).user.unwrap().id;
+        
+        assert_eq!(auth.linked_accounts(&fresh), vec![banned.clone()]);
+        
+        let reviews = auth.pending_reviews();
+        assert_eq!(reviews.len(), 1);
+        assert_eq!(reviews[0].user_id, fresh);
+        assert_eq!(reviews[0].shared_devices, vec!["headset-123".to_string()]);
+        
+        assert!(auth.resolve_review(&fresh, true));
+        assert!(auth.pending_reviews().is_empty());
+        assert!(auth.get_active_user(&fresh).is_err());
+    }
+    
+    #[test]
+    fn test_signup_requires_proof_of_work() {
+        let auth = AuthService::new("test_secret_key".to_string())
+            .with_challenge_verifier(Arc::new(ProofOfWorkVerifier::new(8)));
+        
+        let request = |email: &str, response: Option<String>| SignupRequest {
+            username: "headless".to_string(),
+            email: email.to_string(),
+            password: "password123".to_string(),
+            device_ids: Vec::new(),
+            challenge_response: response,
+        };
+        
+        assert!(!auth.signup(request("a@example.com", None)).success);
+        
+        let challenge = auth.signup_challenge().unwrap();
+        let solution = solve_proof_of_work(&challenge, 8);
+        assert!(auth.signup(request("a@example.com", Some(solution.clone()))).success);
+        
+        // Solutions can't be replayed
+        assert!(!auth.signup(request("b@example.com", Some(solution))).success);
+        
+        // Unauthenticated challenge requests can't grow the outstanding set without bound
+        let verifier = ProofOfWorkVerifier::new(8);
+        let mut latest = String::new();
+        for _ in 0..MAX_OUTSTANDING_CHALLENGES + 10 {
+            latest = verifier.issue_challenge().unwrap();
+        }
+        assert_eq!(verifier.outstanding.lock().unwrap().len(), MAX_OUTSTANDING_CHALLENGES);
+        assert!(verifier.verify(&solve_proof_of_work(&latest, 8)).is_ok());
+    }
+    
+    #[test]
+    fn test_profile_customization_is_validated() {
+        let auth = AuthService::new("test_secret_key".to_string())
+            .with_blocked_words(vec!["badword".to_string()]);
+        
+        let user_id = auth.signup(SignupRequest {
+            username: "login_name".to_string(),
+            email: "profile@example.com".to_string(),
+            password: "password123".to_string(),
+            device_ids: Vec::new(),
+            challenge_response: None,
+        }).user.unwrap().id;
+        
+        assert_eq!(auth.get_user(&user_id).unwrap().display_name, "login_name");
+        
+        let profile = auth.update_profile(&user_id, ProfileUpdate {
+            display_name: Some("  Starfall ".to_string()),
+            pronouns: Some("they/them".to_string()),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(profile.display_name, "Starfall");
+        assert_eq!(profile.pronouns, Some("they/them".to_string()));
+        
+        let rejected = auth.update_profile(&user_id, ProfileUpdate {
+            bio: Some("I am a BadWord".to_string()),
+            ..Default::default()
+        });
+        assert!(rejected.is_err());
+        
+        let cleared = auth.update_profile(&user_id, ProfileUpdate {
+            display_name: Some(String::new()),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(cleared.display_name, "login_name");
+        
+        assert!(auth.award_badge(&user_id, "beta", "Beta Tester"));
+        assert!(!auth.award_badge(&user_id, "beta", "Beta Tester"));
+    }
+    
+    #[test]
+    fn test_friend_search_and_suggestions() {
+        let auth = AuthService::new("test_secret_key".to_string());
+        
+        let me = auth.signup_test_user("searcher");
+        let alice = auth.signup_test_user("alice");
+        let alina = auth.signup_test_user("alina");
+        let albert = auth.signup_test_user("albert");
+        let bob = auth.signup_test_user("bob");
+        
+        auth.set_discoverability(&albert, Discoverability::Nobody);
+        auth.set_discoverability(&alina, Discoverability::FriendsOfFriends);
+        
+        let page = auth.search_users(&me, "AL", 0, 1);
+        assert_eq!(page.total, 1);
+        assert_eq!(page.results[0].id, alice);
+        assert_eq!(page.next_offset, None);
+        
+        // Sharing a friend makes alina visible
+        auth.add_friend(&me, &bob);
+        auth.add_friend(&bob, &me);
+        auth.add_friend(&alina, &bob);
+        auth.add_friend(&bob, &alina);
+        let page = auth.search_users(&me, "al", 0, 1);
+        assert_eq!(page.total, 2);
+        assert_eq!(page.next_offset, Some(1));
+        
+        auth.record_match("game1", &[
+            MatchParticipant { user_id: me.clone(), score: 1, won: true },
+            MatchParticipant { user_id: albert.clone(), score: 0, won: false },
+            MatchParticipant { user_id: alice.clone(), score: 0, won: false },
+        ]).unwrap();
+        
+        let suggestions = auth.suggested_friends(&me, 10);
+        let ids: Vec<&str> = suggestions.iter().map(|s| s.user.id.as_str()).collect();
+        assert_eq!(ids, vec![alice.as_str(), alina.as_str()]);
+        assert!(suggestions[0].played_recently);
+        assert_eq!(suggestions[1].mutual_friends, 1);
+    }
+    
+    #[test]
+    fn test_privacy_settings() {
+        let auth = AuthService::new("test_secret_key".to_string());
+        
+        let owner = auth.signup_test_user("owner");
+        let friend = auth.signup_test_user("friend");
+        let stranger = auth.signup_test_user("stranger");
+        auth.add_friend(&owner, &friend);
+        auth.add_friend(&friend, &owner);
+        
+        assert!(auth.privacy_allows(&stranger, &owner, PrivacyAction::DirectMessage));
+        
+        auth.update_privacy_settings(&owner, PrivacySettings {
+            direct_messages: PrivacyAudience::Friends,
+            invites: PrivacyAudience::Nobody,
+            ..Default::default()
+        });
+        
+        assert!(auth.privacy_allows(&friend, &owner, PrivacyAction::DirectMessage));
+        assert!(!auth.privacy_allows(&stranger, &owner, PrivacyAction::DirectMessage));
+        assert!(!auth.privacy_allows(&friend, &owner, PrivacyAction::Invite));
+        assert!(auth.privacy_allows(&stranger, &owner, PrivacyAction::ViewPresence));
+    }
+    
+    #[test]
+    fn test_content_access_by_age_and_parental_controls() {
+        let auth = AuthService::new("test_secret_key".to_string());
+        
+        let user_id = auth.signup(SignupRequest {
+            username: "young_player".to_string(),
+            email: "young@example.com".to_string(),
+            password: "password123".to_string(),
+            device_ids: Vec::new(),
+            challenge_response: None,
+        }).user.unwrap().id;
+        
+        assert!(auth.check_content_access(&user_id, ContentRating::Teen, &[]).is_ok());
+        assert!(auth.check_content_access(&user_id, ContentRating::Mature, &[]).is_err());
+        
+        auth.set_age_bracket(&user_id, AgeBracket::Adult);
+        assert!(auth.check_content_access(&user_id, ContentRating::Mature, &[]).is_ok());
+        
+        auth.set_parental_controls(&user_id, ParentalControls {
+            max_rating: Some(ContentRating::Everyone),
+            blocked_tags: vec!["gambling".to_string()],
+        });
+        assert!(auth.check_content_access(&user_id, ContentRating::Teen, &[]).is_err());
+        assert!(auth.check_content_access(&user_id, ContentRating::Everyone, &["gambling".to_string()]).is_err());
+        assert!(auth.check_content_access(&user_id, ContentRating::Everyone, &["puzzle".to_string()]).is_ok());
+    }
+    
+    #[test]
+    fn test_social_graph_friendships_blocks_and_migration() {
+        let auth = AuthService::new("test_secret_key".to_string());
+        
+        let a = auth.signup_test_user("social_a");
+        let b = auth.signup_test_user("social_b");
+        let c = auth.signup_test_user("social_c");
+        
+        // A request alone grants nothing until the other side accepts
+        assert!(auth.add_friend(&a, &b));
+        assert!(!auth.add_friend(&a, &b));
+        assert!(auth.get_friends(&b).is_empty());
+        assert_eq!(auth.friend_requests(&b), vec![a.clone()]);
+        assert!(auth.add_friend(&b, &a));
+        assert_eq!(auth.get_friends(&b), vec![a.clone()]);
+        assert!(auth.friend_requests(&b).is_empty());
+        assert!(!auth.add_friend(&b, &a));
+        
+        // Removing from either side ends the friendship for both
+        assert!(auth.remove_friend(&b, &a));
+        assert!(auth.get_friends(&a).is_empty());
+        
+        auth.add_friend(&a, &c);
+        auth.add_friend(&c, &a);
+        assert!(auth.block_user(&c, &a));
+        assert!(auth.get_friends(&a).is_empty());
+        assert!(!auth.add_friend(&a, &c));
+        assert!(!auth.privacy_allows(&a, &c, PrivacyAction::DirectMessage));
+        assert!(auth.unblock_user(&c, &a));
+        
+        // Legacy one-way entries become mutual friendships
+        auth.users.lock().unwrap().get_mut(&a).unwrap().friends.push(b.clone());
+        auth.users.lock().unwrap().get_mut(&b).unwrap().recent_players = vec![c.clone(), a.clone()];
+        assert_eq!(auth.migrate_social_graph().unwrap(), 3);
+        assert_eq!(auth.get_friends(&b), vec![a.clone()]);
+        assert_eq!(auth.recent_players(&b), vec![c.clone(), a.clone()]);
+        assert!(auth.users.lock().unwrap()[&a].friends.is_empty());
+    }
+    
+    #[test]
+    fn test_idempotent_signup_and_unlock() {
+        let auth = AuthService::new("test_secret_key".to_string());
+        
+        let request = || SignupRequest {
+            username: "retry_user".to_string(),
+            email: "retry@example.com".to_string(),
+            password: "password123".to_string(),
+            device_ids: Vec::new(),
+            challenge_response: None,
+        };
+        
+        let first = auth.signup_idempotent("key-1", request());
+        let retried = auth.signup_idempotent("key-1", request());
+        assert!(first.success && retried.success);
+        assert_eq!(first.user.unwrap().id, retried.user.as_ref().unwrap().id);
+        assert!(!auth.signup_idempotent("key-2", request()).success);
+        
+        let user_id = retried.user.unwrap().id;
+        for _ in 0..2 {
+            auth.unlock_achievement_idempotent("unlock-1", &user_id, "first_win", "First Win", "Win a match").unwrap();
+        }
+        assert_eq!(auth.users.lock().unwrap()[&user_id].achievements.len(), 1);
+    }
+    
+    #[test]
+    fn test_account_states_gate_login_and_purge() {
+        let auth = AuthService::new("test_secret_key".to_string());
+        
+        let response = auth.signup(SignupRequest {
+            username: "lifecycle".to_string(),
+            email: "lifecycle@example.com".to_string(),
+            password: "password123".to_string(),
+            device_ids: Vec::new(),
+            challenge_response: None,
+        });
+        let token = response.token.unwrap();
+        let user_id = response.user.unwrap().id;
+        let login = || auth.login(LoginRequest {
+            email: "lifecycle@example.com".to_string(),
+            password: "password123".to_string(),
+            device_ids: Vec::new(),
+        });
+        
+        auth.suspend_account(&user_id, None, "spam").unwrap();
+        assert!(!login().success);
+        assert!(auth.verify_token(&token).is_none());
+        
+        // A lapsed suspension no longer blocks access
+        auth.suspend_account(&user_id, Some(Utc::now().timestamp() - 1), "spam").unwrap();
+        assert!(auth.verify_token(&token).is_some());
+        
+        // Logging in during the grace period cancels the deletion
+        auth.request_deletion(&user_id).unwrap();
+        assert!(auth.get_active_user(&user_id).is_err());
+        assert!(login().success);
+        assert_eq!(auth.account_state(&user_id), Some(AccountState::Active));
+        
+        auth.request_deletion(&user_id).unwrap();
+        assert!(auth.purge_pending_deletions(Utc::now().timestamp()).is_empty());
+        let purged = auth.purge_pending_deletions(Utc::now().timestamp() + ACCOUNT_DELETION_GRACE_SECONDS);
+        assert_eq!(purged, vec![user_id.clone()]);
+        assert!(auth.get_user(&user_id).is_none());
+        assert!(auth.verify_token(&token).is_none());
+        assert!(!login().success);
+    }
+    
+    #[test]
+    fn test_profile_cache_is_invalidated_on_write() {
+        let auth = AuthService::new("test_secret_key".to_string());
+        
+        assert!(auth.get_user("user_missing").is_none());
+        let a = auth.signup_test_user("cached_a");
+        let b = auth.signup_test_user("cached_b");
+        
+        assert_eq!(auth.get_user(&a).unwrap().friend_count, 0);
+        assert_eq!(auth.get_user(&a).unwrap().friend_count, 0);
+        
+        auth.add_friend(&a, &b);
+        auth.add_friend(&b, &a);
+        auth.update_user(&a, Some("https://cdn.example.com/a.png".to_string()));
+        let profile = auth.get_user(&a).unwrap();
+        assert_eq!(profile.friend_count, 1);
+        assert!(profile.avatar_url.is_some());
+        
+        let stats = auth.profile_cache_stats();
+        assert_eq!((stats.hits, stats.misses), (1, 3));
+        
+        // Purging an account drops it from its friends' cached counts
+        auth.request_deletion(&b).unwrap();
+        auth.purge_pending_deletions(Utc::now().timestamp() + ACCOUNT_DELETION_GRACE_SECONDS);
+        assert_eq!(auth.get_user(&a).unwrap().friend_count, 0);
+    }
+    
+    #[test]
+    fn test_auth_requests_decode_untrusted_bodies() {
+        let login = LoginRequest::decode(br#"{"email":"a@example.com","password":"hunter22"}"#).unwrap();
+        assert_eq!(login.email, "a@example.com");
+        assert!(login.device_ids.is_empty());
+        
+        assert_eq!(SignupRequest::decode(b"").unwrap_err(), DecodeError::Empty);
+        assert!(matches!(SignupRequest::decode(br#"{"username":"bob"}"#), Err(DecodeError::Malformed(_))));
+        assert!(matches!(LoginRequest::decode(&[0xff, 0xfe, 0x00]), Err(DecodeError::Malformed(_))));
+        
+        let oversized = format!(r#"{{"email":"a@example.com","password":"{}"}}"#, "x".repeat(MAX_AUTH_REQUEST_BYTES));
+        assert!(matches!(LoginRequest::decode(oversized.as_bytes()), Err(DecodeError::TooLarge { .. })));
+    }
+    
+    #[test]
+    fn test_sandbox_environments_are_isolated_and_wipeable() {
+        let auth = AuthService::new("test_secret_key".to_string());
+        let ids: Vec<String> = ["developer", "player"].iter().map(|name| auth.signup_test_user(name)).collect();
+        let (developer, player) = (&ids[0], &ids[1]);
+        auth.add_created_game(developer, "arena");
+        
+        let dev_game = Environment::Dev.scope_game("arena");
+        assert_eq!(Environment::of_game(&dev_game), ("arena", Environment::Dev));
+        assert_eq!(Environment::Prod.scope_game("arena"), "arena");
+        
+        // Only the developer may connect test builds to the sandbox
+        assert!(auth.mint_game_token(player, &dev_game).is_none());
+        let token = auth.mint_game_token(developer, &dev_game).unwrap();
+        assert!(auth.verify_token_for(&token, &TokenAudience::GameSession(dev_game.clone())).is_some());
+        assert!(auth.verify_token_for(&token, &TokenAudience::GameSession("arena".to_string())).is_none());
+        
+        let result = [
+            MatchParticipant { user_id: developer.clone(), score: 3, won: true },
+            MatchParticipant { user_id: player.clone(), score: 1, won: false },
+        ];
+        auth.record_match("arena", &result).unwrap();
+        auth.record_match(&dev_game, &result).unwrap();
+        auth.unlock_game_achievement(player, &dev_game, "tester", "Tester", "Played a dev build");
+        
+        assert_eq!(auth.leaderboard(&dev_game, 10).len(), 2);
+        assert_eq!(auth.achievements(developer, "arena", Environment::Dev).len(), 1);
+        assert_eq!(auth.achievements(developer, "arena", Environment::Prod).len(), 1);
+        
+        assert!(auth.wipe_environment(player, "arena", Environment::Dev).is_err());
+        assert!(auth.wipe_environment(developer, "arena", Environment::Prod).is_err());
+        assert_eq!(auth.wipe_environment(developer, "arena", Environment::Dev), Ok(2));
+        
+        assert!(auth.leaderboard(&dev_game, 10).is_empty());
+        assert!(auth.achievements(player, "arena", Environment::Dev).is_empty());
+        assert_eq!(auth.leaderboard("arena", 10).len(), 2);
+        assert_eq!(auth.achievements(developer, "arena", Environment::Prod).len(), 1);
+    }
+    
+    #[test]
+    fn test_platform_friend_import_respects_consent_and_rate_limit() {
+        struct FakeSteam;
+        impl PlatformFriendSource for FakeSteam {
+            fn platform(&self) -> Platform {
+                Platform::Steam
+            }
+            fn fetch_friends(&self, external_id: &str, _access_token: &str) -> Result<Vec<String>, String> {
+                Ok(match external_id {
+                    "steam_a" => vec!["steam_b".to_string(), "steam_c".to_string(), "steam_d".to_string(), "steam_unknown".to_string()],
+                    _ => vec!["steam_a".to_string()],
+                })
+            }
+        }
+        
+        let auth = AuthService::new("test_secret_key".to_string()).with_friend_source(Arc::new(FakeSteam));
+        let ids: Vec<String> = ["alice", "bob", "carol", "dave"].iter().map(|name| auth.signup_test_user(name)).collect();
+        let (alice, bob, carol, dave) = (&ids[0], &ids[1], &ids[2], &ids[3]);
+        
+        for (user_id, external_id) in [(alice, "steam_a"), (bob, "steam_b"), (carol, "steam_c"), (dave, "steam_d")] {
+            auth.link_identity(user_id, Platform::Steam, external_id, "token").unwrap();
+        }
+        assert!(auth.link_identity(bob, Platform::Steam, "steam_a", "token").is_err());
+        assert!(auth.link_identity(bob, Platform::Steam, "steam_b", "").is_err());
+        
+        // Dave hides from searches, so he isn't suggested either
+        auth.set_discoverability(dave, Discoverability::Nobody);
+        
+        // Nothing is read without consent
+        assert!(auth.sync_platform_friends(alice, 0).is_err());
+        
+        auth.set_friend_import_consent(alice, FriendImportConsent::AutoAdd).unwrap();
+        auth.set_friend_import_consent(bob, FriendImportConsent::AutoAdd).unwrap();
+        let result = auth.sync_platform_friends(alice, 0).unwrap();
+        assert_eq!(result.matched, 2);
+        assert_eq!(result.added, vec![bob.clone()]);
+        assert_eq!(result.suggested, vec![carol.clone()]);
+        assert!(auth.get_friends(alice).contains(bob));
+        
+        let suggestions = auth.suggested_friends(alice, 10);
+        assert!(suggestions.iter().any(|s| s.user.id == *carol && s.platform_friend));
+        
+        assert!(auth.sync_platform_friends(alice, 60).is_err());
+        assert_eq!(auth.sync_due_platform_friends(60, 10), 1); // bob, never synced
+        assert_eq!(auth.sync_due_platform_friends(PLATFORM_FRIEND_SYNC_INTERVAL_SECONDS, 1), 1);
+        
+        // A token lost on reload asks for a relink instead of silently syncing nothing
+        auth.users.lock().unwrap().get_mut(carol).unwrap().linked_identities[0].access_token.clear();
+        auth.set_friend_import_consent(carol, FriendImportConsent::Suggest).unwrap();
+        let result = auth.sync_platform_friends(carol, PLATFORM_FRIEND_SYNC_INTERVAL_SECONDS).unwrap();
+        assert_eq!(result.needs_relink, vec![Platform::Steam]);
+        assert_eq!(result.matched, 0);
+        
+        auth.set_friend_import_consent(alice, FriendImportConsent::Off).unwrap();
+        assert!(!auth.suggested_friends(alice, 10).iter().any(|s| s.platform_friend));
+    }
 }
@@ -0,0 +1,393 @@
+// billing.rs - Per-developer usage accounting, quotas and usage export for billing
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+/// Voice minutes are metered per billing period of this length, aligned to the Unix epoch
+pub const BILLING_PERIOD_SECONDS: i64 = 30 * 24 * 60 * 60;
+
+/// Limits for one developer account, summed across all of its games. `None` is unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeveloperQuota {
+    /// Concurrent connected players
+    pub max_ccu: Option<usize>,
+    /// Open rooms
+    pub max_rooms: Option<usize>,
+    /// Voice chat minutes per billing period
+    pub voice_minutes_per_period: Option<u64>,
+    pub storage_bytes: Option<u64>,
+}
+
+/// A developer's metered usage over a time range, as handed to the billing system
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub developer_id: String,
+    pub from: i64,
+    pub to: i64,
+    pub peak_ccu: usize,
+    pub peak_rooms: usize,
+    pub rooms_created: usize,
+    pub voice_minutes: f64,
+    /// Storage in use at `to`
+    pub storage_bytes: u64,
+    pub peak_storage_bytes: u64,
+    pub quota: DeveloperQuota,
+}
+
+#[derive(Debug, Clone)]
+struct VoiceSession {
+    developer_id: String,
+    started_at: i64,
+    ended_at: Option<i64>,
+}
+
+/// Counts what each developer's games use and enforces the developer's quota when
+/// players connect and rooms are created. Games with no registered developer are
+/// neither metered nor limited.
+pub struct UsageService {
+    game_owners: Mutex<HashMap<String, String>>, // game_id -> developer_id
+    quotas: Mutex<HashMap<String, DeveloperQuota>>, // developer_id -> quota
+    connections: Mutex<HashMap<String, String>>, // player_id -> developer_id
+    rooms: Mutex<HashMap<String, String>>, // room_id -> developer_id
+    room_games: Mutex<HashMap<String, String>>, // room_id -> game_id, for voice metering
+    storage: Mutex<HashMap<String, u64>>, // game_id -> bytes
+    ccu_samples: Mutex<HashMap<String, Vec<(i64, usize)>>>, // developer_id -> (at, CCU after the change)
+    room_samples: Mutex<HashMap<String, Vec<(i64, usize)>>>, // developer_id -> (at, open rooms after the change)
+    room_creations: Mutex<HashMap<String, Vec<i64>>>, // developer_id -> created at
+    storage_samples: Mutex<HashMap<String, Vec<(i64, u64)>>>, // developer_id -> (at, bytes after the change)
+    voice_sessions: Mutex<HashMap<String, VoiceSession>>, // player_id -> open session
+    voice_history: Mutex<Vec<VoiceSession>>,
+}
+
+impl UsageService {
+    pub fn new() -> Self {
+        Self {
+            game_owners: Mutex::new(HashMap::new()),
+            quotas: Mutex::new(HashMap::new()),
+            connections: Mutex::new(HashMap::new()),
+            rooms: Mutex::new(HashMap::new()),
+            room_games: Mutex::new(HashMap::new()),
+            storage: Mutex::new(HashMap::new()),
+            ccu_samples: Mutex::new(HashMap::new()),
+            room_samples: Mutex::new(HashMap::new()),
+            room_creations: Mutex::new(HashMap::new()),
+            storage_samples: Mutex::new(HashMap::new()),
+            voice_sessions: Mutex::new(HashMap::new()),
+            voice_history: Mutex::new(Vec::new()),
+        }
+    }
+    
+    /// Bill a game's usage to a developer account
+    pub fn register_game(&self, game_id: &str, developer_id: &str) {
+        self.game_owners.lock().unwrap().insert(game_id.to_string(), developer_id.to_string());
+    }
+    
+    pub fn set_quota(&self, developer_id: &str, quota: DeveloperQuota) {
+        println!("[Usage] Quota for {} set to {:?}", developer_id, quota);
+        self.quotas.lock().unwrap().insert(developer_id.to_string(), quota);
+    }
+    
+    pub fn quota(&self, developer_id: &str) -> DeveloperQuota {
+        self.quotas.lock().unwrap().get(developer_id).copied().unwrap_or_default()
+    }
+    
+    pub fn developer_for_game(&self, game_id: &str) -> Option<String> {
+        self.game_owners.lock().unwrap().get(game_id).cloned()
+    }
+    
+    /// Refuse a new room for `game_id` when its developer is at their room limit or
+    /// has used up their voice minutes or storage
+    pub fn check_room_creation(&self, game_id: &str, now: i64) -> Result<(), String> {
+        let Some(developer_id) = self.developer_for_game(game_id) else { return Ok(()) };
+        let quota = self.quota(&developer_id);
+        
+        if let Some(max_rooms) = quota.max_rooms {
+            if self.current_rooms(&developer_id) >= max_rooms {
+                return Err(format!("Room limit of {} reached", max_rooms));
+            }
+        }
+        if let Some(minutes) = quota.voice_minutes_per_period {
+            let period_start = now.div_euclid(BILLING_PERIOD_SECONDS) * BILLING_PERIOD_SECONDS;
+            if self.voice_minutes(&developer_id, period_start, now) >= minutes as f64 {
+                return Err("Voice minutes for this billing period are used up".to_string());
+            }
+        }
+        if let Some(bytes) = quota.storage_bytes {
+            if self.current_storage(&developer_id) > bytes {
+                return Err("Storage quota exceeded".to_string());
+            }
+        }
+        Ok(())
+    }
+    
+    /// Count a connection to `game_id`, refusing it when the game's developer is at
+    /// their CCU limit. The check and the count happen under one lock, so two players
+    /// racing for the last slot can't both get it.
+    pub fn player_connected(&self, player_id: &str, game_id: &str, now: i64) -> Result<(), String> {
+        self.player_disconnected(player_id, now);
+        let Some(developer_id) = self.developer_for_game(game_id) else { return Ok(()) };
+        let max_ccu = self.quota(&developer_id).max_ccu;
+        
+        {
+            let mut connections = self.connections.lock().unwrap();
+            if let Some(max_ccu) = max_ccu {
+                if connections.values().filter(|d| **d == developer_id).count() >= max_ccu {
+                    return Err(format!("Concurrent player limit of {} reached", max_ccu));
+                }
+            }
+            connections.insert(player_id.to_string(), developer_id.clone());
+        }
+        
+        self.sample_ccu(&developer_id, now);
+        Ok(())
+    }
+    
+    pub fn player_disconnected(&self, player_id: &str, now: i64) {
+        self.voice_stopped(player_id, now);
+        let Some(developer_id) = self.connections.lock().unwrap().remove(player_id) else { return };
+        self.sample_ccu(&developer_id, now);
+    }
+    
+    pub fn room_opened(&self, room_id: &str, game_id: &str, now: i64) {
+        self.room_games.lock().unwrap().insert(room_id.to_string(), game_id.to_string());
+        let Some(developer_id) = self.developer_for_game(game_id) else { return };
+        
+        self.rooms.lock().unwrap().insert(room_id.to_string(), developer_id.clone());
+        self.room_creations.lock().unwrap().entry(developer_id.clone()).or_default().push(now);
+        self.sample_rooms(&developer_id, now);
+    }
+    
+    pub fn room_closed(&self, room_id: &str, now: i64) {
+        self.room_games.lock().unwrap().remove(room_id);
+        let Some(developer_id) = self.rooms.lock().unwrap().remove(room_id) else { return };
+        self.sample_rooms(&developer_id, now);
+    }
+    
+    /// Start metering a player's voice chat in a room. A running session is ended first.
+    pub fn voice_started(&self, player_id: &str, room_id: &str, now: i64) {
+        self.voice_stopped(player_id, now);
+        
+        let game_id = self.room_games.lock().unwrap().get(room_id).cloned();
+        let Some(developer_id) = game_id.and_then(|g| self.developer_for_game(&g)) else { return };
+        
+        self.voice_sessions.lock().unwrap().insert(player_id.to_string(), VoiceSession {
+            developer_id,
+            started_at: now,
+            ended_at: None,
+        });
+    }
+    
+    pub fn voice_stopped(&self, player_id: &str, now: i64) {
+        let Some(mut session) = self.voice_sessions.lock().unwrap().remove(player_id) else { return };
+        session.ended_at = Some(now);
+        self.voice_history.lock().unwrap().push(session);
+    }
+    
+    /// Report the bytes a game currently stores (saves, replays, uploads)
+    pub fn record_storage(&self, game_id: &str, bytes: u64, now: i64) {
+        self.storage.lock().unwrap().insert(game_id.to_string(), bytes);
+        let Some(developer_id) = self.developer_for_game(game_id) else { return };
+        
+        let total = self.current_storage(&developer_id);
+        self.storage_samples.lock().unwrap().entry(developer_id).or_default().push((now, total));
+    }
+    
+    pub fn current_ccu(&self, developer_id: &str) -> usize {
+        self.connections.lock().unwrap().values().filter(|d| *d == developer_id).count()
+    }
+    
+    pub fn current_rooms(&self, developer_id: &str) -> usize {
+        self.rooms.lock().unwrap().values().filter(|d| *d == developer_id).count()
+    }
+    
+    pub fn current_storage(&self, developer_id: &str) -> u64 {
+        let games: HashSet<String> = self.game_owners.lock().unwrap()
+            .iter()
+            .filter(|(_, owner)| *owner == developer_id)
+            .map(|(game_id, _)| game_id.clone())
+            .collect();
+        
+        self.storage.lock().unwrap()
+            .iter()
+            .filter(|(game_id, _)| games.contains(*game_id))
+            .map(|(_, bytes)| bytes)
+            .sum()
+    }
+    
+    /// Voice minutes a developer's players used between `from` and `to`
+    pub fn voice_minutes(&self, developer_id: &str, from: i64, to: i64) -> f64 {
+        let overlap = |session: &VoiceSession| {
+            let end = session.ended_at.unwrap_or(to).min(to);
+            (end - session.started_at.max(from)).max(0)
+        };
+        
+        let finished: i64 = self.voice_history.lock().unwrap()
+            .iter()
+            .filter(|s| s.developer_id == developer_id)
+            .map(overlap)
+            .sum();
+        let open: i64 = self.voice_sessions.lock().unwrap()
+            .values()
+            .filter(|s| s.developer_id == developer_id)
+            .map(overlap)
+            .sum();
+        
+        (finished + open) as f64 / 60.0
+    }
+    
+    /// Usage of one developer between `from` and `to` (Unix seconds)
+    pub fn usage_report(&self, developer_id: &str, from: i64, to: i64) -> UsageReport {
+        let storage = self.storage_samples.lock().unwrap().get(developer_id).cloned().unwrap_or_default();
+        let storage_bytes = storage.iter().rev().find(|(at, _)| *at <= to).map_or(0, |(_, b)| *b);
+        
+        UsageReport {
+            developer_id: developer_id.to_string(),
+            from,
+            to,
+            peak_ccu: peak(self.ccu_samples.lock().unwrap().get(developer_id), from, to),
+            peak_rooms: peak(self.room_samples.lock().unwrap().get(developer_id), from, to),
+            rooms_created: self.room_creations.lock().unwrap()
+                .get(developer_id)
+                .map_or(0, |created| created.iter().filter(|at| (from..=to).contains(*at)).count()),
+            voice_minutes: self.voice_minutes(developer_id, from, to),
+            storage_bytes,
+            peak_storage_bytes: peak(Some(&storage), from, to),
+            quota: self.quota(developer_id),
+        }
+    }
+    
+    /// Drop usage from before the previous billing period, so memory stays bounded
+    /// while the last full period can still be exported. Call periodically.
+    pub fn purge_expired(&self, now: i64) {
+        let cutoff = (now.div_euclid(BILLING_PERIOD_SECONDS) - 1) * BILLING_PERIOD_SECONDS;
+        
+        for samples in self.ccu_samples.lock().unwrap().values_mut() {
+            prune_series(samples, cutoff);
+        }
+        for samples in self.room_samples.lock().unwrap().values_mut() {
+            prune_series(samples, cutoff);
+        }
+        for samples in self.storage_samples.lock().unwrap().values_mut() {
+            prune_series(samples, cutoff);
+        }
+        for created in self.room_creations.lock().unwrap().values_mut() {
+            created.retain(|at| *at >= cutoff);
+        }
+        self.voice_history.lock().unwrap().retain(|s| s.ended_at.is_none_or(|end| end >= cutoff));
+    }
+    
+    /// Usage export: one report per developer with registered games, ordered by developer ID
+    pub fn export_usage(&self, from: i64, to: i64) -> Vec<UsageReport> {
+        let mut developers: Vec<String> = self.game_owners.lock().unwrap()
+            .values()
+            .cloned()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        developers.sort();
+        
+        developers.iter().map(|d| self.usage_report(d, from, to)).collect()
+    }
+    
+    fn sample_ccu(&self, developer_id: &str, now: i64) {
+        let ccu = self.current_ccu(developer_id);
+        self.ccu_samples.lock().unwrap().entry(developer_id.to_string()).or_default().push((now, ccu));
+    }
+    
+    fn sample_rooms(&self, developer_id: &str, now: i64) {
+        let rooms = self.current_rooms(developer_id);
+        self.room_samples.lock().unwrap().entry(developer_id.to_string()).or_default().push((now, rooms));
+    }
+}
+
+impl Default for UsageService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Drop samples from before `cutoff` except the newest of them, which is still the
+/// value in effect at `cutoff`
+fn prune_series<T>(samples: &mut Vec<(i64, T)>, cutoff: i64) {
+    let stale = samples.iter().filter(|(at, _)| *at < cutoff).count();
+    if stale > 1 {
+        samples.drain(..stale - 1);
+    }
+}
+
+/// Highest value of a step series between `from` and `to`, including the value
+/// already in effect at `from`
+fn peak<T: Copy + Ord + Default>(samples: Option<&Vec<(i64, T)>>, from: i64, to: i64) -> T {
+    let Some(samples) = samples else { return T::default() };
+    
+    let at_start = samples.iter().rev().find(|(at, _)| *at < from).map(|(_, v)| *v).unwrap_or_default();
+    samples.iter()
+        .filter(|(at, _)| (from..=to).contains(at))
+        .map(|(_, v)| *v)
+        .fold(at_start, T::max)
+}
+
+// ============================================
+// Tests
+// ============================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_quotas_and_usage_export() {
+        let usage = UsageService::new();
+        usage.register_game("arena", "studio");
+        usage.register_game("racer", "studio");
+        usage.set_quota("studio", DeveloperQuota {
+            max_ccu: Some(2),
+            max_rooms: Some(1),
+            voice_minutes_per_period: Some(10),
+            storage_bytes: Some(1_000),
+        });
+        
+        // CCU counts across all of the developer's games
+        usage.player_connected("a", "arena", 100).unwrap();
+        usage.player_connected("b", "racer", 110).unwrap();
+        assert!(usage.player_connected("c", "arena", 115).is_err());
+        assert_eq!(usage.current_ccu("studio"), 2);
+        assert!(usage.player_connected("d", "unowned", 115).is_ok());
+        usage.player_disconnected("b", 200);
+        usage.player_connected("c", "arena", 210).unwrap();
+        usage.player_disconnected("c", 220);
+        
+        usage.room_opened("room_1", "arena", 120);
+        assert!(usage.check_room_creation("racer", 130).is_err());
+        usage.room_closed("room_1", 400);
+        assert!(usage.check_room_creation("racer", 410).is_ok());
+        
+        usage.room_opened("room_2", "arena", 500);
+        usage.voice_started("a", "room_2", 500);
+        usage.voice_stopped("a", 500 + 10 * 60);
+        usage.room_closed("room_2", 1200);
+        assert!(usage.check_room_creation("arena", 1300).is_err());
+        
+        usage.record_storage("arena", 600, 1400);
+        usage.record_storage("racer", 300, 1500);
+        
+        let report = usage.usage_report("studio", 0, 2000);
+        assert_eq!((report.peak_ccu, report.peak_rooms, report.rooms_created), (2, 1, 2));
+        assert_eq!(report.voice_minutes, 10.0);
+        assert_eq!((report.storage_bytes, report.peak_storage_bytes), (900, 900));
+        
+        // A later range starts from the CCU still in effect
+        let later = usage.usage_report("studio", 1600, 2000);
+        assert_eq!((later.peak_ccu, later.rooms_created, later.voice_minutes), (1, 0, 0.0));
+        
+        assert_eq!(usage.export_usage(0, 2000), vec![report]);
+        
+        // Two periods on, only what's in effect survives from the first period
+        usage.purge_expired(2 * BILLING_PERIOD_SECONDS + 10);
+        let purged = usage.usage_report("studio", 0, 2000);
+        assert_eq!((purged.rooms_created, purged.voice_minutes, purged.storage_bytes), (0, 0.0, 900));
+        assert_eq!(usage.ccu_samples.lock().unwrap()["studio"], vec![(220, 1)]);
+        let current = usage.usage_report("studio", BILLING_PERIOD_SECONDS, 2 * BILLING_PERIOD_SECONDS);
+        assert_eq!((current.peak_ccu, current.storage_bytes), (1, 900));
+    }
+}
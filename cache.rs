@@ -0,0 +1,146 @@
+// cache.rs - In-process TTL cache for hot reads, with explicit invalidation and hit/miss metrics
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheStats {
+    pub name: String,
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+impl CacheStats {
+    /// Fraction of lookups served from the cache; 0 before any lookup
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 { 0.0 } else { self.hits as f64 / lookups as f64 }
+    }
+}
+
+/// Values expire `ttl` after they were loaded. Writers call `invalidate` when they
+/// change the underlying data so readers never wait out the TTL for their own writes.
+pub struct TtlCache<K, V> {
+    name: String,
+    ttl: Duration,
+    entries: Mutex<HashMap<K, (Instant, V)>>, // key -> (loaded at, value)
+    /// Bumped by every invalidation, under the entries lock. A load that saw an older
+    /// generation may have read data from before the write, so its result isn't cached.
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlCache<K, V> {
+    pub fn new(name: &str, ttl: Duration) -> Self {
+        Self {
+            name: name.to_string(),
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+    
+    pub fn get_or_load<F>(&self, key: K, load: F) -> V
+    where
+        F: FnOnce() -> V,
+    {
+        self.get_or_load_at(key, Instant::now(), load)
+    }
+    
+    /// Cached value for `key`, or the result of `load` (which is then cached unless
+    /// the cache was invalidated while it ran). `load` runs without the cache lock held.
+    pub fn get_or_load_at<F>(&self, key: K, now: Instant, load: F) -> V
+    where
+        F: FnOnce() -> V,
+    {
+        let generation = {
+            let entries = self.entries.lock().unwrap();
+            if let Some((loaded_at, value)) = entries.get(&key) {
+                if now.duration_since(*loaded_at) < self.ttl {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    return value.clone();
+                }
+            }
+            self.generation.load(Ordering::Relaxed)
+        };
+        
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let value = load();
+        
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (loaded_at, _)| now.duration_since(*loaded_at) < self.ttl);
+        if self.generation.load(Ordering::Relaxed) == generation {
+            entries.insert(key, (now, value.clone()));
+        }
+        
+        value
+    }
+    
+    pub fn invalidate<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let mut entries = self.entries.lock().unwrap();
+        entries.remove(key);
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+    
+    pub fn invalidate_all(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clear();
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+    
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            name: self.name.clone(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.lock().unwrap().len(),
+        }
+    }
+}
+
+// ============================================
+// Tests
+// ============================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_ttl_expiry_invalidation_and_metrics() {
+        let cache = TtlCache::new("test", Duration::from_secs(5));
+        let start = Instant::now();
+        let key = || "k".to_string();
+        
+        assert_eq!(cache.get_or_load_at(key(), start, || 1), 1);
+        assert_eq!(cache.get_or_load_at(key(), start + Duration::from_secs(4), || 2), 1);
+        assert_eq!(cache.get_or_load_at(key(), start + Duration::from_secs(5), || 3), 3);
+        
+        cache.invalidate("k");
+        assert_eq!(cache.get_or_load_at(key(), start + Duration::from_secs(6), || 4), 4);
+        
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 3, 1));
+        assert_eq!(stats.hit_rate(), 0.25);
+        
+        // A write that lands while a load is running keeps the stale result out of the cache
+        cache.invalidate("k");
+        let stale = cache.get_or_load_at(key(), start + Duration::from_secs(7), || {
+            cache.invalidate("k");
+            5
+        });
+        assert_eq!(stale, 5);
+        assert_eq!(cache.get_or_load_at(key(), start + Duration::from_secs(7), || 6), 6);
+    }
+}
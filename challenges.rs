@@ -0,0 +1,398 @@
+// challenges.rs - Rotating daily/weekly challenges tracked against per-game stat events
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::auth_server::{AuthService, MatchParticipant};
+use crate::seasons::SeasonService;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// How many challenges from a game's pool are live at once
+pub const DAILY_CHALLENGE_SLOTS: usize = 3;
+pub const WEEKLY_CHALLENGE_SLOTS: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChallengeCadence {
+    Daily,
+    Weekly,
+}
+
+impl ChallengeCadence {
+    /// Index of the period containing `now`. Days start at 00:00 UTC, weeks on Monday.
+    pub fn period(&self, now: i64) -> i64 {
+        let day = now.div_euclid(SECONDS_PER_DAY);
+        match self {
+            ChallengeCadence::Daily => day,
+            // 1970-01-01 was a Thursday
+            ChallengeCadence::Weekly => (day + 3).div_euclid(7),
+        }
+    }
+    
+    /// When the period containing `now` ends and the board rotates
+    pub fn period_ends_at(&self, now: i64) -> i64 {
+        let next = self.period(now) + 1;
+        match self {
+            ChallengeCadence::Daily => next * SECONDS_PER_DAY,
+            ChallengeCadence::Weekly => (next * 7 - 3) * SECONDS_PER_DAY,
+        }
+    }
+    
+    fn slots(&self) -> usize {
+        match self {
+            ChallengeCadence::Daily => DAILY_CHALLENGE_SLOTS,
+            ChallengeCadence::Weekly => WEEKLY_CHALLENGE_SLOTS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatAggregate {
+    /// Add up every event's value
+    Sum,
+    /// Best single event
+    Max,
+    /// Number of events, ignoring their value
+    Count,
+}
+
+/// One condition of a challenge, e.g. "sum of `kills` reaches 50"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChallengeCriterion {
+    pub stat: String,
+    pub aggregate: StatAggregate,
+    pub target: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChallengeReward {
+    pub xp: u64,
+    #[serde(default)]
+    pub items: Vec<String>,
+}
+
+/// A challenge in a game's rotation pool. Completed once every criterion is met.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChallengeDefinition {
+    pub id: String,
+    pub game_id: String,
+    pub cadence: ChallengeCadence,
+    pub title: String,
+    pub description: String,
+    pub criteria: Vec<ChallengeCriterion>,
+    pub reward: ChallengeReward,
+}
+
+/// A stat reported by a game for one player, e.g. `kills` = 3 at the end of a round
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatEvent {
+    pub stat: String,
+    pub value: u64,
+}
+
+/// One entry on a player's challenge board
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChallengeStatus {
+    pub challenge: ChallengeDefinition,
+    /// Progress per criterion, capped at its target
+    pub progress: Vec<u64>,
+    pub completed: bool,
+    pub ends_at: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ChallengeProgress {
+    values: Vec<u64>,
+    completed: bool,
+}
+
+type ProgressKey = (String, String, String, i64); // (user_id, game_id, challenge_id, ends_at)
+type ChallengeBoard = (i64, Vec<ChallengeDefinition>); // (ends_at, live challenges)
+
+/// Serves each game's rotating challenge board, tracks player progress from stat
+/// events and grants the reward when a challenge completes: through the season
+/// service when one is attached, so the XP also counts towards the live season
+pub struct ChallengeService {
+    auth: Arc<AuthService>,
+    seasons: Option<Arc<SeasonService>>,
+    definitions: Mutex<HashMap<String, Vec<ChallengeDefinition>>>, // game_id -> pool
+    boards: Mutex<HashMap<(String, ChallengeCadence), ChallengeBoard>>, // (game_id, cadence) -> board for the current period
+    progress: Mutex<HashMap<ProgressKey, ChallengeProgress>>,
+}
+
+impl ChallengeService {
+    pub fn new(auth: Arc<AuthService>) -> Self {
+        Self {
+            auth,
+            seasons: None,
+            definitions: Mutex::new(HashMap::new()),
+            boards: Mutex::new(HashMap::new()),
+            progress: Mutex::new(HashMap::new()),
+        }
+    }
+    
+    /// Grant completed challenges' rewards through `seasons` so their XP also counts
+    /// towards the game's live season
+    pub fn with_seasons(mut self, seasons: Arc<SeasonService>) -> Self {
+        self.seasons = Some(seasons);
+        self
+    }
+    
+    /// Add a challenge to its game's pool, replacing one with the same ID. Boards
+    /// already live keep their challenges until the period ends.
+    pub fn define_challenge(&self, challenge: ChallengeDefinition) -> Result<(), String> {
+        if challenge.id.is_empty() || challenge.game_id.is_empty() {
+            return Err("Challenges need an ID and a game".to_string());
+        }
+        if challenge.criteria.is_empty() {
+            return Err("Challenges need at least one criterion".to_string());
+        }
+        if challenge.criteria.iter().any(|c| c.stat.is_empty() || c.target == 0) {
+            return Err("Every criterion needs a stat and a target above zero".to_string());
+        }
+        
+        let mut definitions = self.definitions.lock().unwrap();
+        let pool = definitions.entry(challenge.game_id.clone()).or_default();
+        pool.retain(|c| c.id != challenge.id);
+        pool.push(challenge);
+        pool.sort_by(|a, b| a.id.cmp(&b.id));
+        
+        Ok(())
+    }
+    
+    pub fn remove_challenge(&self, game_id: &str, challenge_id: &str) -> bool {
+        let mut definitions = self.definitions.lock().unwrap();
+        let Some(pool) = definitions.get_mut(game_id) else { return false };
+        let before = pool.len();
+        pool.retain(|c| c.id != challenge_id);
+        before != pool.len()
+    }
+    
+    /// The challenges live at `now`: each cadence shows a window of its pool that
+    /// moves along every period, so the whole pool comes around in turn. The window
+    /// is picked when a period is first seen and holds until it ends, so editing the
+    /// pool never swaps out a challenge players are partway through.
+    pub fn active_challenges(&self, game_id: &str, now: i64) -> Vec<ChallengeDefinition> {
+        let mut boards = self.boards.lock().unwrap();
+        boards.retain(|_, (ends_at, _)| *ends_at > now);
+        
+        let mut active = Vec::new();
+        for cadence in [ChallengeCadence::Daily, ChallengeCadence::Weekly] {
+            let ends_at = cadence.period_ends_at(now);
+            let board = match boards.get(&(game_id.to_string(), cadence)) {
+                Some((board_ends_at, board)) if *board_ends_at == ends_at => board.clone(),
+                _ => {
+                    let board = self.rotate(game_id, cadence, now);
+                    boards.insert((game_id.to_string(), cadence), (ends_at, board.clone()));
+                    board
+                }
+            };
+            active.extend(board);
+        }
+        active
+    }
+    
+    fn rotate(&self, game_id: &str, cadence: ChallengeCadence, now: i64) -> Vec<ChallengeDefinition> {
+        let definitions = self.definitions.lock().unwrap();
+        let candidates: Vec<&ChallengeDefinition> = definitions.get(game_id)
+            .map(|pool| pool.iter().filter(|c| c.cadence == cadence).collect())
+            .unwrap_or_default();
+        if candidates.is_empty() {
+            return Vec::new();
+        }
+        
+        let slots = cadence.slots().min(candidates.len());
+        let start = (cadence.period(now) * slots as i64).rem_euclid(candidates.len() as i64) as usize;
+        (0..slots).map(|i| candidates[(start + i) % candidates.len()].clone()).collect()
+    }
+    
+    /// Apply a stat event to the player's live challenges. Returns the IDs of the
+    /// challenges it completed; their rewards have been granted.
+    pub fn record_event(&self, user_id: &str, game_id: &str, event: &StatEvent, now: i64) -> Vec<String> {
+        let active = self.active_challenges(game_id, now);
+        let mut completed = Vec::new();
+        
+        {
+            let mut progress = self.progress.lock().unwrap();
+            progress.retain(|(_, _, _, ends_at), _| *ends_at > now);
+            
+            for challenge in active.iter().filter(|c| c.criteria.iter().any(|k| k.stat == event.stat)) {
+                let key = (user_id.to_string(), game_id.to_string(), challenge.id.clone(), challenge.cadence.period_ends_at(now));
+                let entry = progress.entry(key).or_default();
+                if entry.completed {
+                    continue;
+                }
+                entry.values.resize(challenge.criteria.len(), 0);
+                
+                for (criterion, value) in challenge.criteria.iter().zip(entry.values.iter_mut()) {
+                    if criterion.stat != event.stat {
+                        continue;
+                    }
+                    *value = match criterion.aggregate {
+                        StatAggregate::Sum => value.saturating_add(event.value),
+                        StatAggregate::Max => (*value).max(event.value),
+                        StatAggregate::Count => value.saturating_add(1),
+                    };
+                }
+                
+                if challenge.criteria.iter().zip(&entry.values).all(|(c, v)| *v >= c.target) {
+                    entry.completed = true;
+                    completed.push(challenge);
+                }
+            }
+        }
+        
+        // Rewards are granted without holding the progress lock
+        for challenge in &completed {
+            println!("[Challenges] {} completed {} ({})", user_id, challenge.id, challenge.game_id);
+            let granted = match &self.seasons {
+                Some(seasons) => seasons.grant_reward(user_id, game_id, &challenge.reward, now),
+                None => self.auth.grant_reward(user_id, challenge.reward.xp, &challenge.reward.items),
+            };
+            if let Err(e) = granted {
+                eprintln!("[Challenges] Could not grant reward for {} to {}: {}", challenge.id, user_id, e);
+            }
+        }
+        
+        completed.into_iter().map(|c| c.id.clone()).collect()
+    }
+    
+    /// Feed a finished match in as `matches_played`, `matches_won` and `score` events
+    pub fn record_match(&self, game_id: &str, participants: &[MatchParticipant], now: i64) {
+        for participant in participants {
+            let mut events = vec![StatEvent { stat: "matches_played".to_string(), value: 1 }];
+            if participant.won {
+                events.push(StatEvent { stat: "matches_won".to_string(), value: 1 });
+            }
+            if participant.score > 0 {
+                events.push(StatEvent { stat: "score".to_string(), value: participant.score as u64 });
+            }
+            
+            for event in &events {
+                self.record_event(&participant.user_id, game_id, event, now);
+            }
+        }
+    }
+    
+    /// The player's challenge board for a game: every live challenge with progress
+    pub fn challenge_board(&self, user_id: &str, game_id: &str, now: i64) -> Vec<ChallengeStatus> {
+        let active = self.active_challenges(game_id, now);
+        let progress = self.progress.lock().unwrap();
+        
+        active.into_iter().map(|challenge| {
+            let ends_at = challenge.cadence.period_ends_at(now);
+            let key = (user_id.to_string(), game_id.to_string(), challenge.id.clone(), ends_at);
+            let entry = progress.get(&key).cloned().unwrap_or_default();
+            let values = challenge.criteria.iter().enumerate()
+                .map(|(i, c)| entry.values.get(i).copied().unwrap_or(0).min(c.target))
+                .collect();
+            
+            ChallengeStatus {
+                challenge,
+                progress: values,
+                completed: entry.completed,
+                ends_at,
+            }
+        }).collect()
+    }
+}
+
+// ============================================
+// Tests
+// ============================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn daily(id: &str, stat: &str, aggregate: StatAggregate, target: u64) -> ChallengeDefinition {
+        ChallengeDefinition {
+            id: id.to_string(),
+            game_id: "arena".to_string(),
+            cadence: ChallengeCadence::Daily,
+            title: id.to_string(),
+            description: String::new(),
+            criteria: vec![ChallengeCriterion { stat: stat.to_string(), aggregate, target }],
+            reward: ChallengeReward { xp: 50, items: vec!["crate".to_string()] },
+        }
+    }
+    
+    #[test]
+    fn test_challenges_rotate_track_progress_and_reward_once() {
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let user_id = auth.signup_test_user("player");
+        
+        let challenges = ChallengeService::new(auth.clone());
+        challenges.define_challenge(daily("a_kills", "kills", StatAggregate::Sum, 10)).unwrap();
+        challenges.define_challenge(daily("b_best", "kills", StatAggregate::Max, 8)).unwrap();
+        challenges.define_challenge(daily("c_matches", "matches_played", StatAggregate::Count, 2)).unwrap();
+        challenges.define_challenge(daily("d_wins", "matches_won", StatAggregate::Count, 1)).unwrap();
+        assert!(challenges.define_challenge(daily("bad", "kills", StatAggregate::Sum, 0)).is_err());
+        
+        // Day 0 shows a, b, c; day 1 moves on to d, a, b
+        let ids = |now| challenges.active_challenges("arena", now).into_iter().map(|c| c.id).collect::<Vec<_>>();
+        assert_eq!(ids(0), vec!["a_kills", "b_best", "c_matches"]);
+        assert_eq!(ids(SECONDS_PER_DAY), vec!["d_wins", "a_kills", "b_best"]);
+        
+        let kills = |value| StatEvent { stat: "kills".to_string(), value };
+        assert!(challenges.record_event(&user_id, "arena", &kills(6), 10).is_empty());
+        assert_eq!(challenges.record_event(&user_id, "arena", &kills(5), 20), vec!["a_kills".to_string()]);
+        assert_eq!(challenges.record_event(&user_id, "arena", &kills(20), 30), vec!["b_best".to_string()]);
+        
+        let board = challenges.challenge_board(&user_id, "arena", 40);
+        assert_eq!(board[0].progress, vec![10]);
+        assert!(board[0].completed && board[1].completed && !board[2].completed);
+        assert_eq!(board[0].ends_at, SECONDS_PER_DAY);
+        
+        assert_eq!(auth.get_user(&user_id).unwrap().xp, 100);
+        assert_eq!(auth.inventory(&user_id).get("crate"), Some(&2));
+        
+        // Progress resets when the period rolls over
+        let tomorrow = SECONDS_PER_DAY + 10;
+        assert!(challenges.challenge_board(&user_id, "arena", tomorrow).iter().all(|s| !s.completed));
+        challenges.record_match("arena", &[MatchParticipant { user_id: user_id.clone(), score: 3, won: true }], tomorrow);
+        assert!(challenges.challenge_board(&user_id, "arena", tomorrow)[0].completed);
+    }
+    
+    #[test]
+    fn test_boards_hold_for_the_period_and_progress_is_per_game() {
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let user_id = auth.signup_test_user("player");
+        
+        let challenges = ChallengeService::new(auth.clone());
+        challenges.define_challenge(daily("kills", "kills", StatAggregate::Sum, 10)).unwrap();
+        challenges.define_challenge(ChallengeDefinition { game_id: "racer".to_string(), ..daily("kills", "kills", StatAggregate::Sum, 10) }).unwrap();
+        
+        // The same challenge ID in another game keeps its own progress
+        let kills = |value| StatEvent { stat: "kills".to_string(), value };
+        challenges.record_event(&user_id, "arena", &kills(6), 10);
+        assert_eq!(challenges.challenge_board(&user_id, "racer", 10)[0].progress, vec![0]);
+        
+        // A challenge added mid-day waits for tomorrow's board, and editing a live
+        // challenge leaves today's criteria in place
+        challenges.define_challenge(daily("assists", "assists", StatAggregate::Sum, 5)).unwrap();
+        let mut harder = daily("kills", "kills", StatAggregate::Sum, 10);
+        harder.criteria.push(ChallengeCriterion { stat: "headshots".to_string(), aggregate: StatAggregate::Sum, target: 3 });
+        challenges.define_challenge(harder).unwrap();
+        
+        let board = challenges.challenge_board(&user_id, "arena", 20);
+        assert_eq!(board.iter().map(|s| s.challenge.id.as_str()).collect::<Vec<_>>(), vec!["kills"]);
+        assert_eq!(board[0].progress, vec![6]);
+        assert_eq!(challenges.record_event(&user_id, "arena", &kills(4), 30), vec!["kills".to_string()]);
+        
+        let tomorrow = challenges.challenge_board(&user_id, "arena", SECONDS_PER_DAY);
+        assert_eq!(tomorrow.len(), 2);
+        assert_eq!(tomorrow[1].progress, vec![0, 0]);
+    }
+    
+    #[test]
+    fn test_weeks_start_on_monday() {
+        // 2024-01-01 was a Monday
+        let monday = 1_704_067_200;
+        let weekly = ChallengeCadence::Weekly;
+        assert_eq!(weekly.period(monday - 1) + 1, weekly.period(monday));
+        assert_eq!(weekly.period_ends_at(monday), monday + 7 * SECONDS_PER_DAY);
+    }
+}
@@ -0,0 +1,60 @@
+// clock.rs - Time source for the game server, swappable for a virtual clock in simulations
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    /// Unix time in seconds, for timestamps that are stored or sent to clients
+    fn timestamp(&self) -> i64;
+}
+
+/// The real clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+    
+    fn timestamp(&self) -> i64 {
+        chrono::Utc::now().timestamp()
+    }
+}
+
+/// A clock that only moves when `advance` is called, so tests can cover
+/// timeouts and expiry deterministically without sleeping
+pub struct ManualClock {
+    origin: Instant,
+    origin_timestamp: i64,
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    pub fn new(start_timestamp: i64) -> Self {
+        Self {
+            origin: Instant::now(),
+            origin_timestamp: start_timestamp,
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+    
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().unwrap() += by;
+    }
+    
+    /// Virtual time since the clock was created
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.origin + self.elapsed()
+    }
+    
+    fn timestamp(&self) -> i64 {
+        self.origin_timestamp + self.elapsed().as_secs() as i64
+    }
+}
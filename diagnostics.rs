@@ -0,0 +1,213 @@
+// diagnostics.rs - Client diagnostic bundles uploaded after abnormal disconnects, kept for support triage
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use crate::transport::decode_json;
+
+/// Largest upload accepted, before parsing
+pub const MAX_DIAGNOSTIC_BUNDLE_BYTES: usize = 64 * 1024;
+pub const MAX_DIAGNOSTIC_MESSAGES: usize = 50;
+pub const MAX_RTT_SAMPLES: usize = 120;
+pub const MAX_LOG_TAIL_BYTES: usize = 16 * 1024;
+/// How long after a disconnect the client may still upload its bundle
+pub const DIAGNOSTIC_UPLOAD_WINDOW_SECONDS: i64 = 24 * 60 * 60;
+/// Reports are deleted this long after upload
+pub const DIAGNOSTIC_RETENTION_SECONDS: i64 = 14 * 24 * 60 * 60;
+/// Oldest reports are dropped past this many
+pub const MAX_STORED_DIAGNOSTICS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisconnectReason {
+    HeartbeatTimeout,
+}
+
+/// What the client sends after reconnecting
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticBundle {
+    /// Last protocol messages the client sent or received, oldest first
+    #[serde(default)]
+    pub recent_messages: Vec<String>,
+    #[serde(default)]
+    pub rtt_history_ms: Vec<u32>,
+    #[serde(default)]
+    pub log_tail: String,
+    #[serde(default)]
+    pub client_version: String,
+}
+
+impl DiagnosticBundle {
+    fn validate(&self) -> Result<(), String> {
+        if self.recent_messages.len() > MAX_DIAGNOSTIC_MESSAGES {
+            return Err(format!("At most {} messages per bundle", MAX_DIAGNOSTIC_MESSAGES));
+        }
+        if self.rtt_history_ms.len() > MAX_RTT_SAMPLES {
+            return Err(format!("At most {} RTT samples per bundle", MAX_RTT_SAMPLES));
+        }
+        if self.log_tail.len() > MAX_LOG_TAIL_BYTES {
+            return Err(format!("Log tail is limited to {} bytes", MAX_LOG_TAIL_BYTES));
+        }
+        Ok(())
+    }
+}
+
+/// A disconnect the server saw as abnormal; the player may attach one bundle to it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisconnectSession {
+    pub session_id: String,
+    pub player_id: String,
+    pub room_id: Option<String>,
+    pub reason: DisconnectReason,
+    pub disconnected_at: i64,
+    pub report_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticReport {
+    pub report_id: String,
+    pub session: DisconnectSession,
+    pub uploaded_at: i64,
+    pub bundle: DiagnosticBundle,
+}
+
+/// Records abnormal disconnects and accepts one diagnostic bundle for each, so
+/// support can see what the client saw next to what the server saw
+pub struct DiagnosticsService {
+    sessions: Mutex<HashMap<String, DisconnectSession>>, // session_id -> disconnect
+    reports: Mutex<VecDeque<DiagnosticReport>>, // oldest first
+}
+
+impl DiagnosticsService {
+    pub fn new() -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            reports: Mutex::new(VecDeque::new()),
+        }
+    }
+    
+    /// Note an abnormal disconnect. Returns the session ID uploads are linked to.
+    pub fn record_disconnect(&self, player_id: &str, room_id: Option<String>, reason: DisconnectReason, now: i64) -> String {
+        let session_id = format!("session_{}", uuid::Uuid::new_v4());
+        
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, s| now - s.disconnected_at < DIAGNOSTIC_UPLOAD_WINDOW_SECONDS);
+        sessions.insert(session_id.clone(), DisconnectSession {
+            session_id: session_id.clone(),
+            player_id: player_id.to_string(),
+            room_id,
+            reason,
+            disconnected_at: now,
+            report_id: None,
+        });
+        
+        session_id
+    }
+    
+    /// Upload endpoint: attach an encoded bundle from an authenticated player to
+    /// their latest abnormal disconnect that has no bundle yet. Returns the report ID.
+    pub fn upload(&self, player_id: &str, body: &[u8], now: i64) -> Result<String, String> {
+        let bundle: DiagnosticBundle = decode_json(body, MAX_DIAGNOSTIC_BUNDLE_BYTES).map_err(|e| e.to_string())?;
+        bundle.validate()?;
+        
+        let session = {
+            let mut sessions = self.sessions.lock().unwrap();
+            let session = sessions.values_mut()
+                .filter(|s| s.player_id == player_id && s.report_id.is_none())
+                .filter(|s| now - s.disconnected_at < DIAGNOSTIC_UPLOAD_WINDOW_SECONDS)
+                .max_by_key(|s| s.disconnected_at)
+                .ok_or_else(|| "No recent disconnect to attach diagnostics to".to_string())?;
+            
+            let report_id = format!("diag_{}", uuid::Uuid::new_v4());
+            session.report_id = Some(report_id);
+            session.clone()
+        };
+        
+        let report_id = session.report_id.clone().unwrap_or_default();
+        println!("[Diagnostics] {} uploaded {} for {:?} in {}", player_id, report_id, session.reason, session.session_id);
+        
+        let mut reports = self.reports.lock().unwrap();
+        reports.push_back(DiagnosticReport {
+            report_id: report_id.clone(),
+            session,
+            uploaded_at: now,
+            bundle,
+        });
+        while reports.len() > MAX_STORED_DIAGNOSTICS {
+            reports.pop_front();
+        }
+        
+        Ok(report_id)
+    }
+    
+    pub fn report(&self, report_id: &str) -> Option<DiagnosticReport> {
+        self.reports.lock().unwrap().iter().find(|r| r.report_id == report_id).cloned()
+    }
+    
+    /// A player's reports, newest first, for support triage
+    pub fn reports_for_player(&self, player_id: &str) -> Vec<DiagnosticReport> {
+        self.reports.lock().unwrap()
+            .iter()
+            .rev()
+            .filter(|r| r.session.player_id == player_id)
+            .cloned()
+            .collect()
+    }
+    
+    /// Delete reports past retention. Returns how many were removed.
+    pub fn purge_expired(&self, now: i64) -> usize {
+        self.sessions.lock().unwrap().retain(|_, s| now - s.disconnected_at < DIAGNOSTIC_UPLOAD_WINDOW_SECONDS);
+        
+        let mut reports = self.reports.lock().unwrap();
+        let before = reports.len();
+        reports.retain(|r| now - r.uploaded_at < DIAGNOSTIC_RETENTION_SECONDS);
+        before - reports.len()
+    }
+}
+
+impl Default for DiagnosticsService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ============================================
+// Tests
+// ============================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_one_bundle_per_disconnect_with_retention() {
+        let diagnostics = DiagnosticsService::new();
+        let body = |bundle: &DiagnosticBundle| serde_json::to_vec(bundle).unwrap();
+        let bundle = DiagnosticBundle {
+            recent_messages: vec!["{\"type\":\"Heartbeat\"}".to_string()],
+            rtt_history_ms: vec![40, 45, 900],
+            log_tail: "socket reset".to_string(),
+            client_version: "1.2.0".to_string(),
+        };
+        
+        // Nothing to attach to until the server saw the player drop
+        assert!(diagnostics.upload("p1", &body(&bundle), 0).is_err());
+        
+        let session_id = diagnostics.record_disconnect("p1", Some("room_1".to_string()), DisconnectReason::HeartbeatTimeout, 100);
+        let oversized = DiagnosticBundle { rtt_history_ms: vec![0; MAX_RTT_SAMPLES + 1], ..bundle.clone() };
+        assert!(diagnostics.upload("p1", &body(&oversized), 110).is_err());
+        assert!(diagnostics.upload("p1", b"not json", 110).is_err());
+        
+        let report_id = diagnostics.upload("p1", &body(&bundle), 120).unwrap();
+        assert!(diagnostics.upload("p1", &body(&bundle), 130).is_err());
+        assert!(diagnostics.upload("p2", &body(&bundle), 130).is_err());
+        
+        let report = diagnostics.report(&report_id).unwrap();
+        assert_eq!(report.session.session_id, session_id);
+        assert_eq!(report.session.room_id.as_deref(), Some("room_1"));
+        assert_eq!(diagnostics.reports_for_player("p1").len(), 1);
+        
+        assert_eq!(diagnostics.purge_expired(120 + DIAGNOSTIC_RETENTION_SECONDS - 1), 0);
+        assert_eq!(diagnostics.purge_expired(120 + DIAGNOSTIC_RETENTION_SECONDS), 1);
+        assert!(diagnostics.report(&report_id).is_none());
+    }
+}
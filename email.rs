@@ -0,0 +1,373 @@
+// email.rs - Outbound email: senders, localized templates and a retrying send queue
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+// ============================================
+// Senders
+// ============================================
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers a single email. Implementations may block; the queue runs them off the async runtime.
+pub trait EmailSender: Send + Sync {
+    fn send(&self, email: &Email) -> Result<(), String>;
+}
+
+/// Prints emails instead of sending them; for development and tests
+pub struct LogEmailSender;
+
+impl EmailSender for LogEmailSender {
+    fn send(&self, email: &Email) -> Result<(), String> {
+        println!("[Email] To: {} | Subject: {}\n{}", email.to, email.subject, email.body);
+        Ok(())
+    }
+}
+
+/// Sends through an SMTP relay over TLS
+pub struct SmtpEmailSender {
+    transport: SmtpTransport,
+    from: Mailbox,
+}
+
+impl SmtpEmailSender {
+    pub fn new(host: &str, username: &str, password: &str, from: &str) -> Result<Self, String> {
+        let transport = SmtpTransport::relay(host)
+            .map_err(|e| e.to_string())?
+            .credentials(Credentials::new(username.to_string(), password.to_string()))
+            .build();
+        
+        let from = from.parse().map_err(|e: lettre::address::AddressError| e.to_string())?;
+        
+        Ok(Self { transport, from })
+    }
+}
+
+impl EmailSender for SmtpEmailSender {
+    fn send(&self, email: &Email) -> Result<(), String> {
+        let to: Mailbox = email.to.parse().map_err(|e: lettre::address::AddressError| e.to_string())?;
+        
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(email.subject.clone())
+            .body(email.body.clone())
+            .map_err(|e| e.to_string())?;
+        
+        self.transport.send(&message).map(|_| ()).map_err(|e| e.to_string())
+    }
+}
+
+// ============================================
+// Templates
+// ============================================
+
+/// Subject and body with `{{name}}` placeholders
+#[derive(Debug, Clone)]
+pub struct EmailTemplate {
+    pub subject: String,
+    pub body: String,
+}
+
+pub struct EmailTemplates {
+    templates: HashMap<(String, String), EmailTemplate>, // (name, locale) -> template
+    default_locale: String,
+}
+
+impl EmailTemplates {
+    pub fn new(default_locale: &str) -> Self {
+        Self {
+            templates: HashMap::new(),
+            default_locale: default_locale.to_string(),
+        }
+    }
+    
+    /// English templates for the platform's built-in emails
+    pub fn with_defaults() -> Self {
+        let mut templates = Self::new("en");
+        
+        templates.register("verify_email", "en", EmailTemplate {
+            subject: "Verify your VR Game Platform account".to_string(),
+            body: "Hi {{username}},\n\nConfirm your email address with this code: {{code}}\n".to_string(),
+        });
+        templates.register("password_reset", "en", EmailTemplate {
+            subject: "Reset your password".to_string(),
+            body: "Hi {{username}},\n\nUse this code to reset your password: {{code}}\nIf you didn't ask for this, ignore this email.\n".to_string(),
+        });
+        templates.register("account_banned", "en", EmailTemplate {
+            subject: "Your account has been banned".to_string(),
+            body: "Hi {{username}},\n\nYour account was banned for violating the community guidelines.\n".to_string(),
+        });
+        
+        templates
+    }
+    
+    pub fn register(&mut self, name: &str, locale: &str, template: EmailTemplate) {
+        self.templates.insert((name.to_string(), locale.to_string()), template);
+    }
+    
+    /// Render a template, falling back from "pt-BR" to "pt" to the default locale
+    pub fn render(&self, name: &str, locale: &str, vars: &HashMap<&str, String>) -> Result<(String, String), String> {
+        let language = locale.split('-').next().unwrap_or(locale);
+        
+        let template = [locale, language, self.default_locale.as_str()].iter()
+            .find_map(|l| self.templates.get(&(name.to_string(), l.to_string())))
+            .ok_or_else(|| format!("No email template '{}'", name))?;
+        
+        Ok((fill_placeholders(&template.subject, vars), fill_placeholders(&template.body, vars)))
+    }
+}
+
+/// Replace each `{{name}}` in one left-to-right pass. Substituted values are never
+/// scanned again, so a username like "{{code}}" stays as typed; unknown names are kept.
+fn fill_placeholders(text: &str, vars: &HashMap<&str, String>) -> String {
+    let mut filled = String::with_capacity(text.len());
+    let mut rest = text;
+    
+    while let Some(start) = rest.find("{{") {
+        filled.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        
+        match after.find("}}").and_then(|end| Some((vars.get(&after[..end])?, end))) {
+            Some((value, end)) => {
+                filled.push_str(value);
+                rest = &after[end + 2..];
+            }
+            None => {
+                filled.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    
+    filled.push_str(rest);
+    filled
+}
+
+// ============================================
+// Send Queue
+// ============================================
+
+/// Longest wait between two attempts, however many have failed
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubles after each failed attempt up to `MAX_RETRY_DELAY`
+    pub base_delay: Duration,
+}
+
+impl RetryConfig {
+    /// Wait after the `attempt`th failed attempt
+    fn delay_after(&self, attempt: u32) -> Duration {
+        self.base_delay.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1))).min(MAX_RETRY_DELAY)
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EmailQueueStats {
+    pub sent: u64,
+    pub failed: u64,
+}
+
+/// Background queue that delivers emails with retries so callers never wait on SMTP
+pub struct EmailQueue {
+    tx: mpsc::UnboundedSender<Email>,
+    stats: Arc<Mutex<EmailQueueStats>>,
+    worker: JoinHandle<()>,
+}
+
+impl EmailQueue {
+    /// Start the delivery worker on the current tokio runtime
+    pub fn start(sender: Arc<dyn EmailSender>, retry: RetryConfig) -> Self {
+        let (tx, mut rx) = mpsc::unbounded_channel::<Email>();
+        let stats = Arc::new(Mutex::new(EmailQueueStats::default()));
+        let worker_stats = stats.clone();
+        
+        let worker = tokio::spawn(async move {
+            while let Some(email) = rx.recv().await {
+                let delivered = deliver_with_retries(sender.clone(), email, &retry).await;
+                
+                let mut stats = worker_stats.lock().unwrap();
+                if delivered {
+                    stats.sent += 1;
+                } else {
+                    stats.failed += 1;
+                }
+            }
+        });
+        
+        Self { tx, stats, worker }
+    }
+    
+    pub fn enqueue(&self, email: Email) -> Result<(), String> {
+        self.tx.send(email).map_err(|_| "Email queue is shut down".to_string())
+    }
+    
+    pub fn stats(&self) -> EmailQueueStats {
+        self.stats.lock().unwrap().clone()
+    }
+    
+    /// Stop accepting emails and wait until everything queued has been attempted
+    pub async fn shutdown(self) {
+        drop(self.tx);
+        let _ = self.worker.await;
+    }
+}
+
+async fn deliver_with_retries(sender: Arc<dyn EmailSender>, email: Email, retry: &RetryConfig) -> bool {
+    for attempt in 1..=retry.max_attempts {
+        let sender = sender.clone();
+        let outgoing = email.clone();
+        
+        let result = tokio::task::spawn_blocking(move || sender.send(&outgoing))
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+        
+        match result {
+            Ok(()) => return true,
+            Err(e) => {
+                eprintln!("[Email] Attempt {} to {} failed: {}", attempt, email.to, e);
+                
+                if attempt < retry.max_attempts {
+                    tokio::time::sleep(retry.delay_after(attempt)).await;
+                }
+            }
+        }
+    }
+    
+    eprintln!("[Email] Giving up on email to {}", email.to);
+    false
+}
+
+/// Renders templates and hands the result to the send queue
+pub struct Mailer {
+    templates: EmailTemplates,
+    queue: EmailQueue,
+}
+
+impl Mailer {
+    pub fn new(templates: EmailTemplates, queue: EmailQueue) -> Self {
+        Self { templates, queue }
+    }
+    
+    pub fn send_template(&self, to: &str, name: &str, locale: &str, vars: &HashMap<&str, String>) -> Result<(), String> {
+        let (subject, body) = self.templates.render(name, locale, vars)?;
+        
+        self.queue.enqueue(Email {
+            to: to.to_string(),
+            subject,
+            body,
+        })
+    }
+    
+    pub fn stats(&self) -> EmailQueueStats {
+        self.queue.stats()
+    }
+    
+    pub async fn shutdown(self) {
+        self.queue.shutdown().await;
+    }
+}
+
+// ============================================
+// Tests
+// ============================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    struct FlakySender {
+        failures_left: Mutex<u32>,
+        delivered: Mutex<Vec<Email>>,
+    }
+    
+    impl EmailSender for FlakySender {
+        fn send(&self, email: &Email) -> Result<(), String> {
+            let mut failures_left = self.failures_left.lock().unwrap();
+            if *failures_left > 0 {
+                *failures_left -= 1;
+                return Err("connection reset".to_string());
+            }
+            
+            self.delivered.lock().unwrap().push(email.clone());
+            Ok(())
+        }
+    }
+    
+    #[test]
+    fn test_template_locale_fallback() {
+        let mut templates = EmailTemplates::with_defaults();
+        templates.register("password_reset", "de", EmailTemplate {
+            subject: "Passwort zurücksetzen".to_string(),
+            body: "Hallo {{username}}".to_string(),
+        });
+        
+        let vars = HashMap::from([("username", "Ada".to_string())]);
+        
+        let (subject, body) = templates.render("password_reset", "de-AT", &vars).unwrap();
+        assert_eq!(subject, "Passwort zurücksetzen");
+        assert_eq!(body, "Hallo Ada");
+        
+        let (subject, _) = templates.render("password_reset", "fr", &vars).unwrap();
+        assert_eq!(subject, "Reset your password");
+        
+        assert!(templates.render("missing", "en", &vars).is_err());
+        
+        // Values are inserted as-is, even when they look like placeholders
+        let vars = HashMap::from([("username", "{{code}}".to_string()), ("code", "123456".to_string())]);
+        let (_, body) = templates.render("verify_email", "en", &vars).unwrap();
+        assert!(body.starts_with("Hi {{code}},"));
+        assert!(body.contains("this code: 123456"));
+        assert_eq!(fill_placeholders("{{unknown}} {{", &vars), "{{unknown}} {{");
+    }
+    
+    #[tokio::test]
+    async fn test_queue_retries_failed_sends() {
+        let sender = Arc::new(FlakySender {
+            failures_left: Mutex::new(2),
+            delivered: Mutex::new(Vec::new()),
+        });
+        let retry = RetryConfig { max_attempts: 3, base_delay: Duration::from_millis(1) };
+        
+        let queue = EmailQueue::start(sender.clone(), retry);
+        queue.enqueue(Email {
+            to: "player@example.com".to_string(),
+            subject: "Hi".to_string(),
+            body: "Welcome".to_string(),
+        }).unwrap();
+        
+        let stats = queue.stats.clone();
+        queue.shutdown().await;
+        
+        assert_eq!(sender.delivered.lock().unwrap().len(), 1);
+        assert_eq!(stats.lock().unwrap().sent, 1);
+        
+        // However many attempts are allowed, the wait between them stays bounded
+        let retry = RetryConfig { max_attempts: u32::MAX, base_delay: Duration::from_secs(2) };
+        assert_eq!(retry.delay_after(2), Duration::from_secs(4));
+        assert_eq!(retry.delay_after(64), MAX_RETRY_DELAY);
+        assert_eq!(retry.delay_after(u32::MAX), MAX_RETRY_DELAY);
+    }
+}
@@ -0,0 +1,11 @@
+// Feeds arbitrary request bodies through the signup and login decoders.
+// Run with: cargo +nightly fuzz run decode_auth_request
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use vr_game_platform::auth_server::{LoginRequest, SignupRequest};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = SignupRequest::decode(data);
+    let _ = LoginRequest::decode(data);
+});
@@ -0,0 +1,14 @@
+// Feeds arbitrary client bytes through the game message decoder.
+// Run with: cargo +nightly fuzz run decode_game_message
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use vr_game_platform::transport::{decode_message, encode_message};
+
+fuzz_target!(|data: &[u8]| {
+    // Anything the decoder accepts must survive a round trip
+    if let Ok(message) = decode_message(data) {
+        let encoded = encode_message(&message).expect("decoded message failed to encode");
+        decode_message(&encoded).expect("re-encoded message failed to decode");
+    }
+});
@@ -0,0 +1,121 @@
+// geoip.rs - Pluggable IP geolocation for region auto-detection
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+// ============================================
+// Lookup Interface
+// ============================================
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeoLocation {
+    pub region: String, // e.g. "us-east", "eu-west"
+}
+
+/// Resolves a client address to a location; plug in MaxMind, IP2Location, etc.
+pub trait GeoIpLookup: Send + Sync {
+    fn lookup(&self, ip: IpAddr) -> Option<GeoLocation>;
+}
+
+// ============================================
+// Static Prefix Table
+// ============================================
+
+/// Lookup backed by a fixed table of network prefixes; the longest matching prefix wins.
+/// Private, loopback and link-local addresses never resolve.
+pub struct PrefixGeoIpLookup {
+    entries: Vec<(IpAddr, u8, GeoLocation)>, // (network, prefix length, location)
+}
+
+impl PrefixGeoIpLookup {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+    
+    /// Map a network (e.g. 203.0.113.0/24) to a location
+    pub fn add_prefix(&mut self, network: IpAddr, prefix_len: u8, location: GeoLocation) {
+        self.entries.push((network, prefix_len, location));
+    }
+}
+
+impl Default for PrefixGeoIpLookup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GeoIpLookup for PrefixGeoIpLookup {
+    fn lookup(&self, ip: IpAddr) -> Option<GeoLocation> {
+        // Dual-stack sockets report IPv4 peers as ::ffff:a.b.c.d
+        let ip = ip.to_canonical();
+        if is_local(ip) {
+            return None;
+        }
+        
+        self.entries.iter()
+            .filter(|(network, prefix_len, _)| prefix_matches(*network, *prefix_len, ip))
+            .max_by_key(|(_, prefix_len, _)| *prefix_len)
+            .map(|(_, _, location)| location.clone())
+    }
+}
+
+fn is_local(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => v4.is_private() || v4.is_loopback() || v4.is_link_local() || v4.is_unspecified(),
+        IpAddr::V6(v6) => {
+            let unique_local = v6.segments()[0] & 0xfe00 == 0xfc00;
+            let link_local = v6.segments()[0] & 0xffc0 == 0xfe80;
+            v6.is_loopback() || v6.is_unspecified() || unique_local || link_local
+        }
+    }
+}
+
+fn prefix_matches(network: IpAddr, prefix_len: u8, ip: IpAddr) -> bool {
+    let (network, ip, bits) = match (network, ip) {
+        (IpAddr::V4(n), IpAddr::V4(i)) => (u32::from(n) as u128, u32::from(i) as u128, 32u32),
+        (IpAddr::V6(n), IpAddr::V6(i)) => (u128::from(n), u128::from(i), 128u32),
+        _ => return false,
+    };
+    
+    let prefix_len = (prefix_len as u32).min(bits);
+    if prefix_len == 0 {
+        return true;
+    }
+    
+    let shift = bits - prefix_len;
+    (network >> shift) == (ip >> shift)
+}
+
+// ============================================
+// Tests
+// ============================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_longest_prefix_wins() {
+        let mut geoip = PrefixGeoIpLookup::new();
+        let na = GeoLocation { region: "us-east".to_string() };
+        let eu = GeoLocation { region: "eu-west".to_string() };
+        
+        geoip.add_prefix("198.51.0.0".parse().unwrap(), 16, na.clone());
+        geoip.add_prefix("198.51.100.0".parse().unwrap(), 24, eu.clone());
+        
+        assert_eq!(geoip.lookup("198.51.7.1".parse().unwrap()), Some(na.clone()));
+        assert_eq!(geoip.lookup("198.51.100.9".parse().unwrap()), Some(eu.clone()));
+        assert_eq!(geoip.lookup("192.168.1.10".parse().unwrap()), None);
+        
+        // IPv4-mapped IPv6 peers are matched as IPv4
+        assert_eq!(geoip.lookup("::ffff:198.51.100.9".parse().unwrap()), Some(eu));
+        assert_eq!(geoip.lookup("::ffff:192.168.1.10".parse().unwrap()), None);
+        
+        // Unique-local and link-local IPv6 never match, even under a catch-all prefix
+        geoip.add_prefix("::".parse().unwrap(), 0, na.clone());
+        assert_eq!(geoip.lookup("2001:db8::1".parse().unwrap()), Some(na));
+        assert_eq!(geoip.lookup("fd12:3456::1".parse().unwrap()), None);
+        assert_eq!(geoip.lookup("fe80::1".parse().unwrap()), None);
+    }
+}
@@ -0,0 +1,101 @@
+// idempotency.rs - Short-lived dedup cache so retried requests don't run twice
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// How long a finished request's result is replayed for a repeated key
+pub const IDEMPOTENCY_KEY_TTL_SECONDS: i64 = 10 * 60;
+
+enum IdempotencyEntry<T> {
+    InProgress { started_at: i64 },
+    Done { finished_at: i64, result: T },
+}
+
+/// Remembers the result of each successful request by its client-chosen key. A retry
+/// with the same key gets the stored result instead of running the operation again.
+/// Failed requests are forgotten so the client can retry them with the same key.
+pub struct IdempotencyCache<T> {
+    entries: Mutex<HashMap<String, IdempotencyEntry<T>>>,
+    ttl_seconds: i64,
+}
+
+impl<T: Clone> IdempotencyCache<T> {
+    pub fn new(ttl_seconds: i64) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            ttl_seconds,
+        }
+    }
+    
+    /// Run `operation` once per key. Keys should already be scoped to the operation
+    /// and caller, e.g. "create_room:{host_id}:{key}".
+    pub fn execute<F>(&self, key: &str, now: i64, operation: F) -> Result<T, String>
+    where
+        F: FnOnce() -> Result<T, String>,
+    {
+        {
+            let mut entries = self.entries.lock().unwrap();
+            let ttl = self.ttl_seconds;
+            entries.retain(|_, entry| match entry {
+                IdempotencyEntry::InProgress { started_at } => now - *started_at < ttl,
+                IdempotencyEntry::Done { finished_at, .. } => now - *finished_at < ttl,
+            });
+            
+            match entries.get(key) {
+                Some(IdempotencyEntry::Done { result, .. }) => {
+                    println!("[Idempotency] Replaying result for {}", key);
+                    return Ok(result.clone());
+                }
+                Some(IdempotencyEntry::InProgress { .. }) => {
+                    return Err("A request with this idempotency key is still in progress".to_string());
+                }
+                None => {
+                    entries.insert(key.to_string(), IdempotencyEntry::InProgress { started_at: now });
+                }
+            }
+        }
+        
+        // The operation runs without the cache lock so unrelated keys aren't serialized
+        let outcome = operation();
+        
+        let mut entries = self.entries.lock().unwrap();
+        match &outcome {
+            Ok(result) => {
+                entries.insert(key.to_string(), IdempotencyEntry::Done { finished_at: now, result: result.clone() });
+            }
+            Err(_) => {
+                entries.remove(key);
+            }
+        }
+        
+        outcome
+    }
+}
+
+impl<T: Clone> Default for IdempotencyCache<T> {
+    fn default() -> Self {
+        Self::new(IDEMPOTENCY_KEY_TTL_SECONDS)
+    }
+}
+
+// ============================================
+// Tests
+// ============================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_repeated_key_replays_until_expiry() {
+        let cache = IdempotencyCache::new(60);
+        let mut runs = 0;
+        
+        assert_eq!(cache.execute("k", 0, || { runs += 1; Ok(runs) }), Ok(1));
+        assert_eq!(cache.execute("k", 30, || { runs += 1; Ok(runs) }), Ok(1));
+        assert_eq!(cache.execute("k", 60, || { runs += 1; Ok(runs) }), Ok(2));
+        
+        // Failures are not remembered
+        assert!(cache.execute("f", 0, || Err::<i32, _>("flaky".to_string())).is_err());
+        assert_eq!(cache.execute("f", 1, || Ok(7)), Ok(7));
+    }
+}
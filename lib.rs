@@ -0,0 +1,17 @@
+// lib.rs - Library target exposing the platform modules to the server binaries and fuzz targets
+pub mod analytics;
+pub mod auth_server;
+pub mod billing;
+pub mod cache;
+pub mod challenges;
+pub mod clock;
+pub mod diagnostics;
+pub mod email;
+pub mod geoip;
+pub mod idempotency;
+pub mod multiplayer_server;
+pub mod persistence;
+pub mod saga;
+pub mod seasons;
+pub mod sim;
+pub mod transport;
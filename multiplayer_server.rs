@@ -1,21 +1,37 @@
 // multiplayer_server.rs - VR Multiplayer Game Server
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::analytics::{AnalyticsService, GameDashboard};
+use crate::auth_server::{AuthService, ContentRating, Environment, MatchParticipant, OnboardingState, PrivacyAction, TokenAudience, UserProfile};
+use crate::billing::UsageService;
+use crate::cache::{CacheStats, TtlCache};
+use crate::challenges::ChallengeService;
+use crate::clock::{Clock, SystemClock};
+use crate::diagnostics::{DiagnosticsService, DisconnectReason};
+use crate::geoip::GeoIpLookup;
+use crate::idempotency::IdempotencyCache;
+use crate::persistence::{PendingWrite, WriteBehindQueue, WriteKind};
+use crate::saga::Saga;
+use crate::seasons::SeasonService;
+use crate::transport::{ChaosConfig, ChaosLink, DeliveryMode};
 
 // ============================================
 // Network Protocol Data Structures
 // ============================================
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Vector3 {
     pub x: f32,
     pub y: f32,
     pub z: f32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Quaternion {
     pub x: f32,
     pub y: f32,
@@ -23,7 +39,7 @@ pub struct Quaternion {
     pub w: f32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PlayerTransform {
     pub position: Vector3,
     pub rotation: Quaternion,
@@ -35,32 +51,123 @@ pub struct PlayerTransform {
     pub right_hand_rotation: Quaternion,
 }
 
+impl PlayerTransform {
+    /// Standing at the origin with default head and hand offsets
+    pub fn identity() -> Self {
+        let identity = Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 };
+        
+        Self {
+            position: Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+            rotation: identity.clone(),
+            head_position: Vector3 { x: 0.0, y: 1.7, z: 0.0 },
+            head_rotation: identity.clone(),
+            left_hand_position: Vector3 { x: -0.3, y: 1.2, z: 0.3 },
+            left_hand_rotation: identity.clone(),
+            right_hand_position: Vector3 { x: 0.3, y: 1.2, z: 0.3 },
+            right_hand_rotation: identity,
+        }
+    }
+    
+    /// Reject transforms with NaN or infinite components or degenerate rotations
+    /// before they are stored or relayed to anyone
+    pub fn validate(&self) -> Result<(), String> {
+        let positions = [
+            ("position", &self.position),
+            ("head_position", &self.head_position),
+            ("left_hand_position", &self.left_hand_position),
+            ("right_hand_position", &self.right_hand_position),
+        ];
+        for (name, position) in positions {
+            position.validate().map_err(|e| format!("{}: {}", name, e))?;
+        }
+        
+        let rotations = [
+            ("rotation", &self.rotation),
+            ("head_rotation", &self.head_rotation),
+            ("left_hand_rotation", &self.left_hand_rotation),
+            ("right_hand_rotation", &self.right_hand_rotation),
+        ];
+        for (name, rotation) in rotations {
+            rotation.validate().map_err(|e| format!("{}: {}", name, e))?;
+        }
+        Ok(())
+    }
+    
+    /// Validate, then renormalize every rotation so drift from client math doesn't accumulate
+    pub fn sanitized(mut self) -> Result<Self, String> {
+        self.validate()?;
+        for rotation in [&mut self.rotation, &mut self.head_rotation, &mut self.left_hand_rotation, &mut self.right_hand_rotation] {
+            *rotation = rotation.normalized();
+        }
+        Ok(self)
+    }
+    
+    /// Blend towards `other`: positions linearly, rotations along the shortest arc.
+    /// `t` past 1.0 extrapolates.
+    pub fn interpolate(&self, other: &PlayerTransform, t: f32) -> PlayerTransform {
+        PlayerTransform {
+            position: self.position.lerp(&other.position, t),
+            rotation: self.rotation.slerp(&other.rotation, t),
+            head_position: self.head_position.lerp(&other.head_position, t),
+            head_rotation: self.head_rotation.slerp(&other.head_rotation, t),
+            left_hand_position: self.left_hand_position.lerp(&other.left_hand_position, t),
+            left_hand_rotation: self.left_hand_rotation.slerp(&other.left_hand_rotation, t),
+            right_hand_position: self.right_hand_position.lerp(&other.right_hand_position, t),
+            right_hand_rotation: self.right_hand_rotation.slerp(&other.right_hand_rotation, t),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlayerState {
     pub player_id: String,
     pub username: String,
+    /// Name shown above the avatar
+    #[serde(default)]
+    pub display_name: String,
     pub transform: PlayerTransform,
     pub avatar_url: Option<String>,
     pub is_talking: bool,
     pub custom_data: HashMap<String, String>,
+    #[serde(default)]
+    pub is_npc: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum GameMessage {
     // Connection
     Connect {
         token: String,
         game_id: String,
+        /// Overrides the region detected from the client's address
+        #[serde(default)]
+        region: Option<String>,
+    },
+    JoinWithTicket {
+        ticket: String,
     },
     Disconnect {
         player_id: String,
     },
+    /// Sent by clients every few seconds; connections silent for `HEARTBEAT_TIMEOUT` are dropped
+    Heartbeat,
+    /// Sent after `Connect` and whenever a tutorial step is completed
+    OnboardingStatus {
+        game_id: String,
+        completed_steps: Vec<String>,
+        remaining_steps: Vec<String>,
+    },
     
     // Player updates
     PlayerJoined {
         player: PlayerState,
     },
+    PlayerProfileUpdated {
+        player_id: String,
+        display_name: String,
+        avatar_url: Option<String>,
+    },
     PlayerLeft {
         player_id: String,
     },
@@ -68,40 +175,250 @@ pub enum GameMessage {
         player_id: String,
         transform: PlayerTransform,
     },
+    /// Sent to the host when crowding past the soft cap changes how the room is synced
+    RoomLoadChanged {
+        room_id: String,
+        level: DegradationLevel,
+        player_count: usize,
+        soft_cap: Option<usize>,
+        max_players: usize,
+    },
+    /// Countdown of a time-limited room; sent at its warning points and at the end
+    RoomTimeRemaining {
+        room_id: String,
+        seconds_remaining: i64,
+        joins_locked: bool,
+    },
+    /// Sent to the host periodically: every player's connection quality
+    RoomNetworkReport {
+        room_id: String,
+        players: Vec<PlayerNetworkStats>,
+    },
     
     // Game state
     ObjectSpawned {
-        object_id: String,
+        /// Assigned by the server; clients send 0 with a `temp_id`
+        object_id: ObjectId,
+        #[serde(default)]
+        temp_id: Option<String>,
         object_type: String,
         position: Vector3,
         rotation: Quaternion,
     },
     ObjectMoved {
-        object_id: String,
+        object_id: ObjectId,
         position: Vector3,
         rotation: Quaternion,
     },
     ObjectDestroyed {
-        object_id: String,
+        object_id: ObjectId,
+    },
+    /// Spawn several objects at once; all succeed or none do
+    ObjectsSpawnedBatch {
+        objects: Vec<SpawnedObject>,
+    },
+    ObjectsDestroyedBatch {
+        object_ids: Vec<ObjectId>,
     },
     ObjectGrabbed {
-        object_id: String,
+        object_id: ObjectId,
         player_id: String,
     },
     ObjectReleased {
-        object_id: String,
+        object_id: ObjectId,
+    },
+    /// Object came to rest; it stops sending moves and gives up its simulation owner
+    ObjectSleep {
+        object_id: ObjectId,
+        position: Vector3,
+        rotation: Quaternion,
+    },
+    ObjectWake {
+        object_id: ObjectId,
+        player_id: String,
+    },
+    /// Sent to the spawner: maps its temporary IDs to server-assigned ones
+    ObjectIdsAssigned {
+        assignments: Vec<ObjectIdAssignment>,
+    },
+    
+    // Shared room state
+    SetGameState {
+        key: String,
+        value: String,
+        /// Reject the write unless the key is still at this version (0 = must not exist)
+        expected_version: Option<u64>,
+    },
+    GameStateChanged {
+        key: String,
+        value: String,
+        version: u64,
+    },
+    GameStateOperation {
+        key: String,
+        operation: StateOperation,
+    },
+    SubscribeGameState {
+        /// Exact keys, or prefixes ending in `*`
+        keys: Vec<String>,
+    },
+    UnsubscribeGameState {
+        keys: Vec<String>,
+    },
+    
+    // Turn-based play
+    EndTurn,
+    TurnChanged {
+        player_id: String,
+        turn_number: u64,
+        /// Unix timestamp when the turn auto-advances, if turns are timed
+        deadline: Option<i64>,
+    },
+    
+    // Match results
+    SubmitMatchResult {
+        result: MatchResult,
+        /// Lets a retried submission be recognized instead of counted again
+        #[serde(default)]
+        idempotency_key: Option<String>,
+    },
+    MatchFinalized {
+        result: MatchResult,
+    },
+    
+    // Social
+    RoomInvite {
+        from_player_id: String,
+        room_id: String,
+    },
+    DirectMessage {
+        from_player_id: String,
+        to_player_id: String,
+        text: String,
+    },
+    
+    // Moderation
+    InteractionsFrozen {
+        frozen: bool,
     },
     
     // Voice chat
+    RecordingStateChanged {
+        room_id: String,
+        /// None once recording stops
+        kind: Option<RecordingKind>,
+    },
+    /// Asks a player whether their voice may be included in an active recording
+    RecordingConsentRequest {
+        room_id: String,
+        kind: RecordingKind,
+    },
+    RecordingConsent {
+        room_id: String,
+        consent: bool,
+    },
+    SetVoiceInputMode {
+        mode: VoiceInputMode,
+    },
+    PushToTalk {
+        pressed: bool,
+    },
+    /// Tells peers which mic icon to show for a player
+    VoiceInputChanged {
+        player_id: String,
+        mode: VoiceInputMode,
+        transmitting: bool,
+    },
     VoiceData {
         player_id: String,
         audio_data: Vec<u8>,
     },
+    // Screen sharing
+    ScreenShareStarted {
+        room_id: String,
+        sharer_id: String,
+    },
+    ScreenShareStopped {
+        room_id: String,
+    },
+    ScreenShareFrame {
+        sharer_id: String,
+        frame: ScreenFrame,
+    },
+    /// Ask the sharer for a fresh keyframe (sent by late joiners, or by the server after drops)
+    RequestKeyframe {
+        room_id: String,
+    },
+    
+    /// Voice falloff and occluders clients should apply to spatial audio
+    AudioSettings {
+        room_id: String,
+        attenuation: AttenuationProfile,
+        occluders: Vec<AudioOccluder>,
+    },
+    /// Low-rate lip-sync data, sent even to players who don't receive the audio
+    Visemes {
+        player_id: String,
+        frame: VisemeFrame,
+    },
     
     // Custom game events
     CustomEvent {
         event_name: String,
         data: String,
+        /// Named channel; only its subscribers receive the event. None = whole room.
+        #[serde(default)]
+        channel: Option<String>,
+    },
+    /// Raw bytes (mesh deltas, compressed input streams, ...) for games that would
+    /// otherwise base64 them into `CustomEvent`; the UDP transport sends `data` as-is
+    CustomBinaryEvent {
+        event_name: String,
+        /// Hint for the receiver, e.g. "application/x-mesh-delta"
+        #[serde(default)]
+        content_type: Option<String>,
+        #[serde(default)]
+        channel: Option<String>,
+        data: Vec<u8>,
+    },
+    SubscribeEvents {
+        channels: Vec<String>,
+    },
+    UnsubscribeEvents {
+        channels: Vec<String>,
+    },
+    
+    // Remote procedure calls
+    RpcCall {
+        /// Chosen by the caller; echoed back in the matching `RpcResponse`
+        call_id: String,
+        target: RpcTarget,
+        procedure: String,
+        args: String,
+        #[serde(default)]
+        timeout_ms: Option<u64>,
+    },
+    /// Server -> host: answer with an `RpcResponse` carrying the same call_id
+    RpcInvoke {
+        call_id: String,
+        caller_id: String,
+        procedure: String,
+        args: String,
+    },
+    RpcResponse {
+        call_id: String,
+        result: Result<String, String>,
+    },
+    
+    // Matchmaking
+    MatchFound {
+        game_id: String,
+        room_id: String,
+        players: Vec<String>,
+    },
+    MatchmakingTicketExpired {
+        game_id: String,
+        reason: String,
     },
     
     // Server responses
@@ -113,6 +430,218 @@ pub enum GameMessage {
     },
 }
 
+/// Atomic read-modify-write operation on a room state key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op")]
+pub enum StateOperation {
+    /// Write only if the current value equals `expected` (None = key must not exist)
+    CompareAndSet {
+        expected: Option<String>,
+        value: String,
+    },
+    /// Add to an integer value; a missing key counts as 0
+    Increment {
+        amount: i64,
+    },
+    /// Append to a JSON string list; a missing key counts as an empty list
+    Append {
+        value: String,
+    },
+}
+
+/// Scores and winners reported at the end of a match
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchResult {
+    pub scores: HashMap<String, i64>, // player_id -> score
+    pub winners: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatchSubmissionStatus {
+    /// Waiting for the host or more matching submissions
+    Pending,
+    /// Enough players agreed; stats were updated and the result broadcast
+    Finalized,
+    /// Submissions conflict and can no longer reach agreement
+    Disputed,
+}
+
+impl GameMessage {
+    /// Default delivery mode when sent over the unreliable transport
+    pub fn delivery_mode(&self) -> DeliveryMode {
+        match self {
+            GameMessage::PlayerUpdate { .. }
+            | GameMessage::ObjectMoved { .. }
+            | GameMessage::VoiceData { .. }
+            | GameMessage::Visemes { .. }
+            | GameMessage::ScreenShareFrame { .. }
+            | GameMessage::RoomNetworkReport { .. } => DeliveryMode::Unreliable,
+            GameMessage::CustomEvent { .. } | GameMessage::CustomBinaryEvent { .. } => DeliveryMode::ReliableUnordered,
+            _ => DeliveryMode::ReliableOrdered,
+        }
+    }
+}
+
+// ============================================
+// Math
+// ============================================
+
+/// Quaternions shorter than this can't be normalized into a rotation
+const MIN_QUATERNION_LENGTH: f32 = 1e-6;
+/// Past this dot product slerp falls back to normalized lerp to avoid dividing by ~0
+const SLERP_LINEAR_THRESHOLD: f32 = 0.9995;
+/// `predicted_transform` extrapolates at most this far past a player's last update
+pub const MAX_EXTRAPOLATION: Duration = Duration::from_millis(250);
+
+impl Vector3 {
+    pub const ZERO: Vector3 = Vector3 { x: 0.0, y: 0.0, z: 0.0 };
+    
+    pub fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+    
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+    }
+    
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.is_finite() {
+            return Err("Vector has NaN or infinite components".to_string());
+        }
+        Ok(())
+    }
+    
+    pub fn length(&self) -> f32 {
+        (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+    }
+    
+    pub fn distance(&self, other: &Vector3) -> f32 {
+        self.sub(other).length()
+    }
+    
+    pub fn add(&self, other: &Vector3) -> Vector3 {
+        Vector3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+    
+    pub fn sub(&self, other: &Vector3) -> Vector3 {
+        Vector3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+    
+    pub fn scale(&self, factor: f32) -> Vector3 {
+        Vector3::new(self.x * factor, self.y * factor, self.z * factor)
+    }
+    
+    /// Unit vector in the same direction; the zero vector stays zero
+    pub fn normalized(&self) -> Vector3 {
+        let length = self.length();
+        if length <= f32::EPSILON {
+            return Vector3::ZERO;
+        }
+        self.scale(1.0 / length)
+    }
+    
+    /// `t` of 0.0 is `self`, 1.0 is `other`; values outside extrapolate
+    pub fn lerp(&self, other: &Vector3, t: f32) -> Vector3 {
+        self.add(&other.sub(self).scale(t))
+    }
+}
+
+impl Quaternion {
+    pub const IDENTITY: Quaternion = Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 };
+    
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Self { x, y, z, w }
+    }
+    
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite() && self.w.is_finite()
+    }
+    
+    /// Finite and long enough to normalize into a rotation
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.is_finite() {
+            return Err("Quaternion has NaN or infinite components".to_string());
+        }
+        if self.length() < MIN_QUATERNION_LENGTH {
+            return Err("Quaternion is too close to zero to be a rotation".to_string());
+        }
+        Ok(())
+    }
+    
+    pub fn length(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+    
+    pub fn dot(&self, other: &Quaternion) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+    
+    /// Unit quaternion for the same rotation; degenerate input becomes the identity
+    pub fn normalized(&self) -> Quaternion {
+        let length = self.length();
+        if !length.is_finite() || length < MIN_QUATERNION_LENGTH {
+            return Quaternion::IDENTITY;
+        }
+        Quaternion::new(self.x / length, self.y / length, self.z / length, self.w / length)
+    }
+    
+    /// Spherical interpolation along the shortest arc between two rotations
+    pub fn slerp(&self, other: &Quaternion, t: f32) -> Quaternion {
+        let from = self.normalized();
+        let mut to = other.normalized();
+        
+        // q and -q are the same rotation; flip to take the short way round
+        let mut cos_theta = from.dot(&to);
+        if cos_theta < 0.0 {
+            to = Quaternion::new(-to.x, -to.y, -to.z, -to.w);
+            cos_theta = -cos_theta;
+        }
+        
+        let (from_weight, to_weight) = if cos_theta > SLERP_LINEAR_THRESHOLD {
+            (1.0 - t, t)
+        } else {
+            let theta = cos_theta.acos();
+            let sin_theta = theta.sin();
+            (((1.0 - t) * theta).sin() / sin_theta, (t * theta).sin() / sin_theta)
+        };
+        
+        Quaternion::new(
+            from.x * from_weight + to.x * to_weight,
+            from.y * from_weight + to.y * to_weight,
+            from.z * from_weight + to.z * to_weight,
+            from.w * from_weight + to.w * to_weight,
+        ).normalized()
+    }
+}
+
+#[cfg(feature = "glam")]
+impl From<glam::Vec3> for Vector3 {
+    fn from(v: glam::Vec3) -> Self {
+        Vector3::new(v.x, v.y, v.z)
+    }
+}
+
+#[cfg(feature = "glam")]
+impl From<Vector3> for glam::Vec3 {
+    fn from(v: Vector3) -> Self {
+        glam::Vec3::new(v.x, v.y, v.z)
+    }
+}
+
+#[cfg(feature = "glam")]
+impl From<glam::Quat> for Quaternion {
+    fn from(q: glam::Quat) -> Self {
+        Quaternion::new(q.x, q.y, q.z, q.w)
+    }
+}
+
+#[cfg(feature = "glam")]
+impl From<Quaternion> for glam::Quat {
+    fn from(q: Quaternion) -> Self {
+        glam::Quat::from_xyzw(q.x, q.y, q.z, q.w)
+    }
+}
+
 // ============================================
 // Game Room Management
 // ============================================
@@ -127,6 +656,127 @@ pub struct GameRoom {
     pub created_at: i64,
     pub is_public: bool,
     pub game_state: HashMap<String, String>,
+    pub state_versions: HashMap<String, u64>,
+    pub state_subscriptions: HashMap<String, Vec<String>>, // player_id -> key patterns
+    pub turns: Option<TurnState>,
+    pub match_submissions: HashMap<String, MatchResult>, // player_id -> submitted result
+    pub world_id: Option<String>,
+    /// Persistent rooms stay open when the last player leaves
+    pub is_persistent: bool,
+    pub region: Option<String>,
+    pub content_rating: ContentRating,
+    /// Content descriptors (e.g. "gambling") checked against parental controls
+    pub tags: Vec<String>,
+    /// Players a moderator has blocked from touching objects
+    pub frozen_players: HashSet<String>,
+    /// Invisible moderators receiving room traffic
+    pub observers: Vec<String>,
+    pub objects: HashMap<ObjectId, NetworkedObject>,
+    pub object_ids: ObjectIdAllocator,
+    /// Checked in order; the first zone containing a player applies
+    pub audio_zones: Vec<AudioZone>,
+    pub attenuation: AttenuationProfile,
+    pub audio_occluders: Vec<AudioOccluder>,
+    pub screen_share: Option<ScreenShareSession>,
+    /// Players the host has allowed to share their screen
+    pub screen_share_permitted: HashSet<String>,
+    pub event_channels: HashMap<String, EventChannel>, // channel name -> channel
+    /// Tutorial instance; players who haven't finished the game's tutorial are routed here
+    pub is_tutorial: bool,
+    /// Past this many players sync is scaled back; `max_players` is the hard cap
+    pub soft_cap: Option<usize>,
+    /// Level last reported to the host
+    pub degradation: DegradationLevel,
+    pub last_player_syncs: HashMap<String, Instant>, // player_id -> last relayed update
+    held_player_syncs: HashSet<String>, // players whose latest update was throttled and not relayed yet
+    motion_samples: HashMap<String, VecDeque<(Instant, PlayerTransform)>>, // player_id -> last two updates, oldest first
+    /// Template the room was created from
+    pub template_id: Option<String>,
+    /// Scene clients load on joining
+    pub scene: Option<String>,
+    pub metadata: HashMap<String, String>,
+    pub permissions: RoomPermissions,
+    /// Players invited in; private rooms only admit these through `join_friend`
+    pub invited: HashSet<String>,
+    /// Parsed from `metadata`; kept in step by `set_metadata`
+    pub permission_zones: Vec<PermissionZone>,
+    /// Set for rooms that close automatically after a fixed time
+    pub countdown: Option<RoomCountdown>,
+}
+
+/// Server-managed turn order for turn-based games
+#[derive(Debug, Clone)]
+pub struct TurnState {
+    pub order: Vec<String>,
+    pub current_index: usize,
+    pub turn_number: u64,
+    pub turn_timeout_seconds: Option<i64>,
+    pub turn_started_at: i64,
+}
+
+impl TurnState {
+    pub fn new(order: Vec<String>, turn_timeout_seconds: Option<i64>, now: i64) -> Self {
+        Self {
+            order,
+            current_index: 0,
+            turn_number: 1,
+            turn_timeout_seconds,
+            turn_started_at: now,
+        }
+    }
+    
+    pub fn current_player(&self) -> Option<&String> {
+        self.order.get(self.current_index)
+    }
+    
+    pub fn deadline(&self) -> Option<i64> {
+        self.turn_timeout_seconds.map(|t| self.turn_started_at + t)
+    }
+    
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.deadline().map(|d| now >= d).unwrap_or(false)
+    }
+    
+    /// Pass the turn to the next player in order
+    pub fn advance(&mut self, now: i64) {
+        if !self.order.is_empty() {
+            self.current_index = (self.current_index + 1) % self.order.len();
+        }
+        self.turn_number += 1;
+        self.turn_started_at = now;
+    }
+    
+    /// Drop a player from the order. Returns true if the turn moved as a result.
+    pub fn remove_player(&mut self, player_id: &str, now: i64) -> bool {
+        let index = match self.order.iter().position(|p| p == player_id) {
+            Some(i) => i,
+            None => return false,
+        };
+        
+        self.order.remove(index);
+        
+        if index < self.current_index {
+            self.current_index -= 1;
+            false
+        } else if index == self.current_index {
+            if self.current_index >= self.order.len() {
+                self.current_index = 0;
+            }
+            self.turn_number += 1;
+            self.turn_started_at = now;
+            true
+        } else {
+            false
+        }
+    }
+    
+    pub fn changed_message(&self) -> Option<GameMessage> {
+        self.current_player().map(|player_id| GameMessage::TurnChanged {
+            player_id: player_id.clone(),
+            turn_number: self.turn_number,
+            deadline: self.deadline(),
+        })
+    }
 }
 
 impl GameRoom {
@@ -140,6 +790,38 @@ impl GameRoom {
             created_at: chrono::Utc::now().timestamp(),
             is_public: true,
             game_state: HashMap::new(),
+            state_versions: HashMap::new(),
+            state_subscriptions: HashMap::new(),
+            turns: None,
+            match_submissions: HashMap::new(),
+            world_id: None,
+            is_persistent: false,
+            region: None,
+            content_rating: ContentRating::Everyone,
+            tags: Vec::new(),
+            frozen_players: HashSet::new(),
+            observers: Vec::new(),
+            objects: HashMap::new(),
+            object_ids: ObjectIdAllocator::new(),
+            audio_zones: Vec::new(),
+            attenuation: AttenuationProfile::default(),
+            audio_occluders: Vec::new(),
+            screen_share: None,
+            screen_share_permitted: HashSet::new(),
+            event_channels: HashMap::new(),
+            is_tutorial: false,
+            soft_cap: None,
+            degradation: DegradationLevel::Normal,
+            last_player_syncs: HashMap::new(),
+            held_player_syncs: HashSet::new(),
+            motion_samples: HashMap::new(),
+            template_id: None,
+            scene: None,
+            metadata: HashMap::new(),
+            permissions: RoomPermissions::default(),
+            invited: HashSet::new(),
+            permission_zones: Vec::new(),
+            countdown: None,
         }
     }
     
@@ -153,344 +835,6811 @@ impl GameRoom {
     }
     
     pub fn remove_player(&mut self, player_id: &str) {
+        self.last_player_syncs.remove(player_id);
+        self.held_player_syncs.remove(player_id);
+        self.motion_samples.remove(player_id);
         self.players.retain(|p| p.player_id != player_id);
+        self.observers.retain(|id| id != player_id);
+        self.frozen_players.remove(player_id);
+        self.state_subscriptions.remove(player_id);
+        for channel in self.event_channels.values_mut() {
+            channel.remove_member(player_id);
+        }
+        
+        // Objects the player was simulating keep going until they sleep, unowned
+        for object in self.objects.values_mut() {
+            if object.owner_id.as_deref() == Some(player_id) {
+                object.owner_id = None;
+            }
+            if object.held_by.as_deref() == Some(player_id) {
+                object.held_by = None;
+            }
+        }
     }
     
     pub fn get_player(&self, player_id: &str) -> Option<&PlayerState> {
         self.players.iter().find(|p| p.player_id == player_id)
     }
     
+    /// Result to finalize when the room's time runs out: the host's if they submitted
+    /// one, otherwise the one most players agreed on. None without submissions or on a tie.
+    pub fn time_up_result(&self) -> Option<MatchResult> {
+        if let Some(result) = self.match_submissions.get(&self.host_id) {
+            return Some(result.clone());
+        }
+        
+        let support = |result: &MatchResult| self.match_submissions.values().filter(|r| *r == result).count();
+        let best = self.match_submissions.values().map(support).max()?;
+        let mut leaders = self.match_submissions.values().filter(|r| support(r) == best);
+        let leader = leaders.next()?;
+        
+        // Every submission of the leading result is counted, so a second distinct one is a tie
+        leaders.all(|r| r == leader).then(|| leader.clone())
+    }
+    
     pub fn update_player(&mut self, player_id: &str, transform: PlayerTransform) {
         if let Some(player) = self.players.iter_mut().find(|p| p.player_id == player_id) {
             player.transform = transform;
         }
     }
     
+    /// When the player's latest transform arrived
+    pub fn last_update_at(&self, player_id: &str) -> Option<Instant> {
+        self.motion_samples.get(player_id)?.back().map(|(at, _)| *at)
+    }
+    
+    /// Remember when a player's transform arrived so it can be extrapolated between updates
+    pub fn record_motion(&mut self, player_id: &str, transform: PlayerTransform, now: Instant) {
+        let samples = self.motion_samples.entry(player_id.to_string()).or_default();
+        samples.push_back((now, transform));
+        while samples.len() > 2 {
+            samples.pop_front();
+        }
+    }
+    
+    /// Where a player most likely is at `at`, interpolating between their last two
+    /// updates or extrapolating past the latest one by up to `MAX_EXTRAPOLATION`
+    pub fn predicted_transform(&self, player_id: &str, at: Instant) -> Option<PlayerTransform> {
+        let current = &self.get_player(player_id)?.transform;
+        
+        let samples = match self.motion_samples.get(player_id) {
+            Some(samples) if samples.len() == 2 => samples,
+            _ => return Some(current.clone()),
+        };
+        let (from_at, from) = &samples[0];
+        let (to_at, to) = &samples[1];
+        
+        let span = to_at.saturating_duration_since(*from_at).as_secs_f32();
+        if span <= 0.0 {
+            return Some(to.clone());
+        }
+        
+        let at = at.clamp(*from_at, *to_at + MAX_EXTRAPOLATION);
+        let t = at.saturating_duration_since(*from_at).as_secs_f32() / span;
+        Some(from.interpolate(to, t))
+    }
+    
     pub fn is_full(&self) -> bool {
         self.players.len() >= self.max_players
     }
     
-    pub fn player_count(&self) -> usize {
-        self.players.len()
+    /// Replace the room's metadata, picking up any permission zones it defines
+    pub fn set_metadata(&mut self, metadata: HashMap<String, String>) -> Result<(), String> {
+        self.permission_zones = parse_permission_zones(&metadata)?;
+        self.metadata = metadata;
+        Ok(())
     }
-}
-
-// ============================================
-// Multiplayer Server
-// ============================================
-
-pub struct MultiplayerServer {
-    rooms: Arc<Mutex<HashMap<String, GameRoom>>>,
-    player_to_room: Arc<Mutex<HashMap<String, String>>>, // player_id -> room_id
-    connections: Arc<Mutex<HashMap<String, SocketAddr>>>, // player_id -> address
-}
-
-impl MultiplayerServer {
-    pub fn new() -> Self {
-        println!("[MultiplayerServer] Initializing multiplayer server...");
+    
+    /// The zone deciding who may handle objects at `point`: the first listed zone
+    /// containing it. Points outside every zone are open to everyone.
+    pub fn permission_zone_at(&self, point: &Vector3) -> Option<&PermissionZone> {
+        self.permission_zones.iter().find(|zone| zone.contains(point))
+    }
+    
+    /// Check a grab, move, sleep or destroy against the zones around each object,
+    /// where it is being put and the player doing it. Unknown objects are left for
+    /// `apply_object_message` to reject.
+    pub fn check_permission_zones(&self, player_id: &str, message: &GameMessage) -> Result<(), String> {
+        if self.permission_zones.is_empty() {
+            return Ok(());
+        }
         
-        Self {
-            rooms: Arc::new(Mutex::new(HashMap::new())),
-            player_to_room: Arc::new(Mutex::new(HashMap::new())),
-            connections: Arc::new(Mutex::new(HashMap::new())),
+        let targets: Vec<(&ObjectId, Option<&Vector3>)> = match message {
+            GameMessage::ObjectGrabbed { object_id, .. } | GameMessage::ObjectDestroyed { object_id } => vec![(object_id, None)],
+            GameMessage::ObjectMoved { object_id, position, .. }
+            | GameMessage::ObjectSleep { object_id, position, .. } => vec![(object_id, Some(position))],
+            GameMessage::ObjectsDestroyedBatch { object_ids } => object_ids.iter().map(|id| (id, None)).collect(),
+            _ => return Ok(()),
+        };
+        
+        let mut points: Vec<&Vector3> = self.get_player(player_id).map(|p| &p.transform.position).into_iter().collect();
+        for (object_id, destination) in targets {
+            let Some(object) = self.objects.get(object_id) else { continue };
+            points.push(&object.position);
+            points.extend(destination);
+        }
+        
+        for point in points {
+            if let Some(zone) = self.permission_zone_at(point) {
+                if !zone.allows(player_id, &self.host_id) {
+                    return Err(format!("Only the host can handle objects in {}", zone.name));
+                }
+            }
         }
+        Ok(())
     }
     
-    /// Create a new game room
-    pub fn create_room(&self, game_id: String, host_id: String, max_players: usize) -> String {
-        let room_id = format!("room_{}", uuid::Uuid::new_v4());
-        let room = GameRoom::new(room_id.clone(), game_id, host_id, max_players);
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
+    
+    pub fn human_count(&self) -> usize {
+        self.players.iter().filter(|p| !p.is_npc).count()
+    }
+    
+    /// Current version of a state key (0 if it was never set)
+    pub fn state_version(&self, key: &str) -> u64 {
+        self.state_versions.get(key).copied().unwrap_or(0)
+    }
+    
+    /// Write a state key, optionally only if it is still at `expected_version`.
+    /// Returns the key's new version.
+    pub fn set_state(&mut self, key: &str, value: String, expected_version: Option<u64>) -> Result<u64, String> {
+        let current = self.state_version(key);
         
-        let mut rooms = self.rooms.lock().unwrap();
-        rooms.insert(room_id.clone(), room);
+        if let Some(expected) = expected_version {
+            if expected != current {
+                return Err(format!("Version conflict on '{}': expected {}, current {}", key, expected, current));
+            }
+        }
         
-        println!("[MultiplayerServer] Room created: {}", room_id);
+        let version = current + 1;
+        self.game_state.insert(key.to_string(), value);
+        self.state_versions.insert(key.to_string(), version);
         
-        room_id
+        Ok(version)
     }
     
-    /// Join an existing room
-    pub fn join_room(&self, room_id: &str, player: PlayerState) -> Result<(), String> {
-        let mut rooms = self.rooms.lock().unwrap();
+    /// Apply an atomic operation to a state key. Returns the new value and version.
+    pub fn apply_state_operation(&mut self, key: &str, operation: StateOperation) -> Result<(String, u64), String> {
+        let current = self.game_state.get(key);
         
-        if let Some(room) = rooms.get_mut(room_id) {
-            if room.is_full() {
-                return Err("Room is full".to_string());
+        let value = match operation {
+            StateOperation::CompareAndSet { expected, value } => {
+                if current != expected.as_ref() {
+                    return Err(format!("Compare-and-set failed on '{}'", key));
+                }
+                value
             }
-            
-            let player_id = player.player_id.clone();
-            
-            if room.add_player(player) {
-                // Update player-to-room mapping
-                drop(rooms); // Release the lock
-                let mut player_to_room = self.player_to_room.lock().unwrap();
-                player_to_room.insert(player_id.clone(), room_id.to_string());
-                
-                println!("[MultiplayerServer] Player {} joined room {}", player_id, room_id);
-                Ok(())
-            } else {
-                Err("Failed to add player to room".to_string())
+            StateOperation::Increment { amount } => {
+                let number = match current {
+                    Some(v) => v.parse::<i64>().map_err(|_| format!("'{}' is not an integer", key))?,
+                    None => 0,
+                };
+                number.checked_add(amount)
+                    .ok_or_else(|| format!("Increment overflow on '{}'", key))?
+                    .to_string()
             }
-        } else {
-            Err("Room not found".to_string())
-        }
-    }
-    
-    /// Leave a room
-    pub fn leave_room(&self, player_id: &str) -> Option<String> {
-        let mut player_to_room = self.player_to_room.lock().unwrap();
-        
-        if let Some(room_id) = player_to_room.remove(player_id) {
-            drop(player_to_room);
-            
-            let mut rooms = self.rooms.lock().unwrap();
-            if let Some(room) = rooms.get_mut(&room_id) {
-                room.remove_player(player_id);
-                
-                // Delete room if empty
-                if room.player_count() == 0 {
-                    rooms.remove(&room_id);
-                    println!("[MultiplayerServer] Room {} deleted (empty)", room_id);
-                } else {
-                    println!("[MultiplayerServer] Player {} left room {}", player_id, room_id);
-                }
-                
-                return Some(room_id);
+            StateOperation::Append { value } => {
+                let mut list: Vec<String> = match current {
+                    Some(v) => serde_json::from_str(v).map_err(|_| format!("'{}' is not a list", key))?,
+                    None => Vec::new(),
+                };
+                list.push(value);
+                serde_json::to_string(&list).map_err(|e| e.to_string())?
             }
-        }
+        };
         
-        None
+        let version = self.set_state(key, value.clone(), None)?;
+        Ok((value, version))
     }
     
-    /// Update player transform
-    pub fn update_player(&self, player_id: &str, transform: PlayerTransform) -> Option<String> {
-        let player_to_room = self.player_to_room.lock().unwrap();
+    /// Add key patterns (exact keys or `prefix*`) to a player's watch list
+    pub fn subscribe_state(&mut self, player_id: &str, patterns: Vec<String>) {
+        let subscribed = self.state_subscriptions.entry(player_id.to_string()).or_default();
         
-        if let Some(room_id) = player_to_room.get(player_id) {
-            let room_id = room_id.clone();
-            drop(player_to_room);
-            
-            let mut rooms = self.rooms.lock().unwrap();
-            if let Some(room) = rooms.get_mut(&room_id) {
-                room.update_player(player_id, transform);
-                return Some(room_id);
+        for pattern in patterns {
+            if !subscribed.contains(&pattern) {
+                subscribed.push(pattern);
             }
         }
-        
-        None
     }
     
-    /// Get all players in a room
-    pub fn get_room_players(&self, room_id: &str) -> Vec<PlayerState> {
-        let rooms = self.rooms.lock().unwrap();
-        
-        if let Some(room) = rooms.get(room_id) {
-            room.players.clone()
-        } else {
-            Vec::new()
+    pub fn unsubscribe_state(&mut self, player_id: &str, patterns: &[String]) {
+        if let Some(subscribed) = self.state_subscriptions.get_mut(player_id) {
+            subscribed.retain(|p| !patterns.contains(p));
+            
+            if subscribed.is_empty() {
+                self.state_subscriptions.remove(player_id);
+            }
         }
     }
     
-    /// Find available rooms for a game
-    pub fn find_rooms(&self, game_id: &str) -> Vec<(String, usize, usize)> {
-        let rooms = self.rooms.lock().unwrap();
-        
-        rooms.values()
-            .filter(|room| room.game_id == game_id && room.is_public && !room.is_full())
-            .map(|room| (room.room_id.clone(), room.player_count(), room.max_players))
+    /// Players watching a state key
+    pub fn state_watchers(&self, key: &str) -> Vec<String> {
+        self.state_subscriptions.iter()
+            .filter(|(_, patterns)| patterns.iter().any(|p| state_key_matches(p, key)))
+            .map(|(player_id, _)| player_id.clone())
             .collect()
     }
     
-    /// Get room info
-    pub fn get_room(&self, room_id: &str) -> Option<GameRoom> {
-        let rooms = self.rooms.lock().unwrap();
-        rooms.get(room_id).cloned()
-    }
-    
-    /// Broadcast message to all players in a room
-    pub fn broadcast_to_room(&self, room_id: &str, message: GameMessage, exclude_player: Option<&str>) {
-        let rooms = self.rooms.lock().unwrap();
-        
-        if let Some(room) = rooms.get(room_id) {
-            for player in &room.players {
-                if let Some(excluded) = exclude_player {
-                    if player.player_id == excluded {
-                        continue;
-                    }
-                }
-                
-                // In real implementation, send message over network
-                println!("[MultiplayerServer] Broadcasting to player: {}", player.player_id);
-            }
-        }
-    }
-    
-    /// Get server statistics
-    pub fn get_stats(&self) -> ServerStats {
-        let rooms = self.rooms.lock().unwrap();
-        let connections = self.connections.lock().unwrap();
-        
-        let total_players: usize = rooms.values().map(|r| r.player_count()).sum();
-        
-        ServerStats {
-            total_rooms: rooms.len(),
-            total_players,
-            active_connections: connections.len(),
-        }
+    /// Current values of every key matching the given patterns
+    pub fn state_snapshot(&self, patterns: &[String]) -> Vec<GameMessage> {
+        self.game_state.iter()
+            .filter(|(key, _)| patterns.iter().any(|p| state_key_matches(p, key)))
+            .map(|(key, value)| GameMessage::GameStateChanged {
+                key: key.clone(),
+                value: value.clone(),
+                version: self.state_version(key),
+            })
+            .collect()
     }
 }
 
-#[derive(Debug, Serialize)]
-pub struct ServerStats {
-    pub total_rooms: usize,
-    pub total_players: usize,
-    pub active_connections: usize,
+fn state_key_matches(pattern: &str, key: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => key.starts_with(prefix),
+        None => pattern == key,
+    }
 }
 
 // ============================================
-// Voice Chat System
+// Networked Objects
 // ============================================
 
-pub struct VoiceChatServer {
-    active_channels: Arc<Mutex<HashMap<String, Vec<String>>>>, // room_id -> [player_ids]
+/// Moves smaller than this (in metres) don't count as motion
+const OBJECT_REST_EPSILON: f32 = 0.001;
+/// Unheld objects that haven't moved for this long are put to sleep
+const OBJECT_SLEEP_SECONDS: i64 = 2;
+/// Destroyed object IDs stay retired this long before being handed out again
+const OBJECT_ID_REUSE_DELAY_SECONDS: i64 = 30;
+
+/// Server-assigned, room-scoped object ID; 0 means "not yet assigned"
+pub type ObjectId = u64;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObjectIdAssignment {
+    pub temp_id: String,
+    pub object_id: ObjectId,
 }
 
-impl VoiceChatServer {
+/// Hands out compact object IDs for one room. Released IDs are recycled only after
+/// a delay, so late messages about a destroyed object can't hit its replacement.
+#[derive(Debug, Clone)]
+pub struct ObjectIdAllocator {
+    next_id: ObjectId,
+    released: VecDeque<(ObjectId, i64)>, // (id, released_at), oldest first
+}
+
+impl ObjectIdAllocator {
     pub fn new() -> Self {
         Self {
-            active_channels: Arc::new(Mutex::new(HashMap::new())),
+            next_id: 1,
+            released: VecDeque::new(),
         }
     }
     
-    pub fn join_voice_channel(&self, room_id: &str, player_id: &str) {
-        let mut channels = self.active_channels.lock().unwrap();
-        
-        channels.entry(room_id.to_string())
-            .or_insert_with(Vec::new)
-            .push(player_id.to_string());
+    pub fn allocate(&mut self, now: i64) -> ObjectId {
+        if let Some(&(id, released_at)) = self.released.front() {
+            if now - released_at >= OBJECT_ID_REUSE_DELAY_SECONDS {
+                self.released.pop_front();
+                return id;
+            }
+        }
         
-        println!("[VoiceChat] Player {} joined voice channel in room {}", player_id, room_id);
+        let id = self.next_id;
+        self.next_id += 1;
+        id
     }
     
-    pub fn leave_voice_channel(&self, room_id: &str, player_id: &str) {
-        let mut channels = self.active_channels.lock().unwrap();
+    pub fn release(&mut self, id: ObjectId, now: i64) {
+        self.released.push_back((id, now));
+    }
+}
+
+impl Default for ObjectIdAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpawnedObject {
+    /// Assigned by the server; clients send 0 with a `temp_id`
+    pub object_id: ObjectId,
+    #[serde(default)]
+    pub temp_id: Option<String>,
+    pub object_type: String,
+    pub position: Vector3,
+    pub rotation: Quaternion,
+}
+
+/// Named set of objects that can be spawned together
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Prefab {
+    pub name: String,
+    pub objects: Vec<PrefabObject>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrefabObject {
+    pub object_type: String,
+    /// Position relative to the spawn origin, in world axes
+    pub offset: Vector3,
+    pub rotation: Quaternion,
+}
+
+#[derive(Debug, Clone)]
+pub struct NetworkedObject {
+    pub object_id: ObjectId,
+    pub object_type: String,
+    pub position: Vector3,
+    pub rotation: Quaternion,
+    /// Player whose client simulates this object; None while asleep
+    pub owner_id: Option<String>,
+    pub held_by: Option<String>,
+    pub asleep: bool,
+    pub last_moved_at: i64,
+}
+
+/// What to do with an object message after the room has applied it
+#[derive(Debug, PartialEq)]
+pub enum ObjectUpdate {
+    Relay,
+    /// A move of a sleeping object that didn't actually move it
+    Suppressed,
+    /// The message woke this sleeping object; announce the wake, then relay
+    Woke(ObjectId),
+    /// New objects were given server IDs; confirm them to the spawner, then relay
+    Spawned(Vec<ObjectIdAssignment>),
+}
+
+impl GameRoom {
+    /// Update tracked objects from a client's object message. Spawns are given
+    /// server IDs, which are written back into the message before it is relayed.
+    pub fn apply_object_message(&mut self, player_id: &str, message: &mut GameMessage, now: i64) -> Result<ObjectUpdate, String> {
+        let is_grab = matches!(message, GameMessage::ObjectGrabbed { .. });
         
-        if let Some(players) = channels.get_mut(room_id) {
-            players.retain(|p| p != player_id);
-            
-            if players.is_empty() {
-                channels.remove(room_id);
+        // Corrupt poses are refused before they touch room state or reach other clients
+        match message {
+            GameMessage::ObjectSpawned { position, rotation, .. }
+            | GameMessage::ObjectMoved { position, rotation, .. }
+            | GameMessage::ObjectSleep { position, rotation, .. } => sanitize_pose(position, rotation)?,
+            GameMessage::ObjectsSpawnedBatch { objects } => {
+                for object in objects.iter_mut() {
+                    sanitize_pose(&object.position, &mut object.rotation)?;
+                }
             }
+            _ => {}
         }
         
-        println!("[VoiceChat] Player {} left voice channel", player_id);
+        match message {
+            GameMessage::ObjectSpawned { object_id, temp_id, object_type, position, rotation } => {
+                *object_id = self.object_ids.allocate(now);
+                
+                self.objects.insert(*object_id, NetworkedObject {
+                    object_id: *object_id,
+                    object_type: object_type.clone(),
+                    position: position.clone(),
+                    rotation: rotation.clone(),
+                    owner_id: Some(player_id.to_string()),
+                    held_by: None,
+                    asleep: false,
+                    last_moved_at: now,
+                });
+                
+                let assignments = temp_id.iter()
+                    .map(|temp_id| ObjectIdAssignment { temp_id: temp_id.clone(), object_id: *object_id })
+                    .collect();
+                Ok(ObjectUpdate::Spawned(assignments))
+            }
+            GameMessage::ObjectMoved { object_id, position, rotation } => {
+                let object = self.objects.get_mut(object_id).ok_or_else(|| "Unknown object".to_string())?;
+                let moved = object.position.distance(position) > OBJECT_REST_EPSILON;
+                
+                if object.asleep && !moved {
+                    return Ok(ObjectUpdate::Suppressed);
+                }
+                
+                if !object.asleep {
+                    if let Some(owner) = object.owner_id.as_deref().filter(|owner| *owner != player_id) {
+                        return Err(format!("Object is simulated by {}", owner));
+                    }
+                }
+                
+                let woke = object.asleep;
+                object.asleep = false;
+                object.owner_id = Some(player_id.to_string());
+                object.position = position.clone();
+                object.rotation = rotation.clone();
+                if moved {
+                    object.last_moved_at = now;
+                }
+                
+                Ok(if woke { ObjectUpdate::Woke(*object_id) } else { ObjectUpdate::Relay })
+            }
+            GameMessage::ObjectGrabbed { object_id, player_id: grabber } | GameMessage::ObjectWake { object_id, player_id: grabber } => {
+                if grabber != player_id {
+                    return Err("Cannot act on behalf of another player".to_string());
+                }
+                
+                let object = self.objects.get_mut(object_id).ok_or_else(|| "Unknown object".to_string())?;
+                if is_grab {
+                    object.held_by = Some(player_id.to_string());
+                }
+                object.asleep = false;
+                object.owner_id = Some(player_id.to_string());
+                object.last_moved_at = now;
+                Ok(ObjectUpdate::Relay)
+            }
+            GameMessage::ObjectReleased { object_id } => {
+                let object = self.objects.get_mut(object_id).ok_or_else(|| "Unknown object".to_string())?;
+                object.held_by = None;
+                object.last_moved_at = now;
+                Ok(ObjectUpdate::Relay)
+            }
+            GameMessage::ObjectSleep { object_id, position, rotation } => {
+                let object = self.objects.get_mut(object_id).ok_or_else(|| "Unknown object".to_string())?;
+                
+                if object.owner_id.as_deref().is_some_and(|owner| owner != player_id) {
+                    return Err("Only the simulating player can put an object to sleep".to_string());
+                }
+                
+                object.put_to_sleep(Some((position.clone(), rotation.clone())));
+                Ok(ObjectUpdate::Relay)
+            }
+            GameMessage::ObjectDestroyed { object_id } => {
+                self.objects.remove(object_id).ok_or_else(|| "Unknown object".to_string())?;
+                self.object_ids.release(*object_id, now);
+                Ok(ObjectUpdate::Relay)
+            }
+            GameMessage::ObjectsSpawnedBatch { objects } => {
+                let mut assignments = Vec::new();
+                
+                for object in objects.iter_mut() {
+                    object.object_id = self.object_ids.allocate(now);
+                    if let Some(temp_id) = &object.temp_id {
+                        assignments.push(ObjectIdAssignment { temp_id: temp_id.clone(), object_id: object.object_id });
+                    }
+                    
+                    self.objects.insert(object.object_id, NetworkedObject {
+                        object_id: object.object_id,
+                        object_type: object.object_type.clone(),
+                        position: object.position.clone(),
+                        rotation: object.rotation.clone(),
+                        owner_id: Some(player_id.to_string()),
+                        held_by: None,
+                        asleep: false,
+                        last_moved_at: now,
+                    });
+                }
+                Ok(ObjectUpdate::Spawned(assignments))
+            }
+            GameMessage::ObjectsDestroyedBatch { object_ids } => {
+                if let Some(missing) = object_ids.iter().find(|id| !self.objects.contains_key(*id)) {
+                    return Err(format!("Unknown object {}", missing));
+                }
+                
+                for object_id in object_ids.iter() {
+                    // Only the first mention releases the ID, so repeats can't hand it out twice
+                    if self.objects.remove(object_id).is_some() {
+                        self.object_ids.release(*object_id, now);
+                    }
+                }
+                Ok(ObjectUpdate::Relay)
+            }
+            _ => Err("Not an object message".to_string()),
+        }
     }
     
-    pub fn broadcast_audio(&self, room_id: &str, sender_id: &str, audio_data: Vec<u8>) {
-        let channels = self.active_channels.lock().unwrap();
+    /// Put unheld objects that stopped moving to sleep, returning their sleep messages
+    pub fn sleep_resting_objects(&mut self, now: i64) -> Vec<GameMessage> {
+        self.objects.values_mut()
+            .filter(|o| !o.asleep && o.held_by.is_none() && now - o.last_moved_at >= OBJECT_SLEEP_SECONDS)
+            .map(|object| {
+                object.put_to_sleep(None);
+                object.sleep_message()
+            })
+            .collect()
+    }
+}
+
+/// Validate an object pose and renormalize its rotation in place
+fn sanitize_pose(position: &Vector3, rotation: &mut Quaternion) -> Result<(), String> {
+    position.validate().map_err(|e| format!("Invalid position: {}", e))?;
+    rotation.validate().map_err(|e| format!("Invalid rotation: {}", e))?;
+    *rotation = rotation.normalized();
+    Ok(())
+}
+
+impl NetworkedObject {
+    fn put_to_sleep(&mut self, rest_pose: Option<(Vector3, Quaternion)>) {
+        if let Some((position, rotation)) = rest_pose {
+            self.position = position;
+            self.rotation = rotation;
+        }
         
-        if let Some(players) = channels.get(room_id) {
-            for player_id in players {
-                if player_id != sender_id {
-                    // Send audio data to player
-                    println!("[VoiceChat] Sending audio from {} to {}", sender_id, player_id);
-                }
-            }
+        self.asleep = true;
+        self.owner_id = None;
+        self.held_by = None;
+    }
+    
+    fn sleep_message(&self) -> GameMessage {
+        GameMessage::ObjectSleep {
+            object_id: self.object_id,
+            position: self.position.clone(),
+            rotation: self.rotation.clone(),
         }
     }
 }
 
 // ============================================
-// Matchmaking System
+// Audio Zones
 // ============================================
 
-pub struct MatchmakingService {
-    queue: Arc<Mutex<HashMap<String, Vec<String>>>>, // game_id -> [player_ids]
+/// Listeners who would hear a speaker quieter than this aren't sent the audio
+const MIN_AUDIBLE_GAIN: f32 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AttenuationCurve {
+    Linear,
+    /// Realistic 1/distance falloff
+    Inverse,
+    Exponential,
 }
 
-impl MatchmakingService {
-    pub fn new() -> Self {
+/// How voice volume falls off with distance
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttenuationProfile {
+    /// Full volume inside this distance, in metres
+    pub min_distance: f32,
+    /// Silent beyond this distance
+    pub max_distance: f32,
+    pub curve: AttenuationCurve,
+}
+
+impl Default for AttenuationProfile {
+    fn default() -> Self {
         Self {
-            queue: Arc::new(Mutex::new(HashMap::new())),
+            min_distance: 1.0,
+            max_distance: 15.0,
+            curve: AttenuationCurve::Inverse,
+        }
+    }
+}
+
+impl AttenuationProfile {
+    /// Both distances must be finite with 0 < min_distance < max_distance
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.min_distance.is_finite() || !self.max_distance.is_finite() {
+            return Err("Attenuation distances must be finite".to_string());
+        }
+        if self.min_distance <= 0.0 {
+            return Err("min_distance must be greater than zero".to_string());
         }
+        if self.max_distance <= self.min_distance {
+            return Err("max_distance must be greater than min_distance".to_string());
+        }
+        Ok(())
     }
     
-    pub fn join_queue(&self, game_id: &str, player_id: &str) {
-        let mut queue = self.queue.lock().unwrap();
-        
-        queue.entry(game_id.to_string())
-            .or_insert_with(Vec::new)
-            .push(player_id.to_string());
-        
-        println!("[Matchmaking] Player {} joined queue for game {}", player_id, game_id);
+    /// This profile with its hearing range replaced, as used by proximity zones
+    pub fn with_range(&self, range: f32) -> Self {
+        Self { max_distance: range, ..self.clone() }
     }
     
-    pub fn leave_queue(&self, game_id: &str, player_id: &str) {
-        let mut queue = self.queue.lock().unwrap();
+    /// Volume multiplier (0.0 to 1.0) at a distance
+    pub fn gain_at(&self, distance: f32) -> f32 {
+        if distance <= self.min_distance {
+            return 1.0;
+        }
+        if distance >= self.max_distance {
+            return 0.0;
+        }
         
-        if let Some(players) = queue.get_mut(game_id) {
-            players.retain(|p| p != player_id);
+        let t = (distance - self.min_distance) / (self.max_distance - self.min_distance);
+        match self.curve {
+            AttenuationCurve::Linear => 1.0 - t,
+            // Rescaled so the curve still reaches zero at max_distance
+            AttenuationCurve::Inverse => {
+                let raw = self.min_distance / distance;
+                let floor = self.min_distance / self.max_distance;
+                (raw - floor) / (1.0 - floor)
+            }
+            AttenuationCurve::Exponential => (1.0 - t).powi(3),
         }
     }
-    
-    pub fn find_match(&self, game_id: &str, required_players: usize) -> Option<Vec<String>> {
-        let mut queue = self.queue.lock().unwrap();
+}
+
+/// A wall or other solid volume that muffles voices passing through it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioOccluder {
+    pub min: Vector3,
+    pub max: Vector3,
+    /// Fraction of volume removed, 0.0 to 1.0
+    pub attenuation: f32,
+}
+
+impl AudioOccluder {
+    /// Whether the segment from `a` to `b` passes through this box (slab test)
+    pub fn blocks(&self, a: &Vector3, b: &Vector3) -> bool {
+        let axes = [(a.x, b.x, self.min.x, self.max.x), (a.y, b.y, self.min.y, self.max.y), (a.z, b.z, self.min.z, self.max.z)];
+        let (mut t_min, mut t_max) = (0.0f32, 1.0f32);
         
-        if let Some(players) = queue.get_mut(game_id) {
-            if players.len() >= required_players {
-                let matched: Vec<String> = players.drain(0..required_players).collect();
-                println!("[Matchmaking] Match found for game {}: {} players", game_id, matched.len());
-                return Some(matched);
+        for (start, end, lo, hi) in axes {
+            let delta = end - start;
+            
+            if delta.abs() < f32::EPSILON {
+                if start < lo || start > hi {
+                    return false;
+                }
+                continue;
+            }
+            
+            let (t0, t1) = ((lo - start) / delta, (hi - start) / delta);
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+            
+            if t_min > t_max {
+                return false;
             }
         }
         
-        None
+        true
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AudioZoneKind {
+    /// Speakers here are heard across the whole room
+    Stage,
+    /// Soundproof: only people inside hear each other
+    Booth,
+    /// Proximity voice with its own hearing range (e.g. a narrow hallway)
+    Proximity { range: f32 },
+}
+
+/// Axis-aligned box in room space with its own voice routing rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioZone {
+    pub name: String,
+    pub kind: AudioZoneKind,
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+impl AudioZone {
+    pub fn contains(&self, point: &Vector3) -> bool {
+        (self.min.x..=self.max.x).contains(&point.x)
+            && (self.min.y..=self.max.y).contains(&point.y)
+            && (self.min.z..=self.max.z).contains(&point.z)
+    }
+}
+
+impl GameRoom {
+    /// Check that `attenuation`, and each proximity zone's range applied to it, is a valid profile
+    fn validate_audio(attenuation: &AttenuationProfile, zones: &[AudioZone]) -> Result<(), String> {
+        attenuation.validate()?;
+        for zone in zones {
+            if let AudioZoneKind::Proximity { range } = zone.kind {
+                attenuation.with_range(range).validate()
+                    .map_err(|e| format!("Proximity zone {}: {}", zone.name, e))?;
+            }
+        }
+        Ok(())
+    }
+    
+    pub fn audio_zone_at(&self, point: &Vector3) -> Option<&AudioZone> {
+        self.audio_zones.iter().find(|zone| zone.contains(point))
+    }
+    
+    /// Volume at which `listener` hears a speaker at `speaker`, after falloff and occlusion
+    pub fn voice_gain(&self, speaker: &Vector3, listener: &Vector3, profile: &AttenuationProfile) -> f32 {
+        self.audio_occluders.iter()
+            .filter(|o| o.blocks(speaker, listener))
+            .fold(profile.gain_at(speaker.distance(listener)), |gain, o| gain * (1.0 - o.attenuation.clamp(0.0, 1.0)))
+    }
+    
+    /// Players who should hear `speaker_id` under the room's zone and attenuation rules.
+    /// Observers hear everything; NPCs never listen.
+    pub fn voice_recipients(&self, speaker_id: &str) -> Vec<String> {
+        let speaker = match self.get_player(speaker_id) {
+            Some(p) => p,
+            None => return Vec::new(),
+        };
+        
+        let speaker_pos = &speaker.transform.position;
+        let speaker_zone = self.audio_zone_at(speaker_pos);
+        
+        let mut recipients: Vec<String> = self.players.iter()
+            .filter(|p| p.player_id != speaker_id && !p.is_npc)
+            .filter(|listener| {
+                let listener_pos = &listener.transform.position;
+                let listener_zone = self.audio_zone_at(listener_pos);
+                
+                let speaker_in_booth = speaker_zone.is_some_and(|z| z.kind == AudioZoneKind::Booth);
+                let listener_in_booth = listener_zone.is_some_and(|z| z.kind == AudioZoneKind::Booth);
+                
+                if speaker_in_booth || listener_in_booth {
+                    return speaker_zone.map(|z| &z.name) == listener_zone.map(|z| &z.name);
+                }
+                
+                match speaker_zone.map(|z| &z.kind) {
+                    Some(AudioZoneKind::Stage) => true,
+                    Some(AudioZoneKind::Proximity { range }) => {
+                        self.voice_gain(speaker_pos, listener_pos, &self.attenuation.with_range(*range)) >= MIN_AUDIBLE_GAIN
+                    }
+                    _ => self.voice_gain(speaker_pos, listener_pos, &self.attenuation) >= MIN_AUDIBLE_GAIN,
+                }
+            })
+            .map(|p| p.player_id.clone())
+            .collect();
+        
+        recipients.extend(self.observers.iter().cloned());
+        recipients
+    }
+}
+
+// ============================================
+// Server-Controlled Players (NPCs)
+// ============================================
+
+/// Drives a server-controlled player; called once per server tick
+pub trait NpcController: Send {
+    /// Return the NPC's new transform, or None to leave it unchanged
+    fn tick(&mut self, npc: &PlayerState, room: &GameRoom, delta_seconds: f32) -> Option<PlayerTransform>;
+}
+
+// ============================================
+// Moderation
+// ============================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ModerationAction {
+    Freeze,
+    Unfreeze,
+    TeleportTo,
+    Observe,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModerationAuditEntry {
+    pub moderator_id: String,
+    pub action: ModerationAction,
+    pub target_player_id: Option<String>,
+    pub room_id: String,
+    pub timestamp: i64,
+}
+
+// ============================================
+// Hub Worlds
+// ============================================
+
+/// A social world that always exists; extra instances are spun up as it fills
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorldTemplate {
+    pub world_id: String,
+    pub game_id: String,
+    pub name: String,
+    pub max_players_per_instance: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorldInstanceSummary {
+    pub room_id: String,
+    pub player_count: usize,
+    pub max_players: usize,
+    pub is_primary: bool,
+    pub friends_present: Vec<String>,
+}
+
+// ============================================
+// Screen Sharing
+// ============================================
+
+/// Bytes of screen-share data relayed per room per second
+const SCREEN_SHARE_MAX_BYTES_PER_SECOND: usize = 512 * 1024;
+/// Keyframe requests are coalesced so a crowd of late joiners asks only once
+const KEYFRAME_REQUEST_INTERVAL: Duration = Duration::from_secs(2);
+
+/// One chunk of an encoded video frame or slide image
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreenFrame {
+    pub sequence: u64,
+    pub is_keyframe: bool,
+    pub chunk_index: u16,
+    pub chunk_count: u16,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScreenShareSession {
+    pub sharer_id: String,
+    window_start: Instant,
+    window_bytes: usize,
+    /// Set after a dropped frame; deltas are useless until the next keyframe
+    awaiting_keyframe: bool,
+    last_keyframe_request: Option<Instant>,
+}
+
+impl ScreenShareSession {
+    fn new(sharer_id: &str, now: Instant) -> Self {
+        Self {
+            sharer_id: sharer_id.to_string(),
+            window_start: now,
+            window_bytes: 0,
+            awaiting_keyframe: true,
+            last_keyframe_request: None,
+        }
+    }
+    
+    /// Whether a frame fits the bandwidth cap and can still be decoded by viewers
+    fn admit(&mut self, frame: &ScreenFrame, now: Instant) -> bool {
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.window_bytes = 0;
+        }
+        
+        let new_keyframe = frame.is_keyframe && frame.chunk_index == 0;
+        if self.awaiting_keyframe && !new_keyframe {
+            return false;
+        }
+        
+        if self.window_bytes + frame.data.len() > SCREEN_SHARE_MAX_BYTES_PER_SECOND {
+            self.awaiting_keyframe = true;
+            return false;
+        }
+        
+        self.awaiting_keyframe = false;
+        self.window_bytes += frame.data.len();
+        true
+    }
+    
+    /// Whether a keyframe request should go to the sharer now
+    fn should_request_keyframe(&mut self, now: Instant) -> bool {
+        if self.last_keyframe_request.is_some_and(|last| now.duration_since(last) < KEYFRAME_REQUEST_INTERVAL) {
+            return false;
+        }
+        
+        self.last_keyframe_request = Some(now);
+        true
+    }
+}
+
+// ============================================
+// Event Channels
+// ============================================
+
+/// Events each sender may publish per second on a channel unless the host changes it
+const DEFAULT_EVENT_CHANNEL_RATE: u32 = 20;
+const MAX_EVENT_CHANNEL_NAME_LEN: usize = 64;
+const MAX_EVENT_CHANNELS_PER_ROOM: usize = 64;
+const MAX_EVENT_SUBSCRIPTIONS_PER_PLAYER: usize = 16;
+const MAX_BINARY_EVENT_BYTES: usize = 64 * 1024;
+
+/// A named stream of `CustomEvent`s within a room. Only subscribers receive its
+/// events, and each sender is held to a per-second limit.
+#[derive(Debug, Clone)]
+pub struct EventChannel {
+    pub subscribers: HashSet<String>,
+    pub max_events_per_second: u32,
+    windows: HashMap<String, (Instant, u32)>, // sender_id -> (window start, events sent)
+}
+
+impl EventChannel {
+    fn new() -> Self {
+        Self {
+            subscribers: HashSet::new(),
+            max_events_per_second: DEFAULT_EVENT_CHANNEL_RATE,
+            windows: HashMap::new(),
+        }
+    }
+    
+    /// Count an event from `sender_id`; false once it is over the channel's limit
+    fn admit(&mut self, sender_id: &str, now: Instant) -> bool {
+        let (window_start, sent) = self.windows.entry(sender_id.to_string()).or_insert((now, 0));
+        
+        if now.duration_since(*window_start) >= Duration::from_secs(1) {
+            *window_start = now;
+            *sent = 0;
+        }
+        
+        if *sent >= self.max_events_per_second {
+            return false;
+        }
+        
+        *sent += 1;
+        true
+    }
+    
+    fn remove_member(&mut self, player_id: &str) {
+        self.subscribers.remove(player_id);
+        self.windows.remove(player_id);
+    }
+    
+    /// Nobody listens and the host left the rate alone, so the channel can go
+    fn is_idle(&self) -> bool {
+        self.subscribers.is_empty() && self.max_events_per_second == DEFAULT_EVENT_CHANNEL_RATE
+    }
+}
+
+fn validate_event_channel(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_EVENT_CHANNEL_NAME_LEN {
+        return Err(format!("Event channel names must be 1-{} characters", MAX_EVENT_CHANNEL_NAME_LEN));
+    }
+    Ok(())
+}
+
+// ============================================
+// Remote Procedure Calls
+// ============================================
+
+const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_RPC_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RpcTarget {
+    /// The room's host client
+    Host,
+    /// The server-side script attached to the room
+    RoomScript,
+}
+
+/// Server-side game logic that answers RPCs for one room
+pub trait RoomScript: Send {
+    fn handle_rpc(&mut self, caller_id: &str, procedure: &str, args: &str, room: &GameRoom) -> Result<String, String>;
+}
+
+/// A call forwarded to a host and waiting for its answer
+#[derive(Debug, Clone)]
+pub struct PendingRpc {
+    pub caller_id: String,
+    /// The caller's own call_id, restored in the response
+    pub caller_call_id: String,
+    pub host_id: String,
+    pub room_id: String,
+    pub deadline: Instant,
+}
+
+// ============================================
+// Room Capacity
+// ============================================
+
+const FULL_SYNC_INTERVAL_MS: u64 = 50;
+
+/// How far a crowded room has scaled back player sync to fit more people
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+pub enum DegradationLevel {
+    #[default]
+    Normal,
+    /// Over the soft cap: slower updates, nearby players only
+    Reduced,
+    /// More than halfway from the soft cap to the hard cap
+    Minimal,
+}
+
+/// Per-player sync limits at a degradation level
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SyncBudget {
+    /// Minimum time between relayed transform updates from one player
+    pub update_interval_ms: u64,
+    /// Only players this close receive a player's updates; None = the whole room
+    pub aoi_radius: Option<f32>,
+}
+
+impl DegradationLevel {
+    pub fn sync_budget(&self) -> SyncBudget {
+        match self {
+            DegradationLevel::Normal => SyncBudget { update_interval_ms: FULL_SYNC_INTERVAL_MS, aoi_radius: None },
+            DegradationLevel::Reduced => SyncBudget { update_interval_ms: 100, aoi_radius: Some(30.0) },
+            DegradationLevel::Minimal => SyncBudget { update_interval_ms: 200, aoi_radius: Some(15.0) },
+        }
+    }
+}
+
+impl GameRoom {
+    /// Degradation for the current player count
+    pub fn degradation_level(&self) -> DegradationLevel {
+        let Some(soft_cap) = self.soft_cap else { return DegradationLevel::Normal };
+        let count = self.player_count();
+        if count <= soft_cap {
+            return DegradationLevel::Normal;
+        }
+        
+        let headroom = self.max_players.saturating_sub(soft_cap).max(1);
+        if (count - soft_cap) * 2 <= headroom {
+            DegradationLevel::Reduced
+        } else {
+            DegradationLevel::Minimal
+        }
+    }
+    
+    /// Record the current level; returns the host's notice if it changed
+    pub fn refresh_degradation(&mut self) -> Option<GameMessage> {
+        let level = self.degradation_level();
+        if level == self.degradation {
+            return None;
+        }
+        
+        self.degradation = level;
+        Some(GameMessage::RoomLoadChanged {
+            room_id: self.room_id.clone(),
+            level,
+            player_count: self.player_count(),
+            soft_cap: self.soft_cap,
+            max_players: self.max_players,
+        })
+    }
+    
+    /// Who should receive a transform update from `player_id` under the room's
+    /// current budget. None when the update comes too soon after the last one; it is
+    /// then held for `due_player_syncs`.
+    pub fn player_update_recipients(&mut self, player_id: &str, now: Instant) -> Option<Vec<String>> {
+        let budget = self.degradation.sync_budget();
+        let interval = Duration::from_millis(budget.update_interval_ms);
+        
+        if self.last_player_syncs.get(player_id).is_some_and(|last| now.duration_since(*last) < interval) {
+            self.held_player_syncs.insert(player_id.to_string());
+            return None;
+        }
+        self.last_player_syncs.insert(player_id.to_string(), now);
+        self.held_player_syncs.remove(player_id);
+        
+        let origin = self.get_player(player_id)?.transform.position.clone();
+        Some(self.players.iter()
+            .filter(|p| p.player_id != player_id && !p.is_npc)
+            .filter(|p| budget.aoi_radius.is_none_or(|radius| origin.distance(&p.transform.position) <= radius))
+            .map(|p| p.player_id.clone())
+            .collect())
+    }
+    
+    /// Held-back updates whose interval has passed: each player's latest transform
+    /// with who should receive it
+    pub fn due_player_syncs(&mut self, now: Instant) -> Vec<(String, PlayerTransform, Vec<String>)> {
+        let mut held: Vec<String> = self.held_player_syncs.iter().cloned().collect();
+        held.sort();
+        
+        held.into_iter()
+            .filter_map(|player_id| {
+                let recipients = self.player_update_recipients(&player_id, now)?;
+                let transform = self.get_player(&player_id)?.transform.clone();
+                Some((player_id, transform, recipients))
+            })
+            .collect()
+    }
+}
+
+// ============================================
+// Connection Quality
+// ============================================
+
+/// Round trips slower than this count as a poor connection
+pub const POOR_CONNECTION_RTT_MS: u32 = 250;
+/// Packet loss (0-1) above this counts as a poor connection
+pub const POOR_CONNECTION_LOSS: f32 = 0.05;
+/// A player whose last transform is older than this looks frozen to everyone else
+pub const STALE_UPDATE_AGE: Duration = Duration::from_secs(1);
+
+/// Latest measurements of a player's link, fed in by the transport
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionSample {
+    pub rtt_ms: u32,
+    /// Fraction (0-1) of packets lost
+    pub packet_loss: f32,
+}
+
+/// One player's line in a `RoomNetworkReport`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerNetworkStats {
+    pub player_id: String,
+    /// None until the transport has measured the player
+    pub rtt_ms: Option<u32>,
+    pub packet_loss: f32,
+    /// Time since the player's last transform update; None if they never sent one
+    pub last_update_age_ms: Option<u64>,
+    /// Slow, lossy or stale; games show a "poor connection" icon over the avatar
+    pub poor_connection: bool,
+}
+
+impl PlayerNetworkStats {
+    fn new(player_id: &str, sample: Option<ConnectionSample>, last_update_age: Option<Duration>) -> Self {
+        let rtt_ms = sample.map(|s| s.rtt_ms);
+        let packet_loss = sample.map(|s| s.packet_loss).unwrap_or(0.0);
+        let poor_connection = rtt_ms.is_some_and(|rtt| rtt > POOR_CONNECTION_RTT_MS)
+            || packet_loss > POOR_CONNECTION_LOSS
+            || last_update_age.is_some_and(|age| age > STALE_UPDATE_AGE);
+        
+        Self {
+            player_id: player_id.to_string(),
+            rtt_ms,
+            packet_loss,
+            last_update_age_ms: last_update_age.map(|age| age.as_millis() as u64),
+            poor_connection,
+        }
+    }
+}
+
+// ============================================
+// Timed Rooms
+// ============================================
+
+/// Longest time limit a room can be given
+pub const MAX_ROOM_TIME_LIMIT_SECONDS: i64 = 24 * 60 * 60;
+
+fn default_time_warnings() -> Vec<i64> {
+    vec![300, 60, 10]
+}
+
+/// Duration limit for a room, e.g. a class period or an arcade session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeLimitConfig {
+    pub duration_seconds: i64,
+    /// Seconds before the end at which everyone is told how long is left
+    #[serde(default = "default_time_warnings")]
+    pub warnings: Vec<i64>,
+    /// Joins are refused this many seconds before the end
+    #[serde(default)]
+    pub lock_joins_seconds: i64,
+}
+
+impl TimeLimitConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.duration_seconds <= 0 {
+            return Err("Time limits must be positive".to_string());
+        }
+        if self.duration_seconds > MAX_ROOM_TIME_LIMIT_SECONDS {
+            return Err(format!("Time limits can't exceed {} seconds", MAX_ROOM_TIME_LIMIT_SECONDS));
+        }
+        if self.warnings.iter().any(|&w| w <= 0) {
+            return Err("Warnings must come before the end".to_string());
+        }
+        if !(0..=self.duration_seconds).contains(&self.lock_joins_seconds) {
+            return Err(format!("Join lock must be between 0 and {} seconds", self.duration_seconds));
+        }
+        Ok(())
+    }
+}
+
+/// Running countdown of a time-limited room
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoomCountdown {
+    pub ends_at: i64,
+    pub lock_joins_at: i64,
+    pending_warnings: Vec<i64>, // seconds remaining not yet announced, largest first
+}
+
+impl RoomCountdown {
+    pub fn new(config: &TimeLimitConfig, now: i64) -> Self {
+        let mut pending_warnings: Vec<i64> = config.warnings.iter()
+            .copied()
+            .filter(|&w| w < config.duration_seconds)
+            .collect();
+        pending_warnings.sort_unstable_by(|a, b| b.cmp(a));
+        pending_warnings.dedup();
+        
+        Self {
+            ends_at: now + config.duration_seconds,
+            lock_joins_at: now + config.duration_seconds - config.lock_joins_seconds,
+            pending_warnings,
+        }
+    }
+    
+    pub fn seconds_remaining(&self, now: i64) -> i64 {
+        (self.ends_at - now).max(0)
+    }
+    
+    pub fn joins_locked(&self, now: i64) -> bool {
+        now >= self.lock_joins_at
+    }
+    
+    pub fn is_over(&self, now: i64) -> bool {
+        now >= self.ends_at
+    }
+    
+    /// Whether a warning point was reached since the last call. Points passed
+    /// between ticks are announced once, not once each.
+    fn take_due_warning(&mut self, now: i64) -> bool {
+        let remaining = self.seconds_remaining(now);
+        let due = self.pending_warnings.iter().take_while(|&&w| remaining <= w).count();
+        self.pending_warnings.drain(..due);
+        due > 0
+    }
+    
+    fn remaining_message(&self, room_id: &str, now: i64) -> GameMessage {
+        GameMessage::RoomTimeRemaining {
+            room_id: room_id.to_string(),
+            seconds_remaining: self.seconds_remaining(now),
+            joins_locked: self.joins_locked(now),
+        }
+    }
+}
+
+// ============================================
+// Room Browser
+// ============================================
+
+const MAX_ROOM_PAGE: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomSummary {
+    pub room_id: String,
+    pub game_id: String,
+    pub player_count: usize,
+    pub max_players: usize,
+    pub created_at: i64,
+    pub region: Option<String>,
+    pub content_rating: ContentRating,
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub template_id: Option<String>,
+    #[serde(default)]
+    pub scene: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum RoomSort {
+    #[default]
+    MostPlayers,
+    FewestPlayers,
+    Newest,
+    Oldest,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoomQuery {
+    #[serde(default)]
+    pub sort: RoomSort,
+    /// `next_cursor` from the previous page
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// Page size; 0 means the maximum
+    #[serde(default)]
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomPage {
+    pub rooms: Vec<RoomSummary>,
+    /// Matching rooms across all pages
+    pub total: usize,
+    pub next_cursor: Option<String>,
+}
+
+/// Position of a room in a sorted listing. Cursors encode the last key of a page, so
+/// rooms opening or closing between requests don't shift later pages.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct RoomSortKey {
+    group: u8,
+    value: i64,
+    room_id: String,
+}
+
+impl RoomSortKey {
+    fn new(room: &GameRoom, sort: RoomSort, group: u8) -> Self {
+        let value = match sort {
+            RoomSort::MostPlayers => -(room.player_count() as i64),
+            RoomSort::FewestPlayers => room.player_count() as i64,
+            RoomSort::Newest => -room.created_at,
+            RoomSort::Oldest => room.created_at,
+        };
+        
+        Self { group, value, room_id: room.room_id.clone() }
+    }
+    
+    fn to_cursor(&self) -> String {
+        format!("{}:{}:{}", self.group, self.value, self.room_id)
+    }
+    
+    fn from_cursor(cursor: &str) -> Result<Self, String> {
+        let mut parts = cursor.splitn(3, ':');
+        let invalid = || "Invalid cursor".to_string();
+        
+        Ok(Self {
+            group: parts.next().and_then(|p| p.parse().ok()).ok_or_else(invalid)?,
+            value: parts.next().and_then(|p| p.parse().ok()).ok_or_else(invalid)?,
+            room_id: parts.next().ok_or_else(invalid)?.to_string(),
+        })
+    }
+}
+
+impl GameRoom {
+    pub fn summary(&self) -> RoomSummary {
+        RoomSummary {
+            room_id: self.room_id.clone(),
+            game_id: self.game_id.clone(),
+            player_count: self.player_count(),
+            max_players: self.max_players,
+            created_at: self.created_at,
+            region: self.region.clone(),
+            content_rating: self.content_rating,
+            tags: self.tags.clone(),
+            template_id: self.template_id.clone(),
+            scene: self.scene.clone(),
+        }
+    }
+}
+
+// ============================================
+// Room Templates
+// ============================================
+
+/// What players other than the host may do in a room
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomPermissions {
+    pub players_can_spawn_objects: bool,
+    /// Without this only the host and players they grant may share their screen
+    pub players_can_screen_share: bool,
+}
+
+impl Default for RoomPermissions {
+    fn default() -> Self {
+        Self {
+            players_can_spawn_objects: true,
+            players_can_screen_share: false,
+        }
+    }
+}
+
+/// Room metadata key holding the room's permission zones as a JSON list
+pub const PERMISSION_ZONES_KEY: &str = "permission_zones";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ZoneAccess {
+    Everyone,
+    HostOnly,
+}
+
+/// An axis-aligned box of the room where only some players may grab or move objects,
+/// e.g. a host-only stage
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PermissionZone {
+    pub name: String,
+    pub min: Vector3,
+    pub max: Vector3,
+    pub access: ZoneAccess,
+}
+
+impl PermissionZone {
+    pub fn contains(&self, point: &Vector3) -> bool {
+        (self.min.x..=self.max.x).contains(&point.x)
+            && (self.min.y..=self.max.y).contains(&point.y)
+            && (self.min.z..=self.max.z).contains(&point.z)
+    }
+    
+    pub fn allows(&self, player_id: &str, host_id: &str) -> bool {
+        match self.access {
+            ZoneAccess::Everyone => true,
+            ZoneAccess::HostOnly => player_id == host_id,
+        }
+    }
+}
+
+/// Read the zones listed under `PERMISSION_ZONES_KEY`, if any
+pub fn parse_permission_zones(metadata: &HashMap<String, String>) -> Result<Vec<PermissionZone>, String> {
+    let Some(raw) = metadata.get(PERMISSION_ZONES_KEY) else { return Ok(Vec::new()) };
+    let zones: Vec<PermissionZone> = serde_json::from_str(raw)
+        .map_err(|e| format!("Invalid {}: {}", PERMISSION_ZONES_KEY, e))?;
+    
+    for zone in &zones {
+        zone.min.validate().and(zone.max.validate())
+            .map_err(|e| format!("Zone {}: {}", zone.name, e))?;
+        if zone.min.x > zone.max.x || zone.min.y > zone.max.y || zone.min.z > zone.max.z {
+            return Err(format!("Zone {}: min must not exceed max", zone.name));
+        }
+    }
+    Ok(zones)
+}
+
+/// A named room configuration registered by a game's developers, so every room
+/// created from it is set up the same way
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomTemplate {
+    pub template_id: String,
+    pub game_id: String,
+    pub name: String,
+    pub max_players: usize,
+    #[serde(default)]
+    pub soft_cap: Option<usize>,
+    #[serde(default)]
+    pub scene: Option<String>,
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    /// Prefabs spawned at the origin when a room is created, asleep and unowned
+    #[serde(default)]
+    pub spawn_prefabs: Vec<String>,
+    #[serde(default)]
+    pub permissions: RoomPermissions,
+    pub is_public: bool,
+    /// Rooms from this template close on their own after this long
+    #[serde(default)]
+    pub time_limit: Option<TimeLimitConfig>,
+}
+
+// ============================================
+// Multiplayer Server
+// ============================================
+
+pub struct MultiplayerServer {
+    rooms: Arc<Mutex<HashMap<String, GameRoom>>>,
+    player_to_room: Arc<Mutex<HashMap<String, String>>>, // player_id -> room_id
+    connections: Arc<Mutex<HashMap<String, SocketAddr>>>, // player_id -> address
+    last_heartbeats: Arc<Mutex<HashMap<String, Instant>>>, // player_id -> last sign of life
+    connection_samples: Arc<Mutex<HashMap<String, ConnectionSample>>>, // player_id -> latest link measurements
+    npc_controllers: Arc<Mutex<HashMap<String, Box<dyn NpcController>>>>, // player_id -> controller
+    room_scripts: Arc<Mutex<HashMap<String, Box<dyn RoomScript>>>>, // room_id -> script
+    pending_rpcs: Arc<Mutex<HashMap<String, PendingRpc>>>, // routed call_id -> call
+    connected_players: Arc<Mutex<HashMap<String, PlayerState>>>, // player_id -> verified state
+    join_tickets: Arc<Mutex<HashMap<String, JoinTicket>>>, // ticket -> reservation
+    worlds: Arc<Mutex<HashMap<String, WorldTemplate>>>, // world_id -> template
+    world_instances: Arc<Mutex<HashMap<String, Vec<String>>>>, // world_id -> [room_ids], primary first
+    player_regions: Arc<Mutex<HashMap<String, String>>>, // player_id -> region
+    game_ratings: Arc<Mutex<HashMap<String, ContentRating>>>, // game_id -> rating
+    game_attenuation: Arc<Mutex<HashMap<String, AttenuationProfile>>>, // game_id -> voice falloff
+    moderation_log: Arc<Mutex<Vec<ModerationAuditEntry>>>,
+    prefabs: Arc<Mutex<HashMap<String, Prefab>>>, // name -> prefab
+    room_templates: Arc<Mutex<HashMap<String, RoomTemplate>>>, // template_id -> template
+    room_creations: IdempotencyCache<String>, // -> room_id
+    match_submissions: IdempotencyCache<MatchSubmissionStatus>,
+    presence_cache: TtlCache<String, PlayerPresence>, // player_id -> presence before privacy filtering
+    room_page_cache: TtlCache<String, RoomPage>, // game_id + query -> page
+    auth: Option<Arc<AuthService>>,
+    geoip: Option<Arc<dyn GeoIpLookup>>,
+    clock: Arc<dyn Clock>,
+    message_sink: Option<Arc<dyn MessageSink>>,
+    chaos_mode: bool,
+    network_chaos: Arc<Mutex<HashMap<String, ChaosLink<GameMessage>>>>, // player_id -> messages held back by injected conditions
+    diagnostics: Option<Arc<DiagnosticsService>>,
+    analytics: Option<Arc<AnalyticsService>>,
+    usage: Option<Arc<UsageService>>,
+    seasons: Option<Arc<SeasonService>>,
+    challenges: Option<Arc<ChallengeService>>,
+    write_behind: Option<Arc<WriteBehindQueue>>,
+    cluster: Option<Arc<dyn ClusterDirectory>>,
+    /// This instance's name in the cluster
+    server_id: String,
+    /// Address clients connect to this instance on
+    public_addr: String,
+}
+
+/// Delivers outgoing messages to connected players, e.g. over the game transport
+pub trait MessageSink: Send + Sync {
+    fn deliver(&self, player_id: &str, message: &GameMessage);
+}
+
+const JOIN_TICKET_TTL_SECONDS: i64 = 60;
+/// Players who haven't sent a heartbeat (or joined a room) for this long are disconnected
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(15);
+/// Rooms nobody has joined are collected this long after creation
+pub const IDLE_ROOM_GRACE_SECONDS: i64 = 60;
+/// Capacity of the private tutorial instances created by `route_to_tutorial`
+const TUTORIAL_ROOM_MAX_PLAYERS: usize = 1;
+const PRESENCE_CACHE_TTL: Duration = Duration::from_secs(5);
+/// Room lists tolerate a little staleness; joins, leaves and new rooms invalidate them anyway
+const ROOM_PAGE_CACHE_TTL: Duration = Duration::from_secs(2);
+
+impl MultiplayerServer {
+    pub fn new() -> Self {
+        println!("[MultiplayerServer] Initializing multiplayer server...");
+        
+        Self {
+            rooms: Arc::new(Mutex::new(HashMap::new())),
+            player_to_room: Arc::new(Mutex::new(HashMap::new())),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            last_heartbeats: Arc::new(Mutex::new(HashMap::new())),
+            connection_samples: Arc::new(Mutex::new(HashMap::new())),
+            npc_controllers: Arc::new(Mutex::new(HashMap::new())),
+            room_scripts: Arc::new(Mutex::new(HashMap::new())),
+            pending_rpcs: Arc::new(Mutex::new(HashMap::new())),
+            connected_players: Arc::new(Mutex::new(HashMap::new())),
+            join_tickets: Arc::new(Mutex::new(HashMap::new())),
+            worlds: Arc::new(Mutex::new(HashMap::new())),
+            world_instances: Arc::new(Mutex::new(HashMap::new())),
+            player_regions: Arc::new(Mutex::new(HashMap::new())),
+            game_ratings: Arc::new(Mutex::new(HashMap::new())),
+            game_attenuation: Arc::new(Mutex::new(HashMap::new())),
+            moderation_log: Arc::new(Mutex::new(Vec::new())),
+            prefabs: Arc::new(Mutex::new(HashMap::new())),
+            room_templates: Arc::new(Mutex::new(HashMap::new())),
+            room_creations: IdempotencyCache::default(),
+            match_submissions: IdempotencyCache::default(),
+            presence_cache: TtlCache::new("presence", PRESENCE_CACHE_TTL),
+            room_page_cache: TtlCache::new("room_pages", ROOM_PAGE_CACHE_TTL),
+            auth: None,
+            geoip: None,
+            clock: Arc::new(SystemClock),
+            message_sink: None,
+            chaos_mode: false,
+            network_chaos: Arc::new(Mutex::new(HashMap::new())),
+            diagnostics: None,
+            analytics: None,
+            usage: None,
+            seasons: None,
+            challenges: None,
+            write_behind: None,
+            cluster: None,
+            server_id: "local".to_string(),
+            public_addr: String::new(),
+        }
+    }
+    
+    /// Create a server that authenticates `Connect` requests against `auth`
+    pub fn with_auth(auth: Arc<AuthService>) -> Self {
+        Self {
+            auth: Some(auth),
+            ..Self::new()
+        }
+    }
+    
+    /// Tag connecting players with a region looked up from their address
+    pub fn with_geoip(mut self, geoip: Arc<dyn GeoIpLookup>) -> Self {
+        self.geoip = Some(geoip);
+        self
+    }
+    
+    /// Read time from `clock` instead of the system clock, e.g. a `ManualClock` in simulations
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+    
+    pub fn with_message_sink(mut self, sink: Arc<dyn MessageSink>) -> Self {
+        self.message_sink = Some(sink);
+        self
+    }
+    
+    /// Development mode: let admins inject latency, jitter, reordering and loss into
+    /// individual connections with `set_network_chaos`. Never enable in production.
+    pub fn with_chaos_mode(mut self) -> Self {
+        println!("[MultiplayerServer] Chaos mode enabled; network conditions can be degraded per connection");
+        self.chaos_mode = true;
+        self
+    }
+    
+    /// Record timed-out connections so the client can upload diagnostics for them
+    pub fn with_diagnostics(mut self, diagnostics: Arc<DiagnosticsService>) -> Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+    
+    /// Record sessions, concurrency and room fill for developer dashboards
+    pub fn with_analytics(mut self, analytics: Arc<AnalyticsService>) -> Self {
+        self.analytics = Some(analytics);
+        self
+    }
+    
+    /// Meter each developer's usage and enforce their quotas on connect and room creation
+    pub fn with_usage(mut self, usage: Arc<UsageService>) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+    
+    /// Record finished matches through `seasons`, so they also earn season XP
+    pub fn with_seasons(mut self, seasons: Arc<SeasonService>) -> Self {
+        self.seasons = Some(seasons);
+        self
+    }
+    
+    /// Feed finished matches into the players' challenges
+    pub fn with_challenges(mut self, challenges: Arc<ChallengeService>) -> Self {
+        self.challenges = Some(challenges);
+        self
+    }
+    
+    /// Persist presence changes through `queue` without blocking the game loop
+    pub fn with_write_behind(mut self, queue: Arc<WriteBehindQueue>) -> Self {
+        self.write_behind = Some(queue);
+        self
+    }
+    
+    /// Share presence with the other instances in `cluster`, so friends on any
+    /// instance can be found and joined. `public_addr` is handed to joining clients.
+    pub fn with_cluster(mut self, cluster: Arc<dyn ClusterDirectory>, server_id: &str, public_addr: &str) -> Self {
+        self.cluster = Some(cluster);
+        self.server_id = server_id.to_string();
+        self.public_addr = public_addr.to_string();
+        self
+    }
+    
+    pub fn server_id(&self) -> &str {
+        &self.server_id
+    }
+    
+    /// Handle a `Connect` request: verify the token and build the player's state
+    /// from the verified account rather than anything the client claims.
+    /// `region` overrides the region detected from the client's address.
+    pub fn connect(
+        &self,
+        token: &str,
+        game_id: &str,
+        addr: SocketAddr,
+        region: Option<String>,
+    ) -> Result<PlayerState, String> {
+        let auth = self.auth.as_ref().ok_or_else(|| "Authentication not configured".to_string())?;
+        
+        let audience = TokenAudience::GameSession(game_id.to_string());
+        let profile = auth.authenticate(token, &audience).map_err(|e| {
+            println!("[MultiplayerServer] Connect rejected from {}: {}", addr, e);
+            e
+        })?;
+        
+        let player = self.register_connection(auth, profile, game_id, addr, region)?;
+        if let Ok(state) = auth.onboarding_state(&player.player_id, game_id) {
+            self.send_onboarding_status(&player.player_id, state);
+        }
+        
+        Ok(player)
+    }
+    
+    /// Mark a tutorial step done for a connected player and send them the new status
+    pub fn complete_tutorial_step(&self, player_id: &str, game_id: &str, step: &str) -> Result<OnboardingState, String> {
+        let auth = self.auth.as_ref().ok_or_else(|| "Authentication not configured".to_string())?;
+        let state = auth.complete_tutorial_step(player_id, game_id, step)?;
+        
+        self.send_onboarding_status(player_id, state.clone());
+        Ok(state)
+    }
+    
+    /// Mark a room as a tutorial instance that first-time players may be routed to
+    pub fn set_room_tutorial(&self, room_id: &str, is_tutorial: bool) -> Result<(), String> {
+        self.rooms.lock().unwrap()
+            .get_mut(room_id)
+            .ok_or_else(|| "Room not found".to_string())?
+            .is_tutorial = is_tutorial;
+        Ok(())
+    }
+    
+    /// Send a connected player who hasn't finished the game's tutorial to a tutorial
+    /// room: an open one if there is one, otherwise a new private instance. Returns
+    /// the room joined, or None when the player needs no tutorial.
+    pub fn route_to_tutorial(&self, player_id: &str, game_id: &str) -> Result<Option<String>, String> {
+        let auth = self.auth.as_ref().ok_or_else(|| "Authentication not configured".to_string())?;
+        if !auth.onboarding_state(player_id, game_id)?.needs_tutorial() {
+            return Ok(None);
+        }
+        
+        let open_room = self.rooms.lock().unwrap()
+            .values()
+            .filter(|room| room.is_tutorial && room.game_id == game_id && !room.is_full())
+            .min_by_key(|room| room.created_at)
+            .map(|room| room.room_id.clone());
+        
+        let room_id = match open_room {
+            Some(room_id) => room_id,
+            None => {
+                let room_id = self.open_room(game_id.to_string(), player_id.to_string(), TUTORIAL_ROOM_MAX_PLAYERS)?;
+                if let Some(room) = self.rooms.lock().unwrap().get_mut(&room_id) {
+                    room.is_tutorial = true;
+                    room.is_public = false;
+                }
+                room_id
+            }
+        };
+        
+        self.join_room_connected(&room_id, player_id)?;
+        println!("[MultiplayerServer] Routed {} to tutorial room {}", player_id, room_id);
+        
+        Ok(Some(room_id))
+    }
+    
+    /// Mint a short-lived, single-use ticket for entering a room. Clients hand
+    /// the ticket to the game transport instead of their long-lived token.
+    pub fn mint_join_ticket(&self, token: &str, room_id: &str) -> Result<String, String> {
+        let auth = self.auth.as_ref().ok_or_else(|| "Authentication not configured".to_string())?;
+        let profile = auth.authenticate(token, &TokenAudience::Platform)?;
+        
+        if !self.rooms.lock().unwrap().contains_key(room_id) {
+            return Err("Room not found".to_string());
+        }
+        
+        Ok(self.issue_join_ticket(room_id, &profile.id).0)
+    }
+    
+    /// Find the room a friend is in, on this instance or another in the cluster, and
+    /// get a join ticket for it if privacy, capacity and invite rules allow
+    pub fn join_friend(&self, player_id: &str, friend_id: &str) -> Result<FriendJoinDetails, JoinDenial> {
+        let auth = self.auth.as_ref().ok_or(JoinDenial::Unavailable)?;
+        
+        if !auth.get_friends(player_id).iter().any(|f| f == friend_id) {
+            return Err(JoinDenial::NotFriends);
+        }
+        if !auth.privacy_allows(player_id, friend_id, PrivacyAction::ViewPresence) {
+            return Err(JoinDenial::PresenceHidden);
+        }
+        
+        if let Some(room_id) = self.player_room(friend_id) {
+            return self.check_room_join(&room_id, player_id);
+        }
+        if self.connected_players.lock().unwrap().contains_key(friend_id) {
+            return Err(JoinDenial::NotInRoom);
+        }
+        
+        let cluster = self.cluster.as_ref().ok_or(JoinDenial::Offline)?;
+        let location = cluster.locate(friend_id)
+            .filter(|location| location.server_id != self.server_id)
+            .ok_or(JoinDenial::Offline)?;
+        if location.room_id.is_none() {
+            return Err(JoinDenial::NotInRoom);
+        }
+        
+        cluster.check_remote_join(&location, player_id)
+    }
+    
+    /// Whether `player_id` may join a room on this instance; if so, a join ticket
+    /// and where to use it. Other instances call this through the cluster.
+    pub fn check_room_join(&self, room_id: &str, player_id: &str) -> Result<FriendJoinDetails, JoinDenial> {
+        let game_id = {
+            let rooms = self.rooms.lock().unwrap();
+            let room = rooms.get(room_id).ok_or(JoinDenial::RoomNotFound)?;
+            
+            if room.is_full() {
+                return Err(JoinDenial::RoomFull);
+            }
+            if room.countdown.as_ref().is_some_and(|c| c.joins_locked(self.clock.timestamp())) {
+                return Err(JoinDenial::RoomClosing);
+            }
+            if !room.is_public && !room.invited.contains(player_id) {
+                return Err(JoinDenial::InviteRequired);
+            }
+            if let Some(auth) = &self.auth {
+                auth.check_content_access(player_id, room.content_rating, &room.tags)
+                    .map_err(|reason| JoinDenial::ContentRestricted { reason })?;
+            }
+            room.game_id.clone()
+        };
+        
+        let (ticket, expires_at) = self.issue_join_ticket(room_id, player_id);
+        
+        Ok(FriendJoinDetails {
+            server_id: self.server_id.clone(),
+            server_addr: self.public_addr.clone(),
+            room_id: room_id.to_string(),
+            game_id,
+            ticket,
+            expires_at,
+        })
+    }
+    
+    /// Consume a join ticket: connect its player and place them in the ticket's room.
+    /// A ticket works once; it is handed back only if the room could not take the player.
+    pub fn join_with_ticket(&self, ticket: &str, addr: SocketAddr) -> Result<PlayerState, String> {
+        let auth = self.auth.as_ref().ok_or_else(|| "Authentication not configured".to_string())?;
+        
+        let reservation = self.join_tickets.lock().unwrap()
+            .remove(ticket)
+            .ok_or_else(|| "Invalid or already used ticket".to_string())?;
+        
+        if reservation.expires_at <= self.clock.timestamp() {
+            return Err("Ticket expired".to_string());
+        }
+        
+        let profile = auth.get_active_user(&reservation.player_id)?;
+        let game_id = self.get_room(&reservation.room_id)
+            .map(|room| room.game_id)
+            .ok_or_else(|| "Room not found".to_string())?;
+        
+        let player = match self.register_connection(auth, profile, &game_id, addr, None) {
+            Ok(player) => player,
+            Err(e) => {
+                self.join_tickets.lock().unwrap().insert(ticket.to_string(), reservation);
+                return Err(e);
+            }
+        };
+        
+        if let Err(e) = self.join_room(&reservation.room_id, player.clone()) {
+            self.join_tickets.lock().unwrap().insert(ticket.to_string(), reservation);
+            return Err(e);
+        }
+        
+        Ok(player)
+    }
+    
+    /// Join a room as a previously connected (authenticated) player
+    pub fn join_room_connected(&self, room_id: &str, player_id: &str) -> Result<(), String> {
+        let player = self.connected_players.lock().unwrap()
+            .get(player_id)
+            .cloned()
+            .ok_or_else(|| "Player is not connected".to_string())?;
+        
+        self.join_room(room_id, player)
+    }
+    
+    /// Pull a connected player's latest profile from the account service and show
+    /// the new display name and avatar to their room
+    pub fn refresh_player_profile(&self, player_id: &str) -> Result<(), String> {
+        let auth = self.auth.as_ref().ok_or_else(|| "Authentication not configured".to_string())?;
+        let profile = auth.get_active_user(player_id)?;
+        
+        if let Some(player) = self.connected_players.lock().unwrap().get_mut(player_id) {
+            player.display_name = profile.display_name.clone();
+            player.avatar_url = profile.avatar_url.clone();
+        }
+        
+        let room_id = match self.player_room(player_id) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        
+        if let Some(room) = self.rooms.lock().unwrap().get_mut(&room_id) {
+            if let Some(player) = room.players.iter_mut().find(|p| p.player_id == player_id) {
+                player.display_name = profile.display_name.clone();
+                player.avatar_url = profile.avatar_url.clone();
+            }
+        }
+        
+        self.broadcast_to_room(&room_id, GameMessage::PlayerProfileUpdated {
+            player_id: player_id.to_string(),
+            display_name: profile.display_name,
+            avatar_url: profile.avatar_url,
+        }, None);
+        
+        Ok(())
+    }
+    
+    /// Drop a player's connection and remove them from their room
+    pub fn disconnect(&self, player_id: &str) -> Option<String> {
+        if let Some(analytics) = &self.analytics {
+            analytics.session_ended(player_id, self.clock.timestamp());
+        }
+        if let Some(usage) = &self.usage {
+            usage.player_disconnected(player_id, self.clock.timestamp());
+        }
+        self.connections.lock().unwrap().remove(player_id);
+        self.last_heartbeats.lock().unwrap().remove(player_id);
+        self.connection_samples.lock().unwrap().remove(player_id);
+        self.network_chaos.lock().unwrap().remove(player_id);
+        self.connected_players.lock().unwrap().remove(player_id);
+        self.player_regions.lock().unwrap().remove(player_id);
+        self.presence_changed(player_id);
+        
+        println!("[MultiplayerServer] Player {} disconnected", player_id);
+        
+        self.leave_room(player_id)
+    }
+    
+    /// Record a heartbeat from a connected or seated player
+    pub fn heartbeat(&self, player_id: &str) -> Result<(), String> {
+        let known = self.connections.lock().unwrap().contains_key(player_id)
+            || self.player_to_room.lock().unwrap().contains_key(player_id);
+        if !known {
+            return Err("Not connected".to_string());
+        }
+        
+        self.last_heartbeats.lock().unwrap().insert(player_id.to_string(), self.clock.now());
+        Ok(())
+    }
+    
+    /// Disconnect players whose last heartbeat is older than `HEARTBEAT_TIMEOUT`.
+    /// Returns the players that were dropped.
+    pub fn tick_heartbeats(&self, now: Instant) -> Vec<String> {
+        let mut timed_out: Vec<String> = self.last_heartbeats.lock().unwrap()
+            .iter()
+            .filter(|(_, last)| now.saturating_duration_since(**last) >= HEARTBEAT_TIMEOUT)
+            .map(|(player_id, _)| player_id.clone())
+            .collect();
+        timed_out.sort();
+        
+        for player_id in &timed_out {
+            println!("[MultiplayerServer] Player {} timed out", player_id);
+            let room_id = self.disconnect(player_id);
+            
+            if let Some(diagnostics) = &self.diagnostics {
+                diagnostics.record_disconnect(player_id, room_id, DisconnectReason::HeartbeatTimeout, self.clock.timestamp());
+            }
+        }
+        
+        timed_out
+    }
+    
+    /// Record a player's latest RTT and packet loss as measured by the transport
+    pub fn record_connection_sample(&self, player_id: &str, sample: ConnectionSample) -> Result<(), String> {
+        if !sample.packet_loss.is_finite() || !(0.0..=1.0).contains(&sample.packet_loss) {
+            return Err("Packet loss must be between 0 and 1".to_string());
+        }
+        if !self.connections.lock().unwrap().contains_key(player_id) && self.player_room(player_id).is_none() {
+            return Err("Not connected".to_string());
+        }
+        
+        self.connection_samples.lock().unwrap().insert(player_id.to_string(), sample);
+        Ok(())
+    }
+    
+    /// Connection quality of every human player in a room, in join order
+    pub fn room_network_stats(&self, room_id: &str, now: Instant) -> Option<Vec<PlayerNetworkStats>> {
+        let players: Vec<(String, Option<Duration>)> = {
+            let rooms = self.rooms.lock().unwrap();
+            let room = rooms.get(room_id)?;
+            room.players.iter()
+                .filter(|p| !p.is_npc)
+                .map(|p| {
+                    let age = room.last_update_at(&p.player_id).map(|at| now.saturating_duration_since(at));
+                    (p.player_id.clone(), age)
+                })
+                .collect()
+        };
+        
+        let samples = self.connection_samples.lock().unwrap();
+        Some(players.iter()
+            .map(|(player_id, age)| PlayerNetworkStats::new(player_id, samples.get(player_id).copied(), *age))
+            .collect())
+    }
+    
+    /// Send each room's host a `RoomNetworkReport`. Call every couple of seconds.
+    /// Returns how many reports were sent.
+    pub fn tick_network_reports(&self, now: Instant) -> usize {
+        let hosts: Vec<(String, String)> = self.rooms.lock().unwrap()
+            .values()
+            .map(|room| (room.room_id.clone(), room.host_id.clone()))
+            .collect();
+        
+        let mut sent = 0;
+        for (room_id, host_id) in hosts {
+            let Some(players) = self.room_network_stats(&room_id, now) else { continue };
+            if self.player_room(&host_id).as_deref() != Some(room_id.as_str()) {
+                continue;
+            }
+            
+            self.send_to_player(&host_id, &GameMessage::RoomNetworkReport { room_id, players });
+            sent += 1;
+        }
+        sent
+    }
+    
+    /// Close rooms that still have no human players `IDLE_ROOM_GRACE_SECONDS` after
+    /// creation, e.g. when the host never arrived. Persistent rooms are kept.
+    pub fn collect_idle_rooms(&self, now: i64) -> Vec<String> {
+        let mut idle: Vec<String> = self.rooms.lock().unwrap()
+            .values()
+            .filter(|room| !room.is_persistent && room.human_count() == 0)
+            .filter(|room| now - room.created_at >= IDLE_ROOM_GRACE_SECONDS)
+            .map(|room| room.room_id.clone())
+            .collect();
+        idle.sort();
+        
+        for room_id in &idle {
+            if let Err(e) = self.close_room(room_id) {
+                eprintln!("[MultiplayerServer] Failed to collect room {}: {}", room_id, e);
+            }
+        }
+        
+        idle
+    }
+    
+    /// Create a server-owned room without checking the developer's quota. Rooms
+    /// players ask for go through `open_room`.
+    pub(crate) fn create_room(&self, game_id: String, host_id: String, max_players: usize) -> String {
+        self.insert_room(self.new_room(game_id, host_id, max_players))
+    }
+    
+    /// Create a room on behalf of a player, refusing it when the game's developer
+    /// is out of quota
+    pub fn open_room(&self, game_id: String, host_id: String, max_players: usize) -> Result<String, String> {
+        self.check_room_quota(&game_id)?;
+        Ok(self.create_room(game_id, host_id, max_players))
+    }
+    
+    fn check_room_quota(&self, game_id: &str) -> Result<(), String> {
+        let Some(usage) = &self.usage else { return Ok(()) };
+        usage.check_room_creation(game_id, self.clock.timestamp()).map_err(|e| {
+            println!("[MultiplayerServer] Room for {} refused: {}", game_id, e);
+            e
+        })
+    }
+    
+    /// A room with the server's defaults for its game, not yet visible to anyone
+    fn new_room(&self, game_id: String, host_id: String, max_players: usize) -> GameRoom {
+        let room_id = format!("room_{}", uuid::Uuid::new_v4());
+        let mut room = GameRoom::new(room_id, game_id, host_id, max_players);
+        room.created_at = self.clock.timestamp();
+        
+        // Rooms are placed in their host's region
+        room.region = self.player_region(&room.host_id);
+        room.content_rating = self.game_rating(&room.game_id);
+        room.attenuation = self.game_attenuation.lock().unwrap().get(&room.game_id).cloned().unwrap_or_default();
+        room
+    }
+    
+    fn insert_room(&self, room: GameRoom) -> String {
+        let room_id = room.room_id.clone();
+        if let Some(usage) = &self.usage {
+            usage.room_opened(&room_id, &room.game_id, room.created_at);
+        }
+        
+        self.rooms.lock().unwrap().insert(room_id.clone(), room);
+        self.room_page_cache.invalidate_all();
+        
+        println!("[MultiplayerServer] Room created: {}", room_id);
+        
+        room_id
+    }
+    
+    /// Create a room at most once per idempotency key; a retry gets the same room ID
+    pub fn create_room_idempotent(&self, idempotency_key: &str, game_id: String, host_id: String, max_players: usize) -> Result<String, String> {
+        let key = format!("create_room:{}:{}", host_id, idempotency_key);
+        
+        self.room_creations.execute(&key, self.clock.timestamp(), || {
+            self.open_room(game_id, host_id, max_players)
+        })
+    }
+    
+    /// Remove a room outright, releasing everyone in it. Returns the removed player IDs.
+    pub fn close_room(&self, room_id: &str) -> Result<Vec<String>, String> {
+        let room = self.rooms.lock().unwrap()
+            .remove(room_id)
+            .ok_or_else(|| "Room not found".to_string())?;
+        
+        let player_ids: Vec<String> = room.players.iter().map(|p| p.player_id.clone()).collect();
+        
+        {
+            // Same lock order as `tick_npcs`: controllers before the player map
+            let mut controllers = self.npc_controllers.lock().unwrap();
+            let mut player_to_room = self.player_to_room.lock().unwrap();
+            for player_id in &player_ids {
+                player_to_room.remove(player_id);
+                controllers.remove(player_id);
+            }
+        }
+        self.room_scripts.lock().unwrap().remove(room_id);
+        if let Some(usage) = &self.usage {
+            usage.room_closed(room_id, self.clock.timestamp());
+        }
+        
+        for player_id in &player_ids {
+            self.presence_changed(player_id);
+            self.forget_unconnected_heartbeat(player_id);
+        }
+        
+        println!("[MultiplayerServer] Room {} closed", room_id);
+        
+        Ok(player_ids)
+    }
+    
+    /// Close every room of a game's sandbox environment (and drop its queued players
+    /// from `matchmaking`) so the developer starts from a clean slate. Pair with
+    /// `AuthService::wipe_environment` for ratings and achievements.
+    pub fn wipe_environment(
+        &self,
+        matchmaking: &MatchmakingService,
+        requester_id: &str,
+        game_id: &str,
+        environment: Environment,
+    ) -> Result<Vec<String>, String> {
+        if environment == Environment::Prod {
+            return Err("The production environment can't be wiped".to_string());
+        }
+        let auth = self.auth.as_ref().ok_or_else(|| "Authentication not configured".to_string())?;
+        if !auth.can_manage_sandbox(requester_id, game_id) {
+            return Err("Only the game's developers can wipe its environments".to_string());
+        }
+        
+        let scoped = environment.scope_game(game_id);
+        let mut room_ids: Vec<String> = self.rooms.lock().unwrap()
+            .values()
+            .filter(|room| room.game_id == scoped)
+            .map(|room| room.room_id.clone())
+            .collect();
+        room_ids.sort();
+        
+        for room_id in &room_ids {
+            self.close_room(room_id)?;
+        }
+        matchmaking.clear_game(&scoped);
+        
+        println!("[MultiplayerServer] {} wiped {}: {} rooms closed", requester_id, scoped, room_ids.len());
+        
+        Ok(room_ids)
+    }
+    
+    /// Turn a matchmaking result into a seated room. Reserving the players, creating
+    /// the room and seating everyone either all happen or are all undone: on failure
+    /// the room is closed and the players go back to the front of the queue.
+    /// Returns Ok(None) when not enough players are queued.
+    pub fn start_match(&self, matchmaking: &MatchmakingService, queue: &str, required_players: usize) -> Result<Option<String>, String> {
+        let players = match matchmaking.find_match(queue, required_players) {
+            Some(p) if !p.is_empty() => p,
+            Some(_) | None => return Ok(None),
+        };
+        
+        // Regional queues are named "game@region"
+        let game_id = queue.split('@').next().unwrap_or(queue).to_string();
+        
+        let mut saga = Saga::new("match found");
+        
+        let reserved = players.clone();
+        saga.compensate_with("reserve players", move || {
+            matchmaking.requeue(queue, &reserved);
+            Ok(())
+        });
+        
+        let room_id = saga.step(
+            "create room",
+            || self.open_room(game_id.clone(), players[0].clone(), required_players),
+            |room_id| self.close_room(&room_id).map(|_| ()),
+        )?;
+        
+        // Closing the room also releases anyone already seated
+        saga.step(
+            "seat players",
+            || players.iter().try_for_each(|p| self.join_room_connected(&room_id, p)),
+            |_| Ok(()),
+        )?;
+        
+        saga.commit();
+        
+        let found = GameMessage::MatchFound {
+            game_id,
+            room_id: room_id.clone(),
+            players: players.clone(),
+        };
+        for player_id in &players {
+            self.send_to_player(player_id, &found);
+        }
+        
+        Ok(Some(room_id))
+    }
+    
+    /// Join an existing room
+    pub fn join_room(&self, room_id: &str, player: PlayerState) -> Result<(), String> {
+        let mut rooms = self.rooms.lock().unwrap();
+        
+        if let Some(room) = rooms.get_mut(room_id) {
+            if room.is_full() {
+                return Err("Room is full".to_string());
+            }
+            if room.countdown.as_ref().is_some_and(|c| c.joins_locked(self.clock.timestamp())) {
+                return Err("Room is closing".to_string());
+            }
+            
+            if let (Some(auth), false) = (&self.auth, player.is_npc) {
+                auth.check_content_access(&player.player_id, room.content_rating, &room.tags)?;
+            }
+            
+            let player_id = player.player_id.clone();
+            let is_npc = player.is_npc;
+            
+            if room.add_player(player) {
+                let load_notice = room.refresh_degradation().map(|notice| (room.host_id.clone(), notice));
+                
+                // Update player-to-room mapping
+                drop(rooms); // Release the lock
+                let mut player_to_room = self.player_to_room.lock().unwrap();
+                player_to_room.insert(player_id.clone(), room_id.to_string());
+                drop(player_to_room);
+                self.presence_changed(&player_id);
+                if !is_npc {
+                    self.last_heartbeats.lock().unwrap().insert(player_id.clone(), self.clock.now());
+                }
+                
+                println!("[MultiplayerServer] Player {} joined room {}", player_id, room_id);
+                
+                if let Some(settings) = self.audio_settings(room_id) {
+                    self.send_to_player(&player_id, &settings);
+                }
+                if let Some((host_id, notice)) = load_notice {
+                    self.send_to_player(&host_id, &notice);
+                }
+                Ok(())
+            } else {
+                Err("Failed to add player to room".to_string())
+            }
+        } else {
+            Err("Room not found".to_string())
+        }
+    }
+    
+    /// Leave a room
+    pub fn leave_room(&self, player_id: &str) -> Option<String> {
+        let mut player_to_room = self.player_to_room.lock().unwrap();
+        
+        if let Some(room_id) = player_to_room.remove(player_id) {
+            drop(player_to_room);
+            self.presence_changed(player_id);
+            self.forget_unconnected_heartbeat(player_id);
+            
+            let mut rooms = self.rooms.lock().unwrap();
+            if let Some(room) = rooms.get_mut(&room_id) {
+                room.remove_player(player_id);
+                
+                let mut notices = Vec::new();
+                if room.screen_share.as_ref().is_some_and(|s| s.sharer_id == player_id) {
+                    room.screen_share = None;
+                    notices.push(GameMessage::ScreenShareStopped { room_id: room_id.clone() });
+                }
+                
+                let now = self.clock.timestamp();
+                if let Some(turns) = room.turns.as_mut() {
+                    if turns.remove_player(player_id, now) {
+                        notices.extend(turns.changed_message());
+                    }
+                }
+                
+                let load_notice = room.refresh_degradation().map(|notice| (room.host_id.clone(), notice));
+                
+                // Delete room once no human players remain; NPCs don't keep it alive
+                if room.human_count() == 0 && !room.is_persistent {
+                    let npc_ids: Vec<String> = room.players.iter().map(|p| p.player_id.clone()).collect();
+                    rooms.remove(&room_id);
+                    drop(rooms);
+                    
+                    // Same lock order as `tick_npcs`: controllers before the player map
+                    let mut controllers = self.npc_controllers.lock().unwrap();
+                    let mut player_to_room = self.player_to_room.lock().unwrap();
+                    for npc_id in &npc_ids {
+                        player_to_room.remove(npc_id);
+                        controllers.remove(npc_id);
+                    }
+                    self.room_scripts.lock().unwrap().remove(&room_id);
+                    if let Some(usage) = &self.usage {
+                        usage.room_closed(&room_id, now);
+                    }
+                    
+                    println!("[MultiplayerServer] Room {} deleted (empty)", room_id);
+                } else {
+                    println!("[MultiplayerServer] Player {} left room {}", player_id, room_id);
+                    
+                    drop(rooms);
+                    for message in notices {
+                        self.broadcast_to_room(&room_id, message, None);
+                    }
+                    if let Some((host_id, notice)) = load_notice {
+                        self.send_to_player(&host_id, &notice);
+                    }
+                }
+                
+                return Some(room_id);
+            }
+        }
+        
+        None
+    }
+    
+    /// Update player transform. Invalid transforms are dropped.
+    pub fn update_player(&self, player_id: &str, transform: PlayerTransform) -> Option<String> {
+        let transform = match transform.sanitized() {
+            Ok(transform) => transform,
+            Err(e) => {
+                eprintln!("[MultiplayerServer] Dropped transform from {}: {}", player_id, e);
+                return None;
+            }
+        };
+        
+        let player_to_room = self.player_to_room.lock().unwrap();
+        
+        if let Some(room_id) = player_to_room.get(player_id) {
+            let room_id = room_id.clone();
+            drop(player_to_room);
+            
+            let mut rooms = self.rooms.lock().unwrap();
+            if let Some(room) = rooms.get_mut(&room_id) {
+                room.update_player(player_id, transform.clone());
+                room.record_motion(player_id, transform, self.clock.now());
+                return Some(room_id);
+            }
+        }
+        
+        None
+    }
+    
+    /// Update a player's transform and relay it to the room under the room's sync
+    /// budget: past the soft cap updates are throttled and only reach nearby players.
+    /// Returns how many players were sent the update.
+    pub fn relay_player_update(&self, player_id: &str, transform: PlayerTransform, now: Instant) -> Result<usize, String> {
+        let transform = transform.sanitized().map_err(|e| format!("Invalid transform: {}", e))?;
+        let room_id = self.player_room(player_id).ok_or_else(|| "Player is not in a room".to_string())?;
+        
+        let recipients = {
+            let mut rooms = self.rooms.lock().unwrap();
+            let room = rooms.get_mut(&room_id).ok_or_else(|| "Room not found".to_string())?;
+            room.update_player(player_id, transform.clone());
+            room.record_motion(player_id, transform.clone(), now);
+            room.player_update_recipients(player_id, now)
+        };
+        
+        let Some(recipients) = recipients else { return Ok(0) };
+        let update = GameMessage::PlayerUpdate { player_id: player_id.to_string(), transform };
+        for recipient in &recipients {
+            self.send_to_player(recipient, &update);
+        }
+        
+        Ok(recipients.len())
+    }
+    
+    /// Relay the latest transform of players whose updates were throttled, once their
+    /// interval is up, so a player's final position always reaches the room. Call
+    /// every tick. Returns how many players were sent an update.
+    pub fn tick_player_syncs(&self, now: Instant) -> usize {
+        let due: Vec<(String, PlayerTransform, Vec<String>)> = self.rooms.lock().unwrap()
+            .values_mut()
+            .flat_map(|room| room.due_player_syncs(now))
+            .collect();
+        
+        let mut sent = 0;
+        for (player_id, transform, recipients) in due {
+            let update = GameMessage::PlayerUpdate { player_id, transform };
+            for recipient in &recipients {
+                self.send_to_player(recipient, &update);
+            }
+            sent += recipients.len();
+        }
+        sent
+    }
+    
+    /// Host-only: let the room fill past `soft_cap` with scaled-back sync, up to its
+    /// hard cap. None turns soft limiting off.
+    pub fn set_room_soft_cap(&self, room_id: &str, requester_id: &str, soft_cap: Option<usize>) -> Result<(), String> {
+        let notice = {
+            let mut rooms = self.rooms.lock().unwrap();
+            let room = rooms.get_mut(room_id).ok_or_else(|| "Room not found".to_string())?;
+            
+            if room.host_id != requester_id {
+                return Err("Only the host can change the room's capacity".to_string());
+            }
+            if soft_cap.is_some_and(|cap| cap == 0 || cap > room.max_players) {
+                return Err(format!("Soft cap must be between 1 and {}", room.max_players));
+            }
+            
+            room.soft_cap = soft_cap;
+            room.refresh_degradation().map(|notice| (room.host_id.clone(), notice))
+        };
+        
+        if let Some((host_id, notice)) = notice {
+            self.send_to_player(&host_id, &notice);
+        }
+        Ok(())
+    }
+    
+    /// Host-only: replace the room's metadata, including its permission zones under
+    /// `PERMISSION_ZONES_KEY`
+    pub fn set_room_metadata(&self, room_id: &str, requester_id: &str, metadata: HashMap<String, String>) -> Result<(), String> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = rooms.get_mut(room_id).ok_or_else(|| "Room not found".to_string())?;
+        
+        if room.host_id != requester_id {
+            return Err("Only the host can change the room's metadata".to_string());
+        }
+        room.set_metadata(metadata)
+    }
+    
+    /// A player's transform at `at`, extrapolated from their recent updates
+    pub fn predicted_transform(&self, player_id: &str, at: Instant) -> Option<PlayerTransform> {
+        let room_id = self.player_room(player_id)?;
+        self.rooms.lock().unwrap().get(&room_id)?.predicted_transform(player_id, at)
+    }
+    
+    /// Sync limits the room is currently running under
+    pub fn sync_budget(&self, room_id: &str) -> Option<SyncBudget> {
+        self.rooms.lock().unwrap().get(room_id).map(|room| room.degradation.sync_budget())
+    }
+    
+    /// Get all players in a room
+    pub fn get_room_players(&self, room_id: &str) -> Vec<PlayerState> {
+        let rooms = self.rooms.lock().unwrap();
+        
+        if let Some(room) = rooms.get(room_id) {
+            room.players.clone()
+        } else {
+            Vec::new()
+        }
+    }
+    
+    /// One page of the public, joinable rooms for a game
+    pub fn find_rooms(&self, game_id: &str, query: &RoomQuery) -> Result<RoomPage, String> {
+        // Validate the cursor up front so bad cursors aren't cached
+        query.cursor.as_deref().map(RoomSortKey::from_cursor).transpose()?;
+        
+        let key = format!("{}|{:?}|{}|{}", game_id, query.sort, query.cursor.as_deref().unwrap_or(""), query.limit);
+        
+        Ok(self.room_page_cache.get_or_load(key, || {
+            let rooms = self.rooms.lock().unwrap();
+            
+            let keyed = rooms.values()
+                .filter(|room| room.game_id == game_id && room.is_public && !room.is_full())
+                .map(|room| (RoomSortKey::new(room, query.sort, 0), room))
+                .collect();
+            
+            Self::page_rooms(keyed, query).expect("cursor was validated")
+        }))
+    }
+    
+    /// Like `find_rooms`, limited to rooms the player may join, with rooms in
+    /// their own region listed first
+    pub fn find_rooms_for_player(&self, game_id: &str, player_id: &str, query: &RoomQuery) -> Result<RoomPage, String> {
+        let region = self.player_region(player_id);
+        let rooms = self.rooms.lock().unwrap();
+        
+        let keyed = rooms.values()
+            .filter(|room| room.game_id == game_id && room.is_public && !room.is_full())
+            .filter(|room| {
+                self.auth.as_ref()
+                    .is_none_or(|auth| auth.check_content_access(player_id, room.content_rating, &room.tags).is_ok())
+            })
+            .map(|room| {
+                let group = if region.is_some() && room.region == region { 0 } else { 1 };
+                (RoomSortKey::new(room, query.sort, group), room)
+            })
+            .collect();
+        
+        Self::page_rooms(keyed, query)
+    }
+    
+    /// Rating applied to new rooms of this game
+    pub fn set_game_rating(&self, game_id: &str, rating: ContentRating) {
+        self.game_ratings.lock().unwrap().insert(game_id.to_string(), rating);
+    }
+    
+    pub fn game_rating(&self, game_id: &str) -> ContentRating {
+        self.game_ratings.lock().unwrap().get(game_id).copied().unwrap_or_default()
+    }
+    
+    /// Rate and tag a room; a room can be rated higher than its game but never lower
+    pub fn set_room_content(&self, room_id: &str, rating: ContentRating, tags: Vec<String>) -> Result<(), String> {
+        let game_rating = {
+            let rooms = self.rooms.lock().unwrap();
+            let room = rooms.get(room_id).ok_or_else(|| "Room not found".to_string())?;
+            self.game_rating(&room.game_id)
+        };
+        
+        if rating < game_rating {
+            return Err(format!("Room cannot be rated below its game ({:?})", game_rating));
+        }
+        
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = rooms.get_mut(room_id).ok_or_else(|| "Room not found".to_string())?;
+        room.content_rating = rating;
+        room.tags = tags;
+        
+        drop(rooms);
+        self.room_page_cache.invalidate_all();
+        
+        println!("[MultiplayerServer] Room {} rated {:?}", room_id, rating);
+        
+        Ok(())
+    }
+    
+    pub fn set_audio_zones(&self, room_id: &str, zones: Vec<AudioZone>) -> Result<(), String> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = rooms.get_mut(room_id).ok_or_else(|| "Room not found".to_string())?;
+        GameRoom::validate_audio(&room.attenuation, &zones)?;
+        
+        println!("[MultiplayerServer] Room {} has {} audio zones", room_id, zones.len());
+        room.audio_zones = zones;
+        
+        Ok(())
+    }
+    
+    /// Voice falloff for new rooms of this game
+    pub fn set_game_attenuation(&self, game_id: &str, profile: AttenuationProfile) -> Result<(), String> {
+        profile.validate()?;
+        self.game_attenuation.lock().unwrap().insert(game_id.to_string(), profile);
+        Ok(())
+    }
+    
+    /// Override a room's voice falloff and occluders, and push them to everyone in it
+    pub fn set_room_audio(
+        &self,
+        room_id: &str,
+        attenuation: AttenuationProfile,
+        occluders: Vec<AudioOccluder>,
+    ) -> Result<(), String> {
+        {
+            let mut rooms = self.rooms.lock().unwrap();
+            let room = rooms.get_mut(room_id).ok_or_else(|| "Room not found".to_string())?;
+            GameRoom::validate_audio(&attenuation, &room.audio_zones)?;
+            room.attenuation = attenuation;
+            room.audio_occluders = occluders;
+        }
+        
+        if let Some(settings) = self.audio_settings(room_id) {
+            self.broadcast_to_room(room_id, settings, None);
+        }
+        
+        Ok(())
+    }
+    
+    /// Region a connected player was tagged with (detected or overridden)
+    pub fn player_region(&self, player_id: &str) -> Option<String> {
+        self.player_regions.lock().unwrap().get(player_id).cloned()
+    }
+    
+    /// Get room info
+    pub fn get_room(&self, room_id: &str) -> Option<GameRoom> {
+        let rooms = self.rooms.lock().unwrap();
+        rooms.get(room_id).cloned()
+    }
+    
+    /// Broadcast message to all players in a room
+    pub fn broadcast_to_room(&self, room_id: &str, message: GameMessage, exclude_player: Option<&str>) {
+        let recipients: Vec<String> = match self.rooms.lock().unwrap().get(room_id) {
+            Some(room) => room.players.iter().map(|p| &p.player_id).chain(room.observers.iter())
+                .filter(|player_id| exclude_player != Some(player_id.as_str()))
+                .cloned()
+                .collect(),
+            None => return,
+        };
+        
+        for player_id in &recipients {
+            self.send_to_player(player_id, &message);
+        }
+    }
+    
+    /// Add a server-controlled player to a room, returning its player ID
+    pub fn add_npc(
+        &self,
+        room_id: &str,
+        username: String,
+        transform: PlayerTransform,
+        controller: Box<dyn NpcController>,
+    ) -> Result<String, String> {
+        let player_id = format!("npc_{}", uuid::Uuid::new_v4());
+        
+        let npc = PlayerState {
+            player_id: player_id.clone(),
+            display_name: username.clone(),
+            username,
+            transform,
+            avatar_url: None,
+            is_talking: false,
+            custom_data: HashMap::new(),
+            is_npc: true,
+        };
+        
+        self.join_room(room_id, npc)?;
+        
+        let mut controllers = self.npc_controllers.lock().unwrap();
+        controllers.insert(player_id.clone(), controller);
+        
+        Ok(player_id)
+    }
+    
+    /// Attach the server-side script that answers `RpcTarget::RoomScript` calls
+    pub fn set_room_script(&self, room_id: &str, script: Box<dyn RoomScript>) -> Result<(), String> {
+        if !self.rooms.lock().unwrap().contains_key(room_id) {
+            return Err("Room not found".to_string());
+        }
+        
+        self.room_scripts.lock().unwrap().insert(room_id.to_string(), script);
+        Ok(())
+    }
+    
+    /// Route a player's `RpcCall`. Room script calls are answered immediately; host
+    /// calls are forwarded as `RpcInvoke` and answered by `respond_rpc` or, once the
+    /// timeout passes, by `tick_rpc_timeouts`. Responses go to the caller only.
+    pub fn call_rpc(&self, caller_id: &str, call: GameMessage, now: Instant) -> Result<(), String> {
+        let (call_id, target, procedure, args, timeout_ms) = match call {
+            GameMessage::RpcCall { call_id, target, procedure, args, timeout_ms } => (call_id, target, procedure, args, timeout_ms),
+            _ => return Err("Not an RPC call".to_string()),
+        };
+        
+        let room_id = self.player_room(caller_id).ok_or_else(|| "Player is not in a room".to_string())?;
+        
+        match target {
+            RpcTarget::RoomScript => {
+                let result = {
+                    let mut scripts = self.room_scripts.lock().unwrap();
+                    let rooms = self.rooms.lock().unwrap();
+                    
+                    match (scripts.get_mut(&room_id), rooms.get(&room_id)) {
+                        (Some(script), Some(room)) => script.handle_rpc(caller_id, &procedure, &args, room),
+                        _ => Err("Room has no script".to_string()),
+                    }
+                };
+                
+                self.send_to_player(caller_id, &GameMessage::RpcResponse { call_id, result });
+            }
+            RpcTarget::Host => {
+                let host_id = self.get_room(&room_id)
+                    .map(|room| room.host_id)
+                    .ok_or_else(|| "Room not found".to_string())?;
+                
+                if host_id == caller_id {
+                    return Err("The host cannot call itself".to_string());
+                }
+                
+                let routed_id = format!("rpc_{}", uuid::Uuid::new_v4());
+                let timeout = timeout_ms.map(Duration::from_millis).unwrap_or(DEFAULT_RPC_TIMEOUT).min(MAX_RPC_TIMEOUT);
+                
+                self.pending_rpcs.lock().unwrap().insert(routed_id.clone(), PendingRpc {
+                    caller_id: caller_id.to_string(),
+                    caller_call_id: call_id,
+                    host_id: host_id.clone(),
+                    room_id,
+                    deadline: now + timeout,
+                });
+                
+                self.send_to_player(&host_id, &GameMessage::RpcInvoke {
+                    call_id: routed_id,
+                    caller_id: caller_id.to_string(),
+                    procedure,
+                    args,
+                });
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// The host's answer to an `RpcInvoke`; relayed to the original caller.
+    /// Returns the caller's player ID.
+    pub fn respond_rpc(&self, host_id: &str, call_id: &str, result: Result<String, String>) -> Result<String, String> {
+        let call = {
+            let mut pending = self.pending_rpcs.lock().unwrap();
+            
+            match pending.get(call_id) {
+                Some(call) if call.host_id == host_id => pending.remove(call_id).unwrap(),
+                Some(_) => return Err("Call was not routed to this player".to_string()),
+                None => return Err("Unknown or expired call".to_string()),
+            }
+        };
+        
+        self.send_to_player(&call.caller_id, &GameMessage::RpcResponse {
+            call_id: call.caller_call_id,
+            result,
+        });
+        
+        Ok(call.caller_id)
+    }
+    
+    /// Fail host calls that timed out or whose host left the room. Returns the
+    /// routed IDs of the failed calls.
+    pub fn tick_rpc_timeouts(&self, now: Instant) -> Vec<String> {
+        let failed: Vec<(String, PendingRpc, &str)> = {
+            let mut pending = self.pending_rpcs.lock().unwrap();
+            let player_to_room = self.player_to_room.lock().unwrap();
+            
+            let expired: Vec<(String, &str)> = pending.iter()
+                .filter_map(|(id, call)| {
+                    if player_to_room.get(&call.host_id) != Some(&call.room_id) {
+                        Some((id.clone(), "Host left the room"))
+                    } else if now >= call.deadline {
+                        Some((id.clone(), "RPC timed out"))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+            
+            expired.into_iter()
+                .filter_map(|(id, reason)| pending.remove(&id).map(|call| (id, call, reason)))
+                .collect()
+        };
+        
+        for (_, call, reason) in &failed {
+            self.send_to_player(&call.caller_id, &GameMessage::RpcResponse {
+                call_id: call.caller_call_id.clone(),
+                result: Err(reason.to_string()),
+            });
+        }
+        
+        failed.into_iter().map(|(id, _, _)| id).collect()
+    }
+    
+    /// Remove a server-controlled player from its room
+    pub fn remove_npc(&self, player_id: &str) -> Option<String> {
+        let removed = self.npc_controllers.lock().unwrap().remove(player_id);
+        
+        if removed.is_some() {
+            self.leave_room(player_id)
+        } else {
+            None
+        }
+    }
+    
+    /// Run every NPC controller once and apply the resulting transforms.
+    /// Returns the `PlayerUpdate` messages to broadcast, keyed by room ID.
+    pub fn tick_npcs(&self, delta_seconds: f32) -> Vec<(String, GameMessage)> {
+        let mut controllers = self.npc_controllers.lock().unwrap();
+        let player_to_room = self.player_to_room.lock().unwrap();
+        let mut rooms = self.rooms.lock().unwrap();
+        
+        let mut updates = Vec::new();
+        
+        for (player_id, controller) in controllers.iter_mut() {
+            let room = match player_to_room.get(player_id).and_then(|id| rooms.get_mut(id)) {
+                Some(r) => r,
+                None => continue,
+            };
+            
+            let transform = match room.get_player(player_id) {
+                Some(npc) => controller.tick(npc, room, delta_seconds),
+                None => continue,
+            };
+            
+            if let Some(transform) = transform {
+                room.update_player(player_id, transform.clone());
+                updates.push((room.room_id.clone(), GameMessage::PlayerUpdate {
+                    player_id: player_id.clone(),
+                    transform,
+                }));
+            }
+        }
+        
+        updates
+    }
+    
+    /// Write a room state key on behalf of a player and notify watchers of the change
+    pub fn set_game_state(
+        &self,
+        player_id: &str,
+        key: &str,
+        value: String,
+        expected_version: Option<u64>,
+    ) -> Result<u64, String> {
+        let room_id = self.player_room(player_id).ok_or_else(|| "Player is not in a room".to_string())?;
+        
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = rooms.get_mut(&room_id).ok_or_else(|| "Room not found".to_string())?;
+        
+        let version = room.set_state(key, value.clone(), expected_version)?;
+        self.notify_state_watchers(room, key, &value, version);
+        
+        Ok(version)
+    }
+    
+    /// Apply an atomic operation to a room state key on behalf of a player
+    pub fn apply_game_state_operation(
+        &self,
+        player_id: &str,
+        key: &str,
+        operation: StateOperation,
+    ) -> Result<(String, u64), String> {
+        let room_id = self.player_room(player_id).ok_or_else(|| "Player is not in a room".to_string())?;
+        
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = rooms.get_mut(&room_id).ok_or_else(|| "Room not found".to_string())?;
+        
+        let (value, version) = room.apply_state_operation(key, operation)?;
+        self.notify_state_watchers(room, key, &value, version);
+        
+        Ok((value, version))
+    }
+    
+    /// Watch room state keys; returns the current values of the matching keys
+    pub fn subscribe_game_state(&self, player_id: &str, patterns: Vec<String>) -> Result<Vec<GameMessage>, String> {
+        let room_id = self.player_room(player_id).ok_or_else(|| "Player is not in a room".to_string())?;
+        
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = rooms.get_mut(&room_id).ok_or_else(|| "Room not found".to_string())?;
+        
+        let snapshot = room.state_snapshot(&patterns);
+        room.subscribe_state(player_id, patterns);
+        
+        Ok(snapshot)
+    }
+    
+    pub fn unsubscribe_game_state(&self, player_id: &str, patterns: &[String]) {
+        if let Some(room_id) = self.player_room(player_id) {
+            if let Some(room) = self.rooms.lock().unwrap().get_mut(&room_id) {
+                room.unsubscribe_state(player_id, patterns);
+            }
+        }
+    }
+    
+    /// Receive `CustomEvent`s published on these channels of the player's room.
+    /// A player may hold `MAX_EVENT_SUBSCRIPTIONS_PER_PLAYER` subscriptions and a room
+    /// `MAX_EVENT_CHANNELS_PER_ROOM` channels; a request over either adds nothing.
+    pub fn subscribe_events(&self, player_id: &str, channels: Vec<String>) -> Result<(), String> {
+        channels.iter().try_for_each(|c| validate_event_channel(c))?;
+        
+        let room_id = self.player_room(player_id).ok_or_else(|| "Player is not in a room".to_string())?;
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = rooms.get_mut(&room_id).ok_or_else(|| "Room not found".to_string())?;
+        
+        let requested: HashSet<&String> = channels.iter().collect();
+        let subscribed = room.event_channels.values().filter(|c| c.subscribers.contains(player_id)).count();
+        let added = requested.iter()
+            .filter(|c| !room.event_channels.get(**c).is_some_and(|c| c.subscribers.contains(player_id)))
+            .count();
+        if subscribed + added > MAX_EVENT_SUBSCRIPTIONS_PER_PLAYER {
+            return Err(format!("Players can subscribe to at most {} event channels", MAX_EVENT_SUBSCRIPTIONS_PER_PLAYER));
+        }
+        let created = requested.iter().filter(|c| !room.event_channels.contains_key(**c)).count();
+        if room.event_channels.len() + created > MAX_EVENT_CHANNELS_PER_ROOM {
+            return Err(format!("Rooms can have at most {} event channels", MAX_EVENT_CHANNELS_PER_ROOM));
+        }
+        
+        for channel in channels {
+            room.event_channels.entry(channel)
+                .or_insert_with(EventChannel::new)
+                .subscribers
+                .insert(player_id.to_string());
+        }
+        
+        Ok(())
+    }
+    
+    pub fn unsubscribe_events(&self, player_id: &str, channels: &[String]) {
+        if let Some(room_id) = self.player_room(player_id) {
+            if let Some(room) = self.rooms.lock().unwrap().get_mut(&room_id) {
+                for name in channels {
+                    if let Some(channel) = room.event_channels.get_mut(name) {
+                        channel.subscribers.remove(player_id);
+                        if channel.is_idle() {
+                            room.event_channels.remove(name);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    
+    /// Host-only: change how many events per second each sender may publish on a channel
+    pub fn set_event_channel_rate(&self, room_id: &str, requester_id: &str, channel: &str, max_events_per_second: u32) -> Result<(), String> {
+        validate_event_channel(channel)?;
+        
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = rooms.get_mut(room_id).ok_or_else(|| "Room not found".to_string())?;
+        
+        if room.host_id != requester_id {
+            return Err("Only the host can change event channel limits".to_string());
+        }
+        if !room.event_channels.contains_key(channel) && room.event_channels.len() >= MAX_EVENT_CHANNELS_PER_ROOM {
+            return Err(format!("Rooms can have at most {} event channels", MAX_EVENT_CHANNELS_PER_ROOM));
+        }
+        
+        room.event_channels.entry(channel.to_string())
+            .or_insert_with(EventChannel::new)
+            .max_events_per_second = max_events_per_second;
+        
+        Ok(())
+    }
+    
+    /// Relay a `CustomEvent` or `CustomBinaryEvent` from a player. Events on a named
+    /// channel go only to that channel's subscribers and count against the sender's
+    /// rate limit; events without a channel go to the whole room. Returns the number
+    /// of recipients.
+    pub fn send_custom_event(&self, sender_id: &str, event: GameMessage, now: Instant) -> Result<usize, String> {
+        let channel = match &event {
+            GameMessage::CustomEvent { channel, .. } => channel.clone(),
+            GameMessage::CustomBinaryEvent { data, .. } if data.len() > MAX_BINARY_EVENT_BYTES => {
+                return Err(format!("Binary events are limited to {} bytes", MAX_BINARY_EVENT_BYTES));
+            }
+            GameMessage::CustomBinaryEvent { channel, .. } => channel.clone(),
+            _ => return Err("Not a custom event".to_string()),
+        };
+        
+        if let Some(channel) = &channel {
+            validate_event_channel(channel)?;
+        }
+        
+        let room_id = self.player_room(sender_id).ok_or_else(|| "Player is not in a room".to_string())?;
+        
+        let channel = match channel {
+            Some(channel) => channel,
+            None => {
+                let recipients = self.get_room(&room_id).map(|room| room.player_count().saturating_sub(1)).unwrap_or(0);
+                self.broadcast_to_room(&room_id, event, Some(sender_id));
+                return Ok(recipients);
+            }
+        };
+        
+        let recipients: Vec<String> = {
+            let mut rooms = self.rooms.lock().unwrap();
+            let room = rooms.get_mut(&room_id).ok_or_else(|| "Room not found".to_string())?;
+            
+            // Nobody subscribed and no limit was set: nothing to deliver, and no channel to create
+            let Some(channel_state) = room.event_channels.get_mut(&channel) else { return Ok(0) };
+            if !channel_state.admit(sender_id, now) {
+                return Err(format!("Event rate limit exceeded on channel {}", channel));
+            }
+            
+            channel_state.subscribers.iter()
+                .filter(|id| *id != sender_id)
+                .cloned()
+                .collect()
+        };
+        
+        for player_id in &recipients {
+            self.send_to_player(player_id, &event);
+        }
+        
+        Ok(recipients.len())
+    }
+    
+    /// Start turn-based play in a room. Only the host may start turns, and every
+    /// player in `order` must be in the room.
+    pub fn start_turns(
+        &self,
+        room_id: &str,
+        requester_id: &str,
+        order: Vec<String>,
+        turn_timeout_seconds: Option<i64>,
+    ) -> Result<(), String> {
+        let message = {
+            let mut rooms = self.rooms.lock().unwrap();
+            let room = rooms.get_mut(room_id).ok_or_else(|| "Room not found".to_string())?;
+            
+            if room.host_id != requester_id {
+                return Err("Only the host can start turns".to_string());
+            }
+            
+            if order.is_empty() {
+                return Err("Turn order is empty".to_string());
+            }
+            
+            if let Some(missing) = order.iter().find(|p| room.get_player(p).is_none()) {
+                return Err(format!("Player {} is not in the room", missing));
+            }
+            
+            let turns = TurnState::new(order, turn_timeout_seconds, self.clock.timestamp());
+            let message = turns.changed_message();
+            room.turns = Some(turns);
+            message
+        };
+        
+        println!("[MultiplayerServer] Turns started in room {}", room_id);
+        
+        if let Some(message) = message {
+            self.broadcast_to_room(room_id, message, None);
+        }
+        
+        Ok(())
+    }
+    
+    /// End the caller's turn and pass it to the next player
+    pub fn end_turn(&self, player_id: &str) -> Result<(), String> {
+        let room_id = self.player_room(player_id).ok_or_else(|| "Player is not in a room".to_string())?;
+        
+        let message = {
+            let mut rooms = self.rooms.lock().unwrap();
+            let room = rooms.get_mut(&room_id).ok_or_else(|| "Room not found".to_string())?;
+            let turns = room.turns.as_mut().ok_or_else(|| "Room has no turn order".to_string())?;
+            
+            if turns.current_player().map(|p| p.as_str()) != Some(player_id) {
+                return Err("It is not your turn".to_string());
+            }
+            
+            turns.advance(self.clock.timestamp());
+            turns.changed_message()
+        };
+        
+        if let Some(message) = message {
+            self.broadcast_to_room(&room_id, message, None);
+        }
+        
+        Ok(())
+    }
+    
+    /// Stop turn-based play in a room
+    pub fn stop_turns(&self, room_id: &str) {
+        if let Some(room) = self.rooms.lock().unwrap().get_mut(room_id) {
+            room.turns = None;
+        }
+    }
+    
+    /// Auto-advance every turn whose timeout elapsed. Returns the affected room IDs.
+    pub fn tick_turns(&self, now: i64) -> Vec<String> {
+        let changes: Vec<(String, GameMessage)> = {
+            let mut rooms = self.rooms.lock().unwrap();
+            
+            rooms.values_mut()
+                .filter_map(|room| {
+                    let turns = room.turns.as_mut().filter(|t| t.is_expired(now))?;
+                    turns.advance(now);
+                    turns.changed_message().map(|m| (room.room_id.clone(), m))
+                })
+                .collect()
+        };
+        
+        let mut advanced = Vec::new();
+        
+        for (room_id, message) in changes {
+            println!("[MultiplayerServer] Turn timed out in room {}", room_id);
+            self.broadcast_to_room(&room_id, message, None);
+            advanced.push(room_id);
+        }
+        
+        advanced
+    }
+    
+    /// Host-only: close the room automatically `duration_seconds` from now. Replaces
+    /// any running countdown.
+    pub fn set_room_time_limit(&self, room_id: &str, requester_id: &str, config: TimeLimitConfig) -> Result<(), String> {
+        config.validate()?;
+        let now = self.clock.timestamp();
+        
+        let message = {
+            let mut rooms = self.rooms.lock().unwrap();
+            let room = rooms.get_mut(room_id).ok_or_else(|| "Room not found".to_string())?;
+            if room.host_id != requester_id {
+                return Err("Only the host can limit the room's time".to_string());
+            }
+            
+            let countdown = RoomCountdown::new(&config, now);
+            let message = countdown.remaining_message(room_id, now);
+            room.countdown = Some(countdown);
+            message
+        };
+        
+        println!("[MultiplayerServer] Room {} closes in {}s", room_id, config.duration_seconds);
+        self.broadcast_to_room(room_id, message, None);
+        Ok(())
+    }
+    
+    /// Seconds left in a time-limited room
+    pub fn room_time_remaining(&self, room_id: &str) -> Option<i64> {
+        let now = self.clock.timestamp();
+        self.rooms.lock().unwrap()
+            .get(room_id)?
+            .countdown.as_ref()
+            .map(|c| c.seconds_remaining(now))
+    }
+    
+    /// Announce countdown warnings and close rooms whose time is up. Before a room
+    /// closes its `time_up_result` is finalized, even without a majority.
+    /// Returns the closed room IDs.
+    pub fn tick_room_time_limits(&self) -> Vec<String> {
+        let now = self.clock.timestamp();
+        let mut warnings = Vec::new();
+        let mut ended = Vec::new();
+        {
+            let mut rooms = self.rooms.lock().unwrap();
+            for room in rooms.values_mut() {
+                let Some(countdown) = room.countdown.as_mut() else { continue };
+                
+                if countdown.is_over(now) {
+                    let message = countdown.remaining_message(&room.room_id, now);
+                    // Taken under the lock so a submission arriving now can't finalize it again
+                    let result = room.time_up_result();
+                    room.match_submissions.clear();
+                    ended.push((room.room_id.clone(), message, result));
+                } else if countdown.take_due_warning(now) {
+                    warnings.push((room.room_id.clone(), countdown.remaining_message(&room.room_id, now)));
+                }
+            }
+        }
+        
+        for (room_id, message) in warnings {
+            self.broadcast_to_room(&room_id, message, None);
+        }
+        
+        let mut closed = Vec::new();
+        for (room_id, message, result) in ended {
+            if let Some(result) = result {
+                if let Err(e) = self.record_match_result(&room_id, result) {
+                    eprintln!("[MultiplayerServer] Could not finalize result of timed room {}: {}", room_id, e);
+                }
+            }
+            self.broadcast_to_room(&room_id, message, None);
+            
+            println!("[MultiplayerServer] Time is up in room {}", room_id);
+            if self.close_room(&room_id).is_ok() {
+                closed.push(room_id);
+            }
+        }
+        closed.sort();
+        closed
+    }
+    
+    /// Submit the result of the room's match. The host's result is finalized once a
+    /// majority of human players in the room submitted the same result.
+    pub fn submit_match_result(&self, player_id: &str, mut result: MatchResult) -> Result<MatchSubmissionStatus, String> {
+        let room_id = self.player_room(player_id).ok_or_else(|| "Player is not in a room".to_string())?;
+        result.winners.sort();
+        
+        let (status, agreed) = {
+            let mut rooms = self.rooms.lock().unwrap();
+            let room = rooms.get_mut(&room_id).ok_or_else(|| "Room not found".to_string())?;
+            
+            if let Some(unknown) = result.scores.keys().chain(result.winners.iter()).find(|p| room.get_player(p).is_none()) {
+                return Err(format!("Player {} is not in the room", unknown));
+            }
+            
+            room.match_submissions.insert(player_id.to_string(), result);
+            
+            let humans = room.human_count();
+            let quorum = humans / 2 + 1;
+            
+            let status = match room.match_submissions.get(&room.host_id) {
+                Some(host_result) => {
+                    let agreeing = room.match_submissions.values().filter(|r| *r == host_result).count();
+                    let outstanding = humans.saturating_sub(room.match_submissions.len());
+                    
+                    if agreeing >= quorum {
+                        MatchSubmissionStatus::Finalized
+                    } else if agreeing + outstanding < quorum {
+                        MatchSubmissionStatus::Disputed
+                    } else {
+                        MatchSubmissionStatus::Pending
+                    }
+                }
+                None => MatchSubmissionStatus::Pending,
+            };
+            
+            // The deciding submission takes the agreed result out of the room, so a
+            // concurrent one can't finalize the same match twice
+            let agreed = (status == MatchSubmissionStatus::Finalized)
+                .then(|| (room.host_id.clone(), std::mem::take(&mut room.match_submissions)));
+            (status, agreed)
+        };
+        
+        match status {
+            MatchSubmissionStatus::Finalized => {
+                let (host_id, submissions) = agreed.unwrap_or_default();
+                let host_result = submissions.get(&host_id).cloned().ok_or_else(|| "Host result missing".to_string())?;
+                
+                if let Err(e) = self.record_match_result(&room_id, host_result) {
+                    // Put the submissions back, behind any that arrived meanwhile, for a retry
+                    if let Some(room) = self.rooms.lock().unwrap().get_mut(&room_id) {
+                        for (player_id, result) in submissions {
+                            room.match_submissions.entry(player_id).or_insert(result);
+                        }
+                    }
+                    return Err(e);
+                }
+            }
+            MatchSubmissionStatus::Disputed => {
+                println!("[MultiplayerServer] Match result disputed in room {}", room_id);
+            }
+            MatchSubmissionStatus::Pending => {}
+        }
+        
+        Ok(status)
+    }
+    
+    /// `submit_match_result` that counts a retried submission with the same key only once
+    pub fn submit_match_result_idempotent(&self, idempotency_key: &str, player_id: &str, result: MatchResult) -> Result<MatchSubmissionStatus, String> {
+        let key = format!("submit_match_result:{}:{}", player_id, idempotency_key);
+        
+        self.match_submissions.execute(&key, self.clock.timestamp(), || {
+            self.submit_match_result(player_id, result)
+        })
+    }
+    
+    /// Record a match result without validation (for server-side game scripts):
+    /// updates ratings, leaderboards, XP and achievements, then broadcasts it
+    pub fn finalize_match_result(&self, room_id: &str, result: MatchResult) -> Result<(), String> {
+        self.record_match_result(room_id, result)?;
+        
+        // Submissions are only cleared once the result is stored, so a failed write can be retried
+        if let Some(room) = self.rooms.lock().unwrap().get_mut(room_id) {
+            room.match_submissions.clear();
+        }
+        Ok(())
+    }
+    
+    fn record_match_result(&self, room_id: &str, result: MatchResult) -> Result<(), String> {
+        let (game_id, participants) = {
+            let rooms = self.rooms.lock().unwrap();
+            let room = rooms.get(room_id).ok_or_else(|| "Room not found".to_string())?;
+            
+            // NPCs don't have accounts and are left out of stats
+            let participants: Vec<MatchParticipant> = result.scores.iter()
+                .filter(|(player_id, _)| room.get_player(player_id).map(|p| !p.is_npc).unwrap_or(false))
+                .map(|(player_id, score)| MatchParticipant {
+                    user_id: player_id.clone(),
+                    score: *score,
+                    won: result.winners.contains(player_id),
+                })
+                .collect();
+            
+            (room.game_id.clone(), participants)
+        };
+        
+        match (&self.seasons, &self.auth) {
+            (Some(seasons), _) => seasons.record_match(&game_id, &participants, self.clock.timestamp())?,
+            (None, Some(auth)) => auth.record_match(&game_id, &participants)?,
+            (None, None) => {}
+        }
+        if let Some(challenges) = &self.challenges {
+            challenges.record_match(&game_id, &participants, self.clock.timestamp());
+        }
+        
+        println!("[MultiplayerServer] Match result finalized in room {}", room_id);
+        self.broadcast_to_room(room_id, GameMessage::MatchFinalized { result }, None);
+        
+        Ok(())
+    }
+    
+    /// Register a hub world and open its persistent primary instance
+    pub fn register_world(&self, template: WorldTemplate) -> String {
+        let world_id = template.world_id.clone();
+        let room_id = self.create_world_instance(&template, true);
+        
+        self.worlds.lock().unwrap().insert(world_id.clone(), template);
+        self.world_instances.lock().unwrap().insert(world_id.clone(), vec![room_id.clone()]);
+        
+        println!("[MultiplayerServer] World registered: {}", world_id);
+        
+        room_id
+    }
+    
+    /// List a world's instances, showing which of the player's friends are in each
+    pub fn list_world_instances(&self, world_id: &str, player_id: &str) -> Vec<WorldInstanceSummary> {
+        let friends = self.auth.as_ref()
+            .map(|auth| auth.get_friends(player_id))
+            .unwrap_or_default();
+        
+        let instance_ids = self.live_world_instances(world_id);
+        let rooms = self.rooms.lock().unwrap();
+        
+        instance_ids.iter()
+            .enumerate()
+            .filter_map(|(index, room_id)| rooms.get(room_id).map(|room| WorldInstanceSummary {
+                room_id: room.room_id.clone(),
+                player_count: room.player_count(),
+                max_players: room.max_players,
+                is_primary: index == 0,
+                friends_present: room.players.iter()
+                    .filter(|p| friends.contains(&p.player_id))
+                    .map(|p| p.player_id.clone())
+                    .collect(),
+            }))
+            .collect()
+    }
+    
+    /// Join a world through its least-full instance, opening a new instance if all are full
+    pub fn quick_join_world(&self, world_id: &str, player: PlayerState) -> Result<String, String> {
+        let template = self.worlds.lock().unwrap()
+            .get(world_id)
+            .cloned()
+            .ok_or_else(|| "World not found".to_string())?;
+        
+        let least_full = {
+            let instance_ids = self.live_world_instances(world_id);
+            let rooms = self.rooms.lock().unwrap();
+            
+            instance_ids.iter()
+                .filter_map(|id| rooms.get(id))
+                .filter(|room| !room.is_full())
+                .min_by_key(|room| room.player_count())
+                .map(|room| room.room_id.clone())
+        };
+        
+        let room_id = match least_full {
+            Some(id) => id,
+            None => {
+                let id = self.create_world_instance(&template, false);
+                self.world_instances.lock().unwrap()
+                    .entry(world_id.to_string())
+                    .or_default()
+                    .push(id.clone());
+                println!("[MultiplayerServer] World {} full, opened instance {}", world_id, id);
+                id
+            }
+        };
+        
+        self.join_room(&room_id, player)?;
+        Ok(room_id)
+    }
+    
+    /// Let a player share their screen in the host's room
+    pub fn grant_screen_share(&self, host_id: &str, player_id: &str) -> Result<(), String> {
+        let room_id = self.player_room(host_id).ok_or_else(|| "Not in a room".to_string())?;
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = rooms.get_mut(&room_id).ok_or_else(|| "Room not found".to_string())?;
+        
+        if room.host_id != host_id {
+            return Err("Only the host can grant screen sharing".to_string());
+        }
+        
+        room.screen_share_permitted.insert(player_id.to_string());
+        Ok(())
+    }
+    
+    /// Start sharing; the host may always share, others need a grant. One sharer per room.
+    pub fn start_screen_share(&self, player_id: &str) -> Result<(), String> {
+        let room_id = self.player_room(player_id).ok_or_else(|| "Not in a room".to_string())?;
+        
+        {
+            let mut rooms = self.rooms.lock().unwrap();
+            let room = rooms.get_mut(&room_id).ok_or_else(|| "Room not found".to_string())?;
+            
+            let permitted = room.permissions.players_can_screen_share || room.screen_share_permitted.contains(player_id);
+            if room.host_id != player_id && !permitted {
+                return Err("Not permitted to share".to_string());
+            }
+            
+            if let Some(session) = &room.screen_share {
+                if session.sharer_id != player_id {
+                    return Err(format!("{} is already sharing", session.sharer_id));
+                }
+            }
+            
+            room.screen_share = Some(ScreenShareSession::new(player_id, self.clock.now()));
+        }
+        
+        println!("[MultiplayerServer] {} started screen share in room {}", player_id, room_id);
+        
+        self.broadcast_to_room(&room_id, GameMessage::ScreenShareStarted {
+            room_id: room_id.clone(),
+            sharer_id: player_id.to_string(),
+        }, None);
+        
+        Ok(())
+    }
+    
+    /// Stop the room's screen share; the sharer or the host may stop it
+    pub fn stop_screen_share(&self, player_id: &str) -> Result<(), String> {
+        let room_id = self.player_room(player_id).ok_or_else(|| "Not in a room".to_string())?;
+        
+        {
+            let mut rooms = self.rooms.lock().unwrap();
+            let room = rooms.get_mut(&room_id).ok_or_else(|| "Room not found".to_string())?;
+            let session = room.screen_share.as_ref().ok_or_else(|| "No screen share in progress".to_string())?;
+            
+            if session.sharer_id != player_id && room.host_id != player_id {
+                return Err("Only the sharer or host can stop sharing".to_string());
+            }
+            
+            room.screen_share = None;
+        }
+        
+        self.broadcast_to_room(&room_id, GameMessage::ScreenShareStopped { room_id: room_id.clone() }, None);
+        Ok(())
+    }
+    
+    /// Relay a frame chunk from the sharer to the room. Returns false if it was dropped
+    /// to stay under the bandwidth cap; the sharer is then asked for a keyframe.
+    pub fn relay_screen_frame(&self, player_id: &str, frame: ScreenFrame, now: Instant) -> Result<bool, String> {
+        let room_id = self.player_room(player_id).ok_or_else(|| "Not in a room".to_string())?;
+        
+        let (admitted, request_keyframe) = {
+            let mut rooms = self.rooms.lock().unwrap();
+            let room = rooms.get_mut(&room_id).ok_or_else(|| "Room not found".to_string())?;
+            let session = room.screen_share.as_mut()
+                .filter(|s| s.sharer_id == player_id)
+                .ok_or_else(|| "Not the active sharer".to_string())?;
+            
+            let admitted = session.admit(&frame, now);
+            (admitted, !admitted && session.should_request_keyframe(now))
+        };
+        
+        if request_keyframe {
+            self.send_to_player(player_id, &GameMessage::RequestKeyframe { room_id: room_id.clone() });
+        }
+        
+        if admitted {
+            self.broadcast_to_room(&room_id, GameMessage::ScreenShareFrame {
+                sharer_id: player_id.to_string(),
+                frame,
+            }, Some(player_id));
+        }
+        
+        Ok(admitted)
+    }
+    
+    /// Forward a viewer's keyframe request to the sharer; returns whether it was forwarded
+    pub fn request_keyframe(&self, player_id: &str, now: Instant) -> Result<bool, String> {
+        let room_id = self.player_room(player_id).ok_or_else(|| "Not in a room".to_string())?;
+        
+        let sharer_id = {
+            let mut rooms = self.rooms.lock().unwrap();
+            let room = rooms.get_mut(&room_id).ok_or_else(|| "Room not found".to_string())?;
+            let session = room.screen_share.as_mut().ok_or_else(|| "No screen share in progress".to_string())?;
+            
+            if !session.should_request_keyframe(now) {
+                return Ok(false);
+            }
+            session.sharer_id.clone()
+        };
+        
+        self.send_to_player(&sharer_id, &GameMessage::RequestKeyframe { room_id });
+        Ok(true)
+    }
+    
+    /// Apply an object message to the sender's room and relay it to the other players.
+    /// Moves of sleeping objects that don't actually move them are dropped.
+    pub fn handle_object_message(&self, player_id: &str, mut message: GameMessage) -> Result<(), String> {
+        let (room_id, update) = self.apply_object_message(player_id, &mut message)?;
+        
+        match update {
+            ObjectUpdate::Suppressed => return Ok(()),
+            ObjectUpdate::Woke(object_id) => {
+                self.broadcast_to_room(&room_id, GameMessage::ObjectWake {
+                    object_id,
+                    player_id: player_id.to_string(),
+                }, Some(player_id));
+            }
+            ObjectUpdate::Spawned(assignments) => {
+                if !assignments.is_empty() {
+                    self.send_to_player(player_id, &GameMessage::ObjectIdsAssigned { assignments });
+                }
+            }
+            ObjectUpdate::Relay => {}
+        }
+        
+        self.broadcast_to_room(&room_id, message, Some(player_id));
+        Ok(())
+    }
+    
+    pub fn register_prefab(&self, prefab: Prefab) {
+        println!("[MultiplayerServer] Prefab registered: {} ({} objects)", prefab.name, prefab.objects.len());
+        self.prefabs.lock().unwrap().insert(prefab.name.clone(), prefab);
+    }
+    
+    /// Spawn every object of a prefab around `origin` in the player's room as one batch,
+    /// sent to everyone in the room; returns the new object IDs
+    pub fn spawn_prefab(&self, player_id: &str, prefab_name: &str, origin: Vector3) -> Result<Vec<ObjectId>, String> {
+        let objects = self.prefab_objects(prefab_name, &origin)?;
+        let mut message = GameMessage::ObjectsSpawnedBatch { objects };
+        let (room_id, _) = self.apply_object_message(player_id, &mut message)?;
+        
+        let object_ids = match &message {
+            GameMessage::ObjectsSpawnedBatch { objects } => objects.iter().map(|o| o.object_id).collect(),
+            _ => Vec::new(),
+        };
+        
+        self.broadcast_to_room(&room_id, message, None);
+        Ok(object_ids)
+    }
+    
+    /// Register or replace a room template. Only the game's developers may.
+    pub fn register_room_template(&self, developer_id: &str, template: RoomTemplate) -> Result<(), String> {
+        let auth = self.auth.as_ref().ok_or_else(|| "Authentication not configured".to_string())?;
+        if !auth.is_game_developer(developer_id, &template.game_id) {
+            return Err("Only the game's developers can register room templates".to_string());
+        }
+        
+        if template.template_id.is_empty() {
+            return Err("Templates need an ID".to_string());
+        }
+        if template.max_players == 0 {
+            return Err("Templates must allow at least one player".to_string());
+        }
+        if template.soft_cap.is_some_and(|cap| cap == 0 || cap > template.max_players) {
+            return Err(format!("Soft cap must be between 1 and {}", template.max_players));
+        }
+        parse_permission_zones(&template.metadata)?;
+        if let Some(time_limit) = &template.time_limit {
+            time_limit.validate()?;
+        }
+        {
+            let prefabs = self.prefabs.lock().unwrap();
+            if let Some(missing) = template.spawn_prefabs.iter().find(|name| !prefabs.contains_key(*name)) {
+                return Err(format!("Unknown prefab: {}", missing));
+            }
+        }
+        
+        let mut templates = self.room_templates.lock().unwrap();
+        if templates.get(&template.template_id).is_some_and(|existing| existing.game_id != template.game_id) {
+            return Err("A template with this ID belongs to another game".to_string());
+        }
+        
+        println!("[MultiplayerServer] Room template {} registered for {} by {}", template.template_id, template.game_id, developer_id);
+        templates.insert(template.template_id.clone(), template);
+        Ok(())
+    }
+    
+    pub fn room_template(&self, template_id: &str) -> Option<RoomTemplate> {
+        self.room_templates.lock().unwrap().get(template_id).cloned()
+    }
+    
+    /// A game's templates, by name
+    pub fn room_templates(&self, game_id: &str) -> Vec<RoomTemplate> {
+        let mut templates: Vec<RoomTemplate> = self.room_templates.lock().unwrap()
+            .values()
+            .filter(|t| t.game_id == game_id)
+            .cloned()
+            .collect();
+        templates.sort_by(|a, b| a.name.cmp(&b.name));
+        templates
+    }
+    
+    /// Create a room configured entirely by a registered template. Quota checks apply
+    /// as for `open_room`.
+    pub fn create_room_from_template(&self, template_id: &str, host_id: String) -> Result<String, String> {
+        let template = self.room_template(template_id)
+            .ok_or_else(|| format!("Unknown room template: {}", template_id))?;
+        
+        let mut spawns = Vec::new();
+        for prefab_name in &template.spawn_prefabs {
+            spawns.extend(self.prefab_objects(prefab_name, &Vector3::ZERO)?);
+        }
+        
+        // The room is set up completely before anyone can see it, so a template that
+        // fails to apply leaves nothing behind
+        self.check_room_quota(&template.game_id)?;
+        let mut room = self.new_room(template.game_id.clone(), host_id, template.max_players);
+        let notice = {
+            room.template_id = Some(template.template_id.clone());
+            room.scene = template.scene.clone();
+            room.set_metadata(template.metadata.clone())?;
+            room.permissions = template.permissions;
+            room.is_public = template.is_public;
+            room.soft_cap = template.soft_cap;
+            room.countdown = template.time_limit.as_ref().map(|limit| RoomCountdown::new(limit, self.clock.timestamp()));
+            
+            if !spawns.is_empty() {
+                let mut message = GameMessage::ObjectsSpawnedBatch { objects: spawns };
+                room.apply_object_message(&room.host_id.clone(), &mut message, self.clock.timestamp())?;
+                // Scenery waits at rest for whoever touches it first
+                for object in room.objects.values_mut() {
+                    object.put_to_sleep(None);
+                }
+            }
+            room.refresh_degradation().map(|notice| (room.host_id.clone(), notice))
+        };
+        let room_id = self.insert_room(room);
+        
+        if let Some((host_id, notice)) = notice {
+            self.send_to_player(&host_id, &notice);
+        }
+        
+        println!("[MultiplayerServer] Room {} created from template {}", room_id, template_id);
+        Ok(room_id)
+    }
+    
+    /// Put resting objects to sleep in every room; returns the affected room IDs
+    pub fn tick_object_sleep(&self, now: i64) -> Vec<String> {
+        let sleeping: Vec<(String, Vec<GameMessage>)> = {
+            let mut rooms = self.rooms.lock().unwrap();
+            
+            rooms.values_mut()
+                .map(|room| (room.room_id.clone(), room.sleep_resting_objects(now)))
+                .filter(|(_, messages)| !messages.is_empty())
+                .collect()
+        };
+        
+        let mut room_ids = Vec::new();
+        for (room_id, messages) in sleeping {
+            for message in messages {
+                self.broadcast_to_room(&room_id, message, None);
+            }
+            room_ids.push(room_id);
+        }
+        
+        room_ids
+    }
+    
+    /// Stop (or resume) a player's object interactions in their current room
+    pub fn freeze_player(&self, moderator_id: &str, target_id: &str, frozen: bool) -> Result<(), String> {
+        let room_id = self.player_room(target_id).ok_or_else(|| "Player is not in a room".to_string())?;
+        self.require_moderator(moderator_id, &room_id)?;
+        
+        {
+            let mut rooms = self.rooms.lock().unwrap();
+            let room = rooms.get_mut(&room_id).ok_or_else(|| "Room not found".to_string())?;
+            
+            if frozen {
+                room.frozen_players.insert(target_id.to_string());
+            } else {
+                room.frozen_players.remove(target_id);
+            }
+        }
+        
+        self.send_to_player(target_id, &GameMessage::InteractionsFrozen { frozen });
+        
+        let action = if frozen { ModerationAction::Freeze } else { ModerationAction::Unfreeze };
+        self.record_moderation(moderator_id, action, Some(target_id), &room_id);
+        
+        Ok(())
+    }
+    
+    /// Move a moderator into a reported player's room, standing where that player is.
+    /// Moderators are admitted even when the room is full.
+    pub fn teleport_to_player(&self, moderator_id: &str, target_id: &str) -> Result<String, String> {
+        let room_id = self.player_room(target_id).ok_or_else(|| "Player is not in a room".to_string())?;
+        self.require_moderator(moderator_id, &room_id)?;
+        
+        let mut moderator = self.connected_players.lock().unwrap()
+            .get(moderator_id)
+            .cloned()
+            .ok_or_else(|| "Moderator is not connected".to_string())?;
+        
+        if self.player_room(moderator_id).as_deref() != Some(room_id.as_str()) {
+            self.leave_room(moderator_id);
+        }
+        
+        {
+            let mut rooms = self.rooms.lock().unwrap();
+            let room = rooms.get_mut(&room_id).ok_or_else(|| "Room not found".to_string())?;
+            
+            moderator.transform = room.get_player(target_id)
+                .map(|p| p.transform.clone())
+                .ok_or_else(|| "Player is not in the room".to_string())?;
+            
+            room.observers.retain(|id| id != moderator_id);
+            room.players.retain(|p| p.player_id != moderator_id);
+            room.players.push(moderator.clone());
+        }
+        
+        self.player_to_room.lock().unwrap().insert(moderator_id.to_string(), room_id.clone());
+        self.broadcast_to_room(&room_id, GameMessage::PlayerJoined { player: moderator }, Some(moderator_id));
+        
+        self.record_moderation(moderator_id, ModerationAction::TeleportTo, Some(target_id), &room_id);
+        
+        Ok(room_id)
+    }
+    
+    /// Join a room invisibly: the moderator receives room traffic but is not listed as a player
+    pub fn observe_room(&self, moderator_id: &str, room_id: &str) -> Result<(), String> {
+        self.require_moderator(moderator_id, room_id)?;
+        
+        if !self.connected_players.lock().unwrap().contains_key(moderator_id) {
+            return Err("Moderator is not connected".to_string());
+        }
+        
+        self.leave_room(moderator_id);
+        
+        {
+            let mut rooms = self.rooms.lock().unwrap();
+            let room = rooms.get_mut(room_id).ok_or_else(|| "Room not found".to_string())?;
+            room.observers.push(moderator_id.to_string());
+        }
+        
+        self.player_to_room.lock().unwrap().insert(moderator_id.to_string(), room_id.to_string());
+        self.record_moderation(moderator_id, ModerationAction::Observe, None, room_id);
+        
+        Ok(())
+    }
+    
+    /// Admin API: degrade (or with None, restore) a connected player's network.
+    /// Messages to the player are held in a `ChaosLink` until `tick_network_chaos`
+    /// releases them; clearing chaos delivers whatever is still held.
+    pub fn set_network_chaos(&self, admin_id: &str, player_id: &str, config: Option<ChaosConfig>) -> Result<(), String> {
+        if !self.chaos_mode {
+            return Err("Chaos mode is not enabled on this server".to_string());
+        }
+        
+        let auth = self.auth.as_ref().ok_or_else(|| "Authentication not configured".to_string())?;
+        if !auth.is_moderator_for(admin_id, "*") {
+            println!("[MultiplayerServer] Chaos change denied for {}", admin_id);
+            return Err("Admin access required".to_string());
+        }
+        
+        if !self.connections.lock().unwrap().contains_key(player_id) {
+            return Err("Player not connected".to_string());
+        }
+        
+        let held = {
+            let mut network_chaos = self.network_chaos.lock().unwrap();
+            match config {
+                Some(config) => {
+                    config.validate()?;
+                    println!("[MultiplayerServer] {} set chaos on {}: {:?}", admin_id, player_id, config);
+                    match network_chaos.get_mut(player_id) {
+                        Some(link) => link.set_config(config),
+                        None => {
+                            network_chaos.insert(player_id.to_string(), ChaosLink::new(config));
+                        }
+                    }
+                    Vec::new()
+                }
+                None => {
+                    println!("[MultiplayerServer] {} cleared chaos on {}", admin_id, player_id);
+                    network_chaos.remove(player_id).map(|mut link| link.drain()).unwrap_or_default()
+                }
+            }
+        };
+        
+        for message in &held {
+            self.deliver(player_id, message);
+        }
+        
+        Ok(())
+    }
+    
+    /// Injected network conditions for a player's connection, if any
+    pub fn network_chaos(&self, player_id: &str) -> Option<ChaosConfig> {
+        self.network_chaos.lock().unwrap().get(player_id).map(|link| link.config().clone())
+    }
+    
+    /// Deliver messages whose injected delay has elapsed. Call periodically.
+    pub fn tick_network_chaos(&self, now: Instant) -> usize {
+        let released: Vec<(String, Vec<GameMessage>)> = self.network_chaos.lock().unwrap()
+            .iter_mut()
+            .map(|(player_id, link)| (player_id.clone(), link.poll(now)))
+            .filter(|(_, messages)| !messages.is_empty())
+            .collect();
+        
+        let mut delivered = 0;
+        for (player_id, messages) in released {
+            for message in &messages {
+                self.deliver(&player_id, message);
+            }
+            delivered += messages.len();
+        }
+        delivered
+    }
+    
+    /// Audit trail of moderator actions, optionally for a single room
+    pub fn moderation_log(&self, room_id: Option<&str>) -> Vec<ModerationAuditEntry> {
+        self.moderation_log.lock().unwrap().iter()
+            .filter(|entry| room_id.is_none_or(|id| entry.room_id == id))
+            .cloned()
+            .collect()
+    }
+    
+    /// Presence of `target_id` as seen by `viewer_id`; hidden players appear offline
+    pub fn get_presence(&self, viewer_id: &str, target_id: &str) -> PlayerPresence {
+        let hidden = PlayerPresence {
+            player_id: target_id.to_string(),
+            online: false,
+            room_id: None,
+            game_id: None,
+        };
+        
+        if !self.privacy_allows(viewer_id, target_id, PrivacyAction::ViewPresence) {
+            return hidden;
+        }
+        
+        self.presence_cache.get_or_load(target_id.to_string(), || {
+            if !self.connected_players.lock().unwrap().contains_key(target_id) {
+                return hidden.clone();
+            }
+            
+            let room_id = self.player_room(target_id);
+            let game_id = room_id.as_ref().and_then(|id| {
+                self.rooms.lock().unwrap().get(id).map(|room| room.game_id.clone())
+            });
+            
+            PlayerPresence {
+                online: true,
+                room_id,
+                game_id,
+                ..hidden.clone()
+            }
+        })
+    }
+    
+    /// Hit/miss counts for the presence and room list caches
+    pub fn cache_stats(&self) -> Vec<CacheStats> {
+        vec![self.presence_cache.stats(), self.room_page_cache.stats()]
+    }
+    
+    /// Invite another connected player to the sender's current room
+    pub fn invite_to_room(&self, from_player_id: &str, to_player_id: &str) -> Result<(), String> {
+        let room_id = self.player_room(from_player_id).ok_or_else(|| "Not in a room".to_string())?;
+        
+        if !self.connected_players.lock().unwrap().contains_key(to_player_id) {
+            return Err("Player is not online".to_string());
+        }
+        
+        if !self.privacy_allows(from_player_id, to_player_id, PrivacyAction::Invite) {
+            return Err("Player is not accepting invites".to_string());
+        }
+        
+        if let Some(room) = self.rooms.lock().unwrap().get_mut(&room_id) {
+            room.invited.insert(to_player_id.to_string());
+        }
+        self.send_to_player(to_player_id, &GameMessage::RoomInvite {
+            from_player_id: from_player_id.to_string(),
+            room_id,
+        });
+        
+        Ok(())
+    }
+    
+    pub fn send_direct_message(&self, from_player_id: &str, to_player_id: &str, text: String) -> Result<(), String> {
+        if !self.connected_players.lock().unwrap().contains_key(to_player_id) {
+            return Err("Player is not online".to_string());
+        }
+        
+        if !self.privacy_allows(from_player_id, to_player_id, PrivacyAction::DirectMessage) {
+            return Err("Player is not accepting messages".to_string());
+        }
+        
+        self.send_to_player(to_player_id, &GameMessage::DirectMessage {
+            from_player_id: from_player_id.to_string(),
+            to_player_id: to_player_id.to_string(),
+            text,
+        });
+        
+        Ok(())
+    }
+    
+    /// Room a player is currently in
+    pub fn player_room(&self, player_id: &str) -> Option<String> {
+        self.player_to_room.lock().unwrap().get(player_id).cloned()
+    }
+    
+    /// Send a message to a single player
+    pub fn send_to_player(&self, player_id: &str, message: &GameMessage) {
+        if let Some(link) = self.network_chaos.lock().unwrap().get_mut(player_id) {
+            link.send(message.clone(), self.clock.now());
+            return;
+        }
+        self.deliver(player_id, message);
+    }
+    
+    fn deliver(&self, player_id: &str, message: &GameMessage) {
+        match &self.message_sink {
+            Some(sink) => sink.deliver(player_id, message),
+            // In real implementation, send message over network
+            None => println!("[MultiplayerServer] Sending to player: {}", player_id),
+        }
+    }
+    
+    /// Sample concurrent players per game and the fill of every room. Call periodically.
+    pub fn record_analytics_sample(&self) {
+        let Some(analytics) = &self.analytics else { return };
+        let now = self.clock.timestamp();
+        
+        let rooms: Vec<(String, usize, usize)> = self.rooms.lock().unwrap()
+            .values()
+            .map(|room| (room.game_id.clone(), room.human_count(), room.max_players))
+            .collect();
+        
+        let mut concurrent: HashMap<&str, usize> = HashMap::new();
+        for (game_id, players, max_players) in &rooms {
+            *concurrent.entry(game_id).or_default() += players;
+            analytics.record_room_fill(game_id, *players, *max_players, now);
+        }
+        for (game_id, players) in concurrent {
+            analytics.record_concurrency(game_id, players, now);
+        }
+    }
+    
+    /// Usage aggregates for a game, available to its developers
+    pub fn developer_dashboard(&self, requester_id: &str, game_id: &str, from: i64, to: i64) -> Result<GameDashboard, String> {
+        let analytics = self.analytics.as_ref().ok_or_else(|| "Analytics not configured".to_string())?;
+        let auth = self.auth.as_ref().ok_or_else(|| "Authentication not configured".to_string())?;
+        
+        if !auth.is_game_developer(requester_id, game_id) {
+            return Err("Only the game's developers can view its dashboard".to_string());
+        }
+        
+        analytics.dashboard(game_id, from, to)
+    }
+    
+    /// Get server statistics
+    pub fn get_stats(&self) -> ServerStats {
+        let rooms = self.rooms.lock().unwrap();
+        let connections = self.connections.lock().unwrap();
+        
+        let total_players: usize = rooms.values().map(|r| r.player_count()).sum();
+        
+        ServerStats {
+            total_rooms: rooms.len(),
+            total_players,
+            active_connections: connections.len(),
+        }
+    }
+    
+    // Private helper methods
+    
+    fn apply_object_message(&self, player_id: &str, message: &mut GameMessage) -> Result<(String, ObjectUpdate), String> {
+        let room_id = self.player_room(player_id).ok_or_else(|| "Not in a room".to_string())?;
+        
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = rooms.get_mut(&room_id).ok_or_else(|| "Room not found".to_string())?;
+        
+        if room.frozen_players.contains(player_id) {
+            return Err("Object interactions are frozen".to_string());
+        }
+        if room.observers.iter().any(|id| id == player_id) {
+            return Err("Observers cannot interact with objects".to_string());
+        }
+        let spawning = matches!(message, GameMessage::ObjectSpawned { .. } | GameMessage::ObjectsSpawnedBatch { .. });
+        if spawning && !room.permissions.players_can_spawn_objects && room.host_id != player_id {
+            return Err("Only the host can spawn objects in this room".to_string());
+        }
+        room.check_permission_zones(player_id, message)?;
+        
+        let update = room.apply_object_message(player_id, message, self.clock.timestamp())?;
+        Ok((room_id, update))
+    }
+    
+    fn issue_join_ticket(&self, room_id: &str, player_id: &str) -> (String, i64) {
+        let ticket = format!("ticket_{}", uuid::Uuid::new_v4());
+        let now = self.clock.timestamp();
+        let expires_at = now + JOIN_TICKET_TTL_SECONDS;
+        
+        let mut tickets = self.join_tickets.lock().unwrap();
+        tickets.retain(|_, t| t.expires_at > now);
+        tickets.insert(ticket.clone(), JoinTicket {
+            room_id: room_id.to_string(),
+            player_id: player_id.to_string(),
+            expires_at,
+        });
+        
+        println!("[MultiplayerServer] Join ticket minted for {} in room {}", player_id, room_id);
+        
+        (ticket, expires_at)
+    }
+    
+    /// A prefab's objects placed around `origin`, ready to spawn as one batch
+    fn prefab_objects(&self, prefab_name: &str, origin: &Vector3) -> Result<Vec<SpawnedObject>, String> {
+        let prefab = self.prefabs.lock().unwrap()
+            .get(prefab_name)
+            .cloned()
+            .ok_or_else(|| format!("Unknown prefab: {}", prefab_name))?;
+        
+        Ok(prefab.objects.iter()
+            .map(|object| SpawnedObject {
+                object_id: 0,
+                temp_id: None,
+                object_type: object.object_type.clone(),
+                position: origin.add(&object.offset),
+                rotation: object.rotation.clone(),
+            })
+            .collect())
+    }
+    
+    fn page_rooms(mut keyed: Vec<(RoomSortKey, &GameRoom)>, query: &RoomQuery) -> Result<RoomPage, String> {
+        let after = query.cursor.as_deref().map(RoomSortKey::from_cursor).transpose()?;
+        let limit = if query.limit == 0 { MAX_ROOM_PAGE } else { query.limit.min(MAX_ROOM_PAGE) };
+        
+        keyed.sort_by(|a, b| a.0.cmp(&b.0));
+        let total = keyed.len();
+        
+        let remaining: Vec<&(RoomSortKey, &GameRoom)> = keyed.iter()
+            .filter(|(key, _)| after.as_ref().is_none_or(|after| key > after))
+            .collect();
+        
+        let page = &remaining[..remaining.len().min(limit)];
+        let next_cursor = if remaining.len() > page.len() {
+            page.last().map(|(key, _)| key.to_cursor())
+        } else {
+            None
+        };
+        
+        Ok(RoomPage {
+            rooms: page.iter().map(|(_, room)| room.summary()).collect(),
+            total,
+            next_cursor,
+        })
+    }
+    
+    /// A player came online, went offline or changed rooms
+    fn presence_changed(&self, player_id: &str) {
+        self.presence_cache.invalidate(player_id);
+        self.room_page_cache.invalidate_all();
+        
+        if let Some(cluster) = &self.cluster {
+            let room_id = self.player_room(player_id);
+            let online = room_id.is_some() || self.connected_players.lock().unwrap().contains_key(player_id);
+            cluster.publish_presence(player_id, online.then(|| PresenceLocation {
+                server_id: self.server_id.clone(),
+                room_id,
+            }));
+        }
+        
+        if let Some(queue) = &self.write_behind {
+            let room_id = self.player_room(player_id);
+            let game_id = room_id.as_ref().and_then(|id| {
+                self.rooms.lock().unwrap().get(id).map(|room| room.game_id.clone())
+            });
+            let presence = PlayerPresence {
+                player_id: player_id.to_string(),
+                online: self.connected_players.lock().unwrap().contains_key(player_id),
+                room_id,
+                game_id,
+            };
+            
+            match PendingWrite::new(WriteKind::Presence, player_id, &presence) {
+                Ok(write) => {
+                    queue.enqueue(write);
+                }
+                Err(e) => eprintln!("[MultiplayerServer] Failed to encode presence of {}: {}", player_id, e),
+            }
+        }
+    }
+    
+    fn audio_settings(&self, room_id: &str) -> Option<GameMessage> {
+        self.rooms.lock().unwrap().get(room_id).map(|room| GameMessage::AudioSettings {
+            room_id: room_id.to_string(),
+            attenuation: room.attenuation.clone(),
+            occluders: room.audio_occluders.clone(),
+        })
+    }
+    
+    fn require_moderator(&self, moderator_id: &str, room_id: &str) -> Result<(), String> {
+        let auth = self.auth.as_ref().ok_or_else(|| "Authentication not configured".to_string())?;
+        
+        let game_id = self.rooms.lock().unwrap()
+            .get(room_id)
+            .map(|room| room.game_id.clone())
+            .ok_or_else(|| "Room not found".to_string())?;
+        
+        if !auth.is_moderator_for(moderator_id, &game_id) {
+            println!("[MultiplayerServer] Moderator action denied for {} in room {}", moderator_id, room_id);
+            return Err("Not a moderator for this room".to_string());
+        }
+        
+        Ok(())
+    }
+    
+    fn record_moderation(&self, moderator_id: &str, action: ModerationAction, target_id: Option<&str>, room_id: &str) {
+        println!("[Moderation] {} {:?} {:?} in room {}", moderator_id, action, target_id, room_id);
+        
+        self.moderation_log.lock().unwrap().push(ModerationAuditEntry {
+            moderator_id: moderator_id.to_string(),
+            action,
+            target_player_id: target_id.map(str::to_string),
+            room_id: room_id.to_string(),
+            timestamp: self.clock.timestamp(),
+        });
+    }
+    
+    /// Without an account service there are no privacy settings to enforce
+    fn privacy_allows(&self, actor_id: &str, target_id: &str, action: PrivacyAction) -> bool {
+        match &self.auth {
+            Some(auth) => auth.privacy_allows(actor_id, target_id, action),
+            None => true,
+        }
+    }
+    
+    /// Players seated without a connection are only tracked while they're in a room
+    fn forget_unconnected_heartbeat(&self, player_id: &str) {
+        if !self.connections.lock().unwrap().contains_key(player_id) {
+            self.last_heartbeats.lock().unwrap().remove(player_id);
+        }
+    }
+    
+    fn send_onboarding_status(&self, player_id: &str, state: OnboardingState) {
+        self.send_to_player(player_id, &GameMessage::OnboardingStatus {
+            game_id: state.game_id,
+            completed_steps: state.completed_steps,
+            remaining_steps: state.remaining_steps,
+        });
+    }
+    
+    fn register_connection(
+        &self,
+        auth: &AuthService,
+        profile: UserProfile,
+        game_id: &str,
+        addr: SocketAddr,
+        region: Option<String>,
+    ) -> Result<PlayerState, String> {
+        // Counted first: a developer at their CCU limit refuses the connection outright
+        if let Some(usage) = &self.usage {
+            usage.player_connected(&profile.id, game_id, self.clock.timestamp())?;
+        }
+        
+        let player = PlayerState {
+            player_id: profile.id.clone(),
+            username: profile.username,
+            display_name: profile.display_name,
+            transform: PlayerTransform::identity(),
+            avatar_url: profile.avatar_url,
+            is_talking: false,
+            custom_data: HashMap::new(),
+            is_npc: false,
+        };
+        
+        auth.add_played_game(&profile.id, game_id);
+        if let Some(analytics) = &self.analytics {
+            analytics.session_started(&profile.id, game_id, self.clock.timestamp());
+        }
+        let region = region.or_else(|| {
+            self.geoip.as_ref()
+                .and_then(|geoip| geoip.lookup(addr.ip()))
+                .map(|location| location.region)
+        });
+        
+        if let Some(region) = region {
+            self.player_regions.lock().unwrap().insert(profile.id.clone(), region);
+        }
+        
+        self.connections.lock().unwrap().insert(profile.id.clone(), addr);
+        self.last_heartbeats.lock().unwrap().insert(profile.id.clone(), self.clock.now());
+        self.connected_players.lock().unwrap().insert(profile.id.clone(), player.clone());
+        self.presence_changed(&profile.id);
+        
+        println!("[MultiplayerServer] Player {} connected from {}", profile.id, addr);
+        
+        Ok(player)
+    }
+    
+    fn create_world_instance(&self, template: &WorldTemplate, primary: bool) -> String {
+        let room_id = self.create_room(
+            template.game_id.clone(),
+            "server".to_string(),
+            template.max_players_per_instance,
+        );
+        
+        if let Some(room) = self.rooms.lock().unwrap().get_mut(&room_id) {
+            room.world_id = Some(template.world_id.clone());
+            room.is_persistent = primary;
+        }
+        
+        room_id
+    }
+    
+    /// Instance room IDs of a world, dropping instances that closed when they emptied
+    fn live_world_instances(&self, world_id: &str) -> Vec<String> {
+        let rooms = self.rooms.lock().unwrap();
+        let mut instances = self.world_instances.lock().unwrap();
+        
+        match instances.get_mut(world_id) {
+            Some(ids) => {
+                ids.retain(|id| rooms.contains_key(id));
+                ids.clone()
+            }
+            None => Vec::new(),
+        }
+    }
+    
+    fn notify_state_watchers(&self, room: &GameRoom, key: &str, value: &str, version: u64) {
+        for watcher in room.state_watchers(key) {
+            let message = GameMessage::GameStateChanged {
+                key: key.to_string(),
+                value: value.to_string(),
+                version,
+            };
+            self.send_to_player(&watcher, &message);
+        }
+    }
+}
+
+/// Single-use ticket that lets one player enter one room
+#[derive(Debug, Clone)]
+struct JoinTicket {
+    room_id: String,
+    player_id: String,
+    expires_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ServerStats {
+    pub total_rooms: usize,
+    pub total_players: usize,
+    pub active_connections: usize,
+}
+
+/// What a viewer may see of another player's whereabouts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerPresence {
+    pub player_id: String,
+    pub online: bool,
+    pub room_id: Option<String>,
+    pub game_id: Option<String>,
+}
+
+// ============================================
+// Cluster Presence
+// ============================================
+
+/// Which instance a player is on, and their room there
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresenceLocation {
+    pub server_id: String,
+    pub room_id: Option<String>,
+}
+
+/// Everything a client needs to join a friend: connect to `server_addr` and
+/// redeem `ticket` there with `JoinWithTicket`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FriendJoinDetails {
+    pub server_id: String,
+    pub server_addr: String,
+    pub room_id: String,
+    pub game_id: String,
+    pub ticket: String,
+    pub expires_at: i64,
+}
+
+/// Why `join_friend` refused, for the client to explain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum JoinDenial {
+    NotFriends,
+    /// The friend's privacy settings hide where they are
+    PresenceHidden,
+    Offline,
+    /// Online but not in a room
+    NotInRoom,
+    RoomNotFound,
+    RoomFull,
+    /// The room's time is nearly up and it no longer takes joins
+    RoomClosing,
+    /// The room is private and the player hasn't been invited
+    InviteRequired,
+    ContentRestricted { reason: String },
+    /// Accounts or the friend's instance can't be reached
+    Unavailable,
+}
+
+impl fmt::Display for JoinDenial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinDenial::NotFriends => write!(f, "Not friends"),
+            JoinDenial::PresenceHidden => write!(f, "Friend's location is private"),
+            JoinDenial::Offline => write!(f, "Friend is offline"),
+            JoinDenial::NotInRoom => write!(f, "Friend is not in a room"),
+            JoinDenial::RoomNotFound => write!(f, "Room not found"),
+            JoinDenial::RoomFull => write!(f, "Room is full"),
+            JoinDenial::RoomClosing => write!(f, "Room is closing"),
+            JoinDenial::InviteRequired => write!(f, "Room is private; an invite is required"),
+            JoinDenial::ContentRestricted { reason } => write!(f, "{}", reason),
+            JoinDenial::Unavailable => write!(f, "Friend join is unavailable"),
+        }
+    }
+}
+
+/// Shared view of the server instances, so players on one can be found from another
+pub trait ClusterDirectory: Send + Sync {
+    /// Record where a player is; None once they're offline
+    fn publish_presence(&self, player_id: &str, location: Option<PresenceLocation>);
+    fn locate(&self, player_id: &str) -> Option<PresenceLocation>;
+    /// Ask the instance at `location` whether `player_id` may join its room there
+    fn check_remote_join(&self, location: &PresenceLocation, player_id: &str) -> Result<FriendJoinDetails, JoinDenial>;
+}
+
+/// Cluster of instances in one process; useful for tests and single-node setups
+pub struct InMemoryCluster {
+    presence: Mutex<HashMap<String, PresenceLocation>>, // player_id -> location
+    servers: Mutex<HashMap<String, Weak<MultiplayerServer>>>, // server_id -> instance
+}
+
+impl InMemoryCluster {
+    pub fn new() -> Self {
+        Self {
+            presence: Mutex::new(HashMap::new()),
+            servers: Mutex::new(HashMap::new()),
+        }
+    }
+    
+    /// Make an instance reachable for remote joins under its `server_id`
+    pub fn register(&self, server: &Arc<MultiplayerServer>) {
+        self.servers.lock().unwrap().insert(server.server_id().to_string(), Arc::downgrade(server));
+    }
+}
+
+impl Default for InMemoryCluster {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClusterDirectory for InMemoryCluster {
+    fn publish_presence(&self, player_id: &str, location: Option<PresenceLocation>) {
+        let mut presence = self.presence.lock().unwrap();
+        match location {
+            Some(location) => presence.insert(player_id.to_string(), location),
+            None => presence.remove(player_id),
+        };
+    }
+    
+    fn locate(&self, player_id: &str) -> Option<PresenceLocation> {
+        self.presence.lock().unwrap().get(player_id).cloned()
+    }
+    
+    fn check_remote_join(&self, location: &PresenceLocation, player_id: &str) -> Result<FriendJoinDetails, JoinDenial> {
+        let room_id = location.room_id.as_deref().ok_or(JoinDenial::NotInRoom)?;
+        let server = self.servers.lock().unwrap()
+            .get(&location.server_id)
+            .and_then(Weak::upgrade)
+            .ok_or(JoinDenial::Unavailable)?;
+        
+        server.check_room_join(room_id, player_id)
+    }
+}
+
+// ============================================
+// Voice Chat System
+// ============================================
+
+/// Decides who hears a speaker, e.g. from positions and audio zones
+pub trait VoiceRouter: Send + Sync {
+    /// Players who should receive `speaker_id`'s audio, or None to use the whole channel
+    fn voice_recipients(&self, room_id: &str, speaker_id: &str) -> Option<Vec<String>>;
+    
+    /// The room's host, who alone may start a recording; None when unknown
+    fn room_host(&self, _room_id: &str) -> Option<String> {
+        None
+    }
+}
+
+impl VoiceRouter for MultiplayerServer {
+    fn voice_recipients(&self, room_id: &str, speaker_id: &str) -> Option<Vec<String>> {
+        self.rooms.lock().unwrap()
+            .get(room_id)
+            .map(|room| room.voice_recipients(speaker_id))
+    }
+    
+    fn room_host(&self, room_id: &str) -> Option<String> {
+        self.rooms.lock().unwrap().get(room_id).map(|room| room.host_id.clone())
+    }
+}
+
+/// Audio kept briefly so moderators can review reports
+#[derive(Debug, Clone)]
+pub struct VoiceClip {
+    pub speaker_id: String,
+    pub recorded_at: i64,
+    pub audio_data: Vec<u8>,
+}
+
+const MAX_MODERATION_CLIPS: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RecordingKind {
+    Moderation,
+    Stream,
+}
+
+/// How a player's microphone transmits
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum VoiceInputMode {
+    #[default]
+    OpenMic,
+    PushToTalk,
+    Muted,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct VoiceInputState {
+    mode: VoiceInputMode,
+    push_to_talk_pressed: bool,
+}
+
+impl VoiceInputState {
+    fn transmitting(&self) -> bool {
+        match self.mode {
+            VoiceInputMode::OpenMic => true,
+            VoiceInputMode::PushToTalk => self.push_to_talk_pressed,
+            VoiceInputMode::Muted => false,
+        }
+    }
+}
+
+/// Mouth shape and loudness at one moment of speech; two bytes on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VisemeFrame {
+    /// Index into the standard 15-viseme set (0 = silence)
+    pub viseme: u8,
+    pub amplitude: u8,
+}
+
+pub const VISEME_SILENCE: u8 = 0;
+/// Open mouth ("aa"); used when only loudness is known
+pub const VISEME_OPEN: u8 = 10;
+/// Lip-sync updates per player are sent at most this often
+const VISEME_INTERVAL: Duration = Duration::from_millis(100);
+/// Amplitudes below this count as silence
+const VISEME_SILENCE_THRESHOLD: u8 = 8;
+
+impl VisemeFrame {
+    /// Estimate a frame from 16-bit little-endian PCM by its loudness alone
+    pub fn from_pcm(audio_data: &[u8]) -> Self {
+        let samples: Vec<f32> = audio_data.chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / i16::MAX as f32)
+            .collect();
+        
+        let rms = if samples.is_empty() {
+            0.0
+        } else {
+            (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+        };
+        
+        let amplitude = (rms * 255.0).round().min(255.0) as u8;
+        let viseme = if amplitude < VISEME_SILENCE_THRESHOLD { VISEME_SILENCE } else { VISEME_OPEN };
+        
+        Self { viseme, amplitude }
+    }
+}
+
+/// An active recording; only speakers who consented are captured
+#[derive(Debug, Clone)]
+struct RoomRecording {
+    kind: RecordingKind,
+    consented: HashSet<String>,
+    clips: Vec<VoiceClip>,
+}
+
+pub struct VoiceChatServer {
+    active_channels: Arc<Mutex<HashMap<String, Vec<String>>>>, // room_id -> [player_ids]
+    moderation_buffer: Arc<Mutex<HashMap<String, VecDeque<VoiceClip>>>>, // room_id -> recent clips
+    recordings: Arc<Mutex<HashMap<String, RoomRecording>>>, // room_id -> recording
+    input_states: Arc<Mutex<HashMap<String, VoiceInputState>>>, // player_id -> mic state
+    last_visemes: Arc<Mutex<HashMap<String, Instant>>>, // player_id -> last lip-sync broadcast
+    /// Derive lip-sync from raw PCM audio for clients that don't send their own
+    pcm_visemes: bool,
+    router: Option<Arc<dyn VoiceRouter>>,
+    auth: Option<Arc<AuthService>>,
+    usage: Option<Arc<UsageService>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl VoiceChatServer {
+    pub fn new() -> Self {
+        Self {
+            active_channels: Arc::new(Mutex::new(HashMap::new())),
+            moderation_buffer: Arc::new(Mutex::new(HashMap::new())),
+            recordings: Arc::new(Mutex::new(HashMap::new())),
+            input_states: Arc::new(Mutex::new(HashMap::new())),
+            last_visemes: Arc::new(Mutex::new(HashMap::new())),
+            pcm_visemes: false,
+            router: None,
+            auth: None,
+            usage: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+    
+    /// Voice server that honours each speaker's recording opt-out
+    pub fn with_auth(auth: Arc<AuthService>) -> Self {
+        Self {
+            auth: Some(auth),
+            ..Self::new()
+        }
+    }
+    
+    /// Compute lip-sync from voice frames server-side; requires uncompressed 16-bit PCM audio
+    pub fn with_pcm_visemes(mut self) -> Self {
+        self.pcm_visemes = true;
+        self
+    }
+    
+    /// Route audio with `router` instead of sending it to the whole channel
+    pub fn with_router(mut self, router: Arc<dyn VoiceRouter>) -> Self {
+        self.router = Some(router);
+        self
+    }
+    
+    /// Meter voice minutes against the developer of each room's game. Share the
+    /// `UsageService` given to the game server, which tells it which game a room is for.
+    pub fn with_usage(mut self, usage: Arc<UsageService>) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+    
+    /// Read time from `clock` instead of the system clock; share the game server's
+    /// clock so voice minutes line up with the rest of the usage
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+    
+    pub fn join_voice_channel(&self, room_id: &str, player_id: &str) {
+        let mut channels = self.active_channels.lock().unwrap();
+        
+        channels.entry(room_id.to_string())
+            .or_insert_with(Vec::new)
+            .push(player_id.to_string());
+        drop(channels);
+        
+        println!("[VoiceChat] Player {} joined voice channel in room {}", player_id, room_id);
+        if let Some(usage) = &self.usage {
+            usage.voice_started(player_id, room_id, self.clock.timestamp());
+        }
+        
+        // Late joiners see the indicator and are asked for consent like everyone else
+        if let Some(kind) = self.recording_state(room_id) {
+            let room_id = room_id.to_string();
+            self.send_to_player(player_id, &GameMessage::RecordingStateChanged { room_id: room_id.clone(), kind: Some(kind) });
+            self.send_to_player(player_id, &GameMessage::RecordingConsentRequest { room_id, kind });
+        }
+    }
+    
+    pub fn leave_voice_channel(&self, room_id: &str, player_id: &str) {
+        let mut channels = self.active_channels.lock().unwrap();
+        
+        if let Some(players) = channels.get_mut(room_id) {
+            players.retain(|p| p != player_id);
+            
+            if players.is_empty() {
+                // A running recording keeps its clips until someone stops it
+                channels.remove(room_id);
+                self.moderation_buffer.lock().unwrap().remove(room_id);
+            }
+        }
+        
+        if let Some(recording) = self.recordings.lock().unwrap().get_mut(room_id) {
+            recording.consented.remove(player_id);
+        }
+        self.input_states.lock().unwrap().remove(player_id);
+        self.last_visemes.lock().unwrap().remove(player_id);
+        if let Some(usage) = &self.usage {
+            usage.voice_stopped(player_id, self.clock.timestamp());
+        }
+        
+        println!("[VoiceChat] Player {} left voice channel", player_id);
+    }
+    
+    pub fn broadcast_audio(&self, room_id: &str, sender_id: &str, audio_data: Vec<u8>) {
+        // Muted players and released push-to-talk keys are enforced here, not trusted to the client
+        if !self.is_transmitting(sender_id) {
+            return;
+        }
+        
+        self.buffer_for_moderation(room_id, sender_id, &audio_data);
+        self.capture_for_recording(room_id, sender_id, &audio_data);
+        
+        if self.pcm_visemes {
+            self.relay_visemes(room_id, sender_id, VisemeFrame::from_pcm(&audio_data));
+        }
+        
+        for player_id in self.audio_recipients(room_id, sender_id) {
+            // Send audio data to player
+            println!("[VoiceChat] Sending audio from {} to {}", sender_id, player_id);
+        }
+    }
+    
+    /// Channel members who receive `sender_id`'s audio after routing
+    pub fn audio_recipients(&self, room_id: &str, sender_id: &str) -> Vec<String> {
+        let members = self.active_channels.lock().unwrap().get(room_id).cloned().unwrap_or_default();
+        let routed = self.router.as_ref().and_then(|router| router.voice_recipients(room_id, sender_id));
+        
+        members.into_iter()
+            .filter(|p| p != sender_id)
+            .filter(|p| routed.as_ref().is_none_or(|recipients| recipients.contains(p)))
+            .collect()
+    }
+    
+    /// Send a speaker's lip-sync frame to the rest of the channel, throttled to a low rate.
+    /// Returns whether the frame was sent.
+    pub fn relay_visemes(&self, room_id: &str, sender_id: &str, frame: VisemeFrame) -> bool {
+        if !self.is_transmitting(sender_id) {
+            return false;
+        }
+        
+        {
+            let now = self.clock.now();
+            let mut last_visemes = self.last_visemes.lock().unwrap();
+            
+            if last_visemes.get(sender_id).is_some_and(|last| now.duration_since(*last) < VISEME_INTERVAL) {
+                return false;
+            }
+            last_visemes.insert(sender_id.to_string(), now);
+        }
+        
+        let players = self.active_channels.lock().unwrap().get(room_id).cloned().unwrap_or_default();
+        let message = GameMessage::Visemes { player_id: sender_id.to_string(), frame };
+        
+        for player_id in players.iter().filter(|p| *p != sender_id) {
+            self.send_to_player(player_id, &message);
+        }
+        
+        true
+    }
+    
+    pub fn set_input_mode(&self, room_id: &str, player_id: &str, mode: VoiceInputMode) {
+        self.update_input_state(room_id, player_id, |state| {
+            state.mode = mode;
+            state.push_to_talk_pressed = false;
+        });
+    }
+    
+    pub fn set_push_to_talk(&self, room_id: &str, player_id: &str, pressed: bool) {
+        self.update_input_state(room_id, player_id, |state| state.push_to_talk_pressed = pressed);
+    }
+    
+    pub fn input_mode(&self, player_id: &str) -> VoiceInputMode {
+        self.input_states.lock().unwrap().get(player_id).map(|s| s.mode).unwrap_or_default()
+    }
+    
+    /// Start recording a room's voice channel. Only a member of the channel may start
+    /// it, and only the room's host when the router knows who that is. Everyone in the
+    /// channel is told and asked for consent; nobody is recorded until they agree.
+    pub fn start_recording(&self, room_id: &str, kind: RecordingKind, started_by: &str) -> Result<(), String> {
+        if !self.in_channel(room_id, started_by) {
+            return Err("Not in this voice channel".to_string());
+        }
+        let host = self.router.as_ref().and_then(|router| router.room_host(room_id));
+        if host.is_some_and(|host| host != started_by) {
+            return Err("Only the host can start a recording".to_string());
+        }
+        
+        self.recordings.lock().unwrap().insert(room_id.to_string(), RoomRecording {
+            kind,
+            consented: HashSet::new(),
+            clips: Vec::new(),
+        });
+        
+        println!("[VoiceChat] {:?} recording started in room {} by {}", kind, room_id, started_by);
+        
+        self.broadcast_to_channel(room_id, GameMessage::RecordingStateChanged {
+            room_id: room_id.to_string(),
+            kind: Some(kind),
+        });
+        self.broadcast_to_channel(room_id, GameMessage::RecordingConsentRequest {
+            room_id: room_id.to_string(),
+            kind,
+        });
+        
+        Ok(())
+    }
+    
+    /// Stop a room's recording and hand back the audio it captured
+    pub fn stop_recording(&self, room_id: &str) -> Vec<VoiceClip> {
+        let Some(recording) = self.recordings.lock().unwrap().remove(room_id) else {
+            return Vec::new();
+        };
+        
+        println!("[VoiceChat] Recording stopped in room {} with {} clips", room_id, recording.clips.len());
+        
+        self.broadcast_to_channel(room_id, GameMessage::RecordingStateChanged {
+            room_id: room_id.to_string(),
+            kind: None,
+        });
+        
+        recording.clips
+    }
+    
+    pub fn recording_state(&self, room_id: &str) -> Option<RecordingKind> {
+        self.recordings.lock().unwrap().get(room_id).map(|r| r.kind)
+    }
+    
+    /// Record a player's answer to a consent prompt
+    pub fn set_recording_consent(&self, room_id: &str, player_id: &str, consent: bool) -> Result<(), String> {
+        if !self.in_channel(room_id, player_id) {
+            return Err("Not in this voice channel".to_string());
+        }
+        
+        let mut recordings = self.recordings.lock().unwrap();
+        let recording = recordings.get_mut(room_id).ok_or_else(|| "No recording in progress".to_string())?;
+        
+        if consent {
+            recording.consented.insert(player_id.to_string());
+        } else {
+            recording.consented.remove(player_id);
+        }
+        
+        Ok(())
+    }
+    
+    /// Recent clips from a room, optionally limited to one speaker
+    pub fn moderation_clips(&self, room_id: &str, speaker_id: Option<&str>) -> Vec<VoiceClip> {
+        let buffer = self.moderation_buffer.lock().unwrap();
+        
+        buffer.get(room_id)
+            .map(|clips| {
+                clips.iter()
+                    .filter(|c| speaker_id.is_none_or(|id| c.speaker_id == id))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    
+    /// Keep recent audio as evidence for reports. This doesn't depend on a recording
+    /// or consent, but speakers who opted out in their privacy settings are skipped.
+    fn buffer_for_moderation(&self, room_id: &str, speaker_id: &str, audio_data: &[u8]) {
+        let opted_out = self.auth.as_ref()
+            .and_then(|auth| auth.privacy_settings(speaker_id))
+            .is_some_and(|settings| settings.voice_recording_opt_out);
+        
+        if opted_out {
+            return;
+        }
+        
+        let mut buffer = self.moderation_buffer.lock().unwrap();
+        let clips = buffer.entry(room_id.to_string()).or_default();
+        
+        clips.push_back(VoiceClip {
+            speaker_id: speaker_id.to_string(),
+            recorded_at: self.clock.timestamp(),
+            audio_data: audio_data.to_vec(),
+        });
+        
+        while clips.len() > MAX_MODERATION_CLIPS {
+            clips.pop_front();
+        }
+    }
+    
+    /// Add audio to the room's recording if the speaker consented to it
+    fn capture_for_recording(&self, room_id: &str, speaker_id: &str, audio_data: &[u8]) {
+        let mut recordings = self.recordings.lock().unwrap();
+        let Some(recording) = recordings.get_mut(room_id) else { return };
+        if !recording.consented.contains(speaker_id) {
+            return;
+        }
+        
+        recording.clips.push(VoiceClip {
+            speaker_id: speaker_id.to_string(),
+            recorded_at: self.clock.timestamp(),
+            audio_data: audio_data.to_vec(),
+        });
+    }
+    
+    fn in_channel(&self, room_id: &str, player_id: &str) -> bool {
+        self.active_channels.lock().unwrap().get(room_id).is_some_and(|players| players.iter().any(|p| p == player_id))
+    }
+    
+    fn is_transmitting(&self, player_id: &str) -> bool {
+        self.input_states.lock().unwrap().get(player_id).is_none_or(|s| s.transmitting())
+    }
+    
+    fn update_input_state(&self, room_id: &str, player_id: &str, update: impl FnOnce(&mut VoiceInputState)) {
+        let state = {
+            let mut states = self.input_states.lock().unwrap();
+            let state = states.entry(player_id.to_string()).or_default();
+            let before = *state;
+            update(state);
+            
+            if before.mode == state.mode && before.transmitting() == state.transmitting() {
+                return;
+            }
+            *state
+        };
+        
+        self.broadcast_to_channel(room_id, GameMessage::VoiceInputChanged {
+            player_id: player_id.to_string(),
+            mode: state.mode,
+            transmitting: state.transmitting(),
+        });
+    }
+    
+    fn broadcast_to_channel(&self, room_id: &str, message: GameMessage) {
+        let players = self.active_channels.lock().unwrap().get(room_id).cloned().unwrap_or_default();
+        
+        for player_id in &players {
+            self.send_to_player(player_id, &message);
+        }
+    }
+    
+    fn send_to_player(&self, player_id: &str, _message: &GameMessage) {
+        // In real implementation, send message over network
+        println!("[VoiceChat] Sending to player: {}", player_id);
+    }
+}
+
+// ============================================
+// Matchmaking System
+// ============================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchmakingTicket {
+    pub ticket_id: String,
+    pub game_id: String,
+    pub player_id: String,
+    pub queued_at: i64,
+}
+
+impl MatchmakingTicket {
+    fn ticket_id_for(game_id: &str, player_id: &str) -> String {
+        format!("{}:{}", game_id, player_id)
+    }
+}
+
+/// Durable storage for queued matchmaking tickets
+pub trait TicketStore: Send + Sync {
+    fn save(&self, ticket: &MatchmakingTicket) -> Result<(), String>;
+    fn remove(&self, ticket_id: &str) -> Result<(), String>;
+    fn load_all(&self) -> Result<Vec<MatchmakingTicket>, String>;
+}
+
+/// How long a regional ticket waits before its search widens. After one window it may
+/// match players from the game's region-less queue, after two from every region.
+pub const REGION_EXPANSION_WINDOW: Duration = Duration::from_secs(30);
+
+pub struct MatchmakingService {
+    queue: Arc<Mutex<HashMap<String, Vec<String>>>>, // game_id -> [player_ids]
+    queued_since: Arc<Mutex<HashMap<String, Instant>>>, // ticket_id -> when the player queued
+    store: Option<Arc<dyn TicketStore>>,
+    clock: Arc<dyn Clock>,
+    analytics: Option<Arc<AnalyticsService>>,
+}
+
+impl MatchmakingService {
+    pub fn new() -> Self {
+        Self {
+            queue: Arc::new(Mutex::new(HashMap::new())),
+            queued_since: Arc::new(Mutex::new(HashMap::new())),
+            store: None,
+            clock: Arc::new(SystemClock),
+            analytics: None,
+        }
+    }
+    
+    /// Create a matchmaking service whose queue survives restarts
+    pub fn with_store(store: Arc<dyn TicketStore>) -> Self {
+        Self {
+            store: Some(store),
+            ..Self::new()
+        }
+    }
+    
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+    
+    /// Report how long matched players waited
+    pub fn with_analytics(mut self, analytics: Arc<AnalyticsService>) -> Self {
+        self.analytics = Some(analytics);
+        self
+    }
+    
+    pub fn join_queue(&self, game_id: &str, player_id: &str) {
+        let mut queue = self.queue.lock().unwrap();
+        
+        queue.entry(game_id.to_string())
+            .or_insert_with(Vec::new)
+            .push(player_id.to_string());
+        
+        self.persist_ticket(game_id, player_id);
+        
+        println!("[Matchmaking] Player {} joined queue for game {}", player_id, game_id);
+    }
+    
+    /// Queue for a game within a region, e.g. the one from `MultiplayerServer::player_region`
+    pub fn join_regional_queue(&self, game_id: &str, region: Option<&str>, player_id: &str) {
+        self.join_queue(&Self::regional_queue(game_id, region), player_id);
+    }
+    
+    pub fn find_regional_match(&self, game_id: &str, region: Option<&str>, required_players: usize) -> Option<Vec<String>> {
+        self.find_match(&Self::regional_queue(game_id, region), required_players)
+    }
+    
+    /// Like `find_regional_match`, but once the region's longest-waiting ticket has been
+    /// queued for a `REGION_EXPANSION_WINDOW` the match is topped up from other queues
+    /// of the same game. Players from the home region are always taken first.
+    pub fn find_expanding_match(&self, game_id: &str, region: Option<&str>, required_players: usize) -> Option<Vec<String>> {
+        let home = Self::regional_queue(game_id, region);
+        if let Some(matched) = self.find_match(&home, required_players) {
+            return Some(matched);
+        }
+        
+        let mut queue = self.queue.lock().unwrap();
+        let oldest = queue.get(&home)?.first()?;
+        let waited = self.queued_since.lock().unwrap()
+            .get(&MatchmakingTicket::ticket_id_for(&home, oldest))
+            .map(|since| self.clock.now().saturating_duration_since(*since))
+            .unwrap_or_default();
+        
+        let mut sources = vec![home.clone()];
+        if waited >= REGION_EXPANSION_WINDOW && home != game_id {
+            sources.push(game_id.to_string());
+        }
+        if waited >= REGION_EXPANSION_WINDOW * 2 {
+            let prefix = format!("{}@", game_id);
+            let mut regions: Vec<String> = queue.keys()
+                .filter(|key| key.starts_with(&prefix) && **key != home)
+                .cloned()
+                .collect();
+            regions.sort();
+            sources.extend(regions);
+        }
+        
+        let available: usize = sources.iter().map(|key| queue.get(key).map_or(0, Vec::len)).sum();
+        if sources.len() == 1 || available < required_players {
+            return None;
+        }
+        
+        let mut matched = Vec::with_capacity(required_players);
+        for key in &sources {
+            // The global queue is searched even when nobody is waiting in it
+            let Some(players) = queue.get_mut(key) else { continue };
+            let take = players.len().min(required_players - matched.len());
+            for player_id in players.drain(0..take) {
+                self.ticket_matched(key, &player_id);
+                matched.push(player_id);
+            }
+        }
+        
+        println!("[Matchmaking] Expanded match found for {} after {}s: {} players", home, waited.as_secs(), matched.len());
+        Some(matched)
+    }
+    
+    /// Queue key for a game in a region; players without a region share the game's global queue
+    pub fn regional_queue(game_id: &str, region: Option<&str>) -> String {
+        match region {
+            Some(region) => format!("{}@{}", game_id, region),
+            None => game_id.to_string(),
+        }
+    }
+    
+    /// Put players back at the front of a queue in their original order, e.g. when
+    /// the match they were taken for could not start
+    pub fn requeue(&self, game_id: &str, player_ids: &[String]) {
+        let mut queue = self.queue.lock().unwrap();
+        let players = queue.entry(game_id.to_string()).or_default();
+        
+        for player_id in player_ids.iter().rev() {
+            if !players.contains(player_id) {
+                players.insert(0, player_id.clone());
+                self.persist_ticket(game_id, player_id);
+            }
+        }
+        
+        println!("[Matchmaking] Re-queued {} players for game {}", player_ids.len(), game_id);
+    }
+    
+    /// Drop every ticket for a game, in all of its regional queues
+    pub fn clear_game(&self, game_id: &str) {
+        let prefix = format!("{}@", game_id);
+        let mut queue = self.queue.lock().unwrap();
+        let keys: Vec<String> = queue.keys()
+            .filter(|key| *key == game_id || key.starts_with(&prefix))
+            .cloned()
+            .collect();
+        
+        for key in keys {
+            for player_id in queue.remove(&key).unwrap_or_default() {
+                self.forget_ticket(&key, &player_id);
+            }
+        }
+    }
+    
+    pub fn leave_queue(&self, game_id: &str, player_id: &str) {
+        let mut queue = self.queue.lock().unwrap();
+        
+        if let Some(players) = queue.get_mut(game_id) {
+            players.retain(|p| p != player_id);
+        }
+        
+        self.forget_ticket(game_id, player_id);
+    }
+    
+    pub fn find_match(&self, game_id: &str, required_players: usize) -> Option<Vec<String>> {
+        let mut queue = self.queue.lock().unwrap();
+        
+        if let Some(players) = queue.get_mut(game_id) {
+            if players.len() >= required_players {
+                let matched: Vec<String> = players.drain(0..required_players).collect();
+                
+                for player_id in &matched {
+                    self.ticket_matched(game_id, player_id);
+                }
+                
+                println!("[Matchmaking] Match found for game {}: {} players", game_id, matched.len());
+                return Some(matched);
+            }
+        }
+        
+        None
+    }
+    
+    /// Reload persisted tickets after a restart. Tickets older than `max_age_seconds`
+    /// are dropped; the returned (player_id, message) pairs ask those players to re-ready.
+    pub fn restore(&self, max_age_seconds: i64) -> Vec<(String, GameMessage)> {
+        let store = match &self.store {
+            Some(s) => s,
+            None => return Vec::new(),
+        };
+        
+        let mut tickets = match store.load_all() {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("[Matchmaking] Failed to load tickets: {}", e);
+                return Vec::new();
+            }
+        };
+        tickets.sort_by_key(|t| t.queued_at);
+        
+        let (now, now_instant) = (self.clock.timestamp(), self.clock.now());
+        let cutoff = now - max_age_seconds;
+        let mut queue = self.queue.lock().unwrap();
+        let mut queued_since = self.queued_since.lock().unwrap();
+        let mut notifications = Vec::new();
+        let mut restored = 0;
+        
+        for ticket in tickets {
+            if ticket.queued_at < cutoff {
+                if let Err(e) = store.remove(&ticket.ticket_id) {
+                    eprintln!("[Matchmaking] Failed to remove expired ticket {}: {}", ticket.ticket_id, e);
+                }
+                
+                notifications.push((ticket.player_id, GameMessage::MatchmakingTicketExpired {
+                    game_id: ticket.game_id,
+                    reason: "Matchmaking was restarted, please ready up again".to_string(),
+                }));
+                continue;
+            }
+            
+            // Carry the wait from before the restart into region expansion and wait metrics
+            let waited = Duration::from_secs(now.saturating_sub(ticket.queued_at).max(0) as u64);
+            queued_since.entry(ticket.ticket_id)
+                .or_insert_with(|| now_instant.checked_sub(waited).unwrap_or(now_instant));
+            
+            let players = queue.entry(ticket.game_id).or_default();
+            if !players.contains(&ticket.player_id) {
+                players.push(ticket.player_id);
+                restored += 1;
+            }
+        }
+        
+        println!("[Matchmaking] Restored {} tickets, expired {}", restored, notifications.len());
+        
+        notifications
+    }
+    
+    // Private helper methods
+    
+    fn persist_ticket(&self, game_id: &str, player_id: &str) {
+        self.queued_since.lock().unwrap()
+            .entry(MatchmakingTicket::ticket_id_for(game_id, player_id))
+            .or_insert_with(|| self.clock.now());
+        
+        if let Some(store) = &self.store {
+            let ticket = MatchmakingTicket {
+                ticket_id: MatchmakingTicket::ticket_id_for(game_id, player_id),
+                game_id: game_id.to_string(),
+                player_id: player_id.to_string(),
+                queued_at: self.clock.timestamp(),
+            };
+            
+            if let Err(e) = store.save(&ticket) {
+                eprintln!("[Matchmaking] Failed to persist ticket {}: {}", ticket.ticket_id, e);
+            }
+        }
+    }
+    
+    fn ticket_matched(&self, queue_key: &str, player_id: &str) {
+        if let Some(analytics) = &self.analytics {
+            let since = self.queued_since.lock().unwrap().get(&MatchmakingTicket::ticket_id_for(queue_key, player_id)).copied();
+            if let Some(since) = since {
+                // Regional queues ("game@region") report under their game
+                let game_id = queue_key.split('@').next().unwrap_or(queue_key);
+                analytics.record_matchmaking_wait(game_id, self.clock.now().saturating_duration_since(since), self.clock.timestamp());
+            }
+        }
+        
+        self.forget_ticket(queue_key, player_id);
+    }
+    
+    fn forget_ticket(&self, game_id: &str, player_id: &str) {
+        let ticket_id = MatchmakingTicket::ticket_id_for(game_id, player_id);
+        self.queued_since.lock().unwrap().remove(&ticket_id);
+        
+        if let Some(store) = &self.store {
+            if let Err(e) = store.remove(&ticket_id) {
+                eprintln!("[Matchmaking] Failed to remove ticket {}: {}", ticket_id, e);
+            }
+        }
+    }
+}
+
+/// Ticket store kept in process memory; useful for tests and single-node setups
+pub struct InMemoryTicketStore {
+    tickets: Mutex<HashMap<String, MatchmakingTicket>>,
+}
+
+impl InMemoryTicketStore {
+    pub fn new() -> Self {
+        Self {
+            tickets: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Default for InMemoryTicketStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TicketStore for InMemoryTicketStore {
+    fn save(&self, ticket: &MatchmakingTicket) -> Result<(), String> {
+        self.tickets.lock().unwrap().insert(ticket.ticket_id.clone(), ticket.clone());
+        Ok(())
+    }
+    
+    fn remove(&self, ticket_id: &str) -> Result<(), String> {
+        self.tickets.lock().unwrap().remove(ticket_id);
+        Ok(())
+    }
+    
+    fn load_all(&self) -> Result<Vec<MatchmakingTicket>, String> {
+        Ok(self.tickets.lock().unwrap().values().cloned().collect())
+    }
+}
+
+/// Ticket store backed by a Redis hash (`ticket_id -> JSON ticket`)
+#[cfg(feature = "database")]
+pub struct RedisTicketStore {
+    client: redis::Client,
+    key: String,
+}
+
+#[cfg(feature = "database")]
+impl RedisTicketStore {
+    pub fn new(redis_url: &str) -> Result<Self, String> {
+        let client = redis::Client::open(redis_url).map_err(|e| e.to_string())?;
+        
+        Ok(Self {
+            client,
+            key: "matchmaking:tickets".to_string(),
+        })
+    }
+}
+
+#[cfg(feature = "database")]
+impl TicketStore for RedisTicketStore {
+    fn save(&self, ticket: &MatchmakingTicket) -> Result<(), String> {
+        let json = serde_json::to_string(ticket).map_err(|e| e.to_string())?;
+        let mut conn = self.client.get_connection().map_err(|e| e.to_string())?;
+        
+        redis::cmd("HSET")
+            .arg(&self.key)
+            .arg(&ticket.ticket_id)
+            .arg(json)
+            .query::<()>(&mut conn)
+            .map_err(|e| e.to_string())
+    }
+    
+    fn remove(&self, ticket_id: &str) -> Result<(), String> {
+        let mut conn = self.client.get_connection().map_err(|e| e.to_string())?;
+        
+        redis::cmd("HDEL")
+            .arg(&self.key)
+            .arg(ticket_id)
+            .query::<()>(&mut conn)
+            .map_err(|e| e.to_string())
+    }
+    
+    fn load_all(&self) -> Result<Vec<MatchmakingTicket>, String> {
+        let mut conn = self.client.get_connection().map_err(|e| e.to_string())?;
+        
+        let entries: HashMap<String, String> = redis::cmd("HGETALL")
+            .arg(&self.key)
+            .query(&mut conn)
+            .map_err(|e| e.to_string())?;
+        
+        Ok(entries.values()
+            .filter_map(|json| serde_json::from_str(json).ok())
+            .collect())
     }
 }
 
 // ============================================
-// Tests
-// ============================================
+// Tests
+// ============================================
 
 #[cfg(test)]
 mod tests {
     use super::*;
     
     #[test]
-    fn test_room_creation() {
+    fn test_room_creation() {
+        let server = MultiplayerServer::new();
+        let room_id = server.create_room("game123".to_string(), "player1".to_string(), 4);
+        
+        assert!(!room_id.is_empty());
+        
+        let room = server.get_room(&room_id);
+        assert!(room.is_some());
+    }
+    
+    #[test]
+    fn test_player_join() {
+        let server = MultiplayerServer::new();
+        let room_id = server.create_room("game123".to_string(), "host".to_string(), 4);
+        
+        let player = PlayerState {
+            player_id: "player1".to_string(),
+            username: "TestPlayer".to_string(),
+            display_name: "TestPlayer".to_string(),
+            transform: PlayerTransform {
+                position: Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+                rotation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
+                head_position: Vector3 { x: 0.0, y: 1.7, z: 0.0 },
+                head_rotation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
+                left_hand_position: Vector3 { x: -0.3, y: 1.2, z: 0.3 },
+                left_hand_rotation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
+                right_hand_position: Vector3 { x: 0.3, y: 1.2, z: 0.3 },
+                right_hand_rotation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
+            },
+            avatar_url: None,
+            is_talking: false,
+            custom_data: HashMap::new(),
+            is_npc: false,
+        };
+        
+        let result = server.join_room(&room_id, player);
+        assert!(result.is_ok());
+    }
+    
+    struct WalkForward;
+    
+    impl NpcController for WalkForward {
+        fn tick(&mut self, npc: &PlayerState, _room: &GameRoom, delta_seconds: f32) -> Option<PlayerTransform> {
+            let mut transform = npc.transform.clone();
+            transform.position.z += delta_seconds;
+            Some(transform)
+        }
+    }
+    
+    fn test_player(player_id: &str) -> PlayerState {
+        PlayerState {
+            player_id: player_id.to_string(),
+            username: player_id.to_string(),
+            display_name: player_id.to_string(),
+            transform: PlayerTransform::identity(),
+            avatar_url: None,
+            is_talking: false,
+            custom_data: HashMap::new(),
+            is_npc: false,
+        }
+    }
+    
+    #[test]
+    fn test_npc_ticks_and_room_closes_without_humans() {
+        let server = MultiplayerServer::new();
+        let room_id = server.create_room("game123".to_string(), "host".to_string(), 4);
+        server.join_room(&room_id, test_player("host")).unwrap();
+        
+        let npc_id = server
+            .add_npc(&room_id, "Guide".to_string(), PlayerTransform::identity(), Box::new(WalkForward))
+            .unwrap();
+        
+        let updates = server.tick_npcs(0.5);
+        assert_eq!(updates.len(), 1);
+        
+        let room = server.get_room(&room_id).unwrap();
+        let npc = room.get_player(&npc_id).unwrap();
+        assert!(npc.is_npc);
+        assert_eq!(npc.transform.position.z, 0.5);
+        
+        server.leave_room("host");
+        assert!(server.get_room(&room_id).is_none());
+        assert!(server.tick_npcs(0.5).is_empty());
+    }
+    
+    #[test]
+    fn test_matchmaking_tickets_survive_restart() {
+        let store = Arc::new(InMemoryTicketStore::new());
+        
+        let before = MatchmakingService::with_store(store.clone());
+        before.join_queue("game123", "player1");
+        before.join_queue("game123", "player2");
+        before.leave_queue("game123", "player2");
+        
+        // Simulate a stale ticket from long before the restart
+        store.save(&MatchmakingTicket {
+            ticket_id: "game123:player3".to_string(),
+            game_id: "game123".to_string(),
+            player_id: "player3".to_string(),
+            queued_at: chrono::Utc::now().timestamp() - 3600,
+        }).unwrap();
+        
+        let after = MatchmakingService::with_store(store.clone());
+        let notifications = after.restore(300);
+        
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].0, "player3");
+        assert_eq!(after.find_match("game123", 1), Some(vec!["player1".to_string()]));
+        assert!(store.load_all().unwrap().is_empty());
+        
+        // A restored ticket keeps its wait, so region expansion carries on where it was
+        let waited = 2 * REGION_EXPANSION_WINDOW.as_secs() as i64;
+        store.save(&MatchmakingTicket {
+            ticket_id: MatchmakingTicket::ticket_id_for("arena@eu", "eu1"),
+            game_id: "arena@eu".to_string(),
+            player_id: "eu1".to_string(),
+            queued_at: chrono::Utc::now().timestamp() - waited,
+        }).unwrap();
+        let after = MatchmakingService::with_store(store.clone());
+        after.restore(300);
+        after.join_regional_queue("arena", Some("us"), "us1");
+        assert_eq!(after.find_expanding_match("arena", Some("eu"), 2), Some(vec!["eu1".to_string(), "us1".to_string()]));
+    }
+    
+    #[test]
+    fn test_connect_uses_verified_account() {
+        use crate::auth_server::SignupRequest;
+        
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let resp = auth.signup(SignupRequest {
+            username: "vruser".to_string(),
+            email: "vr@example.com".to_string(),
+            password: "password123".to_string(),
+            device_ids: Vec::new(),
+            challenge_response: None,
+        });
+        let platform_token = resp.token.unwrap();
+        let user_id = resp.user.unwrap().id;
+        let token = auth.mint_game_token(&user_id, "game123").unwrap();
+        
+        let server = MultiplayerServer::with_auth(auth.clone());
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        
+        assert!(server.connect("not-a-token", "game123", addr, None).is_err());
+        assert!(server.connect(&platform_token, "game123", addr, None).is_err());
+        assert!(server.connect(&token, "other_game", addr, None).is_err());
+        
+        let player = server.connect(&token, "game123", addr, None).unwrap();
+        assert_eq!(player.player_id, user_id);
+        assert_eq!(player.username, "vruser");
+        
+        let room_id = server.create_room("game123".to_string(), user_id.clone(), 4);
+        assert!(server.join_room_connected(&room_id, &user_id).is_ok());
+        assert!(server.join_room_connected(&room_id, "stranger").is_err());
+        
+        auth.set_banned(&user_id, true);
+        assert!(server.connect(&token, "game123", addr, None).is_err());
+    }
+    
+    #[test]
+    fn test_join_ticket_is_single_use() {
+        use crate::auth_server::SignupRequest;
+        
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let resp = auth.signup(SignupRequest {
+            username: "ticketed".to_string(),
+            email: "ticket@example.com".to_string(),
+            password: "password123".to_string(),
+            device_ids: Vec::new(),
+            challenge_response: None,
+        });
+        let token = resp.token.unwrap();
+        let user_id = resp.user.unwrap().id;
+        
+        let server = MultiplayerServer::with_auth(auth);
+        let addr: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+        let room_id = server.create_room("game123".to_string(), "host".to_string(), 4);
+        
+        assert!(server.mint_join_ticket(&token, "room_missing").is_err());
+        
+        let ticket = server.mint_join_ticket(&token, &room_id).unwrap();
+        let player = server.join_with_ticket(&ticket, addr).unwrap();
+        assert_eq!(player.player_id, user_id);
+        assert!(server.get_room(&room_id).unwrap().get_player(&user_id).is_some());
+        
+        assert!(server.join_with_ticket(&ticket, addr).is_err());
+    }
+    
+    #[test]
+    fn test_game_state_versions_and_watchers() {
+        let mut room = GameRoom::new("room1".to_string(), "game123".to_string(), "host".to_string(), 4);
+        
+        assert_eq!(room.set_state("score.red", "1".to_string(), Some(0)), Ok(1));
+        assert!(room.set_state("score.red", "2".to_string(), Some(0)).is_err());
+        assert_eq!(room.set_state("score.red", "2".to_string(), Some(1)), Ok(2));
+        assert_eq!(room.set_state("score.red", "3".to_string(), None), Ok(3));
+        
+        room.subscribe_state("player1", vec!["score.*".to_string()]);
+        room.subscribe_state("player2", vec!["door".to_string()]);
+        
+        assert_eq!(room.state_watchers("score.blue"), vec!["player1".to_string()]);
+        assert_eq!(room.state_watchers("door"), vec!["player2".to_string()]);
+        assert_eq!(room.state_snapshot(&["score.*".to_string()]).len(), 1);
+        
+        room.unsubscribe_state("player1", &["score.*".to_string()]);
+        assert!(room.state_watchers("score.blue").is_empty());
+    }
+    
+    #[test]
+    fn test_atomic_state_operations() {
+        let server = MultiplayerServer::new();
+        let room_id = server.create_room("game123".to_string(), "host".to_string(), 4);
+        server.join_room(&room_id, test_player("player1")).unwrap();
+        
+        let increment = StateOperation::Increment { amount: 5 };
+        assert_eq!(server.apply_game_state_operation("player1", "score", increment.clone()), Ok(("5".to_string(), 1)));
+        assert_eq!(server.apply_game_state_operation("player1", "score", increment), Ok(("10".to_string(), 2)));
+        
+        let stale = StateOperation::CompareAndSet { expected: Some("5".to_string()), value: "0".to_string() };
+        assert!(server.apply_game_state_operation("player1", "score", stale).is_err());
+        
+        let append = StateOperation::Append { value: "sword".to_string() };
+        let (list, _) = server.apply_game_state_operation("player1", "loot", append).unwrap();
+        assert_eq!(list, "[\"sword\"]");
+        
+        let not_a_number = StateOperation::Increment { amount: 1 };
+        assert!(server.apply_game_state_operation("player1", "loot", not_a_number).is_err());
+    }
+    
+    #[test]
+    fn test_turn_order_advances_and_times_out() {
+        let server = MultiplayerServer::new();
+        let room_id = server.create_room("chess".to_string(), "alice".to_string(), 4);
+        for player_id in ["alice", "bob", "carol"] {
+            server.join_room(&room_id, test_player(player_id)).unwrap();
+        }
+        
+        let order = vec!["alice".to_string(), "bob".to_string(), "carol".to_string()];
+        assert!(server.start_turns(&room_id, "bob", order.clone(), Some(30)).is_err());
+        server.start_turns(&room_id, "alice", order, Some(30)).unwrap();
+        
+        assert!(server.end_turn("bob").is_err());
+        server.end_turn("alice").unwrap();
+        
+        let current = |server: &MultiplayerServer| {
+            server.get_room(&room_id).unwrap().turns.unwrap().current_player().cloned()
+        };
+        assert_eq!(current(&server), Some("bob".to_string()));
+        
+        let later = chrono::Utc::now().timestamp() + 31;
+        assert_eq!(server.tick_turns(later), vec![room_id.clone()]);
+        assert_eq!(current(&server), Some("carol".to_string()));
+        
+        // Current player leaving passes the turn on
+        server.leave_room("carol");
+        assert_eq!(current(&server), Some("alice".to_string()));
+    }
+    
+    #[test]
+    fn test_match_result_requires_majority_agreement() {
+        let server = MultiplayerServer::new();
+        let room_id = server.create_room("arena".to_string(), "alice".to_string(), 4);
+        for player_id in ["alice", "bob", "carol"] {
+            server.join_room(&room_id, test_player(player_id)).unwrap();
+        }
+        
+        let result = MatchResult {
+            scores: HashMap::from([("alice".to_string(), 5), ("bob".to_string(), 2)]),
+            winners: vec!["alice".to_string()],
+        };
+        let mut forged = result.clone();
+        forged.winners = vec!["bob".to_string()];
+        
+        assert_eq!(server.submit_match_result("bob", forged), Ok(MatchSubmissionStatus::Pending));
+        assert_eq!(server.submit_match_result("alice", result.clone()), Ok(MatchSubmissionStatus::Pending));
+        assert_eq!(server.submit_match_result("carol", result.clone()), Ok(MatchSubmissionStatus::Finalized));
+        assert!(server.get_room(&room_id).unwrap().match_submissions.is_empty());
+        // A submission racing in after the decision starts a new round instead of finalizing again
+        assert_eq!(server.submit_match_result("bob", result.clone()), Ok(MatchSubmissionStatus::Pending));
+        
+        // A result that can't be recorded leaves the submissions in place for a retry
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let server = MultiplayerServer::with_auth(auth.clone());
+        let alice = auth.signup_test_user("alice");
+        let room_id = server.create_room("arena".to_string(), alice.clone(), 4);
+        server.join_room(&room_id, test_player(&alice)).unwrap();
+        auth.request_deletion(&alice).unwrap();
+        auth.purge_pending_deletions(i64::MAX);
+        
+        let result = MatchResult { scores: HashMap::from([(alice.clone(), 5)]), winners: vec![alice.clone()] };
+        assert!(server.submit_match_result(&alice, result).is_err());
+        assert_eq!(server.get_room(&room_id).unwrap().match_submissions.len(), 1);
+    }
+    
+    #[test]
+    fn test_finished_matches_earn_season_xp() {
+        use crate::auth_server::MATCH_WIN_XP;
+        use crate::clock::ManualClock;
+        use crate::seasons::Season;
+        
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let clock = Arc::new(ManualClock::new(1_000));
+        let seasons = Arc::new(SeasonService::new(auth.clone()));
+        seasons.schedule_season(Season {
+            id: "s1".to_string(),
+            game_id: "arena".to_string(),
+            name: "Season 1".to_string(),
+            starts_at: 0,
+            ends_at: 10_000,
+            tiers: Vec::new(),
+        }).unwrap();
+        let server = MultiplayerServer::with_auth(auth.clone()).with_clock(clock.clone()).with_seasons(seasons.clone());
+        
+        let alice = auth.signup_test_user("alice");
+        let room_id = server.create_room("arena".to_string(), alice.clone(), 4);
+        server.join_room(&room_id, test_player(&alice)).unwrap();
+        
+        let result = MatchResult { scores: HashMap::from([(alice.clone(), 5)]), winners: vec![alice.clone()] };
+        assert_eq!(server.submit_match_result(&alice, result), Ok(MatchSubmissionStatus::Finalized));
+        assert_eq!(auth.get_user(&alice).unwrap().xp, MATCH_WIN_XP);
+        assert_eq!(seasons.standing(&alice, "arena", clock.timestamp()).unwrap().1.xp, MATCH_WIN_XP);
+    }
+    
+    #[test]
+    fn test_finished_matches_progress_challenges() {
+        use crate::challenges::{ChallengeCadence, ChallengeCriterion, ChallengeDefinition, ChallengeReward, StatAggregate};
+        
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let challenges = Arc::new(ChallengeService::new(auth.clone()));
+        challenges.define_challenge(ChallengeDefinition {
+            id: "first_win".to_string(),
+            game_id: "arena".to_string(),
+            cadence: ChallengeCadence::Daily,
+            title: "Win a match".to_string(),
+            description: String::new(),
+            criteria: vec![ChallengeCriterion { stat: "matches_won".to_string(), aggregate: StatAggregate::Count, target: 1 }],
+            reward: ChallengeReward { xp: 0, items: vec!["crate".to_string()] },
+        }).unwrap();
+        let server = MultiplayerServer::with_auth(auth.clone()).with_challenges(challenges.clone());
+        
+        let alice = auth.signup_test_user("alice");
+        let room_id = server.create_room("arena".to_string(), alice.clone(), 4);
+        server.join_room(&room_id, test_player(&alice)).unwrap();
+        
+        let result = MatchResult { scores: HashMap::from([(alice.clone(), 5)]), winners: vec![alice.clone()] };
+        server.finalize_match_result(&room_id, result).unwrap();
+        assert!(challenges.challenge_board(&alice, "arena", server.clock.timestamp())[0].completed);
+        assert_eq!(auth.inventory(&alice).get("crate"), Some(&1));
+    }
+    
+    #[test]
+    fn test_world_instances_fill_and_persist() {
+        let server = MultiplayerServer::new();
+        let primary = server.register_world(WorldTemplate {
+            world_id: "plaza".to_string(),
+            game_id: "hub".to_string(),
+            name: "Plaza".to_string(),
+            max_players_per_instance: 2,
+        });
+        
+        assert_eq!(server.quick_join_world("plaza", test_player("p1")), Ok(primary.clone()));
+        assert_eq!(server.quick_join_world("plaza", test_player("p2")), Ok(primary.clone()));
+        
+        let overflow = server.quick_join_world("plaza", test_player("p3")).unwrap();
+        assert_ne!(overflow, primary);
+        assert_eq!(server.list_world_instances("plaza", "p1").len(), 2);
+        
+        // Least-full instance wins quick-joins
+        server.leave_room("p1");
+        server.leave_room("p2");
+        assert_eq!(server.quick_join_world("plaza", test_player("p4")), Ok(primary.clone()));
+        
+        // Spare instances close when empty; the primary never does
+        server.leave_room("p3");
+        server.leave_room("p4");
+        let instances = server.list_world_instances("plaza", "p1");
+        assert_eq!(instances.len(), 1);
+        assert!(instances[0].is_primary);
+    }
+    
+    #[test]
+    fn test_region_detection_and_override() {
+        use crate::auth_server::SignupRequest;
+        use crate::geoip::{GeoLocation, PrefixGeoIpLookup};
+        
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let mut tokens = Vec::new();
+        for name in ["detected", "override"] {
+            let resp = auth.signup(SignupRequest {
+                username: name.to_string(),
+                email: format!("{}@example.com", name),
+                password: "password123".to_string(),
+                device_ids: Vec::new(),
+                challenge_response: None,
+            });
+            let user_id = resp.user.unwrap().id;
+            tokens.push((user_id.clone(), auth.mint_game_token(&user_id, "game123").unwrap()));
+        }
+        
+        let mut geoip = PrefixGeoIpLookup::new();
+        geoip.add_prefix("203.0.113.0".parse().unwrap(), 24, GeoLocation { region: "eu-west".to_string() });
+        let server = MultiplayerServer::with_auth(auth).with_geoip(Arc::new(geoip));
+        let addr: SocketAddr = "203.0.113.7:9000".parse().unwrap();
+        
+        server.connect(&tokens[0].1, "game123", addr, None).unwrap();
+        server.connect(&tokens[1].1, "game123", addr, Some("us-east".to_string())).unwrap();
+        
+        assert_eq!(server.player_region(&tokens[0].0), Some("eu-west".to_string()));
+        assert_eq!(server.player_region(&tokens[1].0), Some("us-east".to_string()));
+        
+        let room_id = server.create_room("game123".to_string(), tokens[0].0.clone(), 4);
+        assert_eq!(server.get_room(&room_id).unwrap().region, Some("eu-west".to_string()));
+        assert_eq!(MatchmakingService::regional_queue("game123", Some("eu-west")), "game123@eu-west");
+    }
+    
+    #[test]
+    fn test_privacy_settings_are_enforced() {
+        use crate::auth_server::{PrivacyAudience, PrivacySettings};
+        
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let server = MultiplayerServer::with_auth(auth.clone());
+        let voice = VoiceChatServer::with_auth(auth.clone());
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        
+        let connect = |name: &str| {
+            let user_id = auth.signup_test_user(name);
+            let token = auth.mint_game_token(&user_id, "game123").unwrap();
+            server.connect(&token, "game123", addr, None).unwrap();
+            user_id
+        };
+        
+        let private = connect("private");
+        let friend = connect("friend");
+        let stranger = connect("stranger");
+        auth.add_friend(&private, &friend);
+        auth.add_friend(&friend, &private);
+        
+        auth.update_privacy_settings(&private, PrivacySettings {
+            invites: PrivacyAudience::Friends,
+            direct_messages: PrivacyAudience::Nobody,
+            presence: PrivacyAudience::Friends,
+            voice_recording_opt_out: true,
+        });
+        
+        let room_id = server.create_room("game123".to_string(), private.clone(), 4);
+        server.join_room_connected(&room_id, &private).unwrap();
+        
+        assert!(server.get_presence(&friend, &private).online);
+        assert_eq!(server.get_presence(&friend, &private).room_id, Some(room_id.clone()));
+        assert!(!server.get_presence(&stranger, &private).online);
+        
+        server.join_room_connected(&room_id, &stranger).unwrap();
+        server.leave_room(&private);
+        assert!(server.invite_to_room(&stranger, &private).is_err());
+        assert!(server.send_direct_message(&friend, &private, "hi".to_string()).is_err());
+        assert!(server.send_direct_message(&private, &stranger, "hi".to_string()).is_ok());
+        
+        voice.join_voice_channel(&room_id, &private);
+        voice.join_voice_channel(&room_id, &stranger);
+        voice.broadcast_audio(&room_id, &private, vec![1, 2, 3]);
+        voice.broadcast_audio(&room_id, &stranger, vec![4, 5, 6]);
+        
+        let clips = voice.moderation_clips(&room_id, None);
+        assert_eq!(clips.len(), 1);
+        assert_eq!(clips[0].speaker_id, stranger);
+    }
+    
+    #[test]
+    fn test_content_rating_enforced_on_join() {
+        use crate::auth_server::AgeBracket;
+        
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let server = MultiplayerServer::with_auth(auth.clone());
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        
+        let connect = |name: &str, bracket: AgeBracket| {
+            let user_id = auth.signup_test_user(name);
+            auth.set_age_bracket(&user_id, bracket);
+            let token = auth.mint_game_token(&user_id, "game123").unwrap();
+            server.connect(&token, "game123", addr, None).unwrap();
+            user_id
+        };
+        
+        let adult = connect("adult", AgeBracket::Adult);
+        let kid = connect("kid", AgeBracket::Under13);
+        
+        server.set_game_rating("game123", ContentRating::Teen);
+        let room_id = server.create_room("game123".to_string(), adult.clone(), 4);
+        assert_eq!(server.find_rooms("game123", &RoomQuery::default()).unwrap().rooms[0].content_rating, ContentRating::Teen);
+        assert!(server.set_room_content(&room_id, ContentRating::Everyone, Vec::new()).is_err());
+        
+        server.set_room_content(&room_id, ContentRating::Mature, vec!["horror".to_string()]).unwrap();
+        
+        assert_eq!(server.find_rooms_for_player("game123", &kid, &RoomQuery::default()).unwrap().total, 0);
+        assert_eq!(server.find_rooms_for_player("game123", &adult, &RoomQuery::default()).unwrap().total, 1);
+        assert!(server.join_room_connected(&room_id, &kid).is_err());
+        assert!(server.join_room_connected(&room_id, &adult).is_ok());
+    }
+    
+    #[test]
+    fn test_moderator_freeze_teleport_and_observe() {
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let server = MultiplayerServer::with_auth(auth.clone());
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        
+        let connect = |name: &str| {
+            let user_id = auth.signup_test_user(name);
+            let token = auth.mint_game_token(&user_id, "game123").unwrap();
+            server.connect(&token, "game123", addr, None).unwrap();
+            user_id
+        };
+        
+        let moderator = connect("moderator");
+        let griefer = connect("griefer");
+        let bystander = connect("bystander");
+        
+        let room_id = server.create_room("game123".to_string(), griefer.clone(), 2);
+        server.join_room_connected(&room_id, &griefer).unwrap();
+        server.join_room_connected(&room_id, &bystander).unwrap();
+        
+        server.handle_object_message(&griefer, GameMessage::ObjectSpawned {
+            object_id: 0,
+            temp_id: Some("crate".to_string()),
+            object_type: "box".to_string(),
+            position: Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+            rotation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
+        }).unwrap();
+        let crate_id = *server.get_room(&room_id).unwrap().objects.keys().next().unwrap();
+        let grab = || GameMessage::ObjectGrabbed { object_id: crate_id, player_id: griefer.clone() };
+        
+        assert!(server.freeze_player(&moderator, &griefer, true).is_err());
+        auth.grant_moderator(&moderator, "game123");
+        
+        server.observe_room(&moderator, &room_id).unwrap();
+        assert_eq!(server.get_room_players(&room_id).len(), 2);
+        assert!(server.handle_object_message(&moderator, grab()).is_err());
+        
+        server.freeze_player(&moderator, &griefer, true).unwrap();
+        assert!(server.handle_object_message(&griefer, grab()).is_err());
+        assert!(server.handle_object_message(&bystander, GameMessage::ObjectGrabbed {
+            object_id: crate_id,
+            player_id: bystander.clone(),
+        }).is_ok());
+        
+        // Teleporting in works even though the room is full
+        assert_eq!(server.teleport_to_player(&moderator, &griefer).unwrap(), room_id);
+        assert_eq!(server.get_room_players(&room_id).len(), 3);
+        assert!(server.get_room(&room_id).unwrap().observers.is_empty());
+        
+        let actions: Vec<ModerationAction> = server.moderation_log(Some(&room_id)).iter().map(|e| e.action).collect();
+        assert_eq!(actions, vec![ModerationAction::Observe, ModerationAction::Freeze, ModerationAction::TeleportTo]);
+    }
+    
+    #[test]
+    fn test_objects_sleep_and_hand_off_ownership() {
+        let mut room = GameRoom::new("room1".to_string(), "game1".to_string(), "p1".to_string(), 4);
+        let rotation = Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 };
+        
+        let mut spawn = GameMessage::ObjectSpawned {
+            object_id: 0,
+            temp_id: Some("ball-temp".to_string()),
+            object_type: "ball".to_string(),
+            position: Vector3 { x: 0.0, y: 0.0, z: 0.0 },
+            rotation: rotation.clone(),
+        };
+        let update = room.apply_object_message("p1", &mut spawn, 100).unwrap();
+        assert_eq!(update, ObjectUpdate::Spawned(vec![ObjectIdAssignment { temp_id: "ball-temp".to_string(), object_id: 1 }]));
+        
+        let moved_to = |x: f32| GameMessage::ObjectMoved {
+            object_id: 1,
+            position: Vector3 { x, y: 0.0, z: 0.0 },
+            rotation: rotation.clone(),
+        };
+        
+        // Only the simulating player may move an awake object
+        assert!(room.apply_object_message("p2", &mut moved_to(1.0), 100).is_err());
+        assert_eq!(room.apply_object_message("p1", &mut moved_to(1.0), 100), Ok(ObjectUpdate::Relay));
+        
+        assert!(room.sleep_resting_objects(101).is_empty());
+        assert_eq!(room.sleep_resting_objects(102).len(), 1);
+        assert!(room.objects[&1].owner_id.is_none());
+        
+        // Jitter at rest is suppressed; a real move wakes it under the new mover
+        assert_eq!(room.apply_object_message("p1", &mut moved_to(1.0), 103), Ok(ObjectUpdate::Suppressed));
+        assert_eq!(room.apply_object_message("p2", &mut moved_to(2.0), 103), Ok(ObjectUpdate::Woke(1)));
+        assert_eq!(room.objects[&1].owner_id.as_deref(), Some("p2"));
+    }
+    
+    #[test]
+    fn test_object_ids_are_recycled_after_delay() {
+        let mut allocator = ObjectIdAllocator::new();
+        
+        assert_eq!(allocator.allocate(0), 1);
+        assert_eq!(allocator.allocate(0), 2);
+        
+        allocator.release(1, 10);
+        assert_eq!(allocator.allocate(10 + OBJECT_ID_REUSE_DELAY_SECONDS - 1), 3);
+        assert_eq!(allocator.allocate(10 + OBJECT_ID_REUSE_DELAY_SECONDS), 1);
+        assert_eq!(allocator.allocate(10 + OBJECT_ID_REUSE_DELAY_SECONDS), 4);
+        
+        // Naming an object twice in one destroy batch releases its ID once
+        let mut room = GameRoom::new("room1".to_string(), "game1".to_string(), "p1".to_string(), 4);
+        let mut spawn = GameMessage::ObjectSpawned {
+            object_id: 0,
+            temp_id: None,
+            object_type: "crate".to_string(),
+            position: Vector3::ZERO,
+            rotation: Quaternion::IDENTITY,
+        };
+        room.apply_object_message("p1", &mut spawn, 0).unwrap();
+        let mut destroy = GameMessage::ObjectsDestroyedBatch { object_ids: vec![1, 1] };
+        room.apply_object_message("p1", &mut destroy, 0).unwrap();
+        assert_eq!(room.object_ids.allocate(OBJECT_ID_REUSE_DELAY_SECONDS), 1);
+        assert_eq!(room.object_ids.allocate(OBJECT_ID_REUSE_DELAY_SECONDS), 2);
+    }
+    
+    #[test]
+    fn test_prefab_spawns_as_one_batch() {
+        let server = MultiplayerServer::new();
+        let room_id = server.create_room("game1".to_string(), "p1".to_string(), 4);
+        server.join_room(&room_id, test_player("p1")).unwrap();
+        
+        let rotation = Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 };
+        server.register_prefab(Prefab {
+            name: "campfire".to_string(),
+            objects: (0..3).map(|i| PrefabObject {
+                object_type: "log".to_string(),
+                offset: Vector3 { x: i as f32, y: 0.0, z: 0.0 },
+                rotation: rotation.clone(),
+            }).collect(),
+        });
+        
+        assert!(server.spawn_prefab("p1", "missing", Vector3 { x: 0.0, y: 0.0, z: 0.0 }).is_err());
+        
+        let ids = server.spawn_prefab("p1", "campfire", Vector3 { x: 10.0, y: 0.0, z: 0.0 }).unwrap();
+        let room = server.get_room(&room_id).unwrap();
+        assert_eq!(room.objects.len(), 3);
+        assert_eq!(room.objects[&ids[2]].position.x, 12.0);
+        
+        // A batch naming one unknown object destroys nothing
+        let mut doomed = ids.clone();
+        doomed.push(999);
+        assert!(server.handle_object_message("p1", GameMessage::ObjectsDestroyedBatch { object_ids: doomed }).is_err());
+        assert_eq!(server.get_room(&room_id).unwrap().objects.len(), 3);
+        
+        server.handle_object_message("p1", GameMessage::ObjectsDestroyedBatch { object_ids: ids }).unwrap();
+        assert!(server.get_room(&room_id).unwrap().objects.is_empty());
+    }
+    
+    #[test]
+    fn test_recording_requires_consent() {
+        let server = Arc::new(MultiplayerServer::new());
+        let voice = VoiceChatServer::new().with_router(server.clone());
+        let room_id = server.create_room("game1".to_string(), "p1".to_string(), 4);
+        for id in ["p1", "p2"] {
+            server.join_room(&room_id, test_player(id)).unwrap();
+        }
+        
+        voice.join_voice_channel(&room_id, "p1");
+        assert!(voice.start_recording(&room_id, RecordingKind::Stream, "outsider").is_err());
+        voice.start_recording(&room_id, RecordingKind::Stream, "p1").unwrap();
+        voice.join_voice_channel(&room_id, "p2");
+        assert_eq!(voice.recording_state(&room_id), Some(RecordingKind::Stream));
+        assert!(voice.start_recording(&room_id, RecordingKind::Stream, "p2").is_err());
+        assert!(voice.set_recording_consent(&room_id, "outsider", true).is_err());
+        
+        voice.set_recording_consent(&room_id, "p2", true).unwrap();
+        voice.broadcast_audio(&room_id, "p1", vec![2]);
+        voice.broadcast_audio(&room_id, "p2", vec![3]);
+        
+        // Moderation evidence keeps everyone; the recording only those who agreed
+        assert_eq!(voice.moderation_clips(&room_id, None).len(), 2);
+        
+        // Everyone leaving doesn't lose what was recorded
+        voice.leave_voice_channel(&room_id, "p1");
+        voice.leave_voice_channel(&room_id, "p2");
+        let recorded = voice.stop_recording(&room_id);
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].speaker_id, "p2");
+        
+        voice.join_voice_channel(&room_id, "p2");
+        assert!(voice.set_recording_consent(&room_id, "p2", true).is_err());
+        assert!(voice.stop_recording(&room_id).is_empty());
+    }
+    
+    #[test]
+    fn test_voice_input_mode_gates_audio() {
+        let voice = VoiceChatServer::new();
+        voice.join_voice_channel("room1", "p1");
+        
+        voice.set_input_mode("room1", "p1", VoiceInputMode::PushToTalk);
+        assert_eq!(voice.input_mode("p1"), VoiceInputMode::PushToTalk);
+        voice.broadcast_audio("room1", "p1", vec![1]);
+        assert!(voice.moderation_clips("room1", None).is_empty());
+        
+        voice.set_push_to_talk("room1", "p1", true);
+        voice.broadcast_audio("room1", "p1", vec![2]);
+        assert_eq!(voice.moderation_clips("room1", None).len(), 1);
+        
+        voice.set_input_mode("room1", "p1", VoiceInputMode::Muted);
+        voice.set_push_to_talk("room1", "p1", true);
+        voice.broadcast_audio("room1", "p1", vec![3]);
+        assert_eq!(voice.moderation_clips("room1", None).len(), 1);
+    }
+    
+    #[test]
+    fn test_visemes_from_pcm_and_throttled() {
+        let loud: Vec<u8> = [i16::MAX / 2, -(i16::MAX / 2)].iter().cycle().take(160).flat_map(|s| s.to_le_bytes()).collect();
+        let quiet = vec![0u8; 320];
+        
+        let frame = VisemeFrame::from_pcm(&loud);
+        assert_eq!(frame.viseme, VISEME_OPEN);
+        assert!(frame.amplitude > 100);
+        assert_eq!(VisemeFrame::from_pcm(&quiet), VisemeFrame { viseme: VISEME_SILENCE, amplitude: 0 });
+        
+        let voice = VoiceChatServer::new();
+        voice.join_voice_channel("room1", "p1");
+        voice.join_voice_channel("room1", "p2");
+        
+        assert!(voice.relay_visemes("room1", "p1", frame));
+        assert!(!voice.relay_visemes("room1", "p1", frame));
+        
+        voice.set_input_mode("room1", "p2", VoiceInputMode::Muted);
+        assert!(!voice.relay_visemes("room1", "p2", frame));
+    }
+    
+    #[test]
+    fn test_audio_zones_route_voice() {
+        let server = Arc::new(MultiplayerServer::new());
+        let voice = VoiceChatServer::new().with_router(server.clone());
+        let room_id = server.create_room("game1".to_string(), "host".to_string(), 8);
+        
+        let at = |x: f32| Vector3 { x, y: 0.0, z: 0.0 };
+        let place = |id: &str, x: f32| {
+            let mut player = test_player(id);
+            player.transform.position = at(x);
+            server.join_room(&room_id, player).unwrap();
+            voice.join_voice_channel(&room_id, id);
+        };
+        
+        server.set_audio_zones(&room_id, vec![
+            AudioZone { name: "stage".to_string(), kind: AudioZoneKind::Stage, min: at(-5.0), max: at(0.0) },
+            AudioZone { name: "booth".to_string(), kind: AudioZoneKind::Booth, min: at(100.0), max: at(105.0) },
+        ]).unwrap();
+        
+        place("performer", -1.0);
+        place("nearby", 10.0);
+        place("far", 50.0);
+        place("booth_a", 101.0);
+        place("booth_b", 102.0);
+        
+        let mut heard = voice.audio_recipients(&room_id, "performer");
+        heard.sort();
+        assert_eq!(heard, vec!["far", "nearby"]);
+        
+        assert_eq!(voice.audio_recipients(&room_id, "nearby"), vec!["performer"]);
+        assert!(voice.audio_recipients(&room_id, "far").is_empty());
+        assert_eq!(voice.audio_recipients(&room_id, "booth_a"), vec!["booth_b"]);
+    }
+    
+    #[test]
+    fn test_screen_share_permissions_and_bandwidth_cap() {
         let server = MultiplayerServer::new();
-        let room_id = server.create_room("game123".to_string(), "player1".to_string(), 4);
+        let room_id = server.create_room("game1".to_string(), "teacher".to_string(), 8);
+        for id in ["teacher", "student", "latecomer"] {
+            server.join_room(&room_id, test_player(id)).unwrap();
+        }
         
-        assert!(!room_id.is_empty());
+        assert!(server.start_screen_share("student").is_err());
+        server.grant_screen_share("teacher", "student").unwrap();
+        server.start_screen_share("student").unwrap();
+        assert!(server.start_screen_share("teacher").is_err());
         
-        let room = server.get_room(&room_id);
-        assert!(room.is_some());
+        let frame = |sequence: u64, is_keyframe: bool, size: usize| ScreenFrame {
+            sequence,
+            is_keyframe,
+            chunk_index: 0,
+            chunk_count: 1,
+            data: vec![0; size],
+        };
+        let start = Instant::now();
+        
+        // Nothing is relayed until the first keyframe
+        assert!(!server.relay_screen_frame("student", frame(1, false, 1000), start).unwrap());
+        assert!(server.relay_screen_frame("student", frame(2, true, 1000), start).unwrap());
+        assert!(!server.relay_screen_frame("student", frame(3, false, SCREEN_SHARE_MAX_BYTES_PER_SECOND), start).unwrap());
+        
+        // After a drop, deltas wait for a keyframe even once the budget refills
+        let later = start + Duration::from_secs(1);
+        assert!(!server.relay_screen_frame("student", frame(4, false, 1000), later).unwrap());
+        assert!(server.relay_screen_frame("student", frame(5, true, 1000), later).unwrap());
+        
+        assert!(server.request_keyframe("latecomer", later + KEYFRAME_REQUEST_INTERVAL).unwrap());
+        assert!(!server.request_keyframe("latecomer", later + KEYFRAME_REQUEST_INTERVAL).unwrap());
+        
+        server.leave_room("student");
+        assert!(server.get_room(&room_id).unwrap().screen_share.is_none());
     }
     
     #[test]
-    fn test_player_join() {
+    fn test_attenuation_profiles_and_occlusion() {
+        let linear = AttenuationProfile { min_distance: 2.0, max_distance: 12.0, curve: AttenuationCurve::Linear };
+        assert_eq!(linear.gain_at(1.0), 1.0);
+        assert!((linear.gain_at(7.0) - 0.5).abs() < 1e-6);
+        assert_eq!(linear.gain_at(12.0), 0.0);
+        assert!(AttenuationProfile::default().gain_at(10.0) > 0.0);
+        
         let server = MultiplayerServer::new();
-        let room_id = server.create_room("game123".to_string(), "host".to_string(), 4);
+        server.set_game_attenuation("game1", AttenuationProfile { max_distance: 30.0, ..Default::default() }).unwrap();
+        assert!(server.set_game_attenuation("game1", AttenuationProfile { min_distance: 0.0, ..linear.clone() }).is_err());
+        assert!(server.set_game_attenuation("game1", AttenuationProfile { max_distance: 1.0, ..linear.clone() }).is_err());
+        let room_id = server.create_room("game1".to_string(), "a".to_string(), 4);
+        assert_eq!(server.get_room(&room_id).unwrap().attenuation.max_distance, 30.0);
         
-        let player = PlayerState {
-            player_id: "player1".to_string(),
-            username: "TestPlayer".to_string(),
-            transform: PlayerTransform {
-                position: Vector3 { x: 0.0, y: 0.0, z: 0.0 },
-                rotation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
-                head_position: Vector3 { x: 0.0, y: 1.7, z: 0.0 },
-                head_rotation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
-                left_hand_position: Vector3 { x: -0.3, y: 1.2, z: 0.3 },
-                left_hand_rotation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
-                right_hand_position: Vector3 { x: 0.3, y: 1.2, z: 0.3 },
-                right_hand_rotation: Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 },
-            },
-            avatar_url: None,
-            is_talking: false,
-            custom_data: HashMap::new(),
+        for (id, x) in [("a", 0.0), ("b", 20.0)] {
+            let mut player = test_player(id);
+            player.transform.position = Vector3 { x, y: 0.0, z: 0.0 };
+            server.join_room(&room_id, player).unwrap();
+        }
+        assert_eq!(server.get_room(&room_id).unwrap().voice_recipients("a"), vec!["b"]);
+        
+        let wall = AudioOccluder {
+            min: Vector3 { x: 9.0, y: -5.0, z: -5.0 },
+            max: Vector3 { x: 10.0, y: 5.0, z: 5.0 },
+            attenuation: 1.0,
         };
+        assert!(server.set_room_audio(&room_id, linear.clone(), Vec::new()).is_ok());
+        assert!(server.set_room_audio(&room_id, AttenuationProfile { max_distance: 0.5, ..linear.clone() }, Vec::new()).is_err());
+        server.set_room_audio(&room_id, AttenuationProfile { max_distance: 30.0, ..Default::default() }, vec![wall]).unwrap();
+        assert!(server.get_room(&room_id).unwrap().voice_recipients("a").is_empty());
         
-        let result = server.join_room(&room_id, player);
-        assert!(result.is_ok());
+        // A proximity zone's range has to clear the room's min_distance, now and after later changes
+        let hallway = |range| AudioZone {
+            name: "hallway".to_string(),
+            kind: AudioZoneKind::Proximity { range },
+            min: Vector3 { x: 100.0, y: 0.0, z: 0.0 },
+            max: Vector3 { x: 110.0, y: 5.0, z: 5.0 },
+        };
+        assert!(server.set_audio_zones(&room_id, vec![hallway(0.5)]).is_err());
+        server.set_audio_zones(&room_id, vec![hallway(5.0)]).unwrap();
+        assert!(server.set_room_audio(&room_id, AttenuationProfile { min_distance: 6.0, ..linear }, Vec::new()).is_err());
+    }
+    
+    #[test]
+    fn test_room_browser_pages_with_cursor() {
+        let server = MultiplayerServer::new();
+        
+        for players in 0..5 {
+            let room_id = server.create_room("game1".to_string(), format!("host{}", players), 10);
+            for i in 0..players {
+                server.join_room(&room_id, test_player(&format!("p{}_{}", players, i))).unwrap();
+            }
+        }
+        
+        let mut query = RoomQuery { sort: RoomSort::MostPlayers, cursor: None, limit: 2 };
+        let mut counts = Vec::new();
+        
+        loop {
+            let page = server.find_rooms("game1", &query).unwrap();
+            assert_eq!(page.total, 5);
+            counts.extend(page.rooms.iter().map(|r| r.player_count));
+            
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        
+        assert_eq!(counts, vec![4, 3, 2, 1, 0]);
+        
+        let fewest = server.find_rooms("game1", &RoomQuery { sort: RoomSort::FewestPlayers, ..Default::default() }).unwrap();
+        assert_eq!(fewest.rooms[0].player_count, 0);
+        assert!(fewest.next_cursor.is_none());
+        
+        let bad_cursor = RoomQuery { cursor: Some("nonsense".to_string()), ..Default::default() };
+        assert!(server.find_rooms("game1", &bad_cursor).is_err());
+    }
+    
+    #[test]
+    fn test_failed_match_start_requeues_players() {
+        let server = MultiplayerServer::new();
+        let matchmaking = MatchmakingService::new();
+        
+        for id in ["a", "b", "c"] {
+            matchmaking.join_queue("game@eu", id);
+        }
+        for id in ["a", "b"] {
+            server.connected_players.lock().unwrap().insert(id.to_string(), test_player(id));
+        }
+        
+        // "c" never connected, so seating fails and everything is undone
+        assert!(server.start_match(&matchmaking, "game@eu", 3).is_err());
+        assert!(server.rooms.lock().unwrap().is_empty());
+        assert!(server.player_room("a").is_none());
+        assert_eq!(matchmaking.queue.lock().unwrap()["game@eu"], vec!["a", "b", "c"]);
+        
+        let room_id = server.start_match(&matchmaking, "game@eu", 2).unwrap().unwrap();
+        assert_eq!(server.get_room(&room_id).unwrap().game_id, "game");
+        assert_eq!(server.player_room("b"), Some(room_id));
+        assert_eq!(server.start_match(&matchmaking, "game@eu", 2), Ok(None));
+    }
+    
+    #[test]
+    fn test_idempotent_room_creation_and_score_submission() {
+        let server = MultiplayerServer::new();
+        
+        let room_id = server.create_room_idempotent("k1", "game".to_string(), "host".to_string(), 4).unwrap();
+        assert_eq!(server.create_room_idempotent("k1", "game".to_string(), "host".to_string(), 4), Ok(room_id.clone()));
+        assert_eq!(server.rooms.lock().unwrap().len(), 1);
+        
+        server.join_room(&room_id, test_player("host")).unwrap();
+        let result = MatchResult { scores: HashMap::from([("host".to_string(), 3)]), winners: vec!["host".to_string()] };
+        
+        assert_eq!(server.submit_match_result_idempotent("s1", "host", result.clone()), Ok(MatchSubmissionStatus::Finalized));
+        // The retry replays the outcome instead of opening a new submission round
+        assert_eq!(server.submit_match_result_idempotent("s1", "host", result), Ok(MatchSubmissionStatus::Finalized));
+        assert!(server.get_room(&room_id).unwrap().match_submissions.is_empty());
+    }
+    
+    #[test]
+    fn test_presence_and_room_list_caches_invalidate() {
+        let server = MultiplayerServer::new();
+        let room_id = server.create_room("game".to_string(), "host".to_string(), 4);
+        server.connected_players.lock().unwrap().insert("p1".to_string(), test_player("p1"));
+        
+        assert_eq!(server.get_presence("viewer", "p1").room_id, None);
+        assert_eq!(server.find_rooms("game", &RoomQuery::default()).unwrap().rooms[0].player_count, 0);
+        
+        server.join_room_connected(&room_id, "p1").unwrap();
+        assert_eq!(server.get_presence("viewer", "p1").room_id, Some(room_id.clone()));
+        assert_eq!(server.get_presence("viewer", "p1").room_id, Some(room_id));
+        assert_eq!(server.find_rooms("game", &RoomQuery::default()).unwrap().rooms[0].player_count, 1);
+        
+        let stats = server.cache_stats();
+        assert_eq!((stats[0].hits, stats[0].misses), (1, 2));
+        assert_eq!((stats[1].hits, stats[1].misses), (0, 2));
+    }
+    
+    #[test]
+    fn test_custom_event_channels_filter_and_rate_limit() {
+        let server = MultiplayerServer::new();
+        let room_id = server.create_room("game".to_string(), "host".to_string(), 8);
+        for id in ["host", "a", "b"] {
+            server.join_room(&room_id, test_player(id)).unwrap();
+        }
+        
+        server.subscribe_events("a", vec!["scoreboard".to_string()]).unwrap();
+        server.subscribe_events("host", vec!["scoreboard".to_string()]).unwrap();
+        assert!(server.subscribe_events("a", vec![String::new()]).is_err());
+        assert!(server.send_custom_event("a", GameMessage::CustomEvent {
+            event_name: "goal".to_string(),
+            data: "{}".to_string(),
+            channel: Some("x".repeat(MAX_EVENT_CHANNEL_NAME_LEN + 1)),
+        }, Instant::now()).is_err());
+        
+        // Subscriptions are capped per player, and a rejected request adds none of its channels
+        let many = |prefix: &str, n: usize| (0..n).map(|i| format!("{}{}", prefix, i)).collect::<Vec<_>>();
+        server.subscribe_events("b", many("b", MAX_EVENT_SUBSCRIPTIONS_PER_PLAYER)).unwrap();
+        assert!(server.subscribe_events("b", vec!["scoreboard".to_string()]).is_err());
+        server.unsubscribe_events("b", &many("b", MAX_EVENT_SUBSCRIPTIONS_PER_PLAYER));
+        assert_eq!(server.get_room(&room_id).unwrap().event_channels.len(), 1);
+        
+        let event = |channel: Option<&str>| GameMessage::CustomEvent {
+            event_name: "goal".to_string(),
+            data: "{}".to_string(),
+            channel: channel.map(str::to_string),
+        };
+        let now = Instant::now();
+        
+        assert_eq!(server.send_custom_event("host", event(Some("scoreboard")), now), Ok(1));
+        assert_eq!(server.send_custom_event("host", event(None), now), Ok(2));
+        
+        server.set_event_channel_rate(&room_id, "host", "scoreboard", 2).unwrap();
+        assert!(server.set_event_channel_rate(&room_id, "a", "scoreboard", 100).is_err());
+        assert!(server.send_custom_event("b", event(Some("scoreboard")), now).is_ok());
+        assert!(server.send_custom_event("b", event(Some("scoreboard")), now).is_ok());
+        assert!(server.send_custom_event("b", event(Some("scoreboard")), now).is_err());
+        assert_eq!(server.send_custom_event("b", event(Some("scoreboard")), now + Duration::from_secs(1)), Ok(2));
+        
+        server.leave_room("a");
+        server.unsubscribe_events("host", &["scoreboard".to_string()]);
+        assert_eq!(server.send_custom_event("b", event(Some("scoreboard")), now + Duration::from_secs(2)), Ok(0));
+    }
+    
+    #[test]
+    fn test_binary_custom_event_size_limit() {
+        let server = MultiplayerServer::new();
+        let room_id = server.create_room("game".to_string(), "host".to_string(), 4);
+        server.join_room(&room_id, test_player("host")).unwrap();
+        server.join_room(&room_id, test_player("a")).unwrap();
+        
+        let event = |len: usize| GameMessage::CustomBinaryEvent {
+            event_name: "input".to_string(),
+            content_type: Some("application/octet-stream".to_string()),
+            channel: None,
+            data: vec![0; len],
+        };
+        
+        assert_eq!(server.send_custom_event("host", event(MAX_BINARY_EVENT_BYTES), Instant::now()), Ok(1));
+        assert!(server.send_custom_event("host", event(MAX_BINARY_EVENT_BYTES + 1), Instant::now()).is_err());
+    }
+    
+    struct EchoScript {
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+    
+    impl RoomScript for EchoScript {
+        fn handle_rpc(&mut self, caller_id: &str, procedure: &str, args: &str, room: &GameRoom) -> Result<String, String> {
+            self.calls.lock().unwrap().push(format!("{}:{}", caller_id, procedure));
+            match procedure {
+                "echo" => Ok(format!("{} in {}", args, room.room_id)),
+                _ => Err("Unknown procedure".to_string()),
+            }
+        }
+    }
+    
+    #[test]
+    fn test_rpc_routing_and_timeouts() {
+        let server = MultiplayerServer::new();
+        let room_id = server.create_room("game".to_string(), "host".to_string(), 4);
+        for id in ["host", "a"] {
+            server.join_room(&room_id, test_player(id)).unwrap();
+        }
+        
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        server.set_room_script(&room_id, Box::new(EchoScript { calls: calls.clone() })).unwrap();
+        
+        let call = |id: &str, target: RpcTarget, procedure: &str, timeout_ms: Option<u64>| GameMessage::RpcCall {
+            call_id: id.to_string(),
+            target,
+            procedure: procedure.to_string(),
+            args: "hi".to_string(),
+            timeout_ms,
+        };
+        let now = Instant::now();
+        
+        server.call_rpc("a", call("1", RpcTarget::RoomScript, "echo", None), now).unwrap();
+        assert_eq!(*calls.lock().unwrap(), vec!["a:echo".to_string()]);
+        assert!(server.call_rpc("host", call("2", RpcTarget::Host, "ping", None), now).is_err());
+        
+        // Host calls wait for the host's answer, which only the host may give
+        server.call_rpc("a", call("3", RpcTarget::Host, "ping", Some(1000)), now).unwrap();
+        let routed_id = server.pending_rpcs.lock().unwrap().keys().next().unwrap().clone();
+        assert!(server.respond_rpc("a", &routed_id, Ok("pong".to_string())).is_err());
+        assert_eq!(server.respond_rpc("host", &routed_id, Ok("pong".to_string())), Ok("a".to_string()));
+        assert!(server.respond_rpc("host", &routed_id, Ok("pong".to_string())).is_err());
+        
+        server.call_rpc("a", call("4", RpcTarget::Host, "slow", Some(1000)), now).unwrap();
+        assert!(server.tick_rpc_timeouts(now).is_empty());
+        assert_eq!(server.tick_rpc_timeouts(now + Duration::from_secs(1)).len(), 1);
+        
+        server.call_rpc("a", call("5", RpcTarget::Host, "slow", None), now).unwrap();
+        server.leave_room("host");
+        assert_eq!(server.tick_rpc_timeouts(now).len(), 1);
+    }
+    
+    #[test]
+    fn test_network_chaos_requires_chaos_mode_and_admin() {
+        use crate::clock::ManualClock;
+        use crate::sim::InMemoryTransport;
+        
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let admin = auth.signup_test_user("admin");
+        let developer = auth.signup_test_user("developer");
+        auth.grant_moderator(&admin, "*");
+        
+        let lag = ChaosConfig { latency_ms: 200, jitter_ms: 50, loss_rate: 0.05, ..ChaosConfig::default() };
+        
+        let production = MultiplayerServer::with_auth(auth.clone());
+        assert!(production.set_network_chaos(&admin, &developer, Some(lag.clone())).is_err());
+        
+        let clock = Arc::new(ManualClock::new(1_700_000_000));
+        let transport = Arc::new(InMemoryTransport::default());
+        let server = MultiplayerServer::with_auth(auth.clone())
+            .with_clock(clock.clone())
+            .with_message_sink(transport.clone())
+            .with_chaos_mode();
+        assert_eq!(server.set_network_chaos(&admin, &developer, Some(lag.clone())), Err("Player not connected".to_string()));
+        
+        let token = auth.mint_game_token(&developer, "game123").unwrap();
+        server.connect(&token, "game123", addr, None).unwrap();
+        assert_eq!(server.set_network_chaos(&developer, &developer, Some(lag.clone())), Err("Admin access required".to_string()));
+        assert!(server.set_network_chaos(&admin, &developer, Some(ChaosConfig { loss_rate: 2.0, ..lag.clone() })).is_err());
+        
+        server.set_network_chaos(&admin, &developer, Some(lag.clone())).unwrap();
+        assert_eq!(server.network_chaos(&developer), Some(lag.clone()));
+        
+        // Messages are held for the injected latency, then released by the tick
+        let steady = ChaosConfig { latency_ms: 200, ..ChaosConfig::default() };
+        server.set_network_chaos(&admin, &developer, Some(steady)).unwrap();
+        transport.drain(&developer);
+        let ping = GameMessage::DirectMessage { from_player_id: admin.clone(), to_player_id: developer.clone(), text: "ping".to_string() };
+        server.send_to_player(&developer, &ping);
+        server.send_to_player(&developer, &ping);
+        assert_eq!(server.tick_network_chaos(clock.now()), 0);
+        assert_eq!(transport.pending(&developer), 0);
+        
+        clock.advance(Duration::from_millis(200));
+        assert_eq!(server.tick_network_chaos(clock.now()), 2);
+        assert_eq!(transport.drain(&developer).len(), 2);
+        
+        // Clearing chaos hands over anything still held
+        server.send_to_player(&developer, &ping);
+        server.set_network_chaos(&admin, &developer, None).unwrap();
+        assert_eq!(transport.drain(&developer).len(), 1);
+        
+        server.set_network_chaos(&admin, &developer, Some(lag)).unwrap();
+        server.disconnect(&developer);
+        assert_eq!(server.network_chaos(&developer), None);
+    }
+    
+    #[test]
+    fn test_wiping_a_sandbox_leaves_production_alone() {
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let server = MultiplayerServer::with_auth(auth.clone());
+        let matchmaking = MatchmakingService::new();
+        let developer = auth.signup_test_user("developer");
+        auth.add_created_game(&developer, "arena");
+        
+        let dev_game = Environment::Dev.scope_game("arena");
+        let dev_room = server.create_room(dev_game.clone(), developer.clone(), 4);
+        let prod_room = server.create_room("arena".to_string(), developer.clone(), 4);
+        matchmaking.join_regional_queue(&dev_game, Some("eu"), "tester");
+        matchmaking.join_queue("arena", "player");
+        
+        assert_eq!(server.find_rooms("arena", &RoomQuery::default()).unwrap().rooms.len(), 1);
+        assert!(server.wipe_environment(&matchmaking, "stranger", "arena", Environment::Dev).is_err());
+        assert_eq!(server.wipe_environment(&matchmaking, &developer, "arena", Environment::Dev), Ok(vec![dev_room.clone()]));
+        
+        assert!(server.get_room(&dev_room).is_none());
+        assert!(server.get_room(&prod_room).is_some());
+        assert!(matchmaking.find_regional_match(&dev_game, Some("eu"), 1).is_none());
+        assert_eq!(matchmaking.find_match("arena", 1), Some(vec!["player".to_string()]));
+    }
+    
+    #[test]
+    fn test_first_time_players_are_routed_to_a_tutorial() {
+        use crate::sim::InMemoryTransport;
+        
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        auth.set_tutorial_steps("arena", vec!["move".to_string(), "grab".to_string()]);
+        let transport = Arc::new(InMemoryTransport::default());
+        let server = MultiplayerServer::with_auth(auth.clone()).with_message_sink(transport.clone());
+        let user_id = auth.signup_test_user("newcomer");
+        let token = auth.mint_game_token(&user_id, "arena").unwrap();
+        
+        server.connect(&token, "arena", "127.0.0.1:9000".parse().unwrap(), None).unwrap();
+        match transport.drain(&user_id).as_slice() {
+            [GameMessage::OnboardingStatus { remaining_steps, .. }] => assert_eq!(remaining_steps.len(), 2),
+            other => panic!("expected onboarding status, got {:?}", other),
+        }
+        
+        let tutorial = server.route_to_tutorial(&user_id, "arena").unwrap().unwrap();
+        let room = server.get_room(&tutorial).unwrap();
+        assert!(room.is_tutorial && !room.is_public);
+        assert!(server.find_rooms("arena", &RoomQuery::default()).unwrap().rooms.is_empty());
+        
+        assert!(server.complete_tutorial_step(&user_id, "arena", "fly").is_err());
+        server.complete_tutorial_step(&user_id, "arena", "move").unwrap();
+        let state = server.complete_tutorial_step(&user_id, "arena", "grab").unwrap();
+        assert!(!state.needs_tutorial());
+        let updates = transport.drain(&user_id).into_iter()
+            .filter(|m| matches!(m, GameMessage::OnboardingStatus { .. }))
+            .count();
+        assert_eq!(updates, 2);
+        
+        server.leave_room(&user_id);
+        assert_eq!(server.route_to_tutorial(&user_id, "arena"), Ok(None));
+    }
+    
+    #[test]
+    fn test_soft_cap_scales_back_sync_and_reports_to_host() {
+        use crate::sim::InMemoryTransport;
+        
+        let transport = Arc::new(InMemoryTransport::default());
+        let server = MultiplayerServer::new().with_message_sink(transport.clone());
+        let room_id = server.create_room("concert".to_string(), "p0".to_string(), 6);
+        assert!(server.set_room_soft_cap(&room_id, "p1", Some(2)).is_err());
+        assert!(server.set_room_soft_cap(&room_id, "p0", Some(7)).is_err());
+        server.set_room_soft_cap(&room_id, "p0", Some(2)).unwrap();
+        
+        for i in 0..4 {
+            server.join_room(&room_id, test_player(&format!("p{}", i))).unwrap();
+        }
+        let reported: Vec<DegradationLevel> = transport.drain("p0").into_iter()
+            .filter_map(|m| match m {
+                GameMessage::RoomLoadChanged { level, .. } => Some(level),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(reported, vec![DegradationLevel::Reduced]);
+        
+        // Past the halfway point the AOI shrinks to 15m and updates drop to 5 Hz
+        server.join_room(&room_id, test_player("p4")).unwrap();
+        assert_eq!(server.sync_budget(&room_id), Some(DegradationLevel::Minimal.sync_budget()));
+        
+        let mut far = PlayerTransform::identity();
+        far.position = Vector3 { x: 100.0, y: 0.0, z: 0.0 };
+        server.update_player("p4", far);
+        
+        let start = Instant::now();
+        assert_eq!(server.relay_player_update("p0", PlayerTransform::identity(), start), Ok(3));
+        assert_eq!(server.relay_player_update("p0", PlayerTransform::identity(), start + Duration::from_millis(200)), Ok(3));
+        
+        // A throttled update is held and goes out once the interval is up
+        let mut latest = PlayerTransform::identity();
+        latest.position = Vector3 { x: 1.0, y: 0.0, z: 0.0 };
+        assert_eq!(server.relay_player_update("p0", latest.clone(), start + Duration::from_millis(300)), Ok(0));
+        assert_eq!(server.tick_player_syncs(start + Duration::from_millis(350)), 0);
+        transport.drain("p1");
+        assert_eq!(server.tick_player_syncs(start + Duration::from_millis(400)), 3);
+        assert!(matches!(transport.drain("p1").as_slice(), [GameMessage::PlayerUpdate { transform, .. }] if *transform == latest));
+        assert_eq!(server.tick_player_syncs(start + Duration::from_millis(600)), 0);
+        
+        // The hard cap still refuses joins
+        server.join_room(&room_id, test_player("p5")).unwrap();
+        assert!(server.join_room(&room_id, test_player("p6")).is_err());
+        
+        for i in 2..6 {
+            server.leave_room(&format!("p{}", i));
+        }
+        assert_eq!(server.sync_budget(&room_id), Some(DegradationLevel::Normal.sync_budget()));
+    }
+    
+    #[test]
+    fn test_timed_out_players_can_upload_diagnostics() {
+        let diagnostics = Arc::new(DiagnosticsService::new());
+        let server = MultiplayerServer::new().with_diagnostics(diagnostics.clone());
+        let room_id = server.create_room("game".to_string(), "host".to_string(), 4);
+        server.join_room(&room_id, test_player("host")).unwrap();
+        
+        assert_eq!(server.tick_heartbeats(Instant::now() + HEARTBEAT_TIMEOUT), vec!["host".to_string()]);
+        
+        let report_id = diagnostics.upload("host", br#"{"log_tail":"lost wifi"}"#, server.clock.timestamp()).unwrap();
+        let report = diagnostics.report(&report_id).unwrap();
+        assert_eq!(report.session.reason, DisconnectReason::HeartbeatTimeout);
+        assert_eq!(report.session.room_id, Some(room_id));
+    }
+    
+    #[test]
+    fn test_developer_dashboard_is_fed_by_server_and_matchmaking() {
+        use crate::clock::ManualClock;
+        
+        let clock = Arc::new(ManualClock::new(1_700_000_000));
+        let analytics = Arc::new(AnalyticsService::new());
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let server = MultiplayerServer::with_auth(auth.clone()).with_clock(clock.clone()).with_analytics(analytics.clone());
+        let matchmaking = MatchmakingService::new().with_clock(clock.clone()).with_analytics(analytics.clone());
+        let developer = auth.signup_test_user("developer");
+        auth.add_created_game(&developer, "arena");
+        
+        matchmaking.join_regional_queue("arena", Some("eu"), "p1");
+        clock.advance(Duration::from_secs(30));
+        matchmaking.join_regional_queue("arena", Some("eu"), "p2");
+        assert!(matchmaking.find_regional_match("arena", Some("eu"), 2).is_some());
+        
+        let room_id = server.create_room("arena".to_string(), developer.clone(), 4);
+        server.join_room(&room_id, test_player(&developer)).unwrap();
+        server.record_analytics_sample();
+        
+        let now = clock.timestamp();
+        assert!(server.developer_dashboard("p1", "arena", now - 60, now).is_err());
+        let dashboard = server.developer_dashboard(&developer, "arena", now - 60, now).unwrap();
+        assert_eq!(dashboard.concurrent_players.last().map(|s| s.players), Some(1));
+        assert_eq!(dashboard.average_room_fill, Some(0.25));
+        assert_eq!(dashboard.matchmaking_wait.samples, 2);
+        assert_eq!(dashboard.matchmaking_wait.p99_seconds, Some(30.0));
+    }
+    
+    #[test]
+    fn test_developer_quotas_gate_connect_and_rooms() {
+        use crate::billing::DeveloperQuota;
+        use crate::clock::ManualClock;
+        
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let mut tokens = Vec::new();
+        for name in ["first", "second"] {
+            let user_id = auth.signup_test_user(name);
+            tokens.push((user_id.clone(), auth.mint_game_token(&user_id, "arena").unwrap()));
+        }
+        
+        let usage = Arc::new(UsageService::new());
+        usage.register_game("arena", "studio");
+        usage.set_quota("studio", DeveloperQuota { max_ccu: Some(1), max_rooms: Some(1), ..Default::default() });
+        let clock = Arc::new(ManualClock::new(1_000));
+        let server = MultiplayerServer::with_auth(auth).with_usage(usage.clone()).with_clock(clock.clone());
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        
+        server.connect(&tokens[0].1, "arena", addr, None).unwrap();
+        assert!(server.connect(&tokens[1].1, "arena", addr, None).is_err());
+        assert_eq!(usage.current_ccu("studio"), 1);
+        
+        let room_id = server.open_room("arena".to_string(), tokens[0].0.clone(), 4).unwrap();
+        
+        // Voice minutes follow the shared clock
+        let voice = VoiceChatServer::new().with_usage(usage.clone()).with_clock(clock.clone());
+        voice.join_voice_channel(&room_id, &tokens[0].0);
+        clock.advance(Duration::from_secs(90));
+        voice.leave_voice_channel(&room_id, &tokens[0].0);
+        assert_eq!(usage.voice_minutes("studio", 0, i64::MAX), 1.5);
+        assert!(server.open_room("arena".to_string(), tokens[0].0.clone(), 4).is_err());
+        assert!(server.create_room_idempotent("retry", "arena".to_string(), tokens[0].0.clone(), 4).is_err());
+        
+        // Closing the room and disconnecting free the quota again
+        server.close_room(&room_id).unwrap();
+        server.disconnect(&tokens[0].0);
+        server.connect(&tokens[1].1, "arena", addr, None).unwrap();
+        assert!(server.open_room("arena".to_string(), tokens[1].0.clone(), 4).is_ok());
+        
+        let report = usage.usage_report("studio", 0, i64::MAX);
+        assert_eq!((report.peak_ccu, report.peak_rooms, report.rooms_created), (1, 1, 2));
+    }
+    
+    #[test]
+    fn test_transform_math_and_validation() {
+        let a = Vector3::new(0.0, 0.0, 0.0);
+        let b = Vector3::new(2.0, 0.0, 0.0);
+        assert_eq!(a.lerp(&b, 0.5), Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(a.lerp(&b, 1.5), Vector3::new(3.0, 0.0, 0.0));
+        assert_eq!(Vector3::new(0.0, 3.0, 4.0).normalized(), Vector3::new(0.0, 0.6, 0.8));
+        
+        // Halfway between identity and 180 degrees about Y is 90 degrees about Y
+        let turned = Quaternion::new(0.0, 1.0, 0.0, 0.0);
+        let half = Quaternion::IDENTITY.slerp(&turned, 0.5);
+        assert!((half.y - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-5);
+        assert!((half.w - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-5);
+        assert!((Quaternion::new(0.0, 0.0, 0.0, 2.0).normalized().w - 1.0).abs() < 1e-6);
+        
+        let mut corrupt = PlayerTransform::identity();
+        corrupt.head_position.y = f32::NAN;
+        assert!(corrupt.validate().unwrap_err().contains("head_position"));
+        let mut degenerate = PlayerTransform::identity();
+        degenerate.rotation = Quaternion::new(0.0, 0.0, 0.0, 0.0);
+        assert!(degenerate.validate().is_err());
+        
+        let server = MultiplayerServer::new();
+        let room_id = server.create_room("game".to_string(), "host".to_string(), 4);
+        server.join_room(&room_id, test_player("host")).unwrap();
+        assert!(server.relay_player_update("host", corrupt, Instant::now()).is_err());
+        assert!(server.update_player("host", degenerate).is_none());
+        assert_eq!(server.get_room_players(&room_id)[0].transform, PlayerTransform::identity());
+        
+        let mut moved = PlayerTransform::identity();
+        moved.position.x = 1.0;
+        moved.rotation = Quaternion::new(0.0, 0.0, 0.0, 3.0);
+        let start = Instant::now();
+        server.relay_player_update("host", PlayerTransform::identity(), start).unwrap();
+        server.relay_player_update("host", moved, start + Duration::from_millis(100)).unwrap();
+        
+        // Stored rotations are renormalized and motion is extrapolated, but only so far
+        assert_eq!(server.get_room_players(&room_id)[0].transform.rotation, Quaternion::IDENTITY);
+        let ahead = server.predicted_transform("host", start + Duration::from_millis(150)).unwrap();
+        assert!((ahead.position.x - 1.5).abs() < 1e-4);
+        let capped = server.predicted_transform("host", start + Duration::from_secs(5)).unwrap();
+        assert!((capped.position.x - 3.5).abs() < 1e-4);
+        
+        let mut spawn = GameMessage::ObjectSpawned {
+            object_id: 0,
+            temp_id: None,
+            object_type: "crate".to_string(),
+            position: Vector3::new(f32::INFINITY, 0.0, 0.0),
+            rotation: Quaternion::IDENTITY,
+        };
+        let mut rooms = server.rooms.lock().unwrap();
+        assert!(rooms.get_mut(&room_id).unwrap().apply_object_message("host", &mut spawn, 0).is_err());
+    }
+    
+    #[test]
+    fn test_presence_is_persisted_behind_the_game_loop() {
+        use crate::persistence::InMemoryPersistence;
+        
+        let backend = Arc::new(InMemoryPersistence::new());
+        let queue = Arc::new(WriteBehindQueue::new(backend.clone()));
+        let server = MultiplayerServer::new().with_write_behind(queue.clone());
+        
+        let room_id = server.create_room("game".to_string(), "host".to_string(), 4);
+        server.join_room(&room_id, test_player("host")).unwrap();
+        server.join_room(&room_id, test_player("guest")).unwrap();
+        server.leave_room("guest");
+        
+        // Nothing reaches the backend until the queue flushes; repeated changes coalesce
+        assert!(backend.is_empty());
+        assert_eq!(queue.stats().depth, 2);
+        assert_eq!(queue.flush_all(), Ok(2));
+        
+        let stored: PlayerPresence = serde_json::from_str(&backend.get(WriteKind::Presence, "host").unwrap()).unwrap();
+        assert_eq!(stored.room_id.as_deref(), Some(room_id.as_str()));
+        let stored: PlayerPresence = serde_json::from_str(&backend.get(WriteKind::Presence, "guest").unwrap()).unwrap();
+        assert_eq!(stored.room_id, None);
+    }
+    
+    #[test]
+    fn test_rooms_created_from_templates() {
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let developer = auth.signup_test_user("developer");
+        auth.add_created_game(&developer, "arena");
+        let guest = auth.signup_test_user("guest");
+        
+        let server = MultiplayerServer::with_auth(auth);
+        server.register_prefab(Prefab {
+            name: "goals".to_string(),
+            objects: vec![
+                PrefabObject { object_type: "goal".to_string(), offset: Vector3::new(0.0, 0.0, -10.0), rotation: Quaternion::IDENTITY },
+                PrefabObject { object_type: "goal".to_string(), offset: Vector3::new(0.0, 0.0, 10.0), rotation: Quaternion::IDENTITY },
+            ],
+        });
+        
+        let template = RoomTemplate {
+            template_id: "arena_duel".to_string(),
+            game_id: "arena".to_string(),
+            name: "Duel".to_string(),
+            max_players: 2,
+            soft_cap: None,
+            scene: Some("stadium".to_string()),
+            metadata: HashMap::from([("mode".to_string(), "duel".to_string())]),
+            spawn_prefabs: vec!["goals".to_string()],
+            permissions: RoomPermissions { players_can_spawn_objects: false, players_can_screen_share: false },
+            is_public: false,
+            time_limit: None,
+        };
+        assert!(server.register_room_template("someone", template.clone()).is_err());
+        assert!(server.register_room_template(&developer, RoomTemplate { spawn_prefabs: vec!["missing".to_string()], ..template.clone() }).is_err());
+        server.register_room_template(&developer, template).unwrap();
+        assert_eq!(server.room_templates("arena").len(), 1);
+        
+        let room_id = server.create_room_from_template("arena_duel", developer.clone()).unwrap();
+        let room = server.get_room(&room_id).unwrap();
+        assert_eq!((room.max_players, room.is_public), (2, false));
+        assert_eq!(room.scene.as_deref(), Some("stadium"));
+        assert_eq!(room.metadata.get("mode").map(String::as_str), Some("duel"));
+        assert_eq!(room.objects.len(), 2);
+        assert!(room.objects.values().all(|o| o.asleep && o.owner_id.is_none()));
+        assert!(server.create_room_from_template("unknown", developer.clone()).is_err());
+        
+        // A template that no longer applies leaves no half-built room behind
+        let mut broken = server.room_template("arena_duel").unwrap();
+        broken.template_id = "broken".to_string();
+        broken.metadata.insert(PERMISSION_ZONES_KEY.to_string(), "not zones".to_string());
+        server.room_templates.lock().unwrap().insert("broken".to_string(), broken);
+        assert!(server.create_room_from_template("broken", developer.clone()).is_err());
+        assert_eq!(server.rooms.lock().unwrap().len(), 1);
+        
+        // Only the host may spawn objects in rooms from this template
+        server.join_room(&room_id, test_player(&developer)).unwrap();
+        server.join_room(&room_id, test_player(&guest)).unwrap();
+        assert!(server.spawn_prefab(&guest, "goals", Vector3::ZERO).is_err());
+        assert_eq!(server.spawn_prefab(&developer, "goals", Vector3::ZERO).unwrap().len(), 2);
+    }
+    
+    #[test]
+    fn test_join_friend_across_instances() {
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let mut users = Vec::new();
+        for name in ["joiner", "friend", "stranger"] {
+            users.push(auth.signup_test_user(name));
+        }
+        let (joiner, friend, stranger) = (&users[0], &users[1], &users[2]);
+        auth.add_friend(joiner, friend);
+        auth.add_friend(friend, joiner);
+        
+        let cluster = Arc::new(InMemoryCluster::new());
+        let east = Arc::new(MultiplayerServer::with_auth(auth.clone()).with_cluster(cluster.clone(), "east", "east.example.com:7777"));
+        let west = Arc::new(MultiplayerServer::with_auth(auth.clone()).with_cluster(cluster.clone(), "west", "west.example.com:7777"));
+        cluster.register(&east);
+        cluster.register(&west);
+        
+        assert_eq!(east.join_friend(joiner, friend), Err(JoinDenial::Offline));
+        assert_eq!(east.join_friend(joiner, stranger), Err(JoinDenial::NotFriends));
+        
+        // An unanswered request is not a friendship
+        auth.add_friend(stranger, friend);
+        assert_eq!(east.join_friend(stranger, friend), Err(JoinDenial::NotFriends));
+        
+        // The friend plays on the other instance
+        let room_id = west.create_room("arena".to_string(), friend.clone(), 2);
+        west.join_room(&room_id, test_player(friend)).unwrap();
+        
+        let details = east.join_friend(joiner, friend).unwrap();
+        assert_eq!((details.server_id.as_str(), details.server_addr.as_str()), ("west", "west.example.com:7777"));
+        assert_eq!(details.room_id, room_id);
+        
+        west.rooms.lock().unwrap().get_mut(&room_id).unwrap().is_public = false;
+        assert_eq!(east.join_friend(joiner, friend), Err(JoinDenial::InviteRequired));
+        west.rooms.lock().unwrap().get_mut(&room_id).unwrap().invited.insert(joiner.clone());
+        assert!(east.join_friend(joiner, friend).is_ok());
+        
+        west.join_room(&room_id, test_player(stranger)).unwrap();
+        assert_eq!(east.join_friend(joiner, friend), Err(JoinDenial::RoomFull));
+        west.leave_room(stranger);
+        
+        // The ticket is redeemed on the friend's instance
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let details = east.join_friend(joiner, friend).unwrap();
+        west.join_with_ticket(&details.ticket, addr).unwrap();
+        assert_eq!(west.player_room(joiner), Some(room_id));
+    }
+    
+    #[test]
+    fn test_network_report_flags_poor_connections_to_host() {
+        use crate::sim::InMemoryTransport;
+        use crate::transport::{ReliabilityConfig, ReliableConnection};
+        
+        let transport = Arc::new(InMemoryTransport::default());
+        let server = MultiplayerServer::new().with_message_sink(transport.clone());
+        let room_id = server.create_room("arena".to_string(), "host".to_string(), 4);
+        for player_id in ["host", "steady", "laggy", "frozen"] {
+            server.join_room(&room_id, test_player(player_id)).unwrap();
+        }
+        transport.drain("host");
+        
+        // The steady player's sample is measured by their connection's acks
+        let start = Instant::now();
+        let mut link = ReliableConnection::new(ReliabilityConfig::default());
+        let mut peer = ReliableConnection::new(ReliabilityConfig::default());
+        peer.receive(link.send(b"hello".to_vec(), DeliveryMode::ReliableOrdered, start), start);
+        link.receive(peer.ack_packet(), start + Duration::from_millis(40));
+        server.record_connection_sample("steady", link.connection_sample().unwrap()).unwrap();
+        server.record_connection_sample("laggy", ConnectionSample { rtt_ms: 400, packet_loss: 0.01 }).unwrap();
+        assert!(server.record_connection_sample("steady", ConnectionSample { rtt_ms: 40, packet_loss: 1.5 }).is_err());
+        assert!(server.record_connection_sample("stranger", ConnectionSample { rtt_ms: 40, packet_loss: 0.0 }).is_err());
+        server.relay_player_update("frozen", PlayerTransform::identity(), start).unwrap();
+        server.relay_player_update("steady", PlayerTransform::identity(), start + Duration::from_millis(1500)).unwrap();
+        
+        transport.drain("host");
+        
+        assert_eq!(server.tick_network_reports(start + Duration::from_secs(2)), 1);
+        let players = match transport.drain("host").as_slice() {
+            [GameMessage::RoomNetworkReport { players, .. }] => players.clone(),
+            other => panic!("expected a network report, got {:?}", other),
+        };
+        let poor: Vec<&str> = players.iter().filter(|p| p.poor_connection).map(|p| p.player_id.as_str()).collect();
+        assert_eq!(poor, vec!["laggy", "frozen"]);
+        assert_eq!(players[1].last_update_age_ms, Some(500));
+        assert_eq!(players[0].rtt_ms, None);
+        assert_eq!(players[1].rtt_ms, Some(40));
+        assert!(transport.drain("steady").iter().all(|m| !matches!(m, GameMessage::RoomNetworkReport { .. })));
+        
+        // Plain transform updates count as fresh too
+        server.update_player("frozen", PlayerTransform::identity()).unwrap();
+        let players = server.room_network_stats(&room_id, server.clock.now()).unwrap();
+        assert!(!players[3].poor_connection);
+        
+        // Samples go with the connection
+        server.disconnect("laggy");
+        let players = server.room_network_stats(&room_id, start).unwrap();
+        assert!(players.iter().all(|p| p.player_id != "laggy"));
+    }
+    
+    #[test]
+    fn test_permission_zones_guard_object_handling() {
+        let server = MultiplayerServer::new();
+        let room_id = server.create_room("theatre".to_string(), "host".to_string(), 4);
+        server.join_room(&room_id, test_player("host")).unwrap();
+        server.join_room(&room_id, test_player("guest")).unwrap();
+        
+        // The sandbox corner is listed first, so it wins where it overlaps the stage
+        let zones = r#"[
+            {"name":"sandbox","min":{"x":8.0,"y":0.0,"z":8.0},"max":{"x":10.0,"y":5.0,"z":10.0},"access":"Everyone"},
+            {"name":"stage","min":{"x":0.0,"y":0.0,"z":0.0},"max":{"x":10.0,"y":5.0,"z":10.0},"access":"HostOnly"}
+        ]"#;
+        let set_zones = |raw: &str| {
+            server.set_room_metadata(&room_id, "host", HashMap::from([(PERMISSION_ZONES_KEY.to_string(), raw.to_string())]))
+        };
+        assert!(server.set_room_metadata(&room_id, "guest", HashMap::new()).is_err());
+        assert!(set_zones("not json").is_err());
+        assert!(set_zones(&zones.replace("\"x\":10.0", "\"x\":-1.0")).is_err());
+        set_zones(zones).unwrap();
+        
+        let spawn = |x: f32| {
+            server.handle_object_message("host", GameMessage::ObjectSpawned {
+                object_id: 0,
+                temp_id: None,
+                object_type: "prop".to_string(),
+                position: Vector3::new(x, 0.0, 9.0),
+                rotation: Quaternion::IDENTITY,
+            }).unwrap();
+            let room = server.get_room(&room_id).unwrap();
+            room.objects.values().find(|o| o.position.x == x).unwrap().object_id
+        };
+        let grab = |player: &str, object_id| server.handle_object_message(player, GameMessage::ObjectGrabbed { object_id, player_id: player.to_string() });
+        let move_to = |player: &str, object_id, x: f32| server.handle_object_message(player, GameMessage::ObjectMoved {
+            object_id,
+            position: Vector3::new(x, 0.0, 9.0),
+            rotation: Quaternion::IDENTITY,
+        });
+        let (on_stage, in_sandbox) = (spawn(2.0), spawn(9.0));
+        
+        // Players stand at the origin, on the stage, until they move off it
+        assert!(grab("guest", in_sandbox).is_err());
+        server.update_player("guest", PlayerTransform { position: Vector3::new(20.0, 0.0, 0.0), ..PlayerTransform::identity() });
+        assert!(grab("guest", on_stage).is_err());
+        grab("guest", in_sandbox).unwrap();
+        move_to("guest", in_sandbox, 9.5).unwrap();
+        assert!(move_to("guest", in_sandbox, 3.0).is_err());
+        
+        // Putting an object to sleep can't teleport it onto the stage either
+        let sleep_at = |player: &str, object_id, x: f32| server.handle_object_message(player, GameMessage::ObjectSleep {
+            object_id,
+            position: Vector3::new(x, 0.0, 9.0),
+            rotation: Quaternion::IDENTITY,
+        });
+        assert!(sleep_at("guest", in_sandbox, 3.0).is_err());
+        sleep_at("guest", in_sandbox, 9.0).unwrap();
+        
+        // Nor can guests destroy what's on the stage
+        assert!(server.handle_object_message("guest", GameMessage::ObjectDestroyed { object_id: on_stage }).is_err());
+        assert!(server.handle_object_message("guest", GameMessage::ObjectsDestroyedBatch { object_ids: vec![in_sandbox, on_stage] }).is_err());
+        assert_eq!(server.get_room(&room_id).unwrap().objects.len(), 2);
+        
+        grab("host", on_stage).unwrap();
+        move_to("host", on_stage, 4.0).unwrap();
+    }
+    
+    #[test]
+    fn test_timed_room_warns_locks_joins_and_closes() {
+        use crate::clock::ManualClock;
+        use crate::sim::InMemoryTransport;
+        
+        let clock = Arc::new(ManualClock::new(1_000));
+        let transport = Arc::new(InMemoryTransport::default());
+        let server = MultiplayerServer::new().with_clock(clock.clone()).with_message_sink(transport.clone());
+        let room_id = server.create_room("arcade".to_string(), "host".to_string(), 4);
+        server.join_room(&room_id, test_player("host")).unwrap();
+        server.join_room(&room_id, test_player("guest")).unwrap();
+        
+        let config = TimeLimitConfig { duration_seconds: 120, warnings: vec![60, 10], lock_joins_seconds: 30 };
+        assert!(server.set_room_time_limit(&room_id, "guest", config.clone()).is_err());
+        assert!(server.set_room_time_limit(&room_id, "host", TimeLimitConfig { lock_joins_seconds: 200, ..config.clone() }).is_err());
+        assert!(server.set_room_time_limit(&room_id, "host", TimeLimitConfig { duration_seconds: i64::MAX, ..config.clone() }).is_err());
+        server.set_room_time_limit(&room_id, "host", config).unwrap();
+        
+        let countdown = |messages: Vec<GameMessage>| -> Vec<(i64, bool)> {
+            messages.into_iter()
+                .filter_map(|m| match m {
+                    GameMessage::RoomTimeRemaining { seconds_remaining, joins_locked, .. } => Some((seconds_remaining, joins_locked)),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(countdown(transport.drain("guest")), vec![(120, false)]);
+        
+        // Both warning points passed in one tick are announced once
+        clock.advance(Duration::from_secs(30));
+        assert!(server.tick_room_time_limits().is_empty());
+        assert!(countdown(transport.drain("guest")).is_empty());
+        clock.advance(Duration::from_secs(85));
+        assert!(server.tick_room_time_limits().is_empty());
+        assert_eq!(countdown(transport.drain("guest")), vec![(5, true)]);
+        assert_eq!(server.room_time_remaining(&room_id), Some(5));
+        assert_eq!(server.join_room(&room_id, test_player("late")), Err("Room is closing".to_string()));
+        
+        // The host's submitted result stands when time runs out
+        let result = MatchResult { scores: HashMap::from([("host".to_string(), 3), ("guest".to_string(), 1)]), winners: vec!["host".to_string()] };
+        assert_eq!(server.submit_match_result("host", result.clone()).unwrap(), MatchSubmissionStatus::Pending);
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(server.tick_room_time_limits(), vec![room_id.clone()]);
+        let messages = transport.drain("guest");
+        assert!(messages.iter().any(|m| matches!(m, GameMessage::MatchFinalized { result: r } if *r == result)));
+        assert_eq!(countdown(messages), vec![(0, true)]);
+        assert!(server.get_room(&room_id).is_none());
+        assert!(server.player_room("guest").is_none());
+        
+        // Without a host result, the result most players submitted stands
+        let room_id = server.create_room("arcade".to_string(), "host".to_string(), 4);
+        for player_id in ["host", "guest", "third"] {
+            server.join_room(&room_id, test_player(player_id)).unwrap();
+        }
+        server.set_room_time_limit(&room_id, "host", TimeLimitConfig { duration_seconds: 60, warnings: Vec::new(), lock_joins_seconds: 0 }).unwrap();
+        let guest_win = MatchResult { scores: HashMap::from([("guest".to_string(), 5)]), winners: vec!["guest".to_string()] };
+        server.submit_match_result("guest", guest_win.clone()).unwrap();
+        server.submit_match_result("third", guest_win.clone()).unwrap();
+        transport.drain("guest");
+        
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(server.tick_room_time_limits(), vec![room_id]);
+        assert!(transport.drain("guest").iter().any(|m| matches!(m, GameMessage::MatchFinalized { result: r } if *r == guest_win)));
     }
 }
@@ -0,0 +1,421 @@
+// persistence.rs - Write-behind queue for high-frequency persistence (stats, achievements, presence)
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+
+pub const DEFAULT_BATCH_SIZE: usize = 100;
+/// A write that failed this many times is dropped
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+/// Past this many pending writes new ones are dropped rather than growing without bound
+pub const DEFAULT_MAX_QUEUE_DEPTH: usize = 100_000;
+/// Wait before the first retry; doubles with every further failure up to `MAX_RETRY_BACKOFF`
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WriteKind {
+    Stats,
+    Achievement,
+    Presence,
+}
+
+/// One row to persist. Writes with the same kind and key replace each other while
+/// queued, so only the latest value of a hot stat is written.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingWrite {
+    pub kind: WriteKind,
+    pub key: String,
+    /// JSON document to store under the key
+    pub payload: String,
+}
+
+impl PendingWrite {
+    pub fn new<T: Serialize>(kind: WriteKind, key: impl Into<String>, value: &T) -> Result<Self, String> {
+        Ok(Self {
+            kind,
+            key: key.into(),
+            payload: serde_json::to_string(value).map_err(|e| e.to_string())?,
+        })
+    }
+}
+
+/// Where queued writes end up, e.g. a database. A batch either succeeds or is retried whole.
+pub trait PersistenceBackend: Send + Sync {
+    fn write_batch(&self, writes: &[PendingWrite]) -> Result<(), String>;
+}
+
+/// Backend kept in process memory; useful for tests and single-node setups
+pub struct InMemoryPersistence {
+    rows: Mutex<HashMap<(WriteKind, String), String>>,
+}
+
+impl InMemoryPersistence {
+    pub fn new() -> Self {
+        Self {
+            rows: Mutex::new(HashMap::new()),
+        }
+    }
+    
+    pub fn get(&self, kind: WriteKind, key: &str) -> Option<String> {
+        self.rows.lock().unwrap().get(&(kind, key.to_string())).cloned()
+    }
+    
+    pub fn len(&self) -> usize {
+        self.rows.lock().unwrap().len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for InMemoryPersistence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PersistenceBackend for InMemoryPersistence {
+    fn write_batch(&self, writes: &[PendingWrite]) -> Result<(), String> {
+        let mut rows = self.rows.lock().unwrap();
+        for write in writes {
+            rows.insert((write.kind, write.key.clone()), write.payload.clone());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WriteQueueStats {
+    /// Writes waiting to be persisted
+    pub depth: usize,
+    pub depth_by_kind: HashMap<WriteKind, usize>,
+    /// How long the oldest pending write has waited
+    pub oldest_pending_ms: u64,
+    pub batches_written: u64,
+    pub writes_persisted: u64,
+    /// Pending writes replaced by a newer value before they were written
+    pub writes_coalesced: u64,
+    pub failed_batches: u64,
+    /// Writes given up on after `max_attempts` or refused because the queue was full
+    pub writes_dropped: u64,
+}
+
+#[derive(Debug, Clone)]
+struct QueuedWrite {
+    write: PendingWrite,
+    queued_at: Instant,
+    attempts: u32,
+    retry_at: Option<Instant>,
+}
+
+type WriteKey = (WriteKind, String);
+
+/// Takes persistence off the game loop: callers enqueue and return immediately,
+/// and a background job writes batches to the backend, retrying failures with
+/// backoff. Call `flush_all` on shutdown so nothing queued is lost.
+pub struct WriteBehindQueue {
+    backend: Arc<dyn PersistenceBackend>,
+    clock: Arc<dyn Clock>,
+    batch_size: usize,
+    max_attempts: u32,
+    max_depth: usize,
+    order: Mutex<VecDeque<WriteKey>>, // oldest first
+    pending: Mutex<HashMap<WriteKey, QueuedWrite>>,
+    stats: Mutex<WriteQueueStats>,
+}
+
+impl WriteBehindQueue {
+    pub fn new(backend: Arc<dyn PersistenceBackend>) -> Self {
+        Self {
+            backend,
+            clock: Arc::new(SystemClock),
+            batch_size: DEFAULT_BATCH_SIZE,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            max_depth: DEFAULT_MAX_QUEUE_DEPTH,
+            order: Mutex::new(VecDeque::new()),
+            pending: Mutex::new(HashMap::new()),
+            stats: Mutex::new(WriteQueueStats::default()),
+        }
+    }
+    
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+    
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+    
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+    
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+    
+    /// Queue a write without blocking on the backend. A pending write with the same
+    /// kind and key is replaced in place. Returns false if the queue is full.
+    pub fn enqueue(&self, write: PendingWrite) -> bool {
+        let key = (write.kind, write.key.clone());
+        // Same lock order as `take_batch`: order, then pending
+        let mut order = self.order.lock().unwrap();
+        let mut pending = self.pending.lock().unwrap();
+        
+        if let Some(queued) = pending.get_mut(&key) {
+            queued.write = write;
+            self.stats.lock().unwrap().writes_coalesced += 1;
+            return true;
+        }
+        
+        if pending.len() >= self.max_depth {
+            drop(pending);
+            drop(order);
+            eprintln!("[WriteBehind] Queue full, dropping {:?} write for {}", key.0, key.1);
+            self.stats.lock().unwrap().writes_dropped += 1;
+            return false;
+        }
+        
+        pending.insert(key.clone(), QueuedWrite {
+            write,
+            queued_at: self.clock.now(),
+            attempts: 0,
+            retry_at: None,
+        });
+        order.push_back(key);
+        true
+    }
+    
+    /// Write one batch of due writes. Returns how many were persisted.
+    pub fn flush_batch(&self) -> usize {
+        let now = self.clock.now();
+        let batch = self.take_batch(|queued| queued.retry_at.is_none_or(|at| at <= now));
+        self.write(batch, now)
+    }
+    
+    /// Write everything queued, ignoring retry backoff. Meant for shutdown: stops at
+    /// the first failed batch and reports how many writes are still pending.
+    pub fn flush_all(&self) -> Result<usize, String> {
+        let mut persisted = 0;
+        
+        loop {
+            let batch = self.take_batch(|_| true);
+            if batch.is_empty() {
+                return Ok(persisted);
+            }
+            
+            let size = batch.len();
+            let written = self.write(batch, self.clock.now());
+            persisted += written;
+            if written < size {
+                return Err(format!("Flush failed with {} writes still pending", self.depth()));
+            }
+        }
+    }
+    
+    pub fn depth(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+    
+    pub fn stats(&self) -> WriteQueueStats {
+        let now = self.clock.now();
+        let (depth, depth_by_kind, oldest) = {
+            let pending = self.pending.lock().unwrap();
+            let mut by_kind: HashMap<WriteKind, usize> = HashMap::new();
+            for (kind, _) in pending.keys() {
+                *by_kind.entry(*kind).or_default() += 1;
+            }
+            let oldest = pending.values().map(|q| q.queued_at).min();
+            (pending.len(), by_kind, oldest)
+        };
+        
+        WriteQueueStats {
+            depth,
+            depth_by_kind,
+            oldest_pending_ms: oldest.map_or(0, |at| now.saturating_duration_since(at).as_millis() as u64),
+            ..self.stats.lock().unwrap().clone()
+        }
+    }
+    
+    /// Run `flush_batch` every `interval` on the current tokio runtime, draining
+    /// as many batches as are due each time
+    pub fn start_flush_job(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let queue = self.clone();
+        
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                while queue.flush_batch() == queue.batch_size {}
+            }
+        })
+    }
+    
+    fn take_batch(&self, due: impl Fn(&QueuedWrite) -> bool) -> Vec<QueuedWrite> {
+        let mut order = self.order.lock().unwrap();
+        let mut pending = self.pending.lock().unwrap();
+        
+        let mut batch = Vec::new();
+        let mut waiting = VecDeque::new();
+        while batch.len() < self.batch_size {
+            let Some(key) = order.pop_front() else { break };
+            match pending.get(&key) {
+                Some(queued) if due(queued) => batch.extend(pending.remove(&key)),
+                Some(_) => waiting.push_back(key),
+                None => {}
+            }
+        }
+        
+        // Writes still backing off keep their place at the front
+        while let Some(key) = waiting.pop_back() {
+            order.push_front(key);
+        }
+        batch
+    }
+    
+    fn write(&self, batch: Vec<QueuedWrite>, now: Instant) -> usize {
+        if batch.is_empty() {
+            return 0;
+        }
+        
+        let writes: Vec<PendingWrite> = batch.iter().map(|q| q.write.clone()).collect();
+        match self.backend.write_batch(&writes) {
+            Ok(()) => {
+                let mut stats = self.stats.lock().unwrap();
+                stats.batches_written += 1;
+                stats.writes_persisted += writes.len() as u64;
+                writes.len()
+            }
+            Err(e) => {
+                eprintln!("[WriteBehind] Batch of {} writes failed: {}", writes.len(), e);
+                self.stats.lock().unwrap().failed_batches += 1;
+                self.requeue(batch, now);
+                0
+            }
+        }
+    }
+    
+    fn requeue(&self, batch: Vec<QueuedWrite>, now: Instant) {
+        let mut order = self.order.lock().unwrap();
+        let mut pending = self.pending.lock().unwrap();
+        let mut dropped = 0;
+        
+        for mut queued in batch.into_iter().rev() {
+            let key = (queued.write.kind, queued.write.key.clone());
+            // A newer value arrived while this batch was in flight; it supersedes the retry
+            if pending.contains_key(&key) {
+                continue;
+            }
+            
+            queued.attempts += 1;
+            if queued.attempts >= self.max_attempts {
+                eprintln!("[WriteBehind] Giving up on {:?} write for {} after {} attempts", key.0, key.1, queued.attempts);
+                dropped += 1;
+                continue;
+            }
+            
+            queued.retry_at = Some(now + retry_backoff(queued.attempts));
+            pending.insert(key.clone(), queued);
+            order.push_front(key);
+        }
+        
+        self.stats.lock().unwrap().writes_dropped += dropped;
+    }
+}
+
+/// Wait before retrying a write that has failed `attempts` times
+fn retry_backoff(attempts: u32) -> Duration {
+    RETRY_BACKOFF.saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1))).min(MAX_RETRY_BACKOFF)
+}
+
+// ============================================
+// Tests
+// ============================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    
+    /// Fails while `down` is set, otherwise stores like `InMemoryPersistence`
+    struct FlakyBackend {
+        down: AtomicBool,
+        store: InMemoryPersistence,
+    }
+    
+    impl PersistenceBackend for FlakyBackend {
+        fn write_batch(&self, writes: &[PendingWrite]) -> Result<(), String> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err("database unavailable".to_string());
+            }
+            self.store.write_batch(writes)
+        }
+    }
+    
+    fn stat(key: &str, value: u64) -> PendingWrite {
+        PendingWrite::new(WriteKind::Stats, key, &value).unwrap()
+    }
+    
+    #[test]
+    fn test_write_behind_batches_coalesces_and_retries() {
+        let clock = Arc::new(ManualClock::new(0));
+        let backend = Arc::new(FlakyBackend { down: AtomicBool::new(true), store: InMemoryPersistence::new() });
+        let queue = WriteBehindQueue::new(backend.clone())
+            .with_clock(clock.clone())
+            .with_batch_size(2)
+            .with_max_attempts(3);
+        
+        queue.enqueue(stat("a", 1));
+        queue.enqueue(stat("a", 2));
+        queue.enqueue(stat("b", 1));
+        queue.enqueue(PendingWrite::new(WriteKind::Achievement, "a", &"first_win").unwrap());
+        assert_eq!(queue.stats().depth, 3);
+        assert_eq!(queue.stats().writes_coalesced, 1);
+        
+        // The failed batch backs off while the next one goes ahead
+        assert_eq!(queue.flush_batch(), 0);
+        assert_eq!(queue.flush_batch(), 0);
+        assert_eq!(queue.flush_batch(), 0);
+        assert_eq!(queue.stats().failed_batches, 2);
+        
+        backend.down.store(false, Ordering::SeqCst);
+        assert_eq!(queue.flush_batch(), 0);
+        clock.advance(RETRY_BACKOFF);
+        assert_eq!(queue.flush_batch(), 2);
+        assert_eq!(backend.store.get(WriteKind::Stats, "a").as_deref(), Some("2"));
+        
+        // Shutdown flush ignores backoff
+        backend.down.store(true, Ordering::SeqCst);
+        queue.enqueue(stat("c", 1));
+        assert!(queue.flush_all().is_err());
+        backend.down.store(false, Ordering::SeqCst);
+        assert_eq!(queue.flush_all(), Ok(2));
+        assert_eq!(backend.store.len(), 4);
+        
+        let stats = queue.stats();
+        assert_eq!((stats.depth, stats.writes_persisted, stats.writes_dropped), (0, 4, 0));
+        
+        // Writes that keep failing are eventually dropped
+        backend.down.store(true, Ordering::SeqCst);
+        queue.enqueue(stat("d", 1));
+        for _ in 0..3 {
+            clock.advance(Duration::from_secs(60));
+            queue.flush_batch();
+        }
+        assert_eq!((queue.depth(), queue.stats().writes_dropped), (0, 1));
+        
+        // A generous attempt limit never overflows the backoff
+        assert_eq!(retry_backoff(2), RETRY_BACKOFF * 2);
+        assert_eq!(retry_backoff(40), MAX_RETRY_BACKOFF);
+        assert_eq!(retry_backoff(u32::MAX), MAX_RETRY_BACKOFF);
+    }
+}
@@ -0,0 +1,123 @@
+// saga.rs - Coordinates operations that span several subsystems, undoing finished steps on failure
+
+type Compensation<'a> = Box<dyn FnOnce() -> Result<(), String> + 'a>;
+
+/// A multi-step operation where each finished step registers how to undo itself.
+/// If a later step fails, or the saga is dropped without `commit`, the registered
+/// compensations run newest first so no subsystem is left holding half an operation.
+pub struct Saga<'a> {
+    name: String,
+    completed: Vec<(String, Compensation<'a>)>, // (step name, undo), oldest first
+    finished: bool,
+}
+
+impl<'a> Saga<'a> {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            completed: Vec::new(),
+            finished: false,
+        }
+    }
+    
+    /// Run `action`; on success remember `compensate` with its result, on failure
+    /// roll back everything done so far and return the error
+    pub fn step<T, A, C>(&mut self, name: &str, action: A, compensate: C) -> Result<T, String>
+    where
+        T: Clone + 'a,
+        A: FnOnce() -> Result<T, String>,
+        C: FnOnce(T) -> Result<(), String> + 'a,
+    {
+        match action() {
+            Ok(value) => {
+                let undo_value = value.clone();
+                self.completed.push((name.to_string(), Box::new(move || compensate(undo_value))));
+                Ok(value)
+            }
+            Err(e) => {
+                let error = format!("{} failed at '{}': {}", self.name, name, e);
+                println!("[Saga] {}", error);
+                self.rollback();
+                Err(error)
+            }
+        }
+    }
+    
+    /// Register the undo for work that already happened before the saga started
+    pub fn compensate_with<C>(&mut self, name: &str, compensate: C)
+    where
+        C: FnOnce() -> Result<(), String> + 'a,
+    {
+        self.completed.push((name.to_string(), Box::new(compensate)));
+    }
+    
+    /// Keep everything the saga did
+    pub fn commit(mut self) {
+        self.finished = true;
+        self.completed.clear();
+    }
+    
+    /// Undo every finished step. Returns the steps whose compensation failed.
+    pub fn abort(mut self) -> Vec<String> {
+        self.rollback()
+    }
+    
+    fn rollback(&mut self) -> Vec<String> {
+        self.finished = true;
+        let mut failed = Vec::new();
+        
+        while let Some((step, compensate)) = self.completed.pop() {
+            if let Err(e) = compensate() {
+                eprintln!("[Saga] {}: compensation for '{}' failed: {}", self.name, step, e);
+                failed.push(step);
+            }
+        }
+        
+        failed
+    }
+}
+
+impl Drop for Saga<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            println!("[Saga] {} dropped before commit, rolling back", self.name);
+            self.rollback();
+        }
+    }
+}
+
+// ============================================
+// Tests
+// ============================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    
+    #[test]
+    fn test_failed_step_compensates_in_reverse() {
+        let log = RefCell::new(Vec::new());
+        
+        let result = {
+            let mut saga = Saga::new("test");
+            saga.compensate_with("reserve", || { log.borrow_mut().push("release".to_string()); Ok(()) });
+            saga.step("first", || Ok(1), |n| { log.borrow_mut().push(format!("undo {}", n)); Ok(()) }).unwrap();
+            saga.step("second", || Err::<(), _>("boom".to_string()), |_| Ok(()))
+        };
+        
+        assert_eq!(result.unwrap_err(), "test failed at 'second': boom");
+        assert_eq!(*log.borrow(), vec!["undo 1".to_string(), "release".to_string()]);
+        
+        // Dropping without commit also rolls back; committing keeps the work
+        {
+            let mut saga = Saga::new("dropped");
+            saga.step("only", || Ok(()), |_| { log.borrow_mut().push("dropped".to_string()); Ok(()) }).unwrap();
+        }
+        let mut saga = Saga::new("committed");
+        saga.step("only", || Ok(()), |_| { log.borrow_mut().push("committed".to_string()); Ok(()) }).unwrap();
+        saga.commit();
+        
+        assert_eq!(log.borrow().last().unwrap(), "dropped");
+    }
+}
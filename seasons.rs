@@ -0,0 +1,335 @@
+// seasons.rs - Per-game seasons with an XP-fed reward track, rollover and archived standings
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::auth_server::{AuthService, MatchParticipant, MATCH_PLAYED_XP, MATCH_WIN_XP};
+use crate::challenges::ChallengeReward;
+
+/// A step on the season's reward track, unlocked by earning `xp_required` season XP
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RewardTier {
+    pub xp_required: u64,
+    pub reward: ChallengeReward,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Season {
+    pub id: String,
+    pub game_id: String,
+    pub name: String,
+    pub starts_at: i64,
+    pub ends_at: i64,
+    /// Ordered by `xp_required`
+    pub tiers: Vec<RewardTier>,
+}
+
+impl Season {
+    pub fn is_live(&self, now: i64) -> bool {
+        self.starts_at <= now && now < self.ends_at
+    }
+    
+    /// Number of tiers unlocked by `xp`
+    pub fn tier_for(&self, xp: u64) -> usize {
+        self.tiers.iter().take_while(|t| xp >= t.xp_required).count()
+    }
+}
+
+/// A player's standing in one season
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SeasonStanding {
+    pub xp: u64,
+    /// Tiers unlocked so far
+    pub tier: usize,
+    /// Indexes of tiers whose reward was claimed
+    pub claimed: Vec<usize>,
+}
+
+/// A finished season and every player's final standing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SeasonArchive {
+    pub season: Season,
+    pub standings: HashMap<String, SeasonStanding>, // user_id -> standing
+    pub archived_at: i64,
+}
+
+/// Runs each game's season calendar. Season XP is earned alongside account XP and
+/// only counts while a season is live; when a season ends its standings move to the
+/// archive and the next scheduled season takes over. XP grants go through
+/// `record_match` and `grant_reward` here so account and season XP stay in step.
+///
+/// Lock order: seasons -> standings -> archive. Rollover and every standings write
+/// hold the seasons lock, so XP never lands on a season that is being archived.
+pub struct SeasonService {
+    auth: Arc<AuthService>,
+    seasons: Mutex<HashMap<String, Vec<Season>>>, // game_id -> current and upcoming, by start
+    standings: Mutex<HashMap<String, HashMap<String, SeasonStanding>>>, // season_id -> user_id -> standing
+    archive: Mutex<HashMap<String, Vec<SeasonArchive>>>, // game_id -> finished seasons, oldest first
+}
+
+impl SeasonService {
+    pub fn new(auth: Arc<AuthService>) -> Self {
+        Self {
+            auth,
+            seasons: Mutex::new(HashMap::new()),
+            standings: Mutex::new(HashMap::new()),
+            archive: Mutex::new(HashMap::new()),
+        }
+    }
+    
+    /// Add a season to its game's calendar. Seasons of one game may not overlap.
+    pub fn schedule_season(&self, season: Season) -> Result<(), String> {
+        if season.id.is_empty() || season.game_id.is_empty() {
+            return Err("Seasons need an ID and a game".to_string());
+        }
+        if season.ends_at <= season.starts_at {
+            return Err("Seasons must end after they start".to_string());
+        }
+        if season.tiers.windows(2).any(|pair| pair[1].xp_required <= pair[0].xp_required) {
+            return Err("Reward tiers must require increasing XP".to_string());
+        }
+        
+        let mut seasons = self.seasons.lock().unwrap();
+        if seasons.values().flatten().any(|s| s.id == season.id) {
+            return Err("A season with this ID already exists".to_string());
+        }
+        
+        let calendar = seasons.entry(season.game_id.clone()).or_default();
+        if calendar.iter().any(|s| s.starts_at < season.ends_at && season.starts_at < s.ends_at) {
+            return Err("Season overlaps another season of this game".to_string());
+        }
+        
+        println!("[Seasons] Scheduled {} for {} ({} - {})", season.id, season.game_id, season.starts_at, season.ends_at);
+        calendar.push(season);
+        calendar.sort_by_key(|s| s.starts_at);
+        
+        Ok(())
+    }
+    
+    pub fn current_season(&self, game_id: &str, now: i64) -> Option<Season> {
+        let mut seasons = self.seasons.lock().unwrap();
+        self.rollover_locked(&mut seasons, now);
+        live_season(&seasons, game_id, now).cloned()
+    }
+    
+    /// Archive every season that has ended by `now`. Returns the archived season IDs.
+    /// Runs on every read and write, so callers rarely need it directly.
+    pub fn rollover(&self, now: i64) -> Vec<String> {
+        let mut seasons = self.seasons.lock().unwrap();
+        self.rollover_locked(&mut seasons, now)
+    }
+    
+    fn rollover_locked(&self, seasons: &mut HashMap<String, Vec<Season>>, now: i64) -> Vec<String> {
+        let mut ended = Vec::new();
+        for calendar in seasons.values_mut() {
+            let (finished, remaining): (Vec<Season>, Vec<Season>) = calendar.drain(..).partition(|s| s.ends_at <= now);
+            ended.extend(finished);
+            *calendar = remaining;
+        }
+        seasons.retain(|_, calendar| !calendar.is_empty());
+        
+        if ended.is_empty() {
+            return Vec::new();
+        }
+        
+        let mut standings = self.standings.lock().unwrap();
+        let mut archive = self.archive.lock().unwrap();
+        for season in &ended {
+            let final_standings = standings.remove(&season.id).unwrap_or_default();
+            println!("[Seasons] {} ended with {} players, archiving", season.id, final_standings.len());
+            
+            archive.entry(season.game_id.clone()).or_default().push(SeasonArchive {
+                season: season.clone(),
+                standings: final_standings,
+                archived_at: now,
+            });
+        }
+        
+        ended.into_iter().map(|s| s.id).collect()
+    }
+    
+    /// Credit season XP to a player. Returns their new standing, or None when the
+    /// game has no live season.
+    pub fn record_xp(&self, user_id: &str, game_id: &str, xp: u64, now: i64) -> Option<SeasonStanding> {
+        let mut seasons = self.seasons.lock().unwrap();
+        self.rollover_locked(&mut seasons, now);
+        let season = live_season(&seasons, game_id, now)?;
+        
+        let mut standings = self.standings.lock().unwrap();
+        let standing = standings.entry(season.id.clone()).or_default()
+            .entry(user_id.to_string()).or_default();
+        
+        standing.xp = standing.xp.saturating_add(xp);
+        let tier = season.tier_for(standing.xp);
+        if tier > standing.tier {
+            println!("[Seasons] {} reached tier {} in {}", user_id, tier, season.id);
+        }
+        standing.tier = tier;
+        
+        Some(standing.clone())
+    }
+    
+    /// Record a finished match through `AuthService::record_match` and credit the
+    /// same XP to each participant's season standing
+    pub fn record_match(&self, game_id: &str, participants: &[MatchParticipant], now: i64) -> Result<(), String> {
+        self.auth.record_match(game_id, participants)?;
+        
+        for participant in participants {
+            let xp = if participant.won { MATCH_WIN_XP } else { MATCH_PLAYED_XP };
+            self.record_xp(&participant.user_id, game_id, xp, now);
+        }
+        Ok(())
+    }
+    
+    /// Grant a reward earned in `game_id` (e.g. a completed challenge) and credit
+    /// its XP to the player's season standing
+    pub fn grant_reward(&self, user_id: &str, game_id: &str, reward: &ChallengeReward, now: i64) -> Result<(), String> {
+        self.auth.grant_reward(user_id, reward.xp, &reward.items)?;
+        if reward.xp > 0 {
+            self.record_xp(user_id, game_id, reward.xp, now);
+        }
+        Ok(())
+    }
+    
+    /// The player's standing in the game's live season
+    pub fn standing(&self, user_id: &str, game_id: &str, now: i64) -> Option<(Season, SeasonStanding)> {
+        let season = self.current_season(game_id, now)?;
+        let standing = self.standings.lock().unwrap()
+            .get(&season.id)
+            .and_then(|s| s.get(user_id))
+            .cloned()
+            .unwrap_or_default();
+        
+        Some((season, standing))
+    }
+    
+    /// Claim the reward of an unlocked tier of the live season. Each tier can be
+    /// claimed once; unclaimed rewards lapse when the season ends.
+    pub fn claim_tier(&self, user_id: &str, game_id: &str, tier_index: usize, now: i64) -> Result<ChallengeReward, String> {
+        let (season, tier) = {
+            let mut seasons = self.seasons.lock().unwrap();
+            self.rollover_locked(&mut seasons, now);
+            let season = live_season(&seasons, game_id, now).ok_or_else(|| "No season is running".to_string())?;
+            let tier = season.tiers.get(tier_index).ok_or_else(|| "No such tier".to_string())?;
+            
+            let mut standings = self.standings.lock().unwrap();
+            let standing = standings.get_mut(&season.id)
+                .and_then(|s| s.get_mut(user_id))
+                .ok_or_else(|| "No progress this season".to_string())?;
+            
+            if tier_index >= standing.tier {
+                return Err("Tier not unlocked yet".to_string());
+            }
+            if standing.claimed.contains(&tier_index) {
+                return Err("Tier reward already claimed".to_string());
+            }
+            standing.claimed.push(tier_index);
+            (season.id.clone(), tier.clone())
+        };
+        
+        if let Err(e) = self.grant_reward(user_id, game_id, &tier.reward, now) {
+            // Give the claim back so the player can retry
+            if let Some(standing) = self.standings.lock().unwrap().get_mut(&season).and_then(|s| s.get_mut(user_id)) {
+                standing.claimed.retain(|&i| i != tier_index);
+            }
+            return Err(e);
+        }
+        
+        println!("[Seasons] {} claimed tier {} of {}", user_id, tier_index + 1, season);
+        Ok(tier.reward.clone())
+    }
+    
+    /// Finished seasons of a game, oldest first
+    pub fn archived_seasons(&self, game_id: &str, now: i64) -> Vec<SeasonArchive> {
+        self.rollover(now);
+        self.archive.lock().unwrap().get(game_id).cloned().unwrap_or_default()
+    }
+    
+    /// A player's final standing in every finished season of a game they took part in
+    pub fn season_history(&self, user_id: &str, game_id: &str, now: i64) -> Vec<(String, SeasonStanding)> {
+        self.archived_seasons(game_id, now).into_iter()
+            .filter_map(|archive| {
+                let standing = archive.standings.get(user_id)?.clone();
+                Some((archive.season.id, standing))
+            })
+            .collect()
+    }
+    
+    /// Run `rollover` every `interval` on the current tokio runtime so seasons are
+    /// archived on time even when nobody plays
+    pub fn start_rollover_job(self: &Arc<Self>, interval: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        let seasons = self.clone();
+        
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                seasons.rollover(chrono::Utc::now().timestamp());
+            }
+        })
+    }
+}
+
+fn live_season<'a>(seasons: &'a HashMap<String, Vec<Season>>, game_id: &str, now: i64) -> Option<&'a Season> {
+    seasons.get(game_id)?.iter().find(|s| s.is_live(now))
+}
+
+// ============================================
+// Tests
+// ============================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn season(id: &str, starts_at: i64, ends_at: i64) -> Season {
+        Season {
+            id: id.to_string(),
+            game_id: "arena".to_string(),
+            name: id.to_string(),
+            starts_at,
+            ends_at,
+            tiers: vec![
+                RewardTier { xp_required: 100, reward: ChallengeReward { xp: 0, items: vec!["banner".to_string()] } },
+                RewardTier { xp_required: 300, reward: ChallengeReward { xp: 500, items: Vec::new() } },
+            ],
+        }
+    }
+    
+    #[test]
+    fn test_season_track_claims_and_rollover() {
+        let auth = Arc::new(AuthService::new("test_secret_key".to_string()));
+        let user_id = auth.signup_test_user("player");
+        
+        let seasons = SeasonService::new(auth.clone());
+        seasons.schedule_season(season("s1", 0, 1000)).unwrap();
+        seasons.schedule_season(season("s2", 1000, 2000)).unwrap();
+        assert!(seasons.schedule_season(season("overlap", 500, 1500)).is_err());
+        
+        let winner = [MatchParticipant { user_id: user_id.clone(), score: 1, won: true }];
+        seasons.record_match("arena", &winner, 10).unwrap();
+        assert_eq!(auth.get_user(&user_id).unwrap().xp, MATCH_WIN_XP);
+        assert!(seasons.claim_tier(&user_id, "arena", 0, 20).is_ok());
+        assert!(seasons.claim_tier(&user_id, "arena", 0, 20).is_err());
+        assert!(seasons.claim_tier(&user_id, "arena", 1, 20).is_err());
+        assert_eq!(auth.inventory(&user_id).get("banner"), Some(&1));
+        
+        // Challenge rewards count towards the season as well as the account
+        let reward = ChallengeReward { xp: 250, items: Vec::new() };
+        seasons.grant_reward(&user_id, "arena", &reward, 30).unwrap();
+        assert_eq!(seasons.standing(&user_id, "arena", 30).unwrap().1.tier, 2);
+        assert!(seasons.claim_tier(&user_id, "arena", 1, 40).is_ok());
+        assert_eq!(auth.get_user(&user_id).unwrap().xp, MATCH_WIN_XP + 250 + 500);
+        
+        // s1 is archived as s2 begins, and s2 starts from zero
+        let (current, standing) = seasons.standing(&user_id, "arena", 1000).unwrap();
+        assert_eq!((current.id.as_str(), standing.xp), ("s2", 0));
+        
+        let history = seasons.season_history(&user_id, "arena", 1000);
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].0, "s1");
+        assert_eq!((history[0].1.xp, history[0].1.tier, history[0].1.claimed.clone()), (850, 2, vec![0, 1]));
+        
+        assert!(seasons.record_xp(&user_id, "arena", 50, 2500).is_none());
+    }
+}
@@ -0,0 +1,237 @@
+// sim.rs - Deterministic simulation harness: a real server driven by a virtual clock and in-memory transport
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::clock::{Clock, ManualClock};
+use crate::multiplayer_server::{GameMessage, MatchmakingService, MessageSink, MultiplayerServer, PlayerState, PlayerTransform};
+use crate::transport::{decode_message, encode_message};
+
+/// Message sink that keeps each player's messages in memory, encoded exactly as
+/// they would go on the wire
+#[derive(Default)]
+pub struct InMemoryTransport {
+    inboxes: Mutex<HashMap<String, Vec<Vec<u8>>>>, // player_id -> encoded messages, oldest first
+}
+
+impl InMemoryTransport {
+    /// Take and decode everything delivered to a player so far
+    pub fn drain(&self, player_id: &str) -> Vec<GameMessage> {
+        self.inboxes.lock().unwrap()
+            .remove(player_id)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|payload| decode_message(payload).ok())
+            .collect()
+    }
+    
+    pub fn pending(&self, player_id: &str) -> usize {
+        self.inboxes.lock().unwrap().get(player_id).map_or(0, Vec::len)
+    }
+}
+
+impl MessageSink for InMemoryTransport {
+    fn deliver(&self, player_id: &str, message: &GameMessage) {
+        match encode_message(message) {
+            Ok(payload) => self.inboxes.lock().unwrap().entry(player_id.to_string()).or_default().push(payload),
+            Err(e) => eprintln!("[Simulation] Failed to encode message for {}: {}", player_id, e),
+        }
+    }
+}
+
+/// Everything the periodic server jobs did during a tick or run
+#[derive(Debug, Default)]
+pub struct SimTick {
+    pub timed_out_players: Vec<String>,
+    pub collected_rooms: Vec<String>,
+    pub failed_rpcs: Vec<String>,
+    pub advanced_turns: Vec<String>,
+    pub expired_rooms: Vec<String>,
+}
+
+impl SimTick {
+    fn merge(&mut self, other: SimTick) {
+        self.timed_out_players.extend(other.timed_out_players);
+        self.collected_rooms.extend(other.collected_rooms);
+        self.failed_rpcs.extend(other.failed_rpcs);
+        self.advanced_turns.extend(other.advanced_turns);
+        self.expired_rooms.extend(other.expired_rooms);
+    }
+}
+
+/// A game server and matchmaker sharing one `ManualClock`. Time only moves in
+/// `run_for`, so heartbeat timeouts, matchmaking windows and room collection can
+/// be tested to the second without sleeping.
+pub struct Simulation {
+    pub clock: Arc<ManualClock>,
+    pub transport: Arc<InMemoryTransport>,
+    pub server: MultiplayerServer,
+    pub matchmaking: MatchmakingService,
+}
+
+impl Simulation {
+    /// Virtual Unix time every simulation starts at
+    pub const START_TIMESTAMP: i64 = 1_700_000_000;
+    
+    pub fn new() -> Self {
+        let clock = Arc::new(ManualClock::new(Self::START_TIMESTAMP));
+        let transport = Arc::new(InMemoryTransport::default());
+        
+        Self {
+            server: MultiplayerServer::new()
+                .with_clock(clock.clone())
+                .with_message_sink(transport.clone()),
+            matchmaking: MatchmakingService::new().with_clock(clock.clone()),
+            clock,
+            transport,
+        }
+    }
+    
+    pub fn player(player_id: &str) -> PlayerState {
+        PlayerState {
+            player_id: player_id.to_string(),
+            username: player_id.to_string(),
+            display_name: player_id.to_string(),
+            transform: PlayerTransform::identity(),
+            avatar_url: None,
+            is_talking: false,
+            custom_data: HashMap::new(),
+            is_npc: false,
+        }
+    }
+    
+    /// Seat a simulated client in a room
+    pub fn join(&self, room_id: &str, player_id: &str) -> Result<(), String> {
+        self.server.join_room(room_id, Self::player(player_id))
+    }
+    
+    /// Run every periodic server job once at the current virtual time
+    pub fn tick(&self) -> SimTick {
+        let now = self.clock.now();
+        let timestamp = self.clock.timestamp();
+        
+        let tick = SimTick {
+            timed_out_players: self.server.tick_heartbeats(now),
+            collected_rooms: self.server.collect_idle_rooms(timestamp),
+            failed_rpcs: self.server.tick_rpc_timeouts(now),
+            advanced_turns: self.server.tick_turns(timestamp),
+            expired_rooms: self.server.tick_room_time_limits(),
+        };
+        self.server.tick_object_sleep(timestamp);
+        self.server.tick_network_chaos(now);
+        self.server.tick_player_syncs(now);
+        self.server.tick_network_reports(now);
+        
+        tick
+    }
+    
+    /// Advance virtual time by `duration` in increments of `step`. After each step the
+    /// `keep_alive` players send a heartbeat and then the periodic jobs run.
+    pub fn run_for(&self, duration: Duration, step: Duration, keep_alive: &[String]) -> SimTick {
+        let mut total = SimTick::default();
+        let mut remaining = duration;
+        
+        while !remaining.is_zero() {
+            let advance = step.min(remaining);
+            self.clock.advance(advance);
+            remaining -= advance;
+            
+            for player_id in keep_alive {
+                let _ = self.server.heartbeat(player_id);
+            }
+            total.merge(self.tick());
+        }
+        
+        total
+    }
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ============================================
+// Tests
+// ============================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multiplayer_server::{HEARTBEAT_TIMEOUT, IDLE_ROOM_GRACE_SECONDS, REGION_EXPANSION_WINDOW};
+    
+    const SECOND: Duration = Duration::from_secs(1);
+    
+    #[test]
+    fn test_fifty_players_join_and_silent_players_time_out() {
+        let sim = Simulation::new();
+        let room_id = sim.server.create_room("arena".to_string(), "p00".to_string(), 50);
+        let players: Vec<String> = (0..50).map(|i| format!("p{:02}", i)).collect();
+        
+        for player_id in &players {
+            sim.join(&room_id, player_id).unwrap();
+            assert!(matches!(sim.transport.drain(player_id)[..], [GameMessage::AudioSettings { .. }]));
+        }
+        assert!(sim.join(&room_id, "late").is_err());
+        
+        // The first 40 keep heartbeating; the last 10 go silent
+        let (active, silent) = players.split_at(40);
+        let quiet = sim.run_for(HEARTBEAT_TIMEOUT - SECOND, SECOND, active);
+        assert!(quiet.timed_out_players.is_empty());
+        
+        let dropped = sim.run_for(SECOND, SECOND, active);
+        assert_eq!(dropped.timed_out_players, silent.to_vec());
+        assert_eq!(sim.server.get_room(&room_id).unwrap().players.len(), 40);
+        
+        // The host has been getting a network report every tick
+        assert!(sim.transport.drain("p00").iter().all(|m| matches!(m, GameMessage::RoomNetworkReport { .. })));
+        
+        sim.server.broadcast_to_room(&room_id, GameMessage::Success { message: "round over".to_string() }, None);
+        assert!(active.iter().all(|p| sim.transport.pending(p) == 1));
+        assert!(silent.iter().all(|p| sim.transport.pending(p) == 0));
+        
+        // Once everyone goes quiet the room empties and is removed
+        let everyone = sim.run_for(HEARTBEAT_TIMEOUT, SECOND, &[]);
+        assert_eq!(everyone.timed_out_players.len(), 40);
+        assert!(sim.server.get_room(&room_id).is_none());
+    }
+    
+    #[test]
+    fn test_matchmaking_widens_after_each_expansion_window() {
+        let sim = Simulation::new();
+        sim.matchmaking.join_regional_queue("arena", Some("eu"), "eu1");
+        sim.matchmaking.join_regional_queue("arena", None, "global1");
+        sim.matchmaking.join_regional_queue("arena", Some("us"), "us1");
+        sim.matchmaking.join_regional_queue("arena", Some("us"), "us2");
+        
+        assert!(sim.matchmaking.find_expanding_match("arena", Some("eu"), 3).is_none());
+        
+        // One window: the region-less queue joins the search, still short of 3
+        sim.run_for(REGION_EXPANSION_WINDOW, SECOND, &[]);
+        assert!(sim.matchmaking.find_expanding_match("arena", Some("eu"), 3).is_none());
+        
+        // Two windows: every region is searched, home region first
+        sim.run_for(REGION_EXPANSION_WINDOW, SECOND, &[]);
+        let matched = sim.matchmaking.find_expanding_match("arena", Some("eu"), 3).unwrap();
+        assert_eq!(matched, vec!["eu1".to_string(), "global1".to_string(), "us1".to_string()]);
+        assert_eq!(sim.matchmaking.find_regional_match("arena", Some("us"), 1), Some(vec!["us2".to_string()]));
+    }
+    
+    #[test]
+    fn test_rooms_nobody_joins_are_collected_after_grace() {
+        let sim = Simulation::new();
+        let abandoned = sim.server.create_room("arena".to_string(), "ghost".to_string(), 4);
+        let occupied = sim.server.create_room("arena".to_string(), "host".to_string(), 4);
+        sim.join(&occupied, "host").unwrap();
+        
+        let grace = Duration::from_secs(IDLE_ROOM_GRACE_SECONDS as u64);
+        let early = sim.run_for(grace - SECOND, SECOND, &["host".to_string()]);
+        assert!(early.collected_rooms.is_empty());
+        
+        let collected = sim.run_for(SECOND, SECOND, &["host".to_string()]);
+        assert_eq!(collected.collected_rooms, vec![abandoned.clone()]);
+        assert!(sim.server.get_room(&abandoned).is_none());
+        assert!(sim.server.get_room(&occupied).is_some());
+    }
+}
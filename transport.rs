@@ -0,0 +1,1541 @@
+// transport.rs - Reliability and channel multiplexing for the unreliable (UDP) game transport
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::multiplayer_server::{ConnectionSample, GameMessage};
+
+// ============================================
+// Packet Format
+// ============================================
+
+/// How a message is delivered over the unreliable transport
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DeliveryMode {
+    /// Fire and forget; newer data supersedes lost packets (transforms, voice)
+    Unreliable,
+    /// Resent until acked, delivered as soon as it arrives
+    ReliableUnordered,
+    /// Resent until acked, delivered strictly in send order
+    ReliableOrdered,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Packet {
+    /// Per-packet sequence number, starting at 1. Resends get a fresh sequence.
+    pub sequence: u32,
+    /// Latest sequence received from the peer (0 = nothing received yet)
+    pub ack: u32,
+    /// Bit `i` acknowledges sequence `ack - (i + 1)`
+    pub ack_bits: u32,
+    pub mode: DeliveryMode,
+    /// Per-mode message id used for deduplication and ordering (reliable modes only)
+    pub message_id: u32,
+    /// Serialized message; empty for ack-only packets
+    pub payload: Vec<u8>,
+}
+
+impl Packet {
+    pub fn is_ack_only(&self) -> bool {
+        self.payload.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReliabilityConfig {
+    pub resend_timeout: Duration,
+    pub max_resends: u32,
+}
+
+impl Default for ReliabilityConfig {
+    fn default() -> Self {
+        Self {
+            resend_timeout: Duration::from_millis(200),
+            max_resends: 10,
+        }
+    }
+}
+
+// ============================================
+// Message Encoding
+// ============================================
+
+/// First byte of a binary event frame; JSON messages always start with `{`
+const BINARY_EVENT_MARKER: u8 = 0x01;
+
+/// Largest payload `decode_message` will parse; room for the biggest binary event
+pub const MAX_MESSAGE_BYTES: usize = 128 * 1024;
+
+/// Why untrusted bytes could not be turned into a message
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    Empty,
+    TooLarge { len: usize, max: usize },
+    Truncated,
+    Malformed(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Empty => write!(f, "Empty message"),
+            DecodeError::TooLarge { len, max } => write!(f, "Message is {} bytes, limit is {}", len, max),
+            DecodeError::Truncated => write!(f, "Truncated binary event frame"),
+            DecodeError::Malformed(reason) => write!(f, "Malformed message: {}", reason),
+        }
+    }
+}
+
+/// Parse a JSON payload from an untrusted peer, rejecting oversized input before parsing
+pub fn decode_json<T: DeserializeOwned>(payload: &[u8], max_bytes: usize) -> Result<T, DecodeError> {
+    if payload.is_empty() {
+        return Err(DecodeError::Empty);
+    }
+    if payload.len() > max_bytes {
+        return Err(DecodeError::TooLarge { len: payload.len(), max: max_bytes });
+    }
+    
+    serde_json::from_slice(payload).map_err(|e| DecodeError::Malformed(e.to_string()))
+}
+
+/// Everything in a `CustomBinaryEvent` except its data
+#[derive(Serialize, Deserialize)]
+struct BinaryEventHeader {
+    event_name: String,
+    content_type: Option<String>,
+    channel: Option<String>,
+}
+
+/// Encode a message for the wire. Messages are JSON, except `CustomBinaryEvent`,
+/// whose data follows a small JSON header as raw bytes:
+/// `[marker][header length: u32 BE][header][data]`
+pub fn encode_message(message: &GameMessage) -> Result<Vec<u8>, String> {
+    let (event_name, content_type, channel, data) = match message {
+        GameMessage::CustomBinaryEvent { event_name, content_type, channel, data } => (event_name, content_type, channel, data),
+        _ => return serde_json::to_vec(message).map_err(|e| e.to_string()),
+    };
+    
+    let header = serde_json::to_vec(&BinaryEventHeader {
+        event_name: event_name.clone(),
+        content_type: content_type.clone(),
+        channel: channel.clone(),
+    }).map_err(|e| e.to_string())?;
+    
+    let mut frame = Vec::with_capacity(5 + header.len() + data.len());
+    frame.push(BINARY_EVENT_MARKER);
+    frame.extend_from_slice(&(header.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&header);
+    frame.extend_from_slice(data);
+    
+    Ok(frame)
+}
+
+/// Decode bytes received from a client. Never panics; anything that isn't a
+/// well-formed message comes back as a `DecodeError`.
+pub fn decode_message(payload: &[u8]) -> Result<GameMessage, DecodeError> {
+    if payload.first() != Some(&BINARY_EVENT_MARKER) {
+        let value: serde_json::Value = decode_json(payload, MAX_MESSAGE_BYTES)?;
+        if exceeds_f32(&value) {
+            return Err(DecodeError::Malformed("Number out of range".to_string()));
+        }
+        return serde_json::from_value(value).map_err(|e| DecodeError::Malformed(e.to_string()));
+    }
+    if payload.len() > MAX_MESSAGE_BYTES {
+        return Err(DecodeError::TooLarge { len: payload.len(), max: MAX_MESSAGE_BYTES });
+    }
+    
+    let length_bytes: [u8; 4] = match payload.get(1..5) {
+        Some(bytes) => [bytes[0], bytes[1], bytes[2], bytes[3]],
+        None => return Err(DecodeError::Truncated),
+    };
+    // Checked so a hostile length can't overflow on 32-bit targets
+    let header_end = usize::try_from(u32::from_be_bytes(length_bytes)).ok()
+        .and_then(|length| length.checked_add(5))
+        .ok_or(DecodeError::Truncated)?;
+    let header: BinaryEventHeader = decode_json(payload.get(5..header_end).ok_or(DecodeError::Truncated)?, MAX_MESSAGE_BYTES)?;
+    
+    Ok(GameMessage::CustomBinaryEvent {
+        event_name: header.event_name,
+        content_type: header.content_type,
+        channel: header.channel,
+        data: payload[header_end..].to_vec(),
+    })
+}
+
+/// Whether any number is too large for an f32. Such values would decode to
+/// infinity, which can't be encoded again.
+fn exceeds_f32(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Number(n) => n.as_f64().is_some_and(|f| f.abs() > f32::MAX as f64),
+        serde_json::Value::Array(items) => items.iter().any(exceeds_f32),
+        serde_json::Value::Object(fields) => fields.values().any(exceeds_f32),
+        _ => false,
+    }
+}
+
+// ============================================
+// Reliable Connection
+// ============================================
+
+/// Packets older than this many sequences behind the newest one are dropped as
+/// possible replays, as are repeats of any sequence inside the window
+pub const REPLAY_WINDOW: u32 = 64;
+
+/// A jump past the replay window is taken as lost traffic rather than a forgery when
+/// the packet acks one of our last `RESYNC_ACK_WINDOW` sequences, which an attacker
+/// can't see, or after `RESYNC_PACKETS` such packets in a row that keep increasing
+const RESYNC_ACK_WINDOW: u32 = 1024;
+const RESYNC_PACKETS: u32 = 3;
+
+/// Weight of each new ack or timeout in the smoothed RTT and loss (as in TCP's SRTT)
+const LINK_QUALITY_GAIN: f32 = 0.125;
+
+#[derive(Debug, Clone)]
+struct PendingMessage {
+    mode: DeliveryMode,
+    message_id: u32,
+    payload: Vec<u8>,
+    sent_at: Instant,
+    resends: u32,
+}
+
+/// Per-connection reliability state: sequencing, acks, resends and ordering.
+///
+/// The connection never touches a socket; callers send the returned packets
+/// and feed received ones back in, passing the current time explicitly.
+pub struct ReliableConnection {
+    config: ReliabilityConfig,
+    next_sequence: u32,
+    next_message_id: HashMap<DeliveryMode, u32>,
+    pending: HashMap<u32, PendingMessage>, // packet sequence -> unacked message
+    
+    // Receive-side ack tracking
+    remote_sequence: u32,
+    received_bits: u32,
+    
+    // Replay protection: bit `i` marks sequence `remote_sequence - (i + 1)` as seen
+    replay_bits: u64,
+    replays_rejected: u64,
+    resync_run: Option<(u32, u32)>, // (newest sequence past the window, packets in the run)
+    
+    // Reliable-unordered dedup: every id below the floor was delivered
+    unordered_floor: u32,
+    unordered_received: BTreeSet<u32>,
+    
+    // Reliable-ordered reassembly
+    next_ordered_id: u32,
+    ordered_buffer: BTreeMap<u32, Vec<u8>>,
+    
+    // Link quality, smoothed over acked and timed out reliable messages
+    smoothed_rtt: Option<f32>, // milliseconds
+    smoothed_loss: f32,
+    
+    link_failed: bool,
+}
+
+impl ReliableConnection {
+    pub fn new(config: ReliabilityConfig) -> Self {
+        Self {
+            config,
+            next_sequence: 1,
+            next_message_id: HashMap::new(),
+            pending: HashMap::new(),
+            remote_sequence: 0,
+            received_bits: 0,
+            replay_bits: 0,
+            replays_rejected: 0,
+            resync_run: None,
+            unordered_floor: 1,
+            unordered_received: BTreeSet::new(),
+            next_ordered_id: 1,
+            ordered_buffer: BTreeMap::new(),
+            smoothed_rtt: None,
+            smoothed_loss: 0.0,
+            link_failed: false,
+        }
+    }
+    
+    /// Wrap a payload in a packet ready to be sent
+    pub fn send(&mut self, payload: Vec<u8>, mode: DeliveryMode, now: Instant) -> Packet {
+        let message_id = match mode {
+            DeliveryMode::Unreliable => 0,
+            _ => {
+                let id = self.next_message_id.entry(mode).or_insert(1);
+                let current = *id;
+                *id += 1;
+                current
+            }
+        };
+        
+        self.build_packet(mode, message_id, payload, now, 0)
+    }
+    
+    /// Serialize a game message and send it with its default delivery mode
+    pub fn send_message(&mut self, message: &GameMessage, now: Instant) -> Result<Packet, String> {
+        let payload = encode_message(message)?;
+        Ok(self.send(payload, message.delivery_mode(), now))
+    }
+    
+    /// Build a packet carrying only acknowledgement data
+    pub fn ack_packet(&mut self) -> Packet {
+        let sequence = self.take_sequence();
+        
+        Packet {
+            sequence,
+            ack: self.remote_sequence,
+            ack_bits: self.received_bits,
+            mode: DeliveryMode::Unreliable,
+            message_id: 0,
+            payload: Vec::new(),
+        }
+    }
+    
+    /// Process an incoming packet and return payloads ready for delivery, in order.
+    /// Duplicated and replayed packets are dropped before anything else is read.
+    pub fn receive(&mut self, packet: Packet, now: Instant) -> Vec<Vec<u8>> {
+        if !self.accept_sequence(packet.sequence, packet.ack) {
+            self.replays_rejected += 1;
+            return Vec::new();
+        }
+        
+        self.process_acks(packet.ack, packet.ack_bits, now);
+        self.record_received(packet.sequence);
+        
+        if packet.is_ack_only() {
+            return Vec::new();
+        }
+        
+        match packet.mode {
+            DeliveryMode::Unreliable => vec![packet.payload],
+            DeliveryMode::ReliableUnordered => {
+                if packet.message_id < self.unordered_floor
+                    || !self.unordered_received.insert(packet.message_id)
+                {
+                    return Vec::new();
+                }
+                
+                while self.unordered_received.remove(&self.unordered_floor) {
+                    self.unordered_floor += 1;
+                }
+                
+                vec![packet.payload]
+            }
+            DeliveryMode::ReliableOrdered => {
+                if packet.message_id < self.next_ordered_id {
+                    return Vec::new();
+                }
+                
+                self.ordered_buffer.insert(packet.message_id, packet.payload);
+                
+                let mut ready = Vec::new();
+                while let Some(payload) = self.ordered_buffer.remove(&self.next_ordered_id) {
+                    ready.push(payload);
+                    self.next_ordered_id += 1;
+                }
+                ready
+            }
+        }
+    }
+    
+    /// Re-stamp and return every unacked message whose resend timeout elapsed
+    pub fn collect_resends(&mut self, now: Instant) -> Vec<Packet> {
+        self.due_resends(now).into_iter()
+            .filter_map(|sequence| self.resend(sequence, now))
+            .collect()
+    }
+    
+    /// Sequences of unacked messages whose resend timeout elapsed, oldest first.
+    /// Nothing is re-stamped until `resend` is called for a sequence.
+    pub fn due_resends(&self, now: Instant) -> Vec<u32> {
+        let mut expired: Vec<u32> = self.pending.iter()
+            .filter(|(_, m)| now.duration_since(m.sent_at) >= self.config.resend_timeout)
+            .map(|(seq, _)| *seq)
+            .collect();
+        expired.sort_unstable();
+        expired
+    }
+    
+    /// Payload size of an unacked message
+    pub fn pending_len(&self, sequence: u32) -> Option<usize> {
+        self.pending.get(&sequence).map(|m| m.payload.len())
+    }
+    
+    /// Re-stamp an unacked message for sending now. None if it was acked meanwhile,
+    /// or ran out of resends and failed the link.
+    pub fn resend(&mut self, sequence: u32, now: Instant) -> Option<Packet> {
+        let message = self.pending.remove(&sequence)?;
+        self.record_outcome(true, None);
+        if message.resends >= self.config.max_resends {
+            println!("[Transport] Message {} exceeded max resends, link failed", message.message_id);
+            self.link_failed = true;
+            return None;
+        }
+        
+        Some(self.build_packet(
+            message.mode,
+            message.message_id,
+            message.payload,
+            now,
+            message.resends + 1,
+        ))
+    }
+    
+    /// Number of reliable messages still waiting for an ack
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+    
+    /// True once a reliable message could not be delivered within `max_resends`
+    pub fn is_link_failed(&self) -> bool {
+        self.link_failed
+    }
+    
+    /// Number of packets dropped as duplicates, replays or too old to verify
+    pub fn replays_rejected(&self) -> u64 {
+        self.replays_rejected
+    }
+    
+    /// Smoothed RTT and packet loss measured from acks of reliable messages, ready
+    /// for `MultiplayerServer::record_connection_sample`. None until the first
+    /// message is acked without having been resent.
+    pub fn connection_sample(&self) -> Option<ConnectionSample> {
+        self.smoothed_rtt.map(|rtt| ConnectionSample {
+            rtt_ms: rtt.round() as u32,
+            packet_loss: self.smoothed_loss,
+        })
+    }
+    
+    // Private helper methods
+    
+    fn take_sequence(&mut self) -> u32 {
+        let sequence = self.next_sequence;
+        // 0 means "nothing received" in acks, so the counter skips it when it wraps
+        self.next_sequence = self.next_sequence.wrapping_add(1).max(1);
+        sequence
+    }
+    
+    fn build_packet(
+        &mut self,
+        mode: DeliveryMode,
+        message_id: u32,
+        payload: Vec<u8>,
+        now: Instant,
+        resends: u32,
+    ) -> Packet {
+        let sequence = self.take_sequence();
+        
+        if mode != DeliveryMode::Unreliable {
+            self.pending.insert(sequence, PendingMessage {
+                mode,
+                message_id,
+                payload: payload.clone(),
+                sent_at: now,
+                resends,
+            });
+        }
+        
+        Packet {
+            sequence,
+            ack: self.remote_sequence,
+            ack_bits: self.received_bits,
+            mode,
+            message_id,
+            payload,
+        }
+    }
+    
+    fn process_acks(&mut self, ack: u32, ack_bits: u32, now: Instant) {
+        if ack == 0 {
+            return;
+        }
+        
+        self.acknowledge(ack, now);
+        
+        for i in 0..32 {
+            let sequence = ack.wrapping_sub(i + 1);
+            if ack_bits & (1 << i) != 0 && sequence != 0 {
+                self.acknowledge(sequence, now);
+            }
+        }
+    }
+    
+    fn acknowledge(&mut self, sequence: u32, now: Instant) {
+        let Some(message) = self.pending.remove(&sequence) else { return };
+        
+        // A resent message's ack can't be matched to one send, so it gives no RTT
+        let rtt = (message.resends == 0).then(|| now.saturating_duration_since(message.sent_at));
+        self.record_outcome(false, rtt);
+    }
+    
+    /// Fold an acked or timed out message into the link quality estimates
+    fn record_outcome(&mut self, lost: bool, rtt: Option<Duration>) {
+        let lost = if lost { 1.0 } else { 0.0 };
+        self.smoothed_loss += (lost - self.smoothed_loss) * LINK_QUALITY_GAIN;
+        
+        if let Some(rtt) = rtt {
+            let rtt = rtt.as_secs_f32() * 1000.0;
+            self.smoothed_rtt = Some(match self.smoothed_rtt {
+                Some(smoothed) => smoothed + (rtt - smoothed) * LINK_QUALITY_GAIN,
+                None => rtt,
+            });
+        }
+    }
+    
+    /// Sliding-window check that `sequence` is new. Sequences wrap, so a packet is
+    /// newer when it is at most `REPLAY_WINDOW` ahead of the newest one. A bigger jump
+    /// forward (e.g. after a long dropout) moves the window only once the peer proves
+    /// it is genuine (see `RESYNC_ACK_WINDOW`), or one forged packet could push the
+    /// window past all real traffic. Must run before `record_received`.
+    fn accept_sequence(&mut self, sequence: u32, ack: u32) -> bool {
+        if sequence == 0 {
+            return false;
+        }
+        
+        // Nothing received yet: the first packet sets the window
+        if self.remote_sequence == 0 {
+            self.resync(sequence);
+            return true;
+        }
+        
+        let shift = sequence.wrapping_sub(self.remote_sequence);
+        if (1..=REPLAY_WINDOW).contains(&shift) {
+            self.resync_run = None;
+            self.replay_bits = self.replay_bits.checked_shl(shift).unwrap_or(0) | 1u64.checked_shl(shift - 1).unwrap_or(0);
+            return true;
+        }
+        
+        let distance = self.remote_sequence.wrapping_sub(sequence);
+        if distance > REPLAY_WINDOW && shift <= u32::MAX / 2 {
+            return self.accept_jump(sequence, ack);
+        }
+        if !(1..=REPLAY_WINDOW).contains(&distance) {
+            return false;
+        }
+        
+        let bit = 1u64 << (distance - 1);
+        if self.replay_bits & bit != 0 {
+            return false;
+        }
+        self.replay_bits |= bit;
+        true
+    }
+    
+    /// A packet too far ahead of the window: resync to it if its ack is one we sent
+    /// recently, or if it continues a run of increasing out-of-window packets
+    fn accept_jump(&mut self, sequence: u32, ack: u32) -> bool {
+        let acks_our_traffic = ack != 0 && (1..=RESYNC_ACK_WINDOW).contains(&self.next_sequence.wrapping_sub(ack));
+        
+        let run = match self.resync_run {
+            Some((newest, run)) if (1..=REPLAY_WINDOW).contains(&sequence.wrapping_sub(newest)) => run + 1,
+            _ => 1,
+        };
+        
+        if !acks_our_traffic && run < RESYNC_PACKETS {
+            self.resync_run = Some((sequence, run));
+            return false;
+        }
+        
+        self.resync(sequence);
+        true
+    }
+    
+    /// Restart the receive window at `sequence`, forgetting everything before it
+    fn resync(&mut self, sequence: u32) {
+        self.remote_sequence = sequence;
+        self.received_bits = 0;
+        self.replay_bits = 0;
+        self.resync_run = None;
+    }
+    
+    fn record_received(&mut self, sequence: u32) {
+        let shift = sequence.wrapping_sub(self.remote_sequence);
+        let distance = self.remote_sequence.wrapping_sub(sequence);
+        
+        if (1..=REPLAY_WINDOW).contains(&shift) {
+            if shift > 32 {
+                self.received_bits = 0;
+            } else {
+                self.received_bits = self.received_bits.checked_shl(shift).unwrap_or(0) | (1 << (shift - 1));
+            }
+            
+            self.remote_sequence = sequence;
+        } else if (1..=32).contains(&distance) {
+            self.received_bits |= 1 << (distance - 1);
+        }
+    }
+}
+
+// ============================================
+// Channel Multiplexing
+// ============================================
+
+/// Logical channel within a single connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Channel {
+    GameEvents,
+    Voice,
+    Transforms,
+    Chat,
+    /// Screen-share video and binary event payloads: bulky, so they only get what
+    /// bandwidth is left and are shed when there is none
+    Bulk,
+    /// Telemetry the game queues directly; first to go on a constrained link
+    Analytics,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 6] = [Channel::GameEvents, Channel::Voice, Channel::Transforms, Channel::Chat, Channel::Bulk, Channel::Analytics];
+    
+    /// Channel a game message travels on
+    pub fn for_message(message: &GameMessage) -> Channel {
+        match message {
+            GameMessage::PlayerUpdate { .. } | GameMessage::ObjectMoved { .. } => Channel::Transforms,
+            GameMessage::VoiceData { .. } | GameMessage::Visemes { .. } => Channel::Voice,
+            GameMessage::DirectMessage { .. } | GameMessage::RoomInvite { .. } => Channel::Chat,
+            GameMessage::ScreenShareFrame { .. } | GameMessage::CustomBinaryEvent { .. } => Channel::Bulk,
+            _ => Channel::GameEvents,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChannelConfig {
+    /// Higher priority channels are flushed first
+    pub priority: u8,
+    /// Queue bound; unreliable traffic drops its oldest entry, reliable traffic is rejected
+    pub max_queued: usize,
+    /// Packets this channel may send per flush, so one burst can't starve the others
+    pub max_per_flush: usize,
+    /// Discard even reliable payloads when the bandwidth budget runs out, rather
+    /// than holding them for the next flush. Unreliable payloads are always discarded.
+    pub shed_over_budget: bool,
+}
+
+impl ChannelConfig {
+    pub fn default_for(channel: Channel) -> Self {
+        match channel {
+            Channel::GameEvents => Self { priority: 4, max_queued: 256, max_per_flush: 32, shed_over_budget: false },
+            Channel::Voice => Self { priority: 3, max_queued: 64, max_per_flush: 16, shed_over_budget: false },
+            Channel::Transforms => Self { priority: 2, max_queued: 32, max_per_flush: 16, shed_over_budget: false },
+            Channel::Chat => Self { priority: 1, max_queued: 128, max_per_flush: 8, shed_over_budget: false },
+            Channel::Bulk => Self { priority: 0, max_queued: 16, max_per_flush: 4, shed_over_budget: true },
+            Channel::Analytics => Self { priority: 0, max_queued: 64, max_per_flush: 4, shed_over_budget: true },
+        }
+    }
+}
+
+/// Byte rate a connection may send; refills continuously and holds up to one
+/// second of traffic so short bursts still get through
+#[derive(Debug, Clone)]
+struct BandwidthBudget {
+    bytes_per_second: usize,
+    available: f64,
+    refilled_at: Option<Instant>,
+}
+
+impl BandwidthBudget {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = self.refilled_at.map(|at| now.duration_since(at).as_secs_f64()).unwrap_or(1.0);
+        self.available = (self.available + elapsed * self.bytes_per_second as f64).min(self.bytes_per_second as f64);
+        self.refilled_at = Some(now);
+    }
+    
+    /// A payload larger than the whole bucket goes out once the bucket is full,
+    /// leaving it in debt, so it can't block its channel forever
+    fn try_spend(&mut self, bytes: usize) -> bool {
+        let full = self.available >= self.bytes_per_second as f64;
+        if self.available < bytes as f64 && !full {
+            return false;
+        }
+        self.available -= bytes as f64;
+        true
+    }
+}
+
+/// Packet tagged with the logical channel it belongs to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelPacket {
+    pub channel: Channel,
+    pub packet: Packet,
+}
+
+struct ChannelState {
+    config: ChannelConfig,
+    connection: ReliableConnection,
+    queue: VecDeque<(Vec<u8>, DeliveryMode)>,
+    needs_ack: bool,
+    dropped: u64,
+}
+
+/// Multiplexes logical channels over one connection.
+///
+/// Every channel has its own reliability state (sequences, acks, ordering),
+/// so loss or a backlog on one channel never blocks another.
+pub struct ChannelMultiplexer {
+    channels: HashMap<Channel, ChannelState>,
+    budget: Option<BandwidthBudget>,
+    chaos: Option<ChaosLink<ChannelPacket>>,
+}
+
+impl ChannelMultiplexer {
+    pub fn new(reliability: ReliabilityConfig) -> Self {
+        let channels = Channel::ALL.iter()
+            .map(|channel| {
+                (*channel, ChannelState {
+                    config: ChannelConfig::default_for(*channel),
+                    connection: ReliableConnection::new(reliability.clone()),
+                    queue: VecDeque::new(),
+                    needs_ack: false,
+                    dropped: 0,
+                })
+            })
+            .collect();
+        
+        Self { channels, budget: None, chaos: None }
+    }
+    
+    /// Cap outgoing payload bytes per second (None = unlimited). Over budget, higher
+    /// priority channels are served first and lower priority traffic is dropped
+    /// instead of delaying everything.
+    pub fn set_bandwidth_budget(&mut self, bytes_per_second: Option<usize>) {
+        self.budget = bytes_per_second.map(|bytes_per_second| BandwidthBudget {
+            bytes_per_second,
+            available: 0.0,
+            refilled_at: None,
+        });
+    }
+    
+    /// Run outgoing packets through artificial latency, jitter, reordering and loss
+    /// (None = send them as-is). Packets still held when chaos is turned off are
+    /// lost; reliable ones are resent as usual.
+    pub fn set_chaos(&mut self, config: Option<ChaosConfig>) {
+        match (config, self.chaos.as_mut()) {
+            (Some(config), Some(chaos)) => chaos.set_config(config),
+            (config, _) => self.chaos = config.map(ChaosLink::new),
+        }
+    }
+    
+    /// Override the flow control settings of a channel
+    pub fn set_channel_config(&mut self, channel: Channel, config: ChannelConfig) {
+        if let Some(state) = self.channels.get_mut(&channel) {
+            state.config = config;
+        }
+    }
+    
+    /// Queue a payload on a channel. Returns false if a reliable payload was rejected.
+    pub fn queue(&mut self, channel: Channel, payload: Vec<u8>, mode: DeliveryMode) -> bool {
+        let state = match self.channels.get_mut(&channel) {
+            Some(s) => s,
+            None => return false,
+        };
+        
+        if state.queue.len() >= state.config.max_queued {
+            if mode != DeliveryMode::Unreliable {
+                println!("[Transport] Channel {:?} queue full, rejecting reliable message", channel);
+                return false;
+            }
+            
+            // Drop the oldest unreliable entry; fresher data supersedes it
+            match state.queue.iter().position(|(_, m)| *m == DeliveryMode::Unreliable) {
+                Some(index) => {
+                    state.queue.remove(index);
+                    state.dropped += 1;
+                }
+                None => {
+                    state.dropped += 1;
+                    return true;
+                }
+            }
+        }
+        
+        state.queue.push_back((payload, mode));
+        true
+    }
+    
+    /// Serialize a game message and queue it on its channel
+    pub fn queue_message(&mut self, message: &GameMessage) -> Result<(), String> {
+        let payload = encode_message(message)?;
+        
+        if self.queue(Channel::for_message(message), payload, message.delivery_mode()) {
+            Ok(())
+        } else {
+            Err("Channel queue full".to_string())
+        }
+    }
+    
+    /// Drain queued traffic in priority order, sending at most `max_packets` data packets.
+    /// Resends go out before new data on each channel; pending acks are always included.
+    /// With a bandwidth budget, payloads that don't fit are dropped if unreliable (or on
+    /// a shedding channel) and otherwise wait for a later flush. With chaos enabled the
+    /// returned packets are the ones whose artificial delay has elapsed.
+    pub fn flush(&mut self, now: Instant, max_packets: usize) -> Vec<ChannelPacket> {
+        let mut order: Vec<Channel> = self.channels.keys().copied().collect();
+        order.sort_by_key(|c| (std::cmp::Reverse(self.channels[c].config.priority), *c));
+        
+        if let Some(bandwidth) = self.budget.as_mut() {
+            bandwidth.refill(now);
+        }
+        
+        let mut out = Vec::new();
+        let mut budget = max_packets;
+        
+        for channel in order {
+            let state = self.channels.get_mut(&channel).unwrap();
+            let bandwidth = &mut self.budget;
+            let mut fits = |bytes: usize| bandwidth.as_mut().is_none_or(|b| b.try_spend(bytes));
+            let mut sent = 0;
+            
+            // Resends that don't go out this flush keep their old stamp and stay due
+            for sequence in state.connection.due_resends(now) {
+                let Some(size) = state.connection.pending_len(sequence) else { continue };
+                if sent >= state.config.max_per_flush || budget == 0 || !fits(size) {
+                    break;
+                }
+                let Some(packet) = state.connection.resend(sequence, now) else { continue };
+                out.push(ChannelPacket { channel, packet });
+                sent += 1;
+                budget -= 1;
+            }
+            
+            while sent < state.config.max_per_flush && budget > 0 {
+                let size = match state.queue.front() {
+                    Some((payload, _)) => payload.len(),
+                    None => break,
+                };
+                
+                if !fits(size) {
+                    let mode = state.queue.front().map(|(_, mode)| *mode).unwrap();
+                    if mode == DeliveryMode::Unreliable || state.config.shed_over_budget {
+                        state.queue.pop_front();
+                        state.dropped += 1;
+                        continue;
+                    }
+                    break;
+                }
+                
+                let (payload, mode) = state.queue.pop_front().unwrap();
+                out.push(ChannelPacket { channel, packet: state.connection.send(payload, mode, now) });
+                sent += 1;
+                budget -= 1;
+            }
+            
+            if sent == 0 && state.needs_ack {
+                out.push(ChannelPacket { channel, packet: state.connection.ack_packet() });
+            }
+            state.needs_ack = false;
+        }
+        
+        match self.chaos.as_mut() {
+            Some(chaos) => {
+                for packet in out {
+                    chaos.send(packet, now);
+                }
+                chaos.poll(now)
+            }
+            None => out,
+        }
+    }
+    
+    /// Process an incoming packet and return payloads ready for delivery
+    pub fn receive(&mut self, channel_packet: ChannelPacket, now: Instant) -> Vec<(Channel, Vec<u8>)> {
+        let channel = channel_packet.channel;
+        let state = match self.channels.get_mut(&channel) {
+            Some(s) => s,
+            None => return Vec::new(),
+        };
+        
+        if !channel_packet.packet.is_ack_only() && channel_packet.packet.mode != DeliveryMode::Unreliable {
+            state.needs_ack = true;
+        }
+        
+        state.connection.receive(channel_packet.packet, now)
+            .into_iter()
+            .map(|payload| (channel, payload))
+            .collect()
+    }
+    
+    /// Number of payloads waiting to be sent on a channel
+    pub fn queued(&self, channel: Channel) -> usize {
+        self.channels.get(&channel).map(|s| s.queue.len()).unwrap_or(0)
+    }
+    
+    /// Number of payloads dropped on a channel due to queue overflow or the bandwidth budget
+    pub fn dropped(&self, channel: Channel) -> u64 {
+        self.channels.get(&channel).map(|s| s.dropped).unwrap_or(0)
+    }
+    
+    /// Number of replayed or duplicated packets dropped on a channel
+    pub fn replays_rejected(&self, channel: Channel) -> u64 {
+        self.channels.get(&channel).map(|s| s.connection.replays_rejected()).unwrap_or(0)
+    }
+    
+    /// Link quality across all channels: the worst RTT and loss any channel measured
+    pub fn connection_sample(&self) -> Option<ConnectionSample> {
+        self.channels.values()
+            .filter_map(|s| s.connection.connection_sample())
+            .reduce(|a, b| ConnectionSample {
+                rtt_ms: a.rtt_ms.max(b.rtt_ms),
+                packet_loss: a.packet_loss.max(b.packet_loss),
+            })
+    }
+}
+
+// ============================================
+// Network Chaos
+// ============================================
+
+/// Upper bound on the artificial delay a `ChaosConfig` may add
+pub const MAX_CHAOS_DELAY_MS: u64 = 10_000;
+/// Extra delay for a packet picked for reordering, so later packets overtake it
+const REORDER_DELAY: Duration = Duration::from_millis(100);
+
+/// Artificial bad-network conditions for one connection, so developers can see how
+/// their title copes with congested home Wi-Fi. Development use only.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ChaosConfig {
+    /// Added to every packet
+    pub latency_ms: u64,
+    /// Random extra delay of up to this much per packet
+    pub jitter_ms: u64,
+    /// Chance (0-1) that a packet is lost
+    pub loss_rate: f64,
+    /// Chance (0-1) that a packet is held back behind later ones
+    pub reorder_rate: f64,
+    /// Seeds the random source so a run can be repeated exactly
+    #[serde(default)]
+    pub seed: u64,
+}
+
+impl ChaosConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.loss_rate) || !(0.0..=1.0).contains(&self.reorder_rate) {
+            return Err("Loss and reorder rates must be between 0 and 1".to_string());
+        }
+        if self.latency_ms.saturating_add(self.jitter_ms) > MAX_CHAOS_DELAY_MS {
+            return Err(format!("Latency plus jitter is limited to {}ms", MAX_CHAOS_DELAY_MS));
+        }
+        Ok(())
+    }
+}
+
+/// Delays, reorders and drops outgoing packets according to a `ChaosConfig`.
+/// Packets go in with `send` and come back out of `poll` once their delay is up.
+pub struct ChaosLink<T> {
+    config: ChaosConfig,
+    rng: u64,
+    in_flight: BTreeMap<(Instant, u64), T>, // (release at, send order) -> packet
+    next_id: u64,
+    dropped: u64,
+}
+
+impl<T> ChaosLink<T> {
+    pub fn new(config: ChaosConfig) -> Self {
+        Self {
+            rng: config.seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1,
+            config,
+            in_flight: BTreeMap::new(),
+            next_id: 0,
+            dropped: 0,
+        }
+    }
+    
+    pub fn config(&self) -> &ChaosConfig {
+        &self.config
+    }
+    
+    /// Change conditions without losing packets already in flight
+    pub fn set_config(&mut self, config: ChaosConfig) {
+        self.config = config;
+    }
+    
+    pub fn send(&mut self, packet: T, now: Instant) {
+        if self.chance(self.config.loss_rate) {
+            self.dropped += 1;
+            return;
+        }
+        
+        let jitter = match self.config.jitter_ms {
+            0 => 0,
+            max => self.next_random() % (max + 1),
+        };
+        let mut delay = Duration::from_millis(self.config.latency_ms + jitter);
+        if self.chance(self.config.reorder_rate) {
+            delay += REORDER_DELAY;
+        }
+        
+        self.in_flight.insert((now + delay, self.next_id), packet);
+        self.next_id += 1;
+    }
+    
+    /// Packets whose delay has elapsed, in the order they arrive
+    pub fn poll(&mut self, now: Instant) -> Vec<T> {
+        let pending = self.in_flight.split_off(&(now, u64::MAX));
+        std::mem::replace(&mut self.in_flight, pending).into_values().collect()
+    }
+    
+    /// Everything still in flight, in the order it would have arrived
+    pub fn drain(&mut self) -> Vec<T> {
+        std::mem::take(&mut self.in_flight).into_values().collect()
+    }
+    
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+    
+    /// Packets lost on purpose so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+    
+    // xorshift64; quality is irrelevant here, repeatability is what matters
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+    
+    fn chance(&mut self, probability: f64) -> bool {
+        let sample = (self.next_random() >> 11) as f64 / (1u64 << 53) as f64;
+        probability > 0.0 && sample < probability
+    }
+}
+
+// ============================================
+// Tests
+// ============================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multiplayer_server::{
+        AttenuationProfile, AudioOccluder, DegradationLevel, MatchResult, ObjectIdAssignment, PlayerNetworkStats, PlayerState,
+        PlayerTransform, Quaternion, RecordingKind, RpcTarget, ScreenFrame, SpawnedObject, StateOperation, Vector3, VisemeFrame, VoiceInputMode,
+    };
+    
+    fn deliver(from: &mut ReliableConnection, to: &mut ReliableConnection, packet: Packet, now: Instant) -> Vec<Vec<u8>> {
+        let delivered = to.receive(packet, now);
+        let ack = to.ack_packet();
+        from.receive(ack, now);
+        delivered
+    }
+    
+    #[test]
+    fn test_ordered_delivery_buffers_out_of_order_packets() {
+        let now = Instant::now();
+        let mut sender = ReliableConnection::new(ReliabilityConfig::default());
+        let mut receiver = ReliableConnection::new(ReliabilityConfig::default());
+        
+        let first = sender.send(b"first".to_vec(), DeliveryMode::ReliableOrdered, now);
+        let second = sender.send(b"second".to_vec(), DeliveryMode::ReliableOrdered, now);
+        
+        assert!(receiver.receive(second, now).is_empty());
+        
+        let delivered = receiver.receive(first, now);
+        assert_eq!(delivered, vec![b"first".to_vec(), b"second".to_vec()]);
+    }
+    
+    #[test]
+    fn test_lost_packet_is_resent_and_deduplicated() {
+        let now = Instant::now();
+        let config = ReliabilityConfig::default();
+        let timeout = config.resend_timeout;
+        let mut sender = ReliableConnection::new(config);
+        let mut receiver = ReliableConnection::new(ReliabilityConfig::default());
+        
+        let original = sender.send(b"spawn".to_vec(), DeliveryMode::ReliableUnordered, now);
+        assert!(sender.collect_resends(now).is_empty());
+        
+        // Original arrives but the ack is lost, so the sender resends
+        assert_eq!(receiver.receive(original, now).len(), 1);
+        let resends = sender.collect_resends(now + timeout);
+        assert_eq!(resends.len(), 1);
+        
+        let duplicate = resends.into_iter().next().unwrap();
+        assert!(deliver(&mut sender, &mut receiver, duplicate, now + timeout).is_empty());
+        assert_eq!(sender.pending_count(), 0);
+    }
+    
+    #[test]
+    fn test_replayed_packets_are_dropped() {
+        let now = Instant::now();
+        let mut sender = ReliableConnection::new(ReliabilityConfig::default());
+        let mut receiver = ReliableConnection::new(ReliabilityConfig::default());
+        
+        let grab = sender.send(b"grab".to_vec(), DeliveryMode::Unreliable, now);
+        let packets: Vec<Packet> = (0..REPLAY_WINDOW + 2)
+            .map(|i| sender.send(i.to_le_bytes().to_vec(), DeliveryMode::Unreliable, now))
+            .collect();
+        
+        assert_eq!(receiver.receive(grab.clone(), now).len(), 1);
+        assert!(receiver.receive(grab.clone(), now).is_empty());
+        
+        // Out-of-order arrivals inside the window still get through, once
+        let late = packets[REPLAY_WINDOW as usize - 1].clone();
+        assert_eq!(receiver.receive(packets[REPLAY_WINDOW as usize - 2].clone(), now).len(), 1);
+        assert_eq!(receiver.receive(packets.last().unwrap().clone(), now).len(), 1);
+        assert_eq!(receiver.receive(late.clone(), now).len(), 1);
+        assert!(receiver.receive(late, now).is_empty());
+        
+        // Anything further back than the window can't be told apart from a replay
+        assert!(receiver.receive(packets[0].clone(), now).is_empty());
+        assert!(receiver.receive(Packet { sequence: 0, ..grab }, now).is_empty());
+        assert_eq!(receiver.replays_rejected(), 4);
+    }
+    
+    #[test]
+    fn test_connection_sample_measures_rtt_and_loss_from_acks() {
+        let now = Instant::now();
+        let config = ReliabilityConfig::default();
+        let timeout = config.resend_timeout;
+        let mut sender = ReliableConnection::new(config);
+        let mut receiver = ReliableConnection::new(ReliabilityConfig::default());
+        assert!(sender.connection_sample().is_none());
+        
+        let spawn = sender.send(b"spawn".to_vec(), DeliveryMode::ReliableOrdered, now);
+        deliver(&mut sender, &mut receiver, spawn, now + Duration::from_millis(50));
+        assert_eq!(sender.connection_sample(), Some(ConnectionSample { rtt_ms: 50, packet_loss: 0.0 }));
+        
+        // A timeout counts as a loss; the resend's ack lowers loss again but says nothing about RTT
+        sender.send(b"grab".to_vec(), DeliveryMode::ReliableOrdered, now);
+        let resend = sender.collect_resends(now + timeout).pop().unwrap();
+        assert_eq!(sender.connection_sample().unwrap().packet_loss, 0.125);
+        deliver(&mut sender, &mut receiver, resend, now + timeout * 10);
+        assert_eq!(sender.connection_sample(), Some(ConnectionSample { rtt_ms: 50, packet_loss: 0.109375 }));
+    }
+    
+    #[test]
+    fn test_sequence_window_refuses_forged_jumps_resyncs_and_wraps() {
+        let now = Instant::now();
+        let mut sender = ReliableConnection::new(ReliabilityConfig::default());
+        let mut receiver = ReliableConnection::new(ReliabilityConfig::default());
+        
+        // A forged packet far ahead would otherwise make every real one look replayed
+        let first = sender.send(b"first".to_vec(), DeliveryMode::Unreliable, now);
+        assert_eq!(receiver.receive(first, now).len(), 1);
+        let forged = Packet { sequence: 2 + REPLAY_WINDOW, ..sender.ack_packet() };
+        assert!(receiver.receive(forged, now).is_empty());
+        assert_eq!(receiver.receive(sender.send(b"next".to_vec(), DeliveryMode::Unreliable, now), now).len(), 1);
+        
+        // Losing more than a window of packets isn't fatal: the next one acks our own
+        // traffic, so the window moves to it
+        sender.receive(receiver.send(b"reply".to_vec(), DeliveryMode::Unreliable, now), now);
+        for _ in 0..REPLAY_WINDOW * 2 {
+            sender.send(b"lost".to_vec(), DeliveryMode::Unreliable, now);
+        }
+        assert_eq!(receiver.receive(sender.send(b"back".to_vec(), DeliveryMode::Unreliable, now), now).len(), 1);
+        assert_eq!(receiver.receive(sender.send(b"again".to_vec(), DeliveryMode::Unreliable, now), now).len(), 1);
+        
+        // A one-way stream has no acks to vouch for it; the window follows after a short run
+        let mut one_way = ReliableConnection::new(ReliabilityConfig::default());
+        let packet = |sequence| Packet { sequence, ack: 0, ack_bits: 0, mode: DeliveryMode::Unreliable, message_id: 0, payload: b"x".to_vec() };
+        assert_eq!(one_way.receive(packet(500), now).len(), 1);
+        assert!(one_way.receive(packet(700), now).is_empty());
+        assert!(one_way.receive(packet(701), now).is_empty());
+        assert_eq!(one_way.receive(packet(703), now).len(), 1);
+        assert!(one_way.receive(packet(703), now).is_empty());
+        assert_eq!(one_way.receive(packet(704), now).len(), 1);
+        
+        // Sequences wrap past u32::MAX, skipping 0, and acks follow them
+        sender.next_sequence = u32::MAX - 1;
+        receiver.remote_sequence = u32::MAX - 2;
+        let packets: Vec<Packet> = (0..4)
+            .map(|i: u32| sender.send(i.to_le_bytes().to_vec(), DeliveryMode::ReliableUnordered, now))
+            .collect();
+        assert_eq!(packets.iter().map(|p| p.sequence).collect::<Vec<_>>(), vec![u32::MAX - 1, u32::MAX, 1, 2]);
+        for packet in &packets {
+            assert_eq!(receiver.receive(packet.clone(), now).len(), 1);
+        }
+        assert!(receiver.receive(packets[1].clone(), now).is_empty());
+        
+        sender.receive(receiver.ack_packet(), now);
+        assert_eq!(sender.pending_count(), 0);
+    }
+    
+    #[test]
+    fn test_link_fails_after_max_resends() {
+        let mut now = Instant::now();
+        let config = ReliabilityConfig { resend_timeout: Duration::from_millis(10), max_resends: 2 };
+        let mut sender = ReliableConnection::new(config);
+        
+        sender.send(b"lost".to_vec(), DeliveryMode::ReliableOrdered, now);
+        
+        for _ in 0..3 {
+            now += Duration::from_millis(10);
+            sender.collect_resends(now);
+        }
+        
+        assert!(sender.is_link_failed());
+        assert_eq!(sender.pending_count(), 0);
+    }
+    
+    #[test]
+    fn test_voice_burst_does_not_delay_game_events() {
+        let now = Instant::now();
+        let mut mux = ChannelMultiplexer::new(ReliabilityConfig::default());
+        
+        for _ in 0..50 {
+            mux.queue(Channel::Voice, vec![0u8; 32], DeliveryMode::Unreliable);
+        }
+        
+        let joined = GameMessage::PlayerLeft { player_id: "player1".to_string() };
+        mux.queue_message(&joined).unwrap();
+        
+        let packets = mux.flush(now, 4);
+        assert_eq!(packets[0].channel, Channel::GameEvents);
+        assert_eq!(packets.len(), 4);
+        assert_eq!(mux.queued(Channel::Voice), 47);
+    }
+    
+    #[test]
+    fn test_full_unreliable_channel_drops_oldest() {
+        let mut mux = ChannelMultiplexer::new(ReliabilityConfig::default());
+        mux.set_channel_config(Channel::Transforms, ChannelConfig { max_queued: 2, max_per_flush: 2, ..ChannelConfig::default_for(Channel::Transforms) });
+        
+        assert!(mux.queue(Channel::Transforms, b"a".to_vec(), DeliveryMode::Unreliable));
+        assert!(mux.queue(Channel::Transforms, b"b".to_vec(), DeliveryMode::Unreliable));
+        assert!(mux.queue(Channel::Transforms, b"c".to_vec(), DeliveryMode::Unreliable));
+        assert!(!mux.queue(Channel::Transforms, b"d".to_vec(), DeliveryMode::ReliableOrdered));
+        
+        assert_eq!(mux.queued(Channel::Transforms), 2);
+        assert_eq!(mux.dropped(Channel::Transforms), 1);
+    }
+    
+    #[test]
+    fn test_resends_skipped_by_flush_limit_stay_due() {
+        let mut mux = ChannelMultiplexer::new(ReliabilityConfig::default());
+        mux.set_channel_config(Channel::Chat, ChannelConfig { max_per_flush: 1, ..ChannelConfig::default_for(Channel::Chat) });
+        let now = Instant::now();
+        
+        for text in [b"a", b"b"] {
+            mux.queue(Channel::Chat, text.to_vec(), DeliveryMode::ReliableOrdered);
+            assert_eq!(mux.flush(now, 10).len(), 1);
+        }
+        
+        // Only one resend fits per flush; the other must not be pushed back
+        let due = now + Duration::from_millis(200);
+        assert_eq!(mux.flush(due, 10)[0].packet.payload, b"a");
+        assert_eq!(mux.flush(due, 10)[0].packet.payload, b"b");
+        assert!(mux.flush(due, 10).is_empty());
+    }
+    
+    #[test]
+    fn test_bandwidth_budget_sheds_low_priority_traffic() {
+        let mut mux = ChannelMultiplexer::new(ReliabilityConfig::default());
+        mux.set_bandwidth_budget(Some(1000));
+        let now = Instant::now();
+        
+        for _ in 0..3 {
+            mux.queue(Channel::Voice, vec![0u8; 300], DeliveryMode::Unreliable);
+            mux.queue(Channel::Transforms, vec![0u8; 100], DeliveryMode::Unreliable);
+            mux.queue(Channel::Chat, vec![0u8; 50], DeliveryMode::ReliableOrdered);
+            mux.queue(Channel::Analytics, vec![0u8; 50], DeliveryMode::ReliableOrdered);
+        }
+        
+        let packets = mux.flush(now, 100);
+        let count = |channel: Channel| packets.iter().filter(|p| p.channel == channel).count();
+        
+        // 900 bytes of voice, one transform; the rest of the transforms and all
+        // analytics are shed while reliable chat waits for more budget
+        assert_eq!((count(Channel::Voice), count(Channel::Transforms)), (3, 1));
+        assert_eq!((count(Channel::Chat), count(Channel::Analytics)), (0, 0));
+        assert_eq!(mux.dropped(Channel::Transforms), 2);
+        assert_eq!(mux.dropped(Channel::Analytics), 3);
+        assert_eq!(mux.queued(Channel::Chat), 3);
+        
+        let later = mux.flush(now + Duration::from_millis(200), 100);
+        assert_eq!(later.iter().filter(|p| p.channel == Channel::Chat).count(), 3);
+        
+        // Binary events and screen-share frames ride the bulk channel, which yields to
+        // voice and is shed once the budget is spent
+        let binary = GameMessage::CustomBinaryEvent { event_name: "blob".to_string(), content_type: None, channel: None, data: vec![0u8; 64 * 1024] };
+        assert_eq!(Channel::for_message(&binary), Channel::Bulk);
+        let mut mux = ChannelMultiplexer::new(ReliabilityConfig::default());
+        mux.set_bandwidth_budget(Some(1000));
+        for _ in 0..3 {
+            mux.queue(Channel::Voice, vec![0u8; 300], DeliveryMode::Unreliable);
+        }
+        mux.queue_message(&binary).unwrap();
+        let packets = mux.flush(now, 100);
+        assert_eq!(packets.iter().filter(|p| p.channel == Channel::Voice).count(), 3);
+        assert!(packets.iter().all(|p| p.channel != Channel::Bulk));
+        assert_eq!((mux.dropped(Channel::Bulk), mux.queued(Channel::Bulk)), (1, 0));
+        
+        // A reliable payload bigger than the budget waits for a full bucket, then goes
+        // out and the traffic behind it pays off the debt
+        let mut mux = ChannelMultiplexer::new(ReliabilityConfig { resend_timeout: Duration::from_secs(10), ..ReliabilityConfig::default() });
+        mux.set_bandwidth_budget(Some(1000));
+        mux.queue(Channel::Chat, vec![0u8; 50], DeliveryMode::ReliableOrdered);
+        mux.queue(Channel::Chat, vec![0u8; 1500], DeliveryMode::ReliableOrdered);
+        mux.queue(Channel::Chat, vec![0u8; 50], DeliveryMode::ReliableOrdered);
+        assert_eq!(mux.flush(now, 100).len(), 1);
+        assert_eq!(mux.flush(now + Duration::from_secs(1), 100).len(), 1);
+        assert!(mux.flush(now + Duration::from_millis(1100), 100).is_empty());
+        assert_eq!(mux.flush(now + Duration::from_millis(1600), 100).len(), 1);
+    }
+    
+    #[test]
+    fn test_binary_event_round_trips_without_json_overhead() {
+        let data: Vec<u8> = (0..=255).collect();
+        let event = GameMessage::CustomBinaryEvent {
+            event_name: "mesh_delta".to_string(),
+            content_type: Some("application/x-mesh-delta".to_string()),
+            channel: Some("terrain".to_string()),
+            data: data.clone(),
+        };
+        
+        let encoded = encode_message(&event).unwrap();
+        assert!(encoded.len() < data.len() + 100);
+        
+        match decode_message(&encoded).unwrap() {
+            GameMessage::CustomBinaryEvent { event_name, data: decoded, .. } => {
+                assert_eq!(event_name, "mesh_delta");
+                assert_eq!(decoded, data);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+        
+        assert!(decode_message(&encoded[..3]).is_err());
+        assert!(matches!(decode_message(&encode_message(&GameMessage::EndTurn).unwrap()), Ok(GameMessage::EndTurn)));
+    }
+    
+    // ---- Protocol conformance ----
+    
+    /// One `<variant>\t<JSON>\t<wire frame as hex>` line per `GameMessage` variant.
+    /// Only regenerate a line when a wire-format break is intended.
+    const PROTOCOL_GOLDEN: &str = include_str!("testdata/protocol_golden.txt");
+    
+    /// One deterministic sample of every message. Maps hold a single entry so the
+    /// JSON key order is stable.
+    fn protocol_samples() -> Vec<GameMessage> {
+        let s = |value: &str| value.to_string();
+        let position = || Vector3 { x: 1.5, y: 0.0, z: -2.0 };
+        let rotation = || Quaternion { x: 0.0, y: 0.5, z: 0.0, w: 1.0 };
+        let result = || MatchResult { scores: HashMap::from([(s("p1"), 10)]), winners: vec![s("p1")] };
+        
+        vec![
+            GameMessage::Connect { token: s("tok"), game_id: s("g1"), region: Some(s("eu")) },
+            GameMessage::JoinWithTicket { ticket: s("t1") },
+            GameMessage::Disconnect { player_id: s("p1") },
+            GameMessage::Heartbeat,
+            GameMessage::OnboardingStatus { game_id: s("g1"), completed_steps: vec![s("move")], remaining_steps: vec![s("grab")] },
+            GameMessage::PlayerJoined {
+                player: PlayerState {
+                    player_id: s("p1"),
+                    username: s("alice"),
+                    display_name: s("Alice"),
+                    transform: PlayerTransform::identity(),
+                    avatar_url: None,
+                    is_talking: false,
+                    custom_data: HashMap::from([(s("team"), s("red"))]),
+                    is_npc: false,
+                },
+            },
+            GameMessage::PlayerProfileUpdated { player_id: s("p1"), display_name: s("Alice"), avatar_url: Some(s("a.png")) },
+            GameMessage::PlayerLeft { player_id: s("p1") },
+            GameMessage::PlayerUpdate { player_id: s("p1"), transform: PlayerTransform::identity() },
+            GameMessage::RoomLoadChanged { room_id: s("r1"), level: DegradationLevel::Reduced, player_count: 40, soft_cap: Some(32), max_players: 64 },
+            GameMessage::RoomTimeRemaining { room_id: s("r1"), seconds_remaining: 60, joins_locked: false },
+            GameMessage::RoomNetworkReport {
+                room_id: s("r1"),
+                players: vec![PlayerNetworkStats { player_id: s("p1"), rtt_ms: Some(40), packet_loss: 0.25, last_update_age_ms: Some(120), poor_connection: true }],
+            },
+            GameMessage::ObjectSpawned { object_id: 7, temp_id: Some(s("tmp")), object_type: s("cube"), position: position(), rotation: rotation() },
+            GameMessage::ObjectMoved { object_id: 7, position: position(), rotation: rotation() },
+            GameMessage::ObjectDestroyed { object_id: 7 },
+            GameMessage::ObjectsSpawnedBatch {
+                objects: vec![SpawnedObject { object_id: 8, temp_id: None, object_type: s("ball"), position: position(), rotation: rotation() }],
+            },
+            GameMessage::ObjectsDestroyedBatch { object_ids: vec![7, 8] },
+            GameMessage::ObjectGrabbed { object_id: 7, player_id: s("p1") },
+            GameMessage::ObjectReleased { object_id: 7 },
+            GameMessage::ObjectSleep { object_id: 7, position: position(), rotation: rotation() },
+            GameMessage::ObjectWake { object_id: 7, player_id: s("p1") },
+            GameMessage::ObjectIdsAssigned { assignments: vec![ObjectIdAssignment { temp_id: s("tmp"), object_id: 7 }] },
+            GameMessage::SetGameState { key: s("score"), value: s("3"), expected_version: Some(2) },
+            GameMessage::GameStateChanged { key: s("score"), value: s("3"), version: 3 },
+            GameMessage::GameStateOperation { key: s("score"), operation: StateOperation::Increment { amount: 1 } },
+            GameMessage::SubscribeGameState { keys: vec![s("score")] },
+            GameMessage::UnsubscribeGameState { keys: vec![s("score")] },
+            GameMessage::EndTurn,
+            GameMessage::TurnChanged { player_id: s("p2"), turn_number: 4, deadline: Some(1_700_000_000) },
+            GameMessage::SubmitMatchResult { result: result(), idempotency_key: Some(s("k1")) },
+            GameMessage::MatchFinalized { result: result() },
+            GameMessage::RoomInvite { from_player_id: s("p1"), room_id: s("r1") },
+            GameMessage::DirectMessage { from_player_id: s("p1"), to_player_id: s("p2"), text: s("hi") },
+            GameMessage::InteractionsFrozen { frozen: true },
+            GameMessage::RecordingStateChanged { room_id: s("r1"), kind: Some(RecordingKind::Stream) },
+            GameMessage::RecordingConsentRequest { room_id: s("r1"), kind: RecordingKind::Moderation },
+            GameMessage::RecordingConsent { room_id: s("r1"), consent: true },
+            GameMessage::SetVoiceInputMode { mode: VoiceInputMode::PushToTalk },
+            GameMessage::PushToTalk { pressed: true },
+            GameMessage::VoiceInputChanged { player_id: s("p1"), mode: VoiceInputMode::OpenMic, transmitting: true },
+            GameMessage::VoiceData { player_id: s("p1"), audio_data: vec![1, 2, 3] },
+            GameMessage::ScreenShareStarted { room_id: s("r1"), sharer_id: s("p1") },
+            GameMessage::ScreenShareStopped { room_id: s("r1") },
+            GameMessage::ScreenShareFrame {
+                sharer_id: s("p1"),
+                frame: ScreenFrame { sequence: 9, is_keyframe: true, chunk_index: 0, chunk_count: 2, data: vec![255, 0] },
+            },
+            GameMessage::RequestKeyframe { room_id: s("r1") },
+            GameMessage::AudioSettings {
+                room_id: s("r1"),
+                attenuation: AttenuationProfile::default(),
+                occluders: vec![AudioOccluder { min: position(), max: Vector3 { x: 2.0, y: 3.0, z: 0.0 }, attenuation: 0.5 }],
+            },
+            GameMessage::Visemes { player_id: s("p1"), frame: VisemeFrame { viseme: 3, amplitude: 200 } },
+            GameMessage::CustomEvent { event_name: s("score"), data: s("{\"n\":1}"), channel: Some(s("hud")) },
+            GameMessage::CustomBinaryEvent { event_name: s("mesh"), content_type: Some(s("application/octet-stream")), channel: None, data: vec![0, 1, 254, 255] },
+            GameMessage::SubscribeEvents { channels: vec![s("hud")] },
+            GameMessage::UnsubscribeEvents { channels: vec![s("hud")] },
+            GameMessage::RpcCall { call_id: s("c1"), target: RpcTarget::Host, procedure: s("roll"), args: s("[6]"), timeout_ms: Some(1000) },
+            GameMessage::RpcInvoke { call_id: s("c1"), caller_id: s("p2"), procedure: s("roll"), args: s("[6]") },
+            GameMessage::RpcResponse { call_id: s("c1"), result: Err(s("timed out")) },
+            GameMessage::MatchFound { game_id: s("g1"), room_id: s("r1"), players: vec![s("p1"), s("p2")] },
+            GameMessage::MatchmakingTicketExpired { game_id: s("g1"), reason: s("timeout") },
+            GameMessage::Error { message: s("bad") },
+            GameMessage::Success { message: s("ok") },
+        ]
+    }
+    
+    fn variant_name(message: &GameMessage) -> String {
+        serde_json::to_value(message).unwrap()["type"].as_str().unwrap().to_string()
+    }
+    
+    /// Every variant the decoder accepts, read back from serde's unknown-variant error
+    fn protocol_variants() -> Vec<String> {
+        let error = serde_json::from_str::<GameMessage>(r#"{"type":"__probe__"}"#).unwrap_err().to_string();
+        let expected = error.split("expected one of").nth(1).expect("unexpected serde error format");
+        expected.split('`').skip(1).step_by(2).map(|name| name.to_string()).collect()
+    }
+    
+    fn golden_line(message: &GameMessage) -> String {
+        let json = serde_json::to_string(message).unwrap();
+        let wire: String = encode_message(message).unwrap().iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{}\t{}\t{}", variant_name(message), json, wire)
+    }
+    
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+    
+    #[test]
+    fn test_every_variant_has_a_golden_sample() {
+        let sampled: Vec<String> = protocol_samples().iter().map(variant_name).collect();
+        let golden: Vec<&str> = PROTOCOL_GOLDEN.lines().filter_map(|line| line.split('\t').next()).collect();
+        
+        for variant in protocol_variants() {
+            assert!(sampled.contains(&variant), "GameMessage::{} has no protocol sample", variant);
+            assert!(golden.contains(&variant.as_str()), "GameMessage::{} has no golden encoding", variant);
+        }
+        assert_eq!(sampled.len(), golden.len(), "samples and golden file list different variants");
+    }
+    
+    /// Old encodings must still decode (and decode to the same thing), and current
+    /// encodings must match what existing clients expect byte for byte
+    #[test]
+    fn test_wire_format_matches_golden_samples() {
+        let golden: HashMap<&str, (&str, &str)> = PROTOCOL_GOLDEN
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split('\t');
+                Some((fields.next()?, (fields.next()?, fields.next()?)))
+            })
+            .collect();
+        
+        let mut breaks = Vec::new();
+        for sample in protocol_samples() {
+            let name = variant_name(&sample);
+            let Some(&(json, wire)) = golden.get(name.as_str()) else { continue };
+            
+            for (format, encoded) in [("JSON", json.as_bytes().to_vec()), ("binary", from_hex(wire))] {
+                match decode_message(&encoded) {
+                    Ok(decoded) if golden_line(&decoded) == golden_line(&sample) => {}
+                    Ok(decoded) => breaks.push(format!("{} golden {} decodes to {:?}", name, format, decoded)),
+                    Err(e) => breaks.push(format!("{} golden {} no longer decodes: {}", name, format, e)),
+                }
+            }
+            
+            let current = golden_line(&sample);
+            if current != format!("{}\t{}\t{}", name, json, wire) {
+                breaks.push(format!("{} encoding changed, now:\n{}", name, current));
+            }
+        }
+        
+        assert!(breaks.is_empty(), "wire compatibility broken:\n{}", breaks.join("\n"));
+    }
+    
+    /// Property test: corruptions of valid messages either decode to something that
+    /// survives a re-encode, or fail with a `DecodeError`. A panic fails the test.
+    #[test]
+    fn test_decoding_corrupted_messages_never_panics() {
+        let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as usize
+        };
+        
+        for sample in protocol_samples() {
+            let encoded = encode_message(&sample).unwrap();
+            
+            for _ in 0..200 {
+                let mut bytes = encoded.clone();
+                match next() % 4 {
+                    0 => bytes.truncate(next() % bytes.len()),
+                    1 => {
+                        let i = next() % bytes.len();
+                        bytes[i] = next() as u8;
+                    }
+                    2 => bytes.insert(next() % bytes.len(), next() as u8),
+                    _ => bytes[1..5].copy_from_slice(&(next() as u32).to_be_bytes()),
+                }
+                
+                if let Ok(decoded) = decode_message(&bytes) {
+                    assert!(decode_message(&encode_message(&decoded).unwrap()).is_ok(), "{:?} does not round-trip", decoded);
+                }
+            }
+        }
+        
+        assert!(matches!(decode_message(&[]), Err(DecodeError::Empty)));
+        assert!(matches!(decode_message(&[BINARY_EVENT_MARKER, 0xff, 0xff, 0xff, 0xff]), Err(DecodeError::Truncated)));
+        assert!(matches!(decode_message(&vec![b' '; MAX_MESSAGE_BYTES + 1]), Err(DecodeError::TooLarge { .. })));
+        assert!(matches!(decode_message(&[b'['; 4096]), Err(DecodeError::Malformed(_))));
+        
+        // Out-of-range floats would decode to infinity and then fail to re-encode
+        let huge = br#"{"type":"ObjectMoved","object_id":1,"position":{"x":1e39,"y":0.0,"z":0.0},"rotation":{"x":0.0,"y":0.0,"z":0.0,"w":1.0}}"#;
+        assert!(matches!(decode_message(huge), Err(DecodeError::Malformed(_))));
+    }
+    
+    #[test]
+    fn test_chaos_delays_reorders_and_drops_packets() {
+        let now = Instant::now();
+        let ms = Duration::from_millis;
+        
+        let mut link = ChaosLink::new(ChaosConfig { latency_ms: 50, reorder_rate: 1.0, ..ChaosConfig::default() });
+        link.send("first", now);
+        link.set_config(ChaosConfig { latency_ms: 50, ..ChaosConfig::default() });
+        link.send("second", now);
+        assert!(link.poll(now + ms(49)).is_empty());
+        assert_eq!(link.poll(now + ms(50)), vec!["second"]);
+        assert_eq!(link.poll(now + ms(150)), vec!["first"]);
+        
+        let mut lossy = ChaosLink::new(ChaosConfig { loss_rate: 1.0, ..ChaosConfig::default() });
+        lossy.send("lost", now);
+        assert_eq!((lossy.in_flight(), lossy.dropped()), (0, 1));
+        
+        // Through a multiplexer, jittered packets are held until their delay elapses
+        let mut mux = ChannelMultiplexer::new(ReliabilityConfig::default());
+        mux.set_chaos(Some(ChaosConfig { latency_ms: 100, jitter_ms: 20, seed: 7, ..ChaosConfig::default() }));
+        mux.queue(Channel::Chat, b"hi".to_vec(), DeliveryMode::ReliableOrdered);
+        assert!(mux.flush(now, 10).is_empty());
+        assert!(mux.flush(now + ms(99), 10).is_empty());
+        assert_eq!(mux.flush(now + ms(120), 10).len(), 1);
+        
+        assert!(ChaosConfig { loss_rate: 1.5, ..ChaosConfig::default() }.validate().is_err());
+        assert!(ChaosConfig { latency_ms: MAX_CHAOS_DELAY_MS, jitter_ms: 1, ..ChaosConfig::default() }.validate().is_err());
+    }
+}